@@ -0,0 +1,17 @@
+use std::{env, process::Command};
+
+fn main() {
+    // `async_notification_loom`只在模型检查时由`RUSTFLAGS`传入，见Makefile中的`loom`。
+    // 1.80之前的cargo不认识`rustc-check-cfg`并会警告，因此只在新版本上声明
+    if rustc_minor().is_some_and(|minor| minor >= 80) {
+        println!("cargo:rustc-check-cfg=cfg(async_notification_loom)");
+    }
+}
+
+/// rustc的次版本号
+fn rustc_minor() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    version.split('.').nth(1)?.parse().ok()
+}
@@ -0,0 +1,63 @@
+//! 生产者/消费者示例
+//!
+//! 生产者进程以`consumer`参数重新执行自身作为消费者进程。
+//! 消费者申请一个信号通知源，通过标准输出告知生产者其id，之后等待若干次通知后退出。
+//!
+//! 运行：`cargo run --example producer_consumer`
+
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use async_notification::interface::{Notification, NotificationIf};
+
+/// 消费者等待的通知次数
+const ROUNDS: usize = 3;
+
+fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("consumer") => consumer(),
+        _ => producer(),
+    }
+}
+
+fn consumer() {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let id = Notification::new_id_signal().expect("no signal available");
+            // 通知源申请后即开始缓存通知，此时再告知生产者
+            println!("{}", id);
+            for i in 0..ROUNDS {
+                Notification::wait_on(id).await;
+                eprintln!("[consumer] woken {} time(s) on id {:#018x}", i + 1, id);
+            }
+            unsafe { Notification::release_id(id) };
+        });
+}
+
+fn producer() {
+    let mut child = Command::new(env::current_exe().unwrap())
+        .arg("consumer")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn consumer");
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let id: u64 = line.trim().parse().unwrap();
+    eprintln!("[producer] consumer {} uses id {:#018x}", child.id(), id);
+
+    // 相邻的信号可能被合并，因此持续发送通知，直到消费者退出
+    while child.try_wait().unwrap().is_none() {
+        Notification::notify(child.id() as u64, id);
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    eprintln!("[producer] consumer exited");
+}
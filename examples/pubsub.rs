@@ -0,0 +1,73 @@
+//! 发布/订阅示例
+//!
+//! 发布者进程以`subscriber`参数多次重新执行自身作为订阅者进程。
+//! 每个订阅者申请一个信号通知源并通过标准输出告知发布者，之后等待若干次事件后退出。
+//!
+//! 运行：`cargo run --example pubsub`
+
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use async_notification::interface::{Notification, NotificationIf};
+
+/// 订阅者的数量
+const SUBSCRIBERS: usize = 3;
+/// 每个订阅者等待的事件次数
+const EVENTS: usize = 2;
+
+fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("subscriber") => subscriber(),
+        _ => publisher(),
+    }
+}
+
+fn subscriber() {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let id = Notification::new_id_signal().expect("no signal available");
+            println!("{}", id);
+            let pid = std::process::id();
+            for i in 0..EVENTS {
+                Notification::wait_on(id).await;
+                eprintln!("[subscriber {}] event {}", pid, i);
+            }
+            unsafe { Notification::release_id(id) };
+        });
+}
+
+fn publisher() {
+    let mut subscribers: Vec<(Child, u64)> = (0..SUBSCRIBERS)
+        .map(|_| {
+            let mut child = Command::new(env::current_exe().unwrap())
+                .arg("subscriber")
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("failed to spawn subscriber");
+            let mut line = String::new();
+            BufReader::new(child.stdout.take().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            (child, line.trim().parse().unwrap())
+        })
+        .collect();
+
+    let mut round = 0;
+    while !subscribers.is_empty() {
+        eprintln!("[publisher] publishing round {}", round);
+        for (child, id) in &subscribers {
+            Notification::notify(child.id() as u64, *id);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        subscribers.retain_mut(|(child, _)| child.try_wait().unwrap().is_none());
+        round += 1;
+    }
+    eprintln!("[publisher] all subscribers exited");
+}
@@ -0,0 +1,76 @@
+//! 请求/响应示例
+//!
+//! 客户端进程以`server`参数重新执行自身作为服务端进程。
+//! 服务端申请请求通知源并通过标准输出告知客户端；客户端申请响应通知源并通过服务端的标准输入告知服务端。
+//! 之后客户端每发送一个请求，即等待服务端的响应。
+//!
+//! 运行：`cargo run --example rpc`
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+use async_notification::interface::{Notification, NotificationIf};
+
+/// 请求的次数
+const CALLS: usize = 3;
+
+fn main() {
+    let server = env::args().nth(1).as_deref() == Some("server");
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            if server {
+                run_server().await
+            } else {
+                run_client().await
+            }
+        });
+}
+
+async fn run_server() {
+    let req_id = Notification::new_id_signal().expect("no signal available");
+    println!("{}", req_id);
+
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).unwrap();
+    let resp_id: u64 = line.trim().parse().unwrap();
+    let client = unsafe { libc::getppid() } as u64;
+
+    for i in 0..CALLS {
+        Notification::wait_on(req_id).await;
+        eprintln!("[server] handling request {}", i);
+        Notification::notify(client, resp_id);
+    }
+    unsafe { Notification::release_id(req_id) };
+}
+
+async fn run_client() {
+    let resp_id = Notification::new_id_signal().expect("no signal available");
+    let mut child = Command::new(env::current_exe().unwrap())
+        .arg("server")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn server");
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let req_id: u64 = line.trim().parse().unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}", resp_id).unwrap();
+
+    let server = child.id() as u64;
+    for i in 0..CALLS {
+        // 请求与响应交替进行，不会发生信号合并
+        Notification::notify(server, req_id);
+        Notification::wait_on(resp_id).await;
+        eprintln!("[client] got response {}", i);
+    }
+    child.wait().unwrap();
+    unsafe { Notification::release_id(resp_id) };
+}
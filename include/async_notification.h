@@ -0,0 +1,44 @@
+/*
+ * async_notification的C语言接口，需启用`ffi` feature
+ *
+ * 所有权：
+ *   - an_new_id申请的通知源归调用进程所有，只有该进程可以在其上等待，并须恰好调用一次an_release释放；
+ *   - id可以经IPC交给其他进程，其他进程只能以其调用an_notify；
+ *   - an_release之后id不能再被使用。
+ *
+ * 所有函数成功时返回0，失败时返回负的errno：
+ *   -EINVAL    参数无效，或id的类型未知、通知源未被占用
+ *   -EAGAIN    没有可用的通知源，或接收方的通知队列已满、尚未消费的通知已达上限
+ *   -ETIMEDOUT 等待超时
+ *   -ENOTSUP   该类型的通知源不能由其他进程发送通知
+ *   -EPERM     通知被发送钩子拒绝
+ *   -EIO       内部错误
+ *   以及发送失败时的其他errno。
+ */
+
+#ifndef ASYNC_NOTIFICATION_H
+#define ASYNC_NOTIFICATION_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* 申请一个通知源，成功时将其id写入*id */
+int an_new_id(uint64_t *id);
+
+/* 阻塞调用线程，直到通知源上有通知；timeout_ms为负数时不超时 */
+int an_wait_blocking(uint64_t id, int64_t timeout_ms);
+
+/* 向进程process的通知源id发送通知 */
+int an_notify(uint64_t process, uint64_t id);
+
+/* 释放由an_new_id申请的通知源 */
+int an_release(uint64_t id);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ASYNC_NOTIFICATION_H */
@@ -0,0 +1,192 @@
+//! 带确认的可靠通知投递
+//!
+//! 信号等通知机制不保证投递：通知可能因发送队列已满而失败，也可能与尚未被接收的通知合并。
+//! 本模块在任意通知机制之上实现至少一次的投递：
+//!
+//! - 发送方申请一个确认用的通知源`ack_id`，并通过其他途径告知接收方；
+//! - 发送方的[`AckSender::notify_acked`]发送通知后等待`ack_id`上的确认，未在退避时间内收到确认则重发，
+//!   直到收到确认或超时；
+//! - 接收方的[`recv_acked`]等待通知，并向发送方的`ack_id`发送确认。
+//!
+//! 重发可能使接收方收到重复的通知，接收方应能容忍重复。每个`ack_id`同时只应有一个正在进行的`notify_acked`。
+//! 确认通知本身同样可能丢失，此时发送方会重发，接收方重新确认。
+//!
+//! 必须配合tokio运行时，并启用其时钟。
+
+use core::{fmt, task::Context, time::Duration};
+use tokio::time::Instant;
+
+use crate::interface::{Notification, NotificationIf, NotifyError, ProcessRef};
+
+/// 重发与超时的配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckConfig {
+    /// 从首次发送到放弃的时间
+    pub timeout: Duration,
+    /// 首次发送后等待确认的时间，之后每次重发加倍
+    pub initial_backoff: Duration,
+    /// 等待确认的时间的上限
+    pub max_backoff: Duration,
+    /// 最大重发次数，不包括首次发送；达到后持续等待确认直到超时
+    pub max_retries: u32,
+}
+
+impl Default for AckConfig {
+    /// 超时1秒，退避时间从10毫秒开始加倍至200毫秒，最多重发8次
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(200),
+            max_retries: 8,
+        }
+    }
+}
+
+/// 带确认的投递失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckError {
+    /// 超时前未收到确认，附带发送的次数
+    Timeout {
+        /// 发送的次数，包括首次发送
+        attempts: u32,
+    },
+    /// 发送失败；`NotifyError::Overflow`会被重试，不会出现在这里
+    Notify(NotifyError),
+}
+
+impl fmt::Display for AckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AckError::Timeout { attempts } => {
+                write!(
+                    f,
+                    "notification not acknowledged after {} attempts",
+                    attempts
+                )
+            }
+            AckError::Notify(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// 带确认的通知的发送方，持有接收确认的通知源
+pub struct AckSender {
+    ack_id: u64,
+    config: AckConfig,
+}
+
+impl AckSender {
+    /// 新建发送方，确认在本进程的`ack_id`通知源上接收
+    ///
+    /// `ack_id`由调用者申请与释放，在发送方被使用期间不能被释放，也不应被用于其他用途。
+    pub fn new(ack_id: u64, config: AckConfig) -> Self {
+        Self { ack_id, config }
+    }
+
+    /// 接收确认的通知源
+    pub fn ack_id(&self) -> u64 {
+        self.ack_id
+    }
+
+    /// 向`target`的通知源`id`发送通知，并等待接收方的确认，返回发送的次数
+    ///
+    /// 发送前先丢弃此前的调用因重发而多收到的确认。但已经发出、尚未到达的确认仍可能被当作本次的确认。
+    pub async fn notify_acked(&self, target: ProcessRef, id: u64) -> Result<u32, AckError> {
+        self.drain();
+        let deadline = Instant::now() + self.config.timeout;
+        let mut backoff = self.config.initial_backoff;
+        let mut attempts = 0;
+        loop {
+            match Notification::notify_to(target, id) {
+                Ok(()) => attempts += 1,
+                // 发送队列已满，等待一个退避时间后重试
+                Err(NotifyError::Overflow) => {}
+                Err(err) => return Err(AckError::Notify(err)),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(AckError::Timeout { attempts });
+            }
+            let wait = if attempts > self.config.max_retries {
+                deadline - now
+            } else {
+                backoff.min(deadline - now)
+            };
+            if tokio::time::timeout(wait, Notification::wait_on(self.ack_id))
+                .await
+                .is_ok()
+            {
+                return Ok(attempts);
+            }
+            if attempts > self.config.max_retries {
+                return Err(AckError::Timeout { attempts });
+            }
+            backoff = (backoff * 2).min(self.config.max_backoff);
+        }
+    }
+
+    /// 丢弃未被消费的确认
+    ///
+    /// 上一次调用最多重发`max_retries`次，因此最多有同样数量的多余确认；限制次数也避免了在已被释放的通知源上无限循环。
+    fn drain(&self) {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        for _ in 0..self.config.max_retries {
+            if Notification::poll_wait(self.ack_id, &mut cx).is_pending() {
+                break;
+            }
+        }
+    }
+}
+
+/// 在通知源`id`上等待一个通知，并向`sender`的`ack_id`发送确认
+///
+/// 确认发送失败时返回错误，但通知已被消费；发送方会因未收到确认而重发。
+pub async fn recv_acked(id: u64, sender: ProcessRef, ack_id: u64) -> Result<(), NotifyError> {
+    Notification::wait_on(id).await;
+    Notification::notify_to(sender, ack_id)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::Notification;
+
+    fn config() -> AckConfig {
+        AckConfig {
+            timeout: Duration::from_millis(200),
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(20),
+            max_retries: 3,
+        }
+    }
+
+    #[test]
+    fn test_notify_acked() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let id = Notification::new_id_mock().unwrap();
+                let ack_id = Notification::new_id_mock().unwrap();
+                let sender = AckSender::new(ack_id, config());
+                let receiver = tokio::spawn(recv_acked(id, ProcessRef::Process(0), ack_id));
+                assert_eq!(sender.notify_acked(ProcessRef::Process(0), id).await, Ok(1));
+                receiver.await.unwrap().unwrap();
+
+                // 无人确认时重发`max_retries`次后超时
+                assert_eq!(
+                    sender.notify_acked(ProcessRef::Process(0), id).await,
+                    Err(AckError::Timeout { attempts: 4 })
+                );
+                // 通知源已被释放时发送失败
+                unsafe { Notification::release_id(id) };
+                assert!(matches!(
+                    sender.notify_acked(ProcessRef::Process(0), id).await,
+                    Err(AckError::Notify(_))
+                ));
+                unsafe { Notification::release_id(ack_id) };
+            });
+    }
+}
@@ -0,0 +1,69 @@
+//! 用于ArceOS等unikernel的通知机制
+//!
+//! 基于[`SyscallIf`]实现：通知原语由内核以C ABI提供，
+//! 内核在通知对象收到通知时调用本模块导出的[`async_notification_arceos_on_notify`]，唤醒在内核调度器中挂起的异步任务。
+//!
+//! 内核需提供以下函数：
+//!
+//! - `sys_notify_alloc() -> i64`：申请通知对象，失败时返回负数
+//! - `sys_notify_free(obj: u64)`：释放通知对象
+//! - `sys_notify_send(process: u64, obj: u64) -> i32`：发送通知，成功时返回0，失败时返回负的错误码
+//! - `sys_notify_try_recv(obj: u64) -> i32`：若有通知则消费一个通知并返回1，否则返回0；对象已被释放时返回1
+//! - `sys_notify_arm(obj: u64)`：请求在对象收到通知时回调`async_notification_arceos_on_notify`
+
+use crate::{
+    hal::{HalNotification, HalSlots, SyscallIf},
+    interface::NotifyError,
+};
+
+extern "C" {
+    fn sys_notify_alloc() -> i64;
+    fn sys_notify_free(obj: u64);
+    fn sys_notify_send(process: u64, obj: u64) -> i32;
+    fn sys_notify_try_recv(obj: u64) -> i32;
+    fn sys_notify_arm(obj: u64);
+}
+
+/// ArceOS内核提供的通知原语
+pub struct ArceosSyscall;
+
+static SLOTS: HalSlots = HalSlots::new();
+
+impl SyscallIf for ArceosSyscall {
+    fn alloc() -> Option<u64> {
+        let obj = unsafe { sys_notify_alloc() };
+        (obj >= 0).then_some(obj as u64)
+    }
+
+    unsafe fn free(obj: u64) {
+        unsafe { sys_notify_free(obj) };
+    }
+
+    fn send(process: u64, obj: u64) -> Result<(), NotifyError> {
+        match unsafe { sys_notify_send(process, obj) } {
+            0 => Ok(()),
+            res => Err(NotifyError::Os(-res)),
+        }
+    }
+
+    fn try_recv(obj: u64) -> bool {
+        unsafe { sys_notify_try_recv(obj) != 0 }
+    }
+
+    fn arm(obj: u64) {
+        unsafe { sys_notify_arm(obj) };
+    }
+
+    fn slots() -> &'static HalSlots {
+        &SLOTS
+    }
+}
+
+/// 用于ArceOS的通知机制
+pub type ArceosNotification = HalNotification<ArceosSyscall>;
+
+/// 由内核在通知对象`obj`收到通知时调用，可在中断上下文中调用
+#[no_mangle]
+pub extern "C" fn async_notification_arceos_on_notify(obj: u64) {
+    ArceosNotification::on_notify(obj);
+}
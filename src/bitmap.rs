@@ -0,0 +1,341 @@
+//! 无锁的位图分配器
+//!
+//! 用于管理数量固定的通知源（例如信号编号），支持任意大小的池。
+//! 分配与释放只需要对所在的`AtomicU64`字进行CAS操作，对于不超过64个元素的池为O(1)。
+//!
+//! 每个字与每个分配游标各占一个缓存行，游标按线程分片，不同线程的并发分配不会争用同一个缓存行中的游标。
+
+use crate::sync::{
+    CachePadded,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use alloc::vec::Vec;
+
+/// 分配游标的分片数为`1 << SHARD_BITS`
+const SHARD_BITS: u32 = 3;
+const SHARDS: usize = 1 << SHARD_BITS;
+
+/// 无锁的位图分配器，位为1表示对应的index已被占用
+pub(crate) struct IdBitmap {
+    words: Vec<CachePadded<AtomicU64>>,
+    len: usize,
+    /// 每个分片下一次分配开始查找的位置，仅作为提示
+    ///
+    /// 使同一线程的分配按轮转的顺序进行，避免刚刚释放的index被立即重新分配。
+    cursors: Vec<CachePadded<AtomicUsize>>,
+}
+
+/// 调用线程所用的游标分片
+///
+/// 以`pthread_self`区分线程：它只读取线程指针，不进行系统调用，且同一线程总是得到同一个分片。
+fn shard() -> usize {
+    let thread = unsafe { libc::pthread_self() } as u64;
+    // 线程控制块的地址按线程栈的大小对齐，低位相同，以乘法散列取高位
+    (thread.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (64 - SHARD_BITS)) as usize
+}
+
+impl IdBitmap {
+    /// 新建一个可容纳`len`个index的位图，所有index均未被占用
+    pub(crate) fn new(len: usize) -> Self {
+        let mut words = Vec::new();
+        words.resize_with((len + 63) / 64, || CachePadded(AtomicU64::new(0)));
+        let mut cursors = Vec::new();
+        cursors.resize_with(SHARDS, || CachePadded(AtomicUsize::new(0)));
+        Self {
+            words,
+            len,
+            cursors,
+        }
+    }
+
+    /// 第`word`个字中有效位的mask
+    fn valid_mask(&self, word: usize) -> u64 {
+        let rest = self.len - word * 64;
+        if rest >= 64 { !0 } else { (1 << rest) - 1 }
+    }
+
+    /// 分配一个未被占用的index，若均已被占用则返回`None`
+    pub(crate) fn alloc(&self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let cursor = &self.cursors[shard()];
+        let start = cursor.load(Ordering::Relaxed) % self.len;
+        let (start_word, start_bit) = (start / 64, start % 64);
+        let nwords = self.words.len();
+        // 起始字被查找两次：第一次查找起始位之后的部分，最后一次查找起始位之前的部分
+        for i in 0..=nwords {
+            let word = (start_word + i) % nwords;
+            let mut mask = self.valid_mask(word);
+            if i == 0 {
+                mask &= !0 << start_bit;
+            } else if i == nwords {
+                mask &= !(!0 << start_bit);
+            }
+            let mut curr = self.words[word].load(Ordering::Acquire);
+            loop {
+                let free = !curr & mask;
+                if free == 0 {
+                    break;
+                }
+                let bit = free.trailing_zeros() as usize;
+                match self.words[word].compare_exchange_weak(
+                    curr,
+                    curr | (1 << bit),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        let index = word * 64 + bit;
+                        cursor.store(index + 1, Ordering::Relaxed);
+                        return Some(index);
+                    }
+                    Err(actual) => curr = actual,
+                }
+            }
+        }
+        None
+    }
+
+    /// 一次分配至多`max`个未被占用的index并追加到`out`中，返回分配的数量
+    ///
+    /// 每个字只需一次成功的CAS即可占用其中的多个index，用于成批补充线程的本地缓存。
+    pub(crate) fn alloc_batch(&self, max: usize, out: &mut Vec<usize>) -> usize {
+        let mut count = 0;
+        for word in 0..self.words.len() {
+            if count == max {
+                break;
+            }
+            let mask = self.valid_mask(word);
+            let mut curr = self.words[word].load(Ordering::Acquire);
+            loop {
+                // 取最低的至多`max - count`个空闲位
+                let mut free = !curr & mask;
+                let mut take = 0u64;
+                while free != 0 && (take.count_ones() as usize) < max - count {
+                    take |= free & free.wrapping_neg();
+                    free &= free - 1;
+                }
+                if take == 0 {
+                    break;
+                }
+                match self.words[word].compare_exchange_weak(
+                    curr,
+                    curr | take,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        count += take.count_ones() as usize;
+                        while take != 0 {
+                            out.push(word * 64 + take.trailing_zeros() as usize);
+                            take &= take - 1;
+                        }
+                        break;
+                    }
+                    Err(actual) => curr = actual,
+                }
+            }
+        }
+        count
+    }
+
+    /// 释放`indices`中的所有index，每个字只进行一次原子操作
+    pub(crate) fn release_batch(&self, indices: &[usize]) {
+        for word in 0..self.words.len() {
+            let bits = indices
+                .iter()
+                .filter(|&&index| index / 64 == word)
+                .fold(0u64, |bits, &index| bits | 1 << (index % 64));
+            if bits != 0 {
+                self.words[word].fetch_and(!bits, Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// 未被占用的index的数量；并发分配或释放时只是一个近似值
+    pub(crate) fn available(&self) -> usize {
+        (0..self.words.len())
+            .map(|word| {
+                let used = self.words[word].load(Ordering::Acquire);
+                (!used & self.valid_mask(word)).count_ones() as usize
+            })
+            .sum()
+    }
+
+    /// 占用指定的index，若其已被占用则返回`false`
+    pub(crate) fn claim(&self, index: usize) -> bool {
+        assert!(index < self.len);
+        let bit = 1 << (index % 64);
+        self.words[index / 64].fetch_or(bit, Ordering::AcqRel) & bit == 0
+    }
+
+    /// 释放一个index，返回其在释放前是否被占用
+    pub(crate) fn release(&self, index: usize) -> bool {
+        assert!(index < self.len);
+        let bit = 1 << (index % 64);
+        self.words[index / 64].fetch_and(!bit, Ordering::AcqRel) & bit != 0
+    }
+}
+
+#[cfg(all(test, not(async_notification_loom)))]
+mod tests {
+    use super::*;
+    use alloc::{sync::Arc, vec};
+    use core::sync::atomic::AtomicBool;
+
+    extern crate std;
+
+    #[test]
+    fn test_bitmap_exhaust() {
+        for len in [0, 1, 29, 64, 65, 100] {
+            let bitmap = IdBitmap::new(len);
+            let mut seen = vec![false; len];
+            for i in 0..len {
+                assert_eq!(bitmap.available(), len - i);
+                let index = bitmap.alloc().unwrap();
+                assert!(!seen[index]);
+                seen[index] = true;
+            }
+            assert_eq!(bitmap.alloc(), None);
+            assert_eq!(bitmap.available(), 0);
+        }
+    }
+
+    #[test]
+    fn test_bitmap_round_robin() {
+        let bitmap = IdBitmap::new(29);
+        assert_eq!(bitmap.alloc(), Some(0));
+        assert_eq!(bitmap.alloc(), Some(1));
+        assert!(bitmap.release(0));
+        assert!(!bitmap.release(0));
+        // 刚释放的index不会被立即重新分配
+        assert_eq!(bitmap.alloc(), Some(2));
+        for i in 3..29 {
+            assert_eq!(bitmap.alloc(), Some(i));
+        }
+        assert_eq!(bitmap.alloc(), Some(0));
+        assert_eq!(bitmap.alloc(), None);
+        assert!(bitmap.release(28));
+    }
+
+    #[test]
+    fn test_bitmap_shards() {
+        assert_eq!(core::mem::align_of::<CachePadded<AtomicU64>>(), 128);
+        assert_eq!(shard(), shard());
+        let shards: Vec<usize> = (0..16)
+            .map(|_| std::thread::spawn(shard).join().unwrap())
+            .collect();
+        assert!(shards.iter().all(|&shard| shard < SHARDS));
+
+        // 各线程的游标互不影响，每个线程内仍按轮转的顺序分配
+        let bitmap = Arc::new(IdBitmap::new(100));
+        let first = bitmap.alloc().unwrap();
+        let other = {
+            let bitmap = bitmap.clone();
+            std::thread::spawn(move || (bitmap.alloc().unwrap(), bitmap.alloc().unwrap()))
+                .join()
+                .unwrap()
+        };
+        assert_eq!(other.1, other.0 + 1);
+        let second = bitmap.alloc().unwrap();
+        assert!(second > first);
+        assert!(second != other.0 && second != other.1);
+    }
+
+    #[test]
+    fn test_bitmap_batch() {
+        let bitmap = IdBitmap::new(70);
+        assert!(bitmap.claim(1));
+        let mut out = Vec::new();
+        assert_eq!(bitmap.alloc_batch(3, &mut out), 3);
+        assert_eq!(out, [0, 2, 3]);
+        assert_eq!(bitmap.alloc_batch(100, &mut out), 66);
+        assert_eq!(out.len(), 69);
+        assert_eq!(bitmap.available(), 0);
+        bitmap.release_batch(&[0, 3, 65, 69]);
+        assert_eq!(bitmap.available(), 4);
+        assert!(!bitmap.claim(2) && bitmap.claim(3) && bitmap.claim(69));
+    }
+
+    #[test]
+    fn test_bitmap_claim() {
+        let bitmap = IdBitmap::new(3);
+        assert!(bitmap.claim(1));
+        assert!(!bitmap.claim(1));
+        assert_eq!(bitmap.alloc(), Some(0));
+        assert_eq!(bitmap.alloc(), Some(2));
+        assert_eq!(bitmap.alloc(), None);
+        assert!(bitmap.release(1));
+        assert!(bitmap.claim(1));
+    }
+
+    #[test]
+    fn test_bitmap_contention() {
+        const LEN: usize = 70;
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 2000;
+
+        let bitmap = Arc::new(IdBitmap::new(LEN));
+        let owned: Arc<Vec<AtomicBool>> =
+            Arc::new((0..LEN).map(|_| AtomicBool::new(false)).collect());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let bitmap = bitmap.clone();
+                let owned = owned.clone();
+                std::thread::spawn(move || {
+                    let mut held = Vec::new();
+                    for round in 0..ROUNDS {
+                        if let Some(index) = bitmap.alloc() {
+                            assert!(!owned[index].swap(true, Ordering::AcqRel));
+                            held.push(index);
+                        }
+                        if round % 3 == 0 || held.len() > LEN / THREADS {
+                            while let Some(index) = held.pop() {
+                                assert!(owned[index].swap(false, Ordering::AcqRel));
+                                assert!(bitmap.release(index));
+                            }
+                        }
+                    }
+                    for index in held {
+                        assert!(owned[index].swap(false, Ordering::AcqRel));
+                        assert!(bitmap.release(index));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for index in 0..LEN {
+            assert!(!bitmap.release(index));
+        }
+    }
+}
+
+#[cfg(all(test, async_notification_loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+
+    #[test]
+    fn loom_bitmap_alloc() {
+        // 并发的分配得到不同的index，并发的释放与分配不会丢失index
+        loom::model(|| {
+            let bitmap = Arc::new(IdBitmap::new(2));
+            let first = bitmap.alloc().unwrap();
+            let handle = {
+                let bitmap = bitmap.clone();
+                loom::thread::spawn(move || {
+                    assert!(bitmap.release(first));
+                    bitmap.alloc()
+                })
+            };
+            let mine = bitmap.alloc();
+            let theirs = handle.join().unwrap();
+            assert!(mine.is_some() && theirs.is_some());
+            assert_ne!(mine, theirs);
+            assert_eq!(bitmap.alloc(), None);
+        });
+    }
+}
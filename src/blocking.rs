@@ -0,0 +1,193 @@
+//! 不需要异步运行时的阻塞等待
+//!
+//! [`Notification::wait_on_blocking`]与[`Notification::wait_on_blocking_timeout`]阻塞调用线程直到收到通知，
+//! 用于不使用异步运行时的多线程程序：
+//!
+//! - 基于文件描述符的通知源（`timer`、`child`、`signalfd`、`vsock`、`kvm`、`uds`、`netlink`、`net`、`pipe`）
+//!   直接在文件描述符上`poll`，不依赖任何运行时；
+//! - 其他通知源以唤醒调用线程的waker轮询`poll_wait`并挂起线程，
+//!   因此其唤醒需要由其他线程驱动，例如`signal`需要有tokio运行时在其他线程上运行。
+//!
+//! 申请通知源仍需遵守各通知机制的要求（例如在tokio运行时内部调用`new_id`）。
+
+extern crate std;
+
+use alloc::{sync::Arc, task::Wake};
+use core::{task::Context, time::Duration};
+use std::{thread::Thread, time::Instant};
+
+use crate::interface::{Notification, NotificationIf, WakeReason};
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "signalfd",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+use crate::kind::NotificationKind;
+
+/// 唤醒被挂起的线程
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// 以唤醒当前线程的waker轮询`poll_wait`，在两次轮询之间挂起线程
+fn park_wait<N: NotificationIf>(id: u64, timeout: Option<Duration>) -> bool {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if N::poll_wait(id, &mut cx).is_ready() {
+            return true;
+        }
+        match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return false;
+                }
+                std::thread::park_timeout(remaining);
+            }
+            None => std::thread::park(),
+        }
+    }
+}
+
+impl Notification {
+    /// 阻塞调用线程，直到通知源上有通知或通知源被释放，均返回`WakeReason::Notified`
+    pub fn wait_on_blocking(id: u64) -> WakeReason {
+        Self::wait_blocking(id, None);
+        WakeReason::NOTIFIED
+    }
+
+    /// 阻塞调用线程，直到通知源上有通知、通知源被释放或超时；超时时返回`WakeReason::Timeout`
+    pub fn wait_on_blocking_timeout(id: u64, timeout: Duration) -> WakeReason {
+        if Self::wait_blocking(id, Some(timeout)) {
+            WakeReason::NOTIFIED
+        } else {
+            WakeReason::Timeout
+        }
+    }
+
+    fn wait_blocking(id: u64, timeout: Option<Duration>) -> bool {
+        #[allow(unused_variables)]
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match Self::kind_of(id) {
+            #[cfg(feature = "timer")]
+            Some(NotificationKind::Timer) => {
+                crate::timer::TimerNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "child")]
+            Some(NotificationKind::Child) => {
+                crate::child::ChildNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "signalfd")]
+            Some(NotificationKind::Signalfd) => {
+                crate::signalfd::SignalfdNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "vsock")]
+            Some(NotificationKind::Vsock) => {
+                crate::vsock::VsockNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "kvm")]
+            Some(NotificationKind::Kvm) => {
+                crate::kvm::KvmNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "uds")]
+            Some(NotificationKind::Uds) => {
+                crate::uds::UdsNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "netlink")]
+            Some(NotificationKind::Netlink) => {
+                crate::netlink::NetlinkNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "net")]
+            Some(NotificationKind::Net) => {
+                crate::net::NetNotification::wait_blocking(id_inner, timeout)
+            }
+            #[cfg(feature = "pipe")]
+            Some(NotificationKind::Pipe) => {
+                crate::pipe::PipeNotification::wait_blocking(id_inner, timeout)
+            }
+            _ => park_wait::<Self>(id, timeout),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "signal", feature = "pipe")))]
+mod tests {
+    use crate::interface::{Notification, NotificationIf, WakeReason};
+    use core::time::Duration;
+
+    extern crate std;
+
+    #[cfg(feature = "pipe")]
+    #[test]
+    fn test_blocking_pipe() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // 只在申请通知源时使用运行时
+        let id = runtime.block_on(async { Notification::new_id_pipe().unwrap() });
+        assert_eq!(
+            Notification::wait_on_blocking_timeout(id, Duration::from_millis(20)),
+            WakeReason::Timeout
+        );
+        let pid = unsafe { libc::getpid() } as u64;
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            Notification::notify(pid, id);
+        });
+        assert_eq!(Notification::wait_on_blocking(id), WakeReason::NOTIFIED);
+        sender.join().unwrap();
+        assert_eq!(
+            Notification::wait_on_blocking_timeout(id, Duration::from_millis(0)),
+            WakeReason::Timeout
+        );
+        unsafe { Notification::release_id(id) };
+        // 已被释放的通知源上的等待立即结束
+        assert_eq!(
+            Notification::wait_on_blocking_timeout(id, Duration::from_secs(1)),
+            WakeReason::NOTIFIED
+        );
+    }
+
+    #[cfg(feature = "signal")]
+    #[test]
+    fn test_blocking_signal() {
+        let _guard = crate::signal::tests::SIGNAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // 信号的接收由在其他线程上运行的tokio运行时驱动
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let id = runtime.block_on(async { Notification::new_id_signal().unwrap() });
+        let pid = unsafe { libc::getpid() } as u64;
+        Notification::notify(pid, id);
+        assert_eq!(
+            Notification::wait_on_blocking_timeout(id, Duration::from_secs(1)),
+            WakeReason::NOTIFIED
+        );
+        assert_eq!(
+            Notification::wait_on_blocking_timeout(id, Duration::from_millis(20)),
+            WakeReason::Timeout
+        );
+        runtime.block_on(async { unsafe { Notification::release_id(id) } });
+    }
+}
@@ -0,0 +1,272 @@
+//! 多个协程在同一通知源上等待
+//!
+//! 通知源本身只支持一个等待者：多个协程同时`wait_on`同一id时，只有最近一次轮询的协程会被唤醒。
+//! [`Broadcast`]在通知源之上维护等待者列表，每收到一个通知即唤醒所有正在等待的协程。
+
+use alloc::{sync::Arc, task::Wake, vec::Vec};
+use core::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf},
+    sync::SpinMutex,
+};
+
+struct Shared {
+    id: u64,
+    /// 已收到的通知数量
+    seq: AtomicU64,
+    /// 正在等待的协程的waker
+    waiters: SpinMutex<Vec<Waker>>,
+}
+
+impl Shared {
+    fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+/// 注册到通知源上的waker，被唤醒时唤醒所有等待者
+impl Wake for Shared {
+    fn wake(self: Arc<Self>) {
+        self.wake_all();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_all();
+    }
+}
+
+/// 支持多个等待者的通知源，每个通知唤醒所有正在等待的协程
+///
+/// 可被克隆并在多个协程间共享，所有克隆共享同一等待者列表。
+/// 同一通知源上的所有等待都应通过`Broadcast`进行，而不应再直接调用`wait_on`。
+pub struct Broadcast<N: NotificationIf = Notification> {
+    shared: Arc<Shared>,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<N: NotificationIf> Clone for Broadcast<N> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<N: NotificationIf> Broadcast<N> {
+    /// 在已申请的通知源上新建`Broadcast`
+    pub fn new(id: u64) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                id,
+                seq: AtomicU64::new(0),
+                waiters: SpinMutex::new(Vec::new()),
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    /// 通知源id
+    pub fn id(&self) -> u64 {
+        self.shared.id
+    }
+
+    /// 等待下一个通知
+    ///
+    /// 返回的future在调用本函数之后收到的第一个通知到来时完成；若通知源上已缓存有通知，则立即完成。
+    pub fn wait(&self) -> BroadcastWait<'_, N> {
+        BroadcastWait {
+            broadcast: self,
+            start: self.shared.seq.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// `Broadcast::wait`返回的future
+///
+/// 该future是取消安全的：在其完成之前丢弃它不会消费通知，也不会影响其他等待者。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct BroadcastWait<'a, N: NotificationIf> {
+    broadcast: &'a Broadcast<N>,
+    /// 开始等待时已收到的通知数量
+    start: u64,
+}
+
+impl<N: NotificationIf> Future for BroadcastWait<'_, N> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let shared = &self.broadcast.shared;
+        if shared.seq.load(Ordering::Acquire) != self.start {
+            return Poll::Ready(());
+        }
+        {
+            let mut waiters = shared.waiters.lock();
+            if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                waiters.push(cx.waker().clone());
+            }
+            // 其他等待者可能在上面的检查之后消费了通知并清空了列表，此时不会再唤醒刚加入的waker
+            if shared.seq.load(Ordering::Acquire) != self.start {
+                return Poll::Ready(());
+            }
+        }
+        let waker = Waker::from(shared.clone());
+        if N::poll_wait(shared.id, &mut Context::from_waker(&waker)).is_ready() {
+            shared.seq.fetch_add(1, Ordering::AcqRel);
+            shared.wake_all();
+            return Poll::Ready(());
+        }
+        // 通知可能刚被其他等待者消费
+        if shared.seq.load(Ordering::Acquire) != self.start {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::task::Waker;
+
+    extern crate std;
+
+    /// 测试用的通知源，只支持一个等待者
+    struct TestNotification;
+
+    /// 每个id的未消费通知数与waker，使不同测试互不干扰
+    static PENDING: [SpinMutex<(usize, Option<Waker>)>; 2] =
+        [SpinMutex::new((0, None)), SpinMutex::new((0, None))];
+
+    fn trigger(id: u64) {
+        let waker = {
+            let mut state = PENDING[id as usize].lock();
+            state.0 += 1;
+            state.1.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    impl NotificationIf for TestNotification {
+        fn new_id() -> Option<u64> {
+            Some(0)
+        }
+
+        fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = PENDING[id as usize].lock();
+            if state.0 > 0 {
+                state.0 -= 1;
+                Poll::Ready(())
+            } else {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+
+        fn register_waker(id: u64, waker: &Waker) {
+            let mut state = PENDING[id as usize].lock();
+            if state.0 > 0 {
+                waker.wake_by_ref();
+            } else {
+                state.1 = Some(waker.clone());
+            }
+        }
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, id: u64) {
+            trigger(id);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_wakes_all() {
+        const WAITERS: usize = 4;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let broadcast = Broadcast::<TestNotification>::new(0);
+                let handles: Vec<_> = (0..WAITERS)
+                    .map(|_| {
+                        let broadcast = broadcast.clone();
+                        tokio::spawn(async move {
+                            broadcast.wait().await;
+                            broadcast.wait().await;
+                        })
+                    })
+                    .collect();
+                for _ in 0..2 {
+                    for _ in 0..WAITERS {
+                        tokio::task::yield_now().await;
+                    }
+                    assert!(handles.iter().all(|h| !h.is_finished()));
+                    trigger(0);
+                }
+                for handle in handles {
+                    tokio::time::timeout(core::time::Duration::from_secs(1), handle)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                }
+            });
+    }
+
+    #[test]
+    fn test_broadcast_multi_thread() {
+        use core::sync::atomic::AtomicUsize;
+
+        const WAITERS: usize = 8;
+        const ROUNDS: usize = 200;
+
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let broadcast = Broadcast::<TestNotification>::new(1);
+                let ready = Arc::new(AtomicUsize::new(0));
+                let handles: Vec<_> = (0..WAITERS)
+                    .map(|_| {
+                        let broadcast = broadcast.clone();
+                        let ready = ready.clone();
+                        tokio::spawn(async move {
+                            for _ in 0..ROUNDS {
+                                let wait = broadcast.wait();
+                                ready.fetch_add(1, Ordering::AcqRel);
+                                wait.await;
+                            }
+                        })
+                    })
+                    .collect();
+                for round in 1..=ROUNDS {
+                    // 所有等待者都已开始本轮等待后才发送通知
+                    while ready.load(Ordering::Acquire) < WAITERS * round {
+                        tokio::task::yield_now().await;
+                    }
+                    trigger(1);
+                }
+                for handle in handles {
+                    tokio::time::timeout(core::time::Duration::from_secs(5), handle)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                }
+            });
+    }
+}
@@ -0,0 +1,189 @@
+//! 使用pidfd的子进程退出通知机制
+//!
+//! 每个通知源对应一个进程的pidfd，在该进程退出时被触发，从而使监督进程可以在等待IPC通知的同时发现对端进程的退出。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::OwnedFd;
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用pidfd的子进程退出通知机制
+pub struct ChildNotification;
+
+struct ChildSlot {
+    fd: AsyncFd<OwnedRawFd>,
+    /// 进程是否已退出
+    exited: AtomicBool,
+}
+
+impl ChildSlot {
+    /// 进程是否已退出，不阻塞
+    fn take(&self) -> bool {
+        if self.exited.load(Ordering::Acquire) {
+            return true;
+        }
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.get_ref().0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let exited = unsafe { libc::poll(&mut pollfd, 1, 0) } == 1;
+        if exited {
+            self.exited.store(true, Ordering::Release);
+        }
+        exited
+    }
+
+    fn poll_exit(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.exited.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        // pidfd在进程退出后保持可读，因此无需清除就绪状态
+        self.fd.poll_read_ready(cx).map(|guard| {
+            if let Err(err) = guard {
+                fail!((), "poll_read_ready: {}", err)
+            }
+            self.exited.store(true, Ordering::Release);
+        })
+    }
+}
+
+/// 所有被占用的pidfd，以fd为key
+static CHILDREN: SpinMutex<BTreeMap<u64, Arc<ChildSlot>>> = SpinMutex::new(BTreeMap::new());
+
+impl NotificationIf for ChildNotification {
+    /// 返回`None`
+    ///
+    /// 需要指定被监视的进程，应使用`new_id_with_pid`。
+    fn new_id() -> Option<u64> {
+        None
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 被监视的进程退出后，所有等待立即结束；若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_exit(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let ready = match Self::slot(id) {
+            Some(slot) => slot.poll_exit(&mut Context::from_waker(waker)).is_ready(),
+            None => true,
+        };
+        if ready {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// 关闭pidfd
+    unsafe fn release_id(id: u64) {
+        let slot = CHILDREN.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 进程退出事件不能由其他进程发送，调用该函数会panic（启用`no-panic`时输出错误日志后忽略）
+    fn notify(_process: u64, id: u64) {
+        fail!((), "notify: child id 0x{:016x} cannot be notified", id)
+    }
+}
+
+impl ChildNotification {
+    /// 申请一个在进程`pid`退出时被触发的通知源，并返回其id
+    ///
+    /// 进程需仍然存在（可以是尚未被回收的僵尸进程）；被监视的进程不必是本进程的子进程。
+    /// 该函数需要在tokio运行时内部调用，因为其会将pidfd注册到tokio的reactor中。
+    pub fn new_id_with_pid(pid: u64) -> Option<u64> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+        if fd < 0 {
+            return None;
+        }
+        let fd = fd as libc::c_int;
+        let slot = ChildSlot {
+            fd: AsyncFd::new(OwnedRawFd(fd)).ok()?,
+            exited: AtomicBool::new(false),
+        };
+        CHILDREN.lock().insert(fd as u64, Arc::new(slot));
+        Some(fd as u64)
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        crate::fd::take_unique(&CHILDREN, id).map(|slot| slot.fd.into_inner().into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    ///
+    /// 被监视的进程退出后，等待立即结束。
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.fd.get_ref().0,
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<ChildSlot>> {
+        CHILDREN.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    extern crate std;
+
+    #[test]
+    fn test_child_exit() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let mut child = std::process::Command::new("sleep")
+                    .arg("0.1")
+                    .spawn()
+                    .unwrap();
+                let id = Notification::new_id_child(child.id() as u64).unwrap();
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                tokio::time::timeout(Duration::from_secs(2), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                // 退出后的等待立即结束
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                child.wait().unwrap();
+
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
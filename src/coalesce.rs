@@ -0,0 +1,148 @@
+//! 合并对执行器的唤醒
+//!
+//! 同一通知源上的通知成批到达时，通知机制可能多次调用waker，执行器因此反复调度同一个任务、反复轮询通知源。
+//! 以[`Notification::set_wake_coalescing`]为通知源开启合并后，`poll_wait`交给通知机制的是本模块的waker：
+//! 每一轮轮询（从一次`poll_wait`返回`Poll::Pending`到下一次`poll_wait`）至多唤醒任务一次，其余的唤醒只被计数。
+//! 任务在`poll_wait`返回`Poll::Ready(())`后可以从[`Notification::coalesced_wakes`]读出这次等待期间被合并的唤醒次数。
+//!
+//! 设置在通知源被释放时清除。
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, task::Wake};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use futures::task::AtomicWaker;
+
+use crate::{interface::Notification, sync::SpinMutex};
+
+/// 一个通知源的唤醒合并状态，其`Arc`即为交给通知机制的waker
+struct Coalescer {
+    /// 任务的waker
+    task: AtomicWaker,
+    /// 本轮是否已唤醒过任务
+    woken: AtomicBool,
+    /// 自上次`poll_wait`返回`Poll::Ready(())`以来通知机制唤醒的次数
+    wakes: AtomicU64,
+    /// 上次`poll_wait`返回`Poll::Ready(())`时的`wakes`
+    last: AtomicU64,
+}
+
+impl Wake for Coalescer {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::AcqRel);
+        if !self.woken.swap(true, Ordering::AcqRel) {
+            self.task.wake();
+        }
+    }
+}
+
+/// 开启了合并的通知源，以id为key
+static COALESCERS: SpinMutex<BTreeMap<u64, Arc<Coalescer>>> = SpinMutex::new(BTreeMap::new());
+
+/// 以通知源`id`的合并waker轮询`inner`；未开启合并时直接以`cx`轮询
+pub(crate) fn poll<T>(
+    id: u64,
+    cx: &mut Context<'_>,
+    inner: impl FnOnce(&mut Context<'_>) -> Poll<T>,
+) -> Poll<T> {
+    let Some(coalescer) = COALESCERS.lock().get(&id).cloned() else {
+        return inner(cx);
+    };
+    coalescer.task.register(cx.waker());
+    // 开始新的一轮：先登记任务的waker，之后的第一次唤醒必定到达任务
+    coalescer.woken.store(false, Ordering::Release);
+    // 同一个`Arc`构造的waker彼此`will_wake`，通知机制不必每次替换所保存的waker
+    let waker = Waker::from(coalescer.clone());
+    let poll = inner(&mut Context::from_waker(&waker));
+    if poll.is_ready() {
+        let wakes = coalescer.wakes.swap(0, Ordering::AcqRel);
+        coalescer.last.store(wakes, Ordering::Release);
+    }
+    poll
+}
+
+/// 通知源`id`即将被释放
+pub(crate) fn release(id: u64) {
+    COALESCERS.lock().remove(&id);
+}
+
+impl Notification {
+    /// 开启或关闭通知源`id`的唤醒合并，只影响之后的`poll_wait`
+    pub fn set_wake_coalescing(id: u64, enabled: bool) {
+        let mut coalescers = COALESCERS.lock();
+        if !enabled {
+            coalescers.remove(&id);
+            return;
+        }
+        coalescers.entry(id).or_insert_with(|| {
+            Arc::new(Coalescer {
+                task: AtomicWaker::new(),
+                woken: AtomicBool::new(false),
+                wakes: AtomicU64::new(0),
+                last: AtomicU64::new(0),
+            })
+        });
+    }
+
+    /// 通知源`id`最近一次结束的等待期间被合并的唤醒次数，即通知机制调用waker的次数；
+    /// 未开启合并时返回`None`
+    pub fn coalesced_wakes(id: u64) -> Option<u64> {
+        COALESCERS
+            .lock()
+            .get(&id)
+            .map(|coalescer| coalescer.last.load(Ordering::Acquire))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+    use core::sync::atomic::AtomicUsize;
+
+    /// 记录被唤醒次数的waker
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    #[test]
+    fn test_wake_coalescing() {
+        let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let id = Notification::new_id_mock().unwrap();
+        assert_eq!(Notification::coalesced_wakes(id), None);
+        Notification::set_wake_coalescing(id, true);
+        assert_eq!(Notification::coalesced_wakes(id), Some(0));
+
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        crate::mock::trigger(id);
+        // 模拟通知机制在同一轮中多次唤醒
+        let coalescer = COALESCERS.lock().get(&id).cloned().unwrap();
+        let inner = Waker::from(coalescer);
+        inner.wake_by_ref();
+        inner.wake_by_ref();
+        assert_eq!(count.0.load(Ordering::Acquire), 1);
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        assert_eq!(Notification::coalesced_wakes(id), Some(3));
+
+        // 新的一轮中的唤醒仍会到达任务
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        crate::mock::trigger(id);
+        assert_eq!(count.0.load(Ordering::Acquire), 2);
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        assert_eq!(Notification::coalesced_wakes(id), Some(1));
+
+        unsafe { Notification::release_id(id) };
+        assert_eq!(Notification::coalesced_wakes(id), None);
+    }
+}
@@ -0,0 +1,235 @@
+//! Wasm组件模型的宿主侧适配
+//!
+//! `wit/notification.wit`定义了组件可导入的`notification`接口。
+//! 宿主使用wasmtime等运行时根据该WIT生成绑定后，在生成的`Host` trait的实现中调用[`ComponentHost`]的同名方法即可。
+//!
+//! 每个组件实例拥有一个[`ComponentHost`]，组件只能看到宿主分配的句柄，而不能直接使用通知源id，
+//! 从而由宿主决定组件可以申请哪些类型的通知源，并在组件实例销毁时释放其所有通知源。
+
+use alloc::collections::btree_map::BTreeMap;
+
+use crate::interface::{Notification, NotificationIf, NotifyError, ProcessRef, WaitOn};
+
+/// 对应WIT中的`kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ComponentKind {
+    /// 信号
+    Signal,
+    /// 定时器
+    Timer,
+    /// 子进程退出
+    Child,
+}
+
+/// 对应WIT中的`error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentError {
+    /// 宿主未启用或不允许该类型的通知源
+    UnsupportedKind,
+    /// 该类型的通知源已耗尽
+    Exhausted,
+    /// 句柄无效或已被释放
+    InvalidHandle,
+    /// 宿主不允许该操作
+    NotPermitted,
+    /// 通知未能发送，例如目标进程不存在
+    NotifyFailed,
+}
+
+impl From<NotifyError> for ComponentError {
+    fn from(err: NotifyError) -> Self {
+        match err {
+            // id的类型未知或宿主未启用该类型
+            NotifyError::Unsupported => ComponentError::UnsupportedKind,
+            NotifyError::Rejected => ComponentError::NotPermitted,
+            _ => ComponentError::NotifyFailed,
+        }
+    }
+}
+
+/// 一个组件实例的通知源句柄表
+pub struct ComponentHost {
+    /// 句柄到通知源的映射
+    handles: BTreeMap<u32, (ComponentKind, u64)>,
+    /// 下一个分配的句柄
+    #[cfg_attr(
+        not(any(feature = "signal", feature = "timer", feature = "child")),
+        allow(dead_code)
+    )]
+    next: u32,
+    /// 允许组件申请的通知源类型
+    allowed: [bool; 3],
+    /// 是否允许组件向其他进程发送通知
+    allow_notify: bool,
+}
+
+impl Default for ComponentHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ComponentHost {
+    /// 新建句柄表，允许组件申请所有类型的通知源并发送通知
+    pub fn new() -> Self {
+        Self {
+            handles: BTreeMap::new(),
+            next: 0,
+            allowed: [true; 3],
+            allow_notify: true,
+        }
+    }
+
+    /// 设置是否允许组件申请`kind`类型的通知源
+    pub fn allow_kind(mut self, kind: ComponentKind, allowed: bool) -> Self {
+        self.allowed[kind as usize] = allowed;
+        self
+    }
+
+    /// 设置是否允许组件向其他进程发送通知
+    pub fn allow_notify(mut self, allowed: bool) -> Self {
+        self.allow_notify = allowed;
+        self
+    }
+
+    /// 组件持有的句柄数量
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// 组件是否未持有任何句柄
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    #[cfg_attr(
+        not(any(feature = "signal", feature = "timer", feature = "child")),
+        allow(dead_code)
+    )]
+    fn insert(
+        &mut self,
+        kind: ComponentKind,
+        alloc: impl FnOnce() -> Option<u64>,
+    ) -> Result<u32, ComponentError> {
+        if !self.allowed[kind as usize] {
+            return Err(ComponentError::UnsupportedKind);
+        }
+        let id = alloc().ok_or(ComponentError::Exhausted)?;
+        let handle = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.handles.insert(handle, (kind, id));
+        Ok(handle)
+    }
+
+    fn get(&self, handle: u32) -> Result<(ComponentKind, u64), ComponentError> {
+        self.handles
+            .get(&handle)
+            .copied()
+            .ok_or(ComponentError::InvalidHandle)
+    }
+
+    /// `allocate-signal`
+    pub fn allocate_signal(&mut self) -> Result<u32, ComponentError> {
+        #[cfg(feature = "signal")]
+        return self.insert(ComponentKind::Signal, Notification::new_id_signal);
+        #[cfg(not(feature = "signal"))]
+        Err(ComponentError::UnsupportedKind)
+    }
+
+    /// `allocate-timer`
+    #[cfg_attr(not(feature = "timer"), allow(unused_variables))]
+    pub fn allocate_timer(&mut self, period_ns: u64) -> Result<u32, ComponentError> {
+        #[cfg(feature = "timer")]
+        return match period_ns {
+            0 => Err(ComponentError::NotPermitted),
+            _ => self.insert(ComponentKind::Timer, || {
+                Notification::new_id_timer(core::time::Duration::from_nanos(period_ns))
+            }),
+        };
+        #[cfg(not(feature = "timer"))]
+        Err(ComponentError::UnsupportedKind)
+    }
+
+    /// `allocate-child`
+    #[cfg_attr(not(feature = "child"), allow(unused_variables))]
+    pub fn allocate_child(&mut self, pid: u64) -> Result<u32, ComponentError> {
+        #[cfg(feature = "child")]
+        return self.insert(ComponentKind::Child, || Notification::new_id_child(pid));
+        #[cfg(not(feature = "child"))]
+        Err(ComponentError::UnsupportedKind)
+    }
+
+    /// `kind-of`
+    pub fn kind_of(&self, handle: u32) -> Result<ComponentKind, ComponentError> {
+        self.get(handle).map(|(kind, _)| kind)
+    }
+
+    /// `raw-id`
+    pub fn raw_id(&self, handle: u32) -> Result<u64, ComponentError> {
+        self.get(handle).map(|(_, id)| id)
+    }
+
+    /// `wait`
+    ///
+    /// 返回的future应由宿主的异步绑定等待。
+    pub fn wait(&self, handle: u32) -> Result<WaitOn<Notification>, ComponentError> {
+        self.get(handle).map(|(_, id)| Notification::wait_on(id))
+    }
+
+    /// `notify`
+    ///
+    /// `process`与`id`均来自组件，发送失败时返回错误而不会panic。
+    pub fn notify(&self, process: u64, id: u64) -> Result<(), ComponentError> {
+        if !self.allow_notify {
+            return Err(ComponentError::NotPermitted);
+        }
+        Ok(Notification::notify_to(ProcessRef::Process(process), id)?)
+    }
+
+    /// `release`
+    pub fn release(&mut self, handle: u32) -> Result<(), ComponentError> {
+        let (_, id) = self
+            .handles
+            .remove(&handle)
+            .ok_or(ComponentError::InvalidHandle)?;
+        // 句柄已从表中移除，组件无法再在其上等待
+        unsafe { Notification::release_id(id) };
+        Ok(())
+    }
+}
+
+/// 组件实例销毁时释放其所有通知源
+impl Drop for ComponentHost {
+    fn drop(&mut self) {
+        for (_, (_, id)) in core::mem::take(&mut self.handles) {
+            unsafe { Notification::release_id(id) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_policy() {
+        let mut host = ComponentHost::new()
+            .allow_kind(ComponentKind::Signal, false)
+            .allow_notify(false);
+        assert_eq!(host.allocate_signal(), Err(ComponentError::UnsupportedKind));
+        assert_eq!(host.notify(1, 0), Err(ComponentError::NotPermitted));
+        assert_eq!(host.raw_id(0).err(), Some(ComponentError::InvalidHandle));
+        assert_eq!(host.release(0), Err(ComponentError::InvalidHandle));
+        assert!(host.is_empty());
+    }
+
+    #[test]
+    fn test_component_notify_error() {
+        let host = ComponentHost::new();
+        // 未知类型的id
+        assert_eq!(
+            host.notify(1, 0xfe00_0000_0000_0001),
+            Err(ComponentError::UnsupportedKind)
+        );
+    }
+}
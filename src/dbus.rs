@@ -0,0 +1,704 @@
+//! 转发D-Bus信号的通知机制
+//!
+//! 每个通知源对应一条D-Bus匹配规则，匹配的信号被视为该通知源上的一个通知，从而可以与只支持D-Bus的桌面服务互通。
+//! [`DbusNotification::new_id`]申请的通知源匹配路径`/org/async_notification/<pid>/<id>`、
+//! 接口`org.async_notification`、成员`Notify`的信号，`notify`即发出该信号；
+//! [`DbusNotification::new_id_with_match`]申请的通知源匹配其他服务发出的指定信号，可由[`DbusNotification::emit`]发出。
+//!
+//! 本模块实现了D-Bus线协议中所需的最小部分：进程内的所有通知源共用一条到总线的连接，
+//! 默认连接`DBUS_SESSION_BUS_ADDRESS`所指的会话总线，也可以在首次使用前调用[`DbusNotification::connect`]连接其他总线。
+//! 连接建立后，本模块启动一个线程读取总线发来的消息，并唤醒匹配的通知源的等待者。
+
+extern crate std;
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::sync::mpsc;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 转发D-Bus信号的通知机制
+pub struct DbusNotification;
+
+/// `notify`发出的信号的接口
+pub const NOTIFY_INTERFACE: &str = "org.async_notification";
+/// `notify`发出的信号的成员
+pub const NOTIFY_MEMBER: &str = "Notify";
+
+const MSG_METHOD_CALL: u8 = 1;
+const MSG_METHOD_RETURN: u8 = 2;
+const MSG_ERROR: u8 = 3;
+const MSG_SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// 信号的匹配条件，`None`表示不限
+#[derive(Clone, PartialEq, Eq)]
+struct Rule {
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+}
+
+impl Rule {
+    /// D-Bus匹配规则字符串
+    fn to_match(&self) -> String {
+        let mut rule = String::from("type='signal'");
+        for (key, value) in [
+            ("path", &self.path),
+            ("interface", &self.interface),
+            ("member", &self.member),
+        ] {
+            if let Some(value) = value {
+                rule += &format!(",{}='{}'", key, value);
+            }
+        }
+        rule
+    }
+
+    fn matches(&self, signal: &Header) -> bool {
+        [
+            (&self.path, &signal.path),
+            (&self.interface, &signal.interface),
+            (&self.member, &signal.member),
+        ]
+        .iter()
+        .all(|(want, got)| want.is_none() || want == got)
+    }
+}
+
+struct DbusSlot {
+    rule: Rule,
+    /// 已收到但尚未被消费的通知数量
+    pending: AtomicU64,
+    /// 最近一次等待该通知源的waker
+    waker: SpinMutex<Option<Waker>>,
+}
+
+impl DbusSlot {
+    fn poll(&self, cx: &mut Context<'_>, consume: bool) -> Poll<WakeReason> {
+        *self.waker.lock() = Some(cx.waker().clone());
+        let ready = if consume {
+            self.pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+        } else {
+            self.pending.load(Ordering::Acquire) > 0
+        };
+        // 连接断开后不会再收到通知，等待立即结束
+        if ready {
+            Poll::Ready(WakeReason::NOTIFIED)
+        } else if !CONNECTED.load(Ordering::Acquire) {
+            Poll::Ready(WakeReason::Shutdown)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn wake(&self) {
+        let waker = self.waker.lock().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// 到总线的连接
+struct Connection {
+    fd: OwnedRawFd,
+    /// 下一条消息的序号
+    serial: AtomicU32,
+    /// 写入消息时持有，保证消息不会交错
+    write: SpinMutex<()>,
+}
+
+/// 所有被占用的通知源，以id为key
+static SLOTS: SpinMutex<BTreeMap<u64, Arc<DbusSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 到总线的连接，`None`表示尚未连接
+static CONNECTION: SpinMutex<Option<Arc<Connection>>> = SpinMutex::new(None);
+
+/// 连接是否可用，读取线程在连接断开时将其置为`false`
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+
+/// 正在等待回复的方法调用，以序号为key
+static CALLS: SpinMutex<BTreeMap<u32, mpsc::Sender<bool>>> = SpinMutex::new(BTreeMap::new());
+
+/// 下一个被分配的id
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl NotificationIf for DbusNotification {
+    /// 申请一个匹配`notify`所发信号的通知源
+    fn new_id() -> Option<u64> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let rule = Rule {
+            path: Some(Self::notify_path(unsafe { libc::getpid() } as u64, id)),
+            interface: Some(NOTIFY_INTERFACE.to_string()),
+            member: Some(NOTIFY_MEMBER.to_string()),
+        };
+        Self::subscribe(id, rule)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放或连接已断开，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll(cx, true),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let ready = match Self::slot(id) {
+            Some(slot) => slot.poll(&mut Context::from_waker(waker), false).is_ready(),
+            None => true,
+        };
+        if ready {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// 取消匹配规则，并唤醒正在等待的协程
+    unsafe fn release_id(id: u64) {
+        let Some(slot) = SLOTS.lock().remove(&id) else {
+            fail!(return, "release_id: id 0x{:016x} is not allocated", id)
+        };
+        if let Some(conn) = Self::connection() {
+            let body = Self::string_body(&slot.rule.to_match());
+            let _ = Self::call(&conn, "RemoveMatch", &body);
+        }
+        slot.wake();
+    }
+
+    /// 发出进程`process`的通知源`id`所匹配的信号
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl DbusNotification {
+    /// 连接地址为`address`的总线，例如`unix:path=/run/user/1000/bus`
+    ///
+    /// 应在首次使用本模块之前调用；已经连接时返回`false`。
+    pub fn connect(address: &str) -> bool {
+        let mut conn = CONNECTION.lock();
+        if conn.is_some() {
+            return false;
+        }
+        match Self::open(address) {
+            Some(opened) => {
+                *conn = Some(opened);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 申请一个匹配指定信号的通知源，`None`表示不限
+    ///
+    /// 匹配的信号可由其他服务发出，也可由`emit`发出。
+    pub fn new_id_with_match(
+        path: Option<&str>,
+        interface: Option<&str>,
+        member: Option<&str>,
+    ) -> Option<u64> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let rule = Rule {
+            path: path.map(ToString::to_string),
+            interface: interface.map(ToString::to_string),
+            member: member.map(ToString::to_string),
+        };
+        Self::subscribe(id, rule)
+    }
+
+    /// 发出进程`process`的通知源`id`所匹配的信号，失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        Self::emit(
+            &Self::notify_path(process, id),
+            NOTIFY_INTERFACE,
+            NOTIFY_MEMBER,
+        )
+    }
+
+    /// 发出一个不带参数的信号
+    pub fn emit(path: &str, interface: &str, member: &str) -> Result<(), NotifyError> {
+        let conn = Self::connection().ok_or(NotifyError::Unsupported)?;
+        let serial = conn.serial.fetch_add(1, Ordering::Relaxed);
+        let msg = Message::new(MSG_SIGNAL, serial)
+            .field_str(FIELD_PATH, b'o', path)
+            .field_str(FIELD_INTERFACE, b's', interface)
+            .field_str(FIELD_MEMBER, b's', member)
+            .finish(&[], "");
+        Self::send(&conn, &msg)
+    }
+
+    fn notify_path(process: u64, id: u64) -> String {
+        format!("/org/async_notification/{}/{}", process, id)
+    }
+
+    /// 添加匹配规则，并在总线确认后登记通知源
+    fn subscribe(id: u64, rule: Rule) -> Option<u64> {
+        let conn = Self::connection()?;
+        let slot = DbusSlot {
+            rule,
+            pending: AtomicU64::new(0),
+            waker: SpinMutex::new(None),
+        };
+        let body = Self::string_body(&slot.rule.to_match());
+        let reply = Self::call(&conn, "AddMatch", &body).ok()?;
+        // 规则被确认之前发出的信号不保证被转发，因此在确认后才返回id
+        if !reply.recv().unwrap_or(false) {
+            return None;
+        }
+        SLOTS.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    /// 调用总线的方法，返回用于等待回复的通道；回复为错误时收到`false`
+    fn call(
+        conn: &Connection,
+        member: &str,
+        body: &[u8],
+    ) -> Result<mpsc::Receiver<bool>, NotifyError> {
+        let serial = conn.serial.fetch_add(1, Ordering::Relaxed);
+        let msg = Message::new(MSG_METHOD_CALL, serial)
+            .field_str(FIELD_PATH, b'o', "/org/freedesktop/DBus")
+            .field_str(FIELD_INTERFACE, b's', "org.freedesktop.DBus")
+            .field_str(FIELD_MEMBER, b's', member)
+            .field_str(FIELD_DESTINATION, b's', "org.freedesktop.DBus")
+            .finish(body, if body.is_empty() { "" } else { "s" });
+        let (sender, receiver) = mpsc::channel();
+        CALLS.lock().insert(serial, sender);
+        let res = Self::send(conn, &msg);
+        if res.is_err() {
+            CALLS.lock().remove(&serial);
+        }
+        res.map(|_| receiver)
+    }
+
+    fn send(conn: &Connection, msg: &[u8]) -> Result<(), NotifyError> {
+        let _guard = conn.write.lock();
+        write_all(conn.fd.0, msg).map_err(NotifyError::Os)
+    }
+
+    /// 由单个字符串组成的消息体
+    fn string_body(value: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_str(&mut body, value);
+        body
+    }
+
+    /// 获取到总线的连接，首次调用时连接会话总线
+    fn connection() -> Option<Arc<Connection>> {
+        let mut conn = CONNECTION.lock();
+        if conn.is_none() {
+            let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").ok()?;
+            *conn = Some(Self::open(&address)?);
+        }
+        conn.clone().filter(|_| CONNECTED.load(Ordering::Acquire))
+    }
+
+    /// 连接总线、完成认证与`Hello`，并启动读取线程
+    fn open(address: &str) -> Option<Arc<Connection>> {
+        let fd = address.split(';').find_map(connect_unix)?;
+        // 认证：使用本进程的uid进行EXTERNAL认证
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+        write_all(fd.0, format!("\0AUTH EXTERNAL {}\r\n", hex).as_bytes()).ok()?;
+        if !read_line(fd.0)?.starts_with("OK") {
+            return None;
+        }
+        write_all(fd.0, b"BEGIN\r\n").ok()?;
+
+        let conn = Arc::new(Connection {
+            fd,
+            serial: AtomicU32::new(1),
+            write: SpinMutex::new(()),
+        });
+        // `Hello`必须是第一条消息，在启动读取线程之前同步等待其回复
+        let serial = conn.serial.fetch_add(1, Ordering::Relaxed);
+        let hello = Message::new(MSG_METHOD_CALL, serial)
+            .field_str(FIELD_PATH, b'o', "/org/freedesktop/DBus")
+            .field_str(FIELD_INTERFACE, b's', "org.freedesktop.DBus")
+            .field_str(FIELD_MEMBER, b's', "Hello")
+            .field_str(FIELD_DESTINATION, b's', "org.freedesktop.DBus")
+            .finish(&[], "");
+        write_all(conn.fd.0, &hello).ok()?;
+        loop {
+            let header = read_message(conn.fd.0)?;
+            if header.reply_serial == Some(serial) {
+                if header.kind != MSG_METHOD_RETURN {
+                    return None;
+                }
+                break;
+            }
+        }
+        CONNECTED.store(true, Ordering::Release);
+        let reader = conn.clone();
+        std::thread::spawn(move || Self::run(&reader));
+        Some(conn)
+    }
+
+    /// 读取线程：读取总线发来的消息，唤醒匹配的通知源的等待者，并将回复交给等待的方法调用
+    fn run(conn: &Connection) {
+        while let Some(header) = read_message(conn.fd.0) {
+            match header.kind {
+                MSG_SIGNAL => {
+                    let slots: Vec<_> = SLOTS
+                        .lock()
+                        .values()
+                        .filter(|slot| slot.rule.matches(&header))
+                        .cloned()
+                        .collect();
+                    for slot in slots {
+                        slot.pending.fetch_add(1, Ordering::AcqRel);
+                        slot.wake();
+                    }
+                }
+                MSG_METHOD_RETURN | MSG_ERROR => {
+                    let serial = header.reply_serial.unwrap_or(0);
+                    if let Some(sender) = CALLS.lock().remove(&serial) {
+                        let _ = sender.send(header.kind == MSG_METHOD_RETURN);
+                    }
+                }
+                _ => {}
+            }
+        }
+        // 连接已断开：唤醒所有等待者，并使等待中的方法调用失败
+        CONNECTED.store(false, Ordering::Release);
+        CALLS.lock().clear();
+        let slots: Vec<_> = SLOTS.lock().values().cloned().collect();
+        for slot in slots {
+            slot.wake();
+        }
+    }
+
+    fn slot(id: u64) -> Option<Arc<DbusSlot>> {
+        SLOTS.lock().get(&id).cloned()
+    }
+}
+
+/// 按D-Bus线协议（小端序）构造消息
+struct Message {
+    kind: u8,
+    serial: u32,
+    fields: Vec<u8>,
+}
+
+impl Message {
+    fn new(kind: u8, serial: u32) -> Self {
+        Self {
+            kind,
+            serial,
+            fields: Vec::new(),
+        }
+    }
+
+    /// 添加一个字符串类型（`s`或`o`）的头部字段
+    fn field_str(mut self, code: u8, sig: u8, value: &str) -> Self {
+        // 头部字段数组的元素为结构体，按8字节对齐；数组本身从第16字节开始，因此对齐可在字段内计算
+        pad(&mut self.fields, 8);
+        self.fields.extend_from_slice(&[code, 1, sig, 0]);
+        put_str(&mut self.fields, value);
+        self
+    }
+
+    fn finish(mut self, body: &[u8], signature: &str) -> Vec<u8> {
+        if !signature.is_empty() {
+            pad(&mut self.fields, 8);
+            self.fields
+                .extend_from_slice(&[FIELD_SIGNATURE, 1, b'g', 0]);
+            self.fields.push(signature.len() as u8);
+            self.fields.extend_from_slice(signature.as_bytes());
+            self.fields.push(0);
+        }
+        let mut msg = vec![b'l', self.kind, 0, 1];
+        msg.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        msg.extend_from_slice(&self.serial.to_le_bytes());
+        msg.extend_from_slice(&(self.fields.len() as u32).to_le_bytes());
+        msg.extend_from_slice(&self.fields);
+        pad(&mut msg, 8);
+        msg.extend_from_slice(body);
+        msg
+    }
+}
+
+/// 消息头部中本模块关心的部分
+struct Header {
+    kind: u8,
+    reply_serial: Option<u32>,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+}
+
+fn pad(buf: &mut Vec<u8>, align: usize) {
+    while buf.len() % align != 0 {
+        buf.push(0);
+    }
+}
+
+/// 写入一个字符串（`s`或`o`）：4字节对齐的长度、内容与结尾的0
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    pad(buf, 4);
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+/// 读取一条完整的消息，并解析其头部；连接断开或消息格式错误时返回`None`
+fn read_message(fd: libc::c_int) -> Option<Header> {
+    let mut fixed = [0u8; 16];
+    read_exact(fd, &mut fixed)?;
+    let little = match fixed[0] {
+        b'l' => true,
+        b'B' => false,
+        _ => return None,
+    };
+    let u32_at = |buf: &[u8], at: usize| -> Option<u32> {
+        let bytes: [u8; 4] = buf.get(at..at + 4)?.try_into().ok()?;
+        Some(if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let body_len = u32_at(&fixed, 4)? as usize;
+    let fields_len = u32_at(&fixed, 12)? as usize;
+    let padded = (fields_len + 7) / 8 * 8;
+    let mut rest = vec![0u8; padded + body_len];
+    read_exact(fd, &mut rest)?;
+
+    let mut header = Header {
+        kind: fixed[1],
+        reply_serial: None,
+        path: None,
+        interface: None,
+        member: None,
+    };
+    // 字段的偏移以消息开头计算，头部字段数组从第16字节开始，而16是8的倍数
+    let fields = &rest[..fields_len];
+    let mut at = 0;
+    while at < fields.len() {
+        at = (at + 7) / 8 * 8;
+        let code = *fields.get(at)?;
+        let sig_len = *fields.get(at + 1)? as usize;
+        let sig = *fields.get(at + 2)?;
+        at += 2 + sig_len + 1;
+        match sig {
+            b's' | b'o' => {
+                at = (at + 3) / 4 * 4;
+                let len = u32_at(fields, at)? as usize;
+                let value = core::str::from_utf8(fields.get(at + 4..at + 4 + len)?).ok()?;
+                at += 4 + len + 1;
+                match code {
+                    FIELD_PATH => header.path = Some(value.to_string()),
+                    FIELD_INTERFACE => header.interface = Some(value.to_string()),
+                    FIELD_MEMBER => header.member = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            b'u' => {
+                at = (at + 3) / 4 * 4;
+                let value = u32_at(fields, at)?;
+                at += 4;
+                if code == FIELD_REPLY_SERIAL {
+                    header.reply_serial = Some(value);
+                }
+            }
+            b'g' => {
+                let len = *fields.get(at)? as usize;
+                at += 1 + len + 1;
+            }
+            _ => return None,
+        }
+    }
+    Some(header)
+}
+
+/// 连接形如`unix:path=...`或`unix:abstract=...`的地址
+fn connect_unix(address: &str) -> Option<OwnedRawFd> {
+    let params = address.strip_prefix("unix:")?;
+    let (path, is_abstract) = params.split(',').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        match key {
+            "path" => Some((unescape(value)?, false)),
+            "abstract" => Some((unescape(value)?, true)),
+            _ => None,
+        }
+    })?;
+    let mut addr: libc::sockaddr_un = unsafe { core::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let offset = is_abstract as usize;
+    if path.len() + offset >= addr.sun_path.len() {
+        return None;
+    }
+    for (dst, &src) in addr.sun_path[offset..].iter_mut().zip(path.iter()) {
+        *dst = src as libc::c_char;
+    }
+    let len =
+        core::mem::size_of::<libc::sa_family_t>() + offset + path.len() + !is_abstract as usize;
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return None;
+    }
+    let fd = OwnedRawFd(fd);
+    let res = unsafe {
+        libc::connect(
+            fd.0,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len as libc::socklen_t,
+        )
+    };
+    (res == 0).then_some(fd)
+}
+
+/// 解码地址中以`%XX`转义的字节
+fn unescape(value: &str) -> Option<Vec<u8>> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = core::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+fn write_all(fd: libc::c_int, mut buf: &[u8]) -> Result<(), i32> {
+    while !buf.is_empty() {
+        let res = unsafe {
+            libc::send(
+                fd,
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        if res < 0 {
+            match unsafe { *libc::__errno_location() } {
+                libc::EINTR => continue,
+                errno => return Err(errno),
+            }
+        }
+        buf = &buf[res as usize..];
+    }
+    Ok(())
+}
+
+fn read_exact(fd: libc::c_int, mut buf: &mut [u8]) -> Option<()> {
+    while !buf.is_empty() {
+        let res = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if res < 0 && unsafe { *libc::__errno_location() } == libc::EINTR {
+            continue;
+        }
+        if res <= 0 {
+            return None;
+        }
+        buf = &mut buf[res as usize..];
+    }
+    Some(())
+}
+
+/// 逐字节读取认证阶段的一行，以免读入之后的消息
+fn read_line(fd: libc::c_int) -> Option<String> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        let mut byte = [0u8];
+        read_exact(fd, &mut byte)?;
+        line.push(byte[0]);
+    }
+    String::from_utf8(line).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DbusNotification;
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+    use std::io::{BufRead, BufReader};
+
+    extern crate std;
+
+    #[test]
+    fn test_dbus_bridge() {
+        // 启动一个私有的会话总线；没有dbus-daemon时无法测试
+        let Ok(mut daemon) = std::process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        else {
+            return;
+        };
+        let mut address = std::string::String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        assert!(DbusNotification::connect(address.trim()));
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_dbus().unwrap();
+                let other =
+                    Notification::new_id_dbus_match(Some("/test"), None, Some("Ping")).unwrap();
+                Notification::notify(pid, id);
+                Notification::notify(pid, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(2), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+
+                // 只有匹配的信号被转发
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(other, &mut cx).is_pending());
+                DbusNotification::emit("/test", "org.example.Test", "Ping").unwrap();
+                tokio::time::timeout(Duration::from_secs(2), Notification::wait_on(other))
+                    .await
+                    .unwrap();
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                unsafe {
+                    Notification::release_id(id);
+                    Notification::release_id(other);
+                }
+            });
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+//! 延迟与定时发送的通知
+//!
+//! [`Notification::notify_after`]与[`Notification::notify_at`]在发送方登记一个通知，到期时经由
+//! [`Notification::notify_to`]发送，并返回可以取消它的[`ScheduledNotify`]。协议的重传与超时因此不需要另外的定时器子系统：
+//! 发送请求时登记一个延迟的通知，收到应答后取消即可。
+//!
+//! 所有登记的通知按到期时刻保存在同一个队列中，由一个后台的tokio任务在最早的到期时刻醒来并发送到期的通知，
+//! 因此需要在tokio运行时内部登记，并启用其时钟。发送失败的通知被丢弃，结果只记录在`tracing`中。
+//! 到期时刻相同的通知按登记的顺序发送。运行时关闭时尚未发送的通知不再发送。
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+use crate::{
+    interface::{Notification, ProcessRef},
+    sync::SpinMutex,
+};
+
+/// 登记的通知，以到期时刻与登记序号为key
+static QUEUE: SpinMutex<BTreeMap<(Instant, u64), (ProcessRef, u64)>> =
+    SpinMutex::new(BTreeMap::new());
+
+/// 下一个登记序号
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+/// 后台任务是否正在运行
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// 后台任务的waker，登记了更早到期的通知时唤醒它
+static DRIVER: SpinMutex<Option<Waker>> = SpinMutex::new(None);
+
+/// 一个登记的通知，可以在到期之前取消
+///
+/// 被drop时不取消通知。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use = "dropping the handle does not cancel the notification"]
+pub struct ScheduledNotify {
+    deadline: Instant,
+    key: u64,
+}
+
+impl ScheduledNotify {
+    /// 到期时刻
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// 通知是否尚未发送且未被取消
+    pub fn is_pending(&self) -> bool {
+        QUEUE.lock().contains_key(&(self.deadline, self.key))
+    }
+
+    /// 取消通知，返回是否在发送之前取消了它
+    pub fn cancel(&self) -> bool {
+        QUEUE.lock().remove(&(self.deadline, self.key)).is_some()
+    }
+}
+
+/// 发送到期通知的后台任务
+struct Driver {
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl Future for Driver {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            *DRIVER.lock() = Some(cx.waker().clone());
+            let now = Instant::now();
+            // 发送时不持有队列的锁，钩子或通知机制可以再登记通知
+            let due = {
+                let mut queue = QUEUE.lock();
+                match queue.first_key_value() {
+                    Some((&(deadline, _), _)) if deadline <= now => queue.pop_first(),
+                    Some((&(deadline, _), _)) => {
+                        self.sleep.as_mut().reset(deadline);
+                        None
+                    }
+                    // 没有登记的通知时等待`schedule`唤醒
+                    None => return Poll::Pending,
+                }
+            };
+            match due {
+                Some((_, (target, id))) => {
+                    let _ = Notification::notify_to(target, id);
+                }
+                None => {
+                    if self.sleep.as_mut().poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        DRIVER.lock().take();
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+fn schedule(target: ProcessRef, id: u64, deadline: Instant) -> ScheduledNotify {
+    let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let earliest = {
+        let mut queue = QUEUE.lock();
+        queue.insert((deadline, key), (target, id));
+        queue.first_key_value().map(|(&(first, _), _)| first) == Some(deadline)
+    };
+    if !RUNNING.swap(true, Ordering::AcqRel) {
+        tokio::spawn(Driver {
+            sleep: Box::pin(tokio::time::sleep_until(deadline)),
+        });
+    } else if earliest {
+        if let Some(waker) = DRIVER.lock().take() {
+            waker.wake();
+        }
+    }
+    ScheduledNotify { deadline, key }
+}
+
+impl Notification {
+    /// 在`delay`之后向`target`的通知源`id`发送通知，需要在tokio运行时内部调用
+    pub fn notify_after(target: ProcessRef, id: u64, delay: Duration) -> ScheduledNotify {
+        schedule(target, id, Instant::now() + delay)
+    }
+
+    /// 在`at`时向`target`的通知源`id`发送通知，`at`已过去时尽快发送；需要在tokio运行时内部调用
+    pub fn notify_at(target: ProcessRef, id: u64, at: Instant) -> ScheduledNotify {
+        schedule(target, id, at)
+    }
+
+    /// 尚未发送的延迟通知数
+    pub fn scheduled_count() -> usize {
+        QUEUE.lock().len()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    #[test]
+    fn test_notify_after() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let target = ProcessRef::Process(0);
+                let id = Notification::new_id_mock().unwrap();
+                let start = Instant::now();
+                let late = Notification::notify_after(target, id, Duration::from_millis(40));
+                let early = Notification::notify_at(target, id, start + Duration::from_millis(10));
+                let cancelled = Notification::notify_after(target, id, Duration::from_millis(20));
+                assert!(cancelled.is_pending());
+                assert!(cancelled.cancel());
+                assert!(!cancelled.cancel());
+
+                Notification::wait_on(id).await;
+                assert!(start.elapsed() >= Duration::from_millis(10));
+                assert!(!early.is_pending() && late.is_pending());
+                Notification::wait_on(id).await;
+                assert!(start.elapsed() >= Duration::from_millis(40));
+                assert!(!late.is_pending() && !late.cancel());
+
+                // 被取消的通知不再发送
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                assert_eq!(crate::mock::pending(id), Some(0));
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
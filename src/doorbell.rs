@@ -0,0 +1,266 @@
+//! 基于共享内存门铃的通知快速路径
+//!
+//! 收发双方共享一个[`DoorbellWord`]（例如位于`MAP_SHARED`映射的内存中），其中记录未被消费的通知数量，以及接收方是否正在睡眠。
+//! 发送方总是先修改共享内存中的计数，只有在接收方正在睡眠时，才通过通知源发送通知；
+//! 接收方在计数为0时才标记睡眠并在通知源上等待。通知频繁时，大部分通知都不需要系统调用。
+
+use core::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::interface::{Notification, NotificationIf, WakeReason};
+
+/// 一次轮询中至多重新睡眠的次数，超过后让出执行器
+const MAX_RESLEEP: usize = 4;
+
+/// 接收方正在睡眠
+const SLEEPING: u32 = 1 << 31;
+/// 未被消费的通知数量
+const COUNT_MASK: u32 = SLEEPING - 1;
+
+/// 收发双方共享的门铃
+///
+/// 最高位表示接收方正在睡眠，其余位为未被消费的通知数量。
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct DoorbellWord(AtomicU32);
+
+impl DoorbellWord {
+    /// 新建门铃
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// 将共享内存中的一个`u32`视为门铃
+    ///
+    /// # Safety
+    ///
+    /// `ptr`必须非空、按4字节对齐，且在`'a`内有效，并只被以原子操作访问。
+    pub unsafe fn from_ptr<'a>(ptr: *mut u32) -> &'a Self {
+        unsafe { &*(ptr as *const Self) }
+    }
+
+    /// 未被消费的通知数量
+    pub fn count(&self) -> u32 {
+        self.0.load(Ordering::Acquire) & COUNT_MASK
+    }
+}
+
+/// 门铃的发送方
+pub struct DoorbellSender<'a, N: NotificationIf = Notification> {
+    word: &'a DoorbellWord,
+    process: u64,
+    id: u64,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<'a, N: NotificationIf> DoorbellSender<'a, N> {
+    /// 新建发送方，接收方睡眠时向进程`process`的通知源`id`发送通知
+    pub fn new(word: &'a DoorbellWord, process: u64, id: u64) -> Self {
+        Self {
+            word,
+            process,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 发送一个通知，返回是否通过通知源唤醒了接收方
+    ///
+    /// 计数达到上限时，新的通知与未被消费的通知合并。
+    pub fn ring(&self) -> bool {
+        let prev = self
+            .word
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |word| {
+                let count = (word & COUNT_MASK).saturating_add(1).min(COUNT_MASK);
+                Some(count)
+            })
+            .unwrap();
+        if prev & SLEEPING == 0 {
+            return false;
+        }
+        N::notify(self.process, self.id);
+        true
+    }
+}
+
+/// 门铃的接收方
+///
+/// 门铃只支持一个接收方，`id`应为接收方专用的通知源。
+pub struct DoorbellReceiver<'a, N: NotificationIf = Notification> {
+    word: &'a DoorbellWord,
+    id: u64,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<'a, N: NotificationIf> DoorbellReceiver<'a, N> {
+    /// 新建接收方，睡眠时在本进程的通知源`id`上等待
+    pub fn new(word: &'a DoorbellWord, id: u64) -> Self {
+        Self {
+            word,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 轮询门铃
+    ///
+    /// 若有未被消费的通知，则消费一个通知并返回`WakeReason::NOTIFIED`；否则标记睡眠，并在通知源上等待。
+    /// 通知源已被释放或已关闭时返回`WakeReason::Shutdown`。
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        for _ in 0..MAX_RESLEEP {
+            let res = self
+                .word
+                .0
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |word| {
+                    match word & COUNT_MASK {
+                        0 => Some(SLEEPING),
+                        count => Some(count - 1),
+                    }
+                })
+                .unwrap();
+            if res & COUNT_MASK != 0 {
+                return Poll::Ready(WakeReason::NOTIFIED);
+            }
+            // 已标记睡眠，此后的发送方会通过通知源唤醒本接收方
+            match N::poll_wait_reason(self.id, cx) {
+                Poll::Ready(WakeReason::Shutdown) => {
+                    self.word.0.fetch_and(!SLEEPING, Ordering::AcqRel);
+                    return Poll::Ready(WakeReason::Shutdown);
+                }
+                // 被唤醒后重新检查计数；若计数仍为0（例如此前的睡眠中遗留的通知），则重新睡眠
+                Poll::Ready(_) => continue,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        // 通知源反复报告通知而计数始终为0，让出执行器，避免在一次轮询中忙等
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+
+    /// 等待下一个通知
+    pub fn wait(&self) -> DoorbellWait<'_, 'a, N> {
+        DoorbellWait { receiver: self }
+    }
+}
+
+/// `DoorbellReceiver::wait`返回的future
+///
+/// 该future是取消安全的：通知只在其完成时被消费。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct DoorbellWait<'r, 'a, N: NotificationIf> {
+    receiver: &'r DoorbellReceiver<'a, N>,
+}
+
+impl<N: NotificationIf> Future for DoorbellWait<'_, '_, N> {
+    type Output = WakeReason;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        self.receiver.poll_wait(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::SpinMutex;
+    use core::task::Waker;
+
+    /// 测试用的通知源，记录发送次数
+    ///
+    /// id为`RELEASED`时视为已被释放，为`STALE`时总是报告有通知。
+    struct TestNotification;
+
+    const RELEASED: u64 = 1;
+    const STALE: u64 = 2;
+
+    /// 未被消费的通知数量，发送次数
+    static STATE: SpinMutex<(usize, usize)> = SpinMutex::new((0, 0));
+
+    impl NotificationIf for TestNotification {
+        fn new_id() -> Option<u64> {
+            Some(0)
+        }
+
+        fn poll_wait(id: u64, _cx: &mut Context<'_>) -> Poll<()> {
+            if id == STALE {
+                return Poll::Ready(());
+            }
+            let mut state = STATE.lock();
+            if state.0 > 0 {
+                state.0 -= 1;
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+            if id == RELEASED {
+                return Poll::Ready(WakeReason::Shutdown);
+            }
+            Self::poll_wait(id, cx).map(|()| WakeReason::NOTIFIED)
+        }
+
+        fn register_waker(_id: u64, _waker: &Waker) {}
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, _id: u64) {
+            let mut state = STATE.lock();
+            state.0 += 1;
+            state.1 += 1;
+        }
+    }
+
+    #[test]
+    fn test_doorbell_sleep_and_ring() {
+        let word = DoorbellWord::new();
+        let sender = DoorbellSender::<TestNotification>::new(&word, 0, 0);
+        let receiver = DoorbellReceiver::<TestNotification>::new(&word, 0);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // 接收方未睡眠时不发送通知
+        assert!(!sender.ring());
+        assert!(!sender.ring());
+        assert_eq!(word.count(), 2);
+        assert!(receiver.poll_wait(&mut cx).is_ready());
+        assert!(receiver.poll_wait(&mut cx).is_ready());
+        assert_eq!(STATE.lock().1, 0);
+
+        // 接收方睡眠后，第一个通知经过通知源，之后的通知不再发送
+        assert!(receiver.poll_wait(&mut cx).is_pending());
+        assert!(sender.ring());
+        assert!(!sender.ring());
+        assert_eq!(STATE.lock().1, 1);
+        assert!(receiver.poll_wait(&mut cx).is_ready());
+        assert!(receiver.poll_wait(&mut cx).is_ready());
+        assert!(receiver.poll_wait(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn test_doorbell_released() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // 通知源已被释放时等待结束，而不是在轮询中忙等
+        let word = DoorbellWord::new();
+        let receiver = DoorbellReceiver::<TestNotification>::new(&word, RELEASED);
+        assert_eq!(
+            receiver.poll_wait(&mut cx),
+            Poll::Ready(WakeReason::Shutdown)
+        );
+        assert_eq!(word.0.load(Ordering::Acquire), 0);
+
+        // 通知源总是报告有通知而计数为0时，让出执行器
+        let word = DoorbellWord::new();
+        let receiver = DoorbellReceiver::<TestNotification>::new(&word, STALE);
+        assert!(receiver.poll_wait(&mut cx).is_pending());
+    }
+}
@@ -0,0 +1,160 @@
+//! 可作为trait对象使用的通知接口
+//!
+//! [`NotificationIf`]的方法均为关联函数，且`wait_on`返回与实现类型相关的future，因此不能作为trait对象。
+//! [`DynNotification`]以`&self`方法提供相同的操作，`wait_on`返回装箱的future；
+//! [`DynAdapter`]将任意[`NotificationIf`]的实现适配为[`DynNotification`]，从而可以在运行时选择通知机制，
+//! 并将其保存在结构体或注册表中。
+
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::interface::{NotificationIf, WakeReason};
+
+/// 装箱的等待通知的future
+pub type BoxWaitOn = Pin<Box<dyn Future<Output = WakeReason> + Send + 'static>>;
+
+/// 可作为trait对象使用的通知接口，各方法的语义与[`NotificationIf`]中的同名方法相同
+pub trait DynNotification: Send + Sync {
+    /// 见[`NotificationIf::new_id`]
+    fn new_id(&self) -> Option<u64>;
+    /// 见[`NotificationIf::wait_on`]
+    fn wait_on(&self, id: u64) -> BoxWaitOn;
+    /// 见[`NotificationIf::poll_wait`]
+    fn poll_wait(&self, id: u64, cx: &mut Context<'_>) -> Poll<()>;
+    /// 见[`NotificationIf::register_waker`]
+    fn register_waker(&self, id: u64, waker: &Waker);
+    /// 见[`NotificationIf::release_id`]
+    ///
+    /// # Safety
+    ///
+    /// 与[`NotificationIf::release_id`]相同。
+    unsafe fn release_id(&self, id: u64);
+    /// 见[`NotificationIf::notify`]
+    fn notify(&self, process: u64, id: u64);
+}
+
+/// 将`N`适配为[`DynNotification`]
+pub struct DynAdapter<N: NotificationIf> {
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<N: NotificationIf> DynAdapter<N> {
+    /// 新建适配器
+    pub const fn new() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<N: NotificationIf> Default for DynAdapter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: NotificationIf> Clone for DynAdapter<N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N: NotificationIf> Copy for DynAdapter<N> {}
+
+impl<N: NotificationIf> fmt::Debug for DynAdapter<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DynAdapter<{}>", core::any::type_name::<N>())
+    }
+}
+
+impl<N: NotificationIf + 'static> DynNotification for DynAdapter<N> {
+    fn new_id(&self) -> Option<u64> {
+        N::new_id()
+    }
+
+    fn wait_on(&self, id: u64) -> BoxWaitOn {
+        Box::pin(N::wait_on(id))
+    }
+
+    fn poll_wait(&self, id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        N::poll_wait(id, cx)
+    }
+
+    fn register_waker(&self, id: u64, waker: &Waker) {
+        N::register_waker(id, waker)
+    }
+
+    unsafe fn release_id(&self, id: u64) {
+        unsafe { N::release_id(id) }
+    }
+
+    fn notify(&self, process: u64, id: u64) {
+        N::notify(process, id)
+    }
+}
+
+/// 将`N`装箱为trait对象
+pub fn boxed<N: NotificationIf + 'static>() -> Box<dyn DynNotification> {
+    Box::new(DynAdapter::<N>::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DynNotification, boxed};
+    use crate::interface::{NotificationIf, WakeReason};
+    use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+    use core::{
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll, Waker},
+    };
+
+    /// 只有一个通知源的通知机制
+    struct Flag;
+
+    static FLAG: AtomicBool = AtomicBool::new(false);
+
+    impl NotificationIf for Flag {
+        fn new_id() -> Option<u64> {
+            Some(1)
+        }
+
+        fn poll_wait(_id: u64, _cx: &mut Context<'_>) -> Poll<()> {
+            if FLAG.swap(false, Ordering::AcqRel) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn register_waker(_id: u64, _waker: &Waker) {}
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, _id: u64) {
+            FLAG.store(true, Ordering::Release);
+        }
+    }
+
+    #[test]
+    fn test_dyn_registry() {
+        let mut registry: BTreeMap<&str, Box<dyn DynNotification>> = BTreeMap::new();
+        registry.insert("flag", boxed::<Flag>());
+        let backend = &registry["flag"];
+        let id = backend.new_id().unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(backend.poll_wait(id, &mut cx).is_pending());
+        backend.notify(0, id);
+        assert_eq!(
+            futures::executor::block_on(backend.wait_on(id)),
+            WakeReason::NOTIFIED
+        );
+        unsafe { backend.release_id(id) };
+    }
+}
@@ -0,0 +1,232 @@
+//! 用于事后分析的事件环形缓冲区
+//!
+//! 启用`event-log` feature时，[`Notification`](crate::interface::Notification)的申请、发送、消费与释放
+//! 被记录在固定大小的静态环形缓冲区中，只保留最近的[`CAPACITY`]个事件。记录事件不分配内存也不加锁，
+//! 因此可在任何上下文中进行；进程因等待通知而死锁时，可在调试器或panic hook中调用[`dump`]查看最近的历史。
+//!
+//! 事件的时刻由[`set_clock`]设置的时钟给出，未设置时为0；事件总带有全局递增的序号，可用于确定先后顺序。
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::interface::Notification;
+
+/// 缓冲区中保留的事件数量
+pub const CAPACITY: usize = 256;
+
+/// 事件的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// 申请了通知源
+    NewId,
+    /// 发送了通知
+    Notify,
+    /// 发送通知失败
+    NotifyFailed,
+    /// 一个通知被`poll_wait`消费
+    Wake,
+    /// 释放了通知源
+    Release,
+}
+
+impl EventKind {
+    const ALL: [EventKind; 5] = [
+        EventKind::NewId,
+        EventKind::Notify,
+        EventKind::NotifyFailed,
+        EventKind::Wake,
+        EventKind::Release,
+    ];
+
+    /// 事件类型的名称
+    pub const fn name(self) -> &'static str {
+        match self {
+            EventKind::NewId => "new_id",
+            EventKind::Notify => "notify",
+            EventKind::NotifyFailed => "notify_failed",
+            EventKind::Wake => "wake",
+            EventKind::Release => "release",
+        }
+    }
+}
+
+/// 一个事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// 全局递增的序号
+    pub seq: u64,
+    /// 事件的时刻，由[`set_clock`]设置的时钟给出
+    pub time: u64,
+    /// 事件的类型
+    pub kind: EventKind,
+    /// 通知源id
+    pub id: u64,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} t={} {} {}",
+            self.seq,
+            self.time,
+            self.kind.name(),
+            Notification::display(self.id)
+        )
+    }
+}
+
+/// 缓冲区中的一项
+///
+/// `stamp`为奇数时该项正被写入；为偶数`2 * (seq + 1)`时保存的是序号为`seq`的事件。
+struct Entry {
+    stamp: AtomicU64,
+    time: AtomicU64,
+    kind: AtomicU8,
+    id: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Entry = Entry {
+    stamp: AtomicU64::new(0),
+    time: AtomicU64::new(0),
+    kind: AtomicU8::new(0),
+    id: AtomicU64::new(0),
+};
+
+static ENTRIES: [Entry; CAPACITY] = [EMPTY; CAPACITY];
+
+/// 下一个事件的序号
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// 时钟函数的地址，0表示未设置
+static CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// 设置给出事件时刻的时钟，例如返回单调时钟的纳秒数的函数
+pub fn set_clock(clock: fn() -> u64) {
+    CLOCK.store(clock as usize, Ordering::Release);
+}
+
+fn now() -> u64 {
+    match CLOCK.load(Ordering::Acquire) {
+        0 => 0,
+        clock => {
+            // `CLOCK`中只保存由`set_clock`存入的函数指针
+            let clock: fn() -> u64 = unsafe { core::mem::transmute(clock) };
+            clock()
+        }
+    }
+}
+
+/// 记录一个事件
+pub(crate) fn record(kind: EventKind, id: u64) {
+    let seq = NEXT.fetch_add(1, Ordering::Relaxed);
+    let entry = &ENTRIES[(seq % CAPACITY as u64) as usize];
+    entry.stamp.store(2 * seq + 1, Ordering::Relaxed);
+    core::sync::atomic::fence(Ordering::Release);
+    entry.time.store(now(), Ordering::Relaxed);
+    entry.kind.store(kind as u8, Ordering::Relaxed);
+    entry.id.store(id, Ordering::Relaxed);
+    entry.stamp.store(2 * (seq + 1), Ordering::Release);
+}
+
+/// 读出一项，正被写入或被覆盖的项返回`None`
+fn read(entry: &Entry) -> Option<Event> {
+    let stamp = entry.stamp.load(Ordering::Acquire);
+    if stamp == 0 || stamp % 2 == 1 {
+        return None;
+    }
+    let time = entry.time.load(Ordering::Relaxed);
+    let kind = entry.kind.load(Ordering::Relaxed);
+    let id = entry.id.load(Ordering::Relaxed);
+    core::sync::atomic::fence(Ordering::Acquire);
+    if entry.stamp.load(Ordering::Relaxed) != stamp {
+        return None;
+    }
+    Some(Event {
+        seq: stamp / 2 - 1,
+        time,
+        kind: EventKind::ALL[kind as usize],
+        id,
+    })
+}
+
+/// 按从旧到新的顺序对缓冲区中的每个事件调用`f`
+///
+/// 读出期间仍可记录事件，此时被覆盖的事件被跳过。
+pub fn for_each(mut f: impl FnMut(Event)) {
+    let next = NEXT.load(Ordering::Acquire);
+    let first = next.saturating_sub(CAPACITY as u64);
+    for seq in first..next {
+        match read(&ENTRIES[(seq % CAPACITY as u64) as usize]) {
+            Some(event) if event.seq == seq => f(event),
+            _ => {}
+        }
+    }
+}
+
+/// 将缓冲区中的事件按从旧到新的顺序写入`out`，返回写入的数量
+pub fn snapshot(out: &mut [Event]) -> usize {
+    let mut len = 0;
+    for_each(|event| {
+        if len < out.len() {
+            out[len] = event;
+            len += 1;
+        }
+    });
+    len
+}
+
+/// 将缓冲区中的事件按从旧到新的顺序逐行输出
+pub fn dump(w: &mut impl fmt::Write) -> fmt::Result {
+    let mut res = Ok(());
+    for_each(|event| {
+        if res.is_ok() {
+            res = writeln!(w, "{}", event);
+        }
+    });
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec::Vec};
+
+    #[test]
+    fn test_event_log() {
+        // 其他测试也会记录事件，因此只检查本测试的id
+        let id = 0xff00_0000_0000_0320;
+        for _ in 0..CAPACITY {
+            record(EventKind::Notify, id);
+        }
+        record(EventKind::Wake, id);
+        record(EventKind::Release, id);
+        let mut events = Vec::new();
+        for_each(|event| events.push(event));
+        assert!(events.len() <= CAPACITY);
+        assert!(events.windows(2).all(|w| w[0].seq < w[1].seq));
+        let ours: Vec<_> = events
+            .iter()
+            .filter(|e| e.id == id)
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            &ours[ours.len() - 2..],
+            [EventKind::Wake, EventKind::Release]
+        );
+
+        set_clock(|| 42);
+        record(EventKind::NewId, id);
+        let mut out = [events[0]; CAPACITY];
+        let len = snapshot(&mut out);
+        let last = out[..len].iter().rev().find(|e| e.id == id).unwrap();
+        assert_eq!((last.kind, last.time), (EventKind::NewId, 42));
+
+        let mut text = String::new();
+        dump(&mut text).unwrap();
+        assert!(text.contains("t=42 new_id unknown(0xff):800"));
+    }
+}
@@ -0,0 +1,277 @@
+//! 故障注入
+//!
+//! [`FaultInject<N>`]包装任意通知机制`N`，按照[`FaultPolicy`]以一定的概率丢弃、重复或延迟经其发送的通知，
+//! 用于检验构建在本库之上的协议能否容忍通知的合并与丢失。随机数生成器带有种子，相同的种子与发送序列产生相同的故障。
+//!
+//! 策略是全局的，对所有`FaultInject<N>`生效；未设置策略时通知被原样发送。
+//! 延迟以发送次数计：被延迟的通知在其后经本模块发送了若干个通知之后才被发出，从而与之后的通知乱序；
+//! 调用[`flush`]立即发出所有被延迟的通知。
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    marker::PhantomData,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{interface::NotificationIf, rng::Rng, sync::SpinMutex};
+
+/// 故障注入的策略，各概率的取值范围为`[0, 1]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultPolicy {
+    /// 随机数生成器的种子
+    pub seed: u64,
+    /// 丢弃通知的概率
+    pub drop: f64,
+    /// 重复发送通知的概率
+    pub duplicate: f64,
+    /// 延迟通知的概率
+    pub delay: f64,
+    /// 被延迟的通知最多在其后发送多少个通知之后发出，至少为1
+    pub max_delay: u32,
+}
+
+impl Default for FaultPolicy {
+    /// 不注入任何故障
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            drop: 0.0,
+            duplicate: 0.0,
+            delay: 0.0,
+            max_delay: 1,
+        }
+    }
+}
+
+/// 已注入的故障数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultCounters {
+    /// 被丢弃的通知数量
+    pub dropped: u64,
+    /// 被重复发送的通知数量
+    pub duplicated: u64,
+    /// 被延迟的通知数量
+    pub delayed: u64,
+}
+
+/// 被延迟的通知
+#[derive(Clone, Copy)]
+struct Delayed {
+    /// 还需经本模块发送多少个通知
+    remaining: u32,
+    send: fn(u64, u64),
+    process: u64,
+    id: u64,
+}
+
+struct State {
+    policy: FaultPolicy,
+    rng: Rng,
+    counters: FaultCounters,
+    delayed: Vec<Delayed>,
+}
+
+static STATE: SpinMutex<Option<State>> = SpinMutex::new(None);
+
+/// 设置故障注入的策略，并以其种子重建随机数生成器、清零计数
+///
+/// 此前被延迟的通知被立即发出。
+pub fn set_policy(policy: FaultPolicy) {
+    assert!(policy.max_delay >= 1);
+    flush();
+    *STATE.lock() = Some(State {
+        policy,
+        rng: Rng::new(policy.seed),
+        counters: FaultCounters::default(),
+        delayed: Vec::new(),
+    });
+}
+
+/// 取消故障注入，此前被延迟的通知被立即发出
+pub fn clear_policy() {
+    flush();
+    *STATE.lock() = None;
+}
+
+/// 已注入的故障数量
+pub fn counters() -> FaultCounters {
+    STATE
+        .lock()
+        .as_ref()
+        .map_or_else(FaultCounters::default, |state| state.counters)
+}
+
+/// 立即发出所有被延迟的通知，返回发出的数量
+pub fn flush() -> usize {
+    let delayed = STATE
+        .lock()
+        .as_mut()
+        .map_or_else(Vec::new, |state| core::mem::take(&mut state.delayed));
+    for delayed in &delayed {
+        (delayed.send)(delayed.process, delayed.id);
+    }
+    delayed.len()
+}
+
+/// 按照策略发送通知
+fn inject(send: fn(u64, u64), process: u64, id: u64) {
+    // 在锁外发送，以免`send`中的钩子等再次进入本模块
+    let mut now = Vec::new();
+    {
+        let mut state = STATE.lock();
+        let Some(state) = state.as_mut() else {
+            drop(state);
+            send(process, id);
+            return;
+        };
+        let policy = state.policy;
+        if state.rng.chance(policy.drop) {
+            state.counters.dropped += 1;
+        } else if state.rng.chance(policy.delay) {
+            state.counters.delayed += 1;
+            let remaining = state.rng.range(1, policy.max_delay as u64) as u32;
+            state.delayed.push(Delayed {
+                remaining,
+                send,
+                process,
+                id,
+            });
+            // 不计入本次发送
+            return;
+        } else {
+            let notice = Delayed {
+                remaining: 0,
+                send,
+                process,
+                id,
+            };
+            if state.rng.chance(policy.duplicate) {
+                state.counters.duplicated += 1;
+                now.push(notice);
+            }
+            now.push(notice);
+        }
+        // 本次发送之后到期的延迟通知
+        state.delayed.retain_mut(|delayed| {
+            delayed.remaining -= 1;
+            if delayed.remaining == 0 {
+                now.push(*delayed);
+            }
+            delayed.remaining > 0
+        });
+    }
+    for delayed in now {
+        (delayed.send)(delayed.process, delayed.id);
+    }
+}
+
+/// 向`N`注入故障的包装，等待与申请、释放通知源的操作被原样转发
+pub struct FaultInject<N: NotificationIf> {
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<N: NotificationIf> fmt::Debug for FaultInject<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultInject<{}>", core::any::type_name::<N>())
+    }
+}
+
+impl<N: NotificationIf> NotificationIf for FaultInject<N> {
+    fn new_id() -> Option<u64> {
+        N::new_id()
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        N::poll_wait(id, cx)
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        N::register_waker(id, waker)
+    }
+
+    unsafe fn release_id(id: u64) {
+        unsafe { N::release_id(id) }
+    }
+
+    /// 按照策略丢弃、重复或延迟通知
+    fn notify(process: u64, id: u64) {
+        inject(N::notify, process, id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// 测试用的通知机制：记录收到的通知
+    struct Recorder;
+
+    static RECEIVED: SpinMutex<Vec<u64>> = SpinMutex::new(Vec::new());
+
+    impl NotificationIf for Recorder {
+        fn new_id() -> Option<u64> {
+            Some(0)
+        }
+
+        fn poll_wait(_id: u64, _cx: &mut Context<'_>) -> Poll<()> {
+            Poll::Ready(())
+        }
+
+        fn register_waker(_id: u64, waker: &Waker) {
+            waker.wake_by_ref();
+        }
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, id: u64) {
+            RECEIVED.lock().push(id);
+        }
+    }
+
+    type Faulty = FaultInject<Recorder>;
+
+    fn run(policy: FaultPolicy) -> (Vec<u64>, FaultCounters) {
+        set_policy(policy);
+        RECEIVED.lock().clear();
+        for id in 0..100 {
+            Faulty::notify(0, id);
+        }
+        let counters = counters();
+        flush();
+        (core::mem::take(&mut *RECEIVED.lock()), counters)
+    }
+
+    #[test]
+    fn test_fault_inject() {
+        let policy = FaultPolicy {
+            seed: 3,
+            drop: 0.1,
+            duplicate: 0.1,
+            delay: 0.1,
+            max_delay: 5,
+        };
+        let (received, counters) = run(policy);
+        // 相同的种子产生相同的故障
+        assert_eq!(run(policy), (received.clone(), counters));
+        assert!(counters.dropped > 0 && counters.duplicated > 0 && counters.delayed > 0);
+        assert_eq!(
+            received.len() as u64,
+            100 - counters.dropped + counters.duplicated
+        );
+        assert!(received.windows(2).any(|w| w[0] > w[1]));
+
+        let (received, counters) = run(FaultPolicy {
+            drop: 1.0,
+            ..FaultPolicy::default()
+        });
+        assert!(received.is_empty());
+        assert_eq!(counters.dropped, 100);
+
+        clear_policy();
+        RECEIVED.lock().clear();
+        Faulty::notify(0, 7);
+        assert_eq!(*RECEIVED.lock(), [7]);
+    }
+}
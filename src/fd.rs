@@ -0,0 +1,296 @@
+//! 基于文件描述符的通知机制的公共部分
+
+extern crate std;
+
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+#[cfg(any(
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::{AsRawFd, RawFd};
+
+#[cfg(feature = "kvm")]
+use crate::interface::WakeReason;
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+use crate::sync::SpinMutex;
+
+/// 拥有一个文件描述符，并在被丢弃时关闭它
+pub(crate) struct OwnedRawFd(pub(crate) RawFd);
+
+impl AsRawFd for OwnedRawFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+impl OwnedRawFd {
+    /// 转换为标准库的`OwnedFd`，之后由其负责关闭
+    pub(crate) fn into_owned(self) -> std::os::fd::OwnedFd {
+        let fd = self.0;
+        core::mem::forget(self);
+        unsafe { std::os::fd::FromRawFd::from_raw_fd(fd) }
+    }
+}
+
+/// 从`map`中取出id对应的通知源的唯一所有权
+///
+/// 若通知源正被其他线程使用（例如正在轮询），则将其放回并返回`None`。
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+pub(crate) fn take_unique<T>(map: &SpinMutex<BTreeMap<u64, Arc<T>>>, id: u64) -> Option<T> {
+    let mut map = map.lock();
+    let slot = map.remove(&id)?;
+    match Arc::try_unwrap(slot) {
+        Ok(slot) => Some(slot),
+        Err(slot) => {
+            map.insert(id, slot);
+            None
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "signalfd",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+/// 阻塞等待时每次`poll`的最长时间，使等待期间被释放的通知源能够被察觉
+const BLOCKING_SLICE_MS: u64 = 50;
+
+/// 阻塞当前线程，直到`take`消费了一个通知、通知源被释放（`slot`返回`None`）或超时
+///
+/// 每次`take`失败后在文件描述符上`poll`至可读，返回是否在超时前结束等待。
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "signalfd",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+pub(crate) fn wait_blocking<S>(
+    timeout: Option<core::time::Duration>,
+    slot: impl Fn() -> Option<S>,
+    fd: impl Fn(&S) -> RawFd,
+    take: impl Fn(&S) -> bool,
+) -> bool {
+    let deadline = timeout.map(|timeout| std::time::Instant::now() + timeout);
+    loop {
+        let Some(slot) = slot() else {
+            return true;
+        };
+        if take(&slot) {
+            return true;
+        }
+        let slice = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    return false;
+                }
+                // 向上取整，以免在剩余不足1ms时空转
+                (remaining.as_micros() as u64).saturating_add(999) / 1000
+            }
+            None => BLOCKING_SLICE_MS,
+        }
+        .min(BLOCKING_SLICE_MS);
+        let mut pollfd = libc::pollfd {
+            fd: fd(&slot),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pollfd, 1, slice as libc::c_int) };
+    }
+}
+
+impl Drop for OwnedRawFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+/// 以文件描述符可读表示收到通知的通知源
+///
+/// `drain`读出文件描述符上已到达的全部通知并返回其数量，文件描述符需为非阻塞的。
+#[cfg(any(
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+pub(crate) struct ReadySlot {
+    fd: tokio::io::unix::AsyncFd<OwnedRawFd>,
+    /// 已从文件描述符中读出、但尚未被`poll_wait`消费的通知数量
+    pending: AtomicU64,
+    drain: fn(RawFd) -> u64,
+}
+
+#[cfg(any(
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+impl ReadySlot {
+    /// 将文件描述符注册到tokio的reactor中，需要在tokio运行时内部调用
+    pub(crate) fn new(fd: OwnedRawFd, drain: fn(RawFd) -> u64) -> Option<Self> {
+        Some(Self {
+            fd: tokio::io::unix::AsyncFd::new(fd).ok()?,
+            pending: AtomicU64::new(0),
+            drain,
+        })
+    }
+
+    /// 从tokio的reactor中注销，并取回文件描述符
+    pub(crate) fn into_fd(self) -> OwnedRawFd {
+        self.fd.into_inner()
+    }
+
+    /// 文件描述符
+    pub(crate) fn raw_fd(&self) -> RawFd {
+        self.fd.get_ref().0
+    }
+
+    /// 读出已到达的通知，返回是否有新的通知
+    fn drain(&self) -> bool {
+        match (self.drain)(self.raw_fd()) {
+            0 => false,
+            count => {
+                self.pending.fetch_add(count, Ordering::AcqRel);
+                true
+            }
+        }
+    }
+
+    #[cfg(any(
+        feature = "vsock",
+        feature = "uds",
+        feature = "netlink",
+        feature = "net",
+        feature = "pipe"
+    ))]
+    pub(crate) fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if self
+                .pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+            {
+                return Poll::Ready(());
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(())),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.drain() {
+                guard.clear_ready();
+            }
+        }
+    }
+
+    /// 与`poll_wait`相同，但一次消费所有已读出的通知，并报告其数量
+    #[cfg(feature = "kvm")]
+    pub(crate) fn poll_wait_all(&self, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        loop {
+            match self.pending.swap(0, Ordering::AcqRel) {
+                0 => {}
+                count => {
+                    return Poll::Ready(WakeReason::Notified {
+                        count: count as usize,
+                    });
+                }
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(WakeReason::Shutdown)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.drain() {
+                guard.clear_ready();
+            }
+        }
+    }
+
+    /// 若有通知则消费一个，不阻塞
+    pub(crate) fn take(&self) -> bool {
+        let take = || {
+            self.pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+        };
+        take() || (self.drain() && take())
+    }
+
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        if self.pending.load(Ordering::Acquire) > 0 {
+            waker.wake_by_ref();
+            return;
+        }
+        let mut cx = Context::from_waker(waker);
+        if let Poll::Ready(guard) = self.fd.poll_read_ready(&mut cx) {
+            let mut guard = ready_guard!(guard, return waker.wake_by_ref());
+            if self.drain() {
+                waker.wake_by_ref();
+            } else {
+                guard.clear_ready();
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! C语言接口
+//!
+//! 导出`an_new_id`、`an_wait_blocking`、`an_notify`与`an_release`，使同一IPC系统中的C与C++程序可以申请通知源，
+//! 并与使用本crate的Rust程序互相发送通知。声明见`include/async_notification.h`。
+//!
+//! # 所有权
+//!
+//! - `an_new_id`申请的通知源归调用进程所有，只有该进程可以在其上等待，并须恰好调用一次`an_release`释放；
+//! - id可以经IPC交给其他进程，其他进程只能以其调用`an_notify`；
+//! - `an_release`之后id不能再被使用，包括不能再在其上等待。
+//!
+//! # 错误
+//!
+//! 所有函数成功时返回0，失败时返回负的errno：
+//!
+//! - `-EINVAL`：参数无效，或id的类型未知、通知源未被占用；
+//! - `-EAGAIN`：没有可用的通知源，或接收方的通知队列已满；
+//! - `-ETIMEDOUT`：等待超时；
+//! - `-ENOTSUP`：该类型的通知源不能由其他进程发送通知；
+//! - `-EPERM`：通知被发送钩子拒绝；
+//! - 发送失败时的其他errno；
+//! - `-EIO`：内部错误。
+//!
+//! 通知源按照[`policy`](crate::policy)选择通知机制。大部分通知机制需要tokio运行时，
+//! 本模块在首次调用时创建一个只有一个工作线程的运行时，用于申请、驱动与释放通知源，因此调用方不需要异步运行时。
+
+extern crate std;
+
+use core::time::Duration;
+use std::{
+    panic::{self, UnwindSafe},
+    sync::OnceLock,
+};
+
+use crate::interface::{Delivery, Notification, NotificationIf, NotifyError};
+
+/// 本模块使用的tokio运行时
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("async-notification-ffi")
+            .enable_all()
+            .build()
+            .expect("failed to create tokio runtime")
+    })
+}
+
+/// 执行`f`，并将panic转换为`error`，以免panic跨越C语言栈帧
+fn catch(error: libc::c_int, f: impl FnOnce() -> libc::c_int + UnwindSafe) -> libc::c_int {
+    panic::catch_unwind(f).unwrap_or(-error)
+}
+
+/// 申请一个通知源，成功时将其id写入`id`
+///
+/// # Safety
+///
+/// `id`须为可写的指针。
+#[no_mangle]
+pub unsafe extern "C" fn an_new_id(id: *mut u64) -> libc::c_int {
+    if id.is_null() {
+        return -libc::EINVAL;
+    }
+    let new = panic::catch_unwind(|| {
+        let _guard = runtime().enter();
+        Notification::new_id_auto()
+    });
+    match new {
+        Ok(Some(new)) => {
+            unsafe { *id = new };
+            0
+        }
+        Ok(None) => -libc::EAGAIN,
+        Err(_) => -libc::EIO,
+    }
+}
+
+/// 阻塞调用线程，直到通知源上有通知
+///
+/// `timeout_ms`为负数时不超时。超时返回`-ETIMEDOUT`。
+#[no_mangle]
+pub extern "C" fn an_wait_blocking(id: u64, timeout_ms: i64) -> libc::c_int {
+    if !Notification::is_valid(id) {
+        return -libc::EINVAL;
+    }
+    catch(libc::EIO, || {
+        let reason = match u64::try_from(timeout_ms) {
+            Ok(timeout_ms) => {
+                Notification::wait_on_blocking_timeout(id, Duration::from_millis(timeout_ms))
+            }
+            Err(_) => Notification::wait_on_blocking(id),
+        };
+        if reason.is_notified() {
+            0
+        } else {
+            -libc::ETIMEDOUT
+        }
+    })
+}
+
+/// 向进程`process`的通知源`id`发送通知
+#[no_mangle]
+pub extern "C" fn an_notify(process: u64, id: u64) -> libc::c_int {
+    if !Notification::is_valid(id) {
+        return -libc::EINVAL;
+    }
+    catch(libc::EIO, || {
+        match Notification::notify_with(process, id, Delivery::Reliable) {
+            Ok(()) => 0,
+            Err(NotifyError::Overflow | NotifyError::Full) => -libc::EAGAIN,
+            Err(NotifyError::Unsupported) => -libc::ENOTSUP,
+            Err(NotifyError::Os(errno)) => -errno,
+            Err(NotifyError::Rejected) => -libc::EPERM,
+        }
+    })
+}
+
+/// 释放由`an_new_id`申请的通知源
+///
+/// 通知源未被占用（包括已被释放）时返回`-EINVAL`。
+/// 启用`no-panic`时，各通知机制不再通过panic报告重复释放，此时只输出错误日志并返回0。
+#[no_mangle]
+pub extern "C" fn an_release(id: u64) -> libc::c_int {
+    if !Notification::is_valid(id) {
+        return -libc::EINVAL;
+    }
+    // 释放未被占用的通知源时，各通知机制通过panic报告错误
+    catch(libc::EINVAL, || {
+        let _guard = runtime().enter();
+        unsafe { Notification::release_id(id) };
+        0
+    })
+}
+
+#[cfg(all(test, feature = "kvm"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        let mut id = 0;
+        assert_eq!(unsafe { an_new_id(core::ptr::null_mut()) }, -libc::EINVAL);
+        assert_eq!(unsafe { an_new_id(&mut id) }, 0);
+        assert!(Notification::is_valid(id));
+        let pid = unsafe { libc::getpid() } as u64;
+        assert_eq!(an_wait_blocking(id, 10), -libc::ETIMEDOUT);
+        assert_eq!(an_notify(pid, id), 0);
+        assert_eq!(an_wait_blocking(id, 1000), 0);
+        assert_eq!(an_release(id), 0);
+        assert_eq!(an_notify(pid, 0), -libc::EINVAL);
+        assert_eq!(an_release(0), -libc::EINVAL);
+    }
+}
@@ -0,0 +1,153 @@
+//! 按发送方或令牌过滤通知
+//!
+//! 任何能向本进程发送信号或数据报的进程都能伪造通知。可以为通知源设置[`SenderFilter`]，
+//! 只接受来自给定进程或用户的通知，见[`Notification::set_sender_filter`]；
+//! 也可以为通知源生成一个随机的64位令牌，只接受带有该令牌的通知，见[`Notification::mint_token`]。
+//! 接收方将令牌告知被授权的发送方，发送方通过[`Notification::notify_with_token`]发送通知。
+//! 两者同时设置时，通知需同时满足。不满足的通知被丢弃并计数。
+//!
+//! 只有由内核提供发送方身份、且通知能携带数据的通知机制支持过滤：
+//! `signalfd`使用`ssi_pid`与`ssi_uid`，令牌由`sigqueue`的附加值携带；`uds`使用`SCM_CREDENTIALS`，令牌由数据报携带。
+//! `signal`的接收由signal-hook完成，同一信号的多个实例被合并，无法得到每个通知的发送方与附加值，因此不支持过滤。
+//!
+//! 令牌只能防止误发或猜测id的恶意通知，不能防止能读取本进程内存或截获令牌的对端。
+//!
+//! [`Notification::set_sender_filter`]: crate::interface::Notification::set_sender_filter
+//! [`Notification::mint_token`]: crate::interface::Notification::mint_token
+//! [`Notification::notify_with_token`]: crate::interface::Notification::notify_with_token
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sync::SpinMutex;
+
+/// 允许的发送方，进程号或用户id之一在其中的发送方被允许
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SenderFilter {
+    pids: Vec<u32>,
+    uids: Vec<u32>,
+}
+
+impl SenderFilter {
+    /// 不允许任何发送方的过滤器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 允许进程号为`pid`的发送方
+    pub fn allow_pid(mut self, pid: u32) -> Self {
+        self.pids.push(pid);
+        self
+    }
+
+    /// 允许用户id为`uid`的发送方
+    pub fn allow_uid(mut self, uid: u32) -> Self {
+        self.uids.push(uid);
+        self
+    }
+
+    /// 是否允许进程号为`pid`、用户id为`uid`的发送方
+    pub fn allows(&self, pid: u32, uid: u32) -> bool {
+        self.pids.contains(&pid) || self.uids.contains(&uid)
+    }
+}
+
+/// 生成一个非零的随机令牌，无法取得随机数时返回`None`
+pub(crate) fn mint() -> Option<u64> {
+    loop {
+        let mut token = 0u64;
+        let size = core::mem::size_of::<u64>();
+        let res = unsafe { libc::getrandom(&mut token as *mut u64 as *mut libc::c_void, size, 0) };
+        if res != size as isize {
+            return None;
+        }
+        if token != 0 {
+            return Some(token);
+        }
+    }
+}
+
+/// 通知源上的过滤条件
+struct Rules {
+    sender: Option<SenderFilter>,
+    token: Option<u64>,
+}
+
+/// 通知源的过滤条件，以及被其丢弃的通知数
+pub(crate) struct FilterSlot {
+    rules: SpinMutex<Rules>,
+    dropped: AtomicU64,
+}
+
+impl FilterSlot {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rules: SpinMutex::new(Rules {
+                sender: None,
+                token: None,
+            }),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// 替换发送方过滤器，`None`表示接受所有发送方；被丢弃的通知数不被清零
+    pub(crate) fn set(&self, filter: Option<SenderFilter>) {
+        self.rules.lock().sender = filter;
+    }
+
+    /// 替换要求的令牌，`None`表示不要求令牌
+    pub(crate) fn set_token(&self, token: Option<u64>) {
+        self.rules.lock().token = token;
+    }
+
+    /// 被丢弃的通知数
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 检查通知的发送方`(pid, uid)`与其携带的令牌，不满足时计数并返回`false`
+    ///
+    /// 发送方未知（`None`）的通知只在未设置发送方过滤器时被接受，未携带令牌的通知只在不要求令牌时被接受。
+    pub(crate) fn accept(&self, sender: Option<(u32, u32)>, token: Option<u64>) -> bool {
+        let rules = self.rules.lock();
+        let sender_ok = match (&rules.sender, sender) {
+            (None, _) => true,
+            (Some(filter), Some((pid, uid))) => filter.allows(pid, uid),
+            (Some(_), None) => false,
+        };
+        let accepted = sender_ok && (rules.token.is_none() || rules.token == token);
+        drop(rules);
+        if !accepted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        accepted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_slot() {
+        let slot = FilterSlot::new();
+        assert!(slot.accept(None, None));
+        slot.set(Some(SenderFilter::new().allow_pid(10).allow_uid(1000)));
+        assert!(slot.accept(Some((10, 0)), None));
+        assert!(slot.accept(Some((11, 1000)), None));
+        assert!(!slot.accept(Some((11, 0)), None));
+        assert!(!slot.accept(None, None));
+        slot.set(None);
+        assert!(slot.accept(Some((11, 0)), None));
+        assert_eq!(slot.dropped(), 2);
+
+        let token = mint().unwrap();
+        slot.set_token(Some(token));
+        assert!(slot.accept(None, Some(token)));
+        assert!(!slot.accept(None, None));
+        assert!(!slot.accept(None, Some(token ^ 1)));
+        slot.set_token(None);
+        assert!(slot.accept(None, Some(token ^ 1)));
+        assert_eq!(slot.dropped(), 4);
+    }
+}
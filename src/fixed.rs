@@ -0,0 +1,368 @@
+//! 不需要分配内存的通知机制
+//!
+//! 不启用`alloc`时（`default-features = false`且不启用任何通知机制），本crate只提供本模块与
+//! [`NotificationIf`](crate::interface::NotificationIf)等接口，可用于没有堆的嵌入式或内核环境。
+//!
+//! [`StaticPool`]的容量由const泛型给出，通知源的状态保存在固定大小的数组中，可以在静态变量中构造；
+//! [`static_notification!`](crate::static_notification)以一个静态的池定义实现了`NotificationIf`的通知机制。
+//! 通知只能在同一地址空间内发送（`process`被忽略），例如由中断处理程序或另一个核心调用`notify`：
+//! `notify`只进行原子操作，不获取锁，可以在中断上下文中调用。
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, AtomicU32, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+/// 通知源未被占用
+const SLOT_FREE: u8 = 0;
+/// 通知源已被占用
+const SLOT_USED: u8 = 1;
+
+/// 没有进行中的注册或唤醒
+const WAKER_IDLE: u8 = 0;
+/// 正在注册waker
+const WAKER_REGISTERING: u8 = 1;
+/// 正在唤醒，或注册期间有唤醒到达
+const WAKER_WAKING: u8 = 2;
+
+/// 不需要分配内存的`AtomicWaker`
+///
+/// 注册与唤醒争用时由状态位裁决：注册期间到达的唤醒由注册者在注册完成后代为执行，唤醒者从不等待。
+pub(crate) struct StaticWaker {
+    state: AtomicU8,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// `waker`只由将`state`从`WAKER_IDLE`改为`WAKER_REGISTERING`或`WAKER_WAKING`的一方访问
+unsafe impl Sync for StaticWaker {}
+
+impl StaticWaker {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(WAKER_IDLE),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(
+            WAKER_IDLE,
+            WAKER_REGISTERING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                let slot = unsafe { &mut *self.waker.get() };
+                if !slot.as_ref().is_some_and(|old| old.will_wake(waker)) {
+                    *slot = Some(waker.clone());
+                }
+                if self
+                    .state
+                    .compare_exchange(
+                        WAKER_REGISTERING,
+                        WAKER_IDLE,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_err()
+                {
+                    // 注册期间有唤醒到达，由注册者代为唤醒
+                    let waker = slot.take();
+                    self.state.store(WAKER_IDLE, Ordering::Release);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            // 正在被唤醒：直接唤醒新的waker，使调用者再次轮询
+            Err(_) => waker.wake_by_ref(),
+        }
+    }
+
+    pub(crate) fn wake(&self) {
+        // 状态不为`WAKER_IDLE`时，由注册者或另一个唤醒者处理
+        if self.state.fetch_or(WAKER_WAKING, Ordering::AcqRel) == WAKER_IDLE {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKER_WAKING, Ordering::Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// 一个通知源的状态
+struct Slot {
+    state: AtomicU8,
+    /// 尚未被`poll_wait`消费的通知数量
+    pending: AtomicU32,
+    waker: StaticWaker,
+}
+
+impl Slot {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Slot = Slot {
+        state: AtomicU8::new(SLOT_FREE),
+        pending: AtomicU32::new(0),
+        waker: StaticWaker::new(),
+    };
+}
+
+/// 容量为`N`的通知源池，所有状态保存在固定大小的数组中
+///
+/// id即为通知源在池中的下标。
+pub struct StaticPool<const N: usize> {
+    slots: [Slot; N],
+}
+
+impl<const N: usize> StaticPool<N> {
+    /// 新建所有通知源均未被占用的池，可用于初始化静态变量
+    pub const fn new() -> Self {
+        Self {
+            slots: [Slot::INIT; N],
+        }
+    }
+
+    /// 池的容量
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 未被占用的通知源的数量
+    pub fn available(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.state.load(Ordering::Acquire) == SLOT_FREE)
+            .count()
+    }
+
+    /// 占用一个通知源，返回其id；均已被占用时返回`None`
+    pub fn alloc(&self) -> Option<u64> {
+        self.slots.iter().enumerate().find_map(|(index, slot)| {
+            slot.state
+                .compare_exchange(SLOT_FREE, SLOT_USED, Ordering::AcqRel, Ordering::Acquire)
+                .ok()
+                .map(|_| {
+                    slot.pending.store(0, Ordering::Release);
+                    index as u64
+                })
+        })
+    }
+
+    fn slot(&self, id: u64) -> Option<&Slot> {
+        self.slots.get(usize::try_from(id).ok()?)
+    }
+
+    /// 释放通知源，并唤醒正在等待的协程；通知源未被占用时返回`false`
+    pub fn release(&self, id: u64) -> bool {
+        let Some(slot) = self.slot(id) else {
+            return false;
+        };
+        if slot
+            .state
+            .compare_exchange(SLOT_USED, SLOT_FREE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return false;
+        }
+        slot.waker.wake();
+        true
+    }
+
+    /// 使通知源收到一个通知，唤醒在其上等待的协程；通知源未被占用时返回`false`
+    ///
+    /// 只进行原子操作，可以在中断上下文中调用。
+    pub fn notify(&self, id: u64) -> bool {
+        if !self.post(id) {
+            return false;
+        }
+        self.wake(id);
+        true
+    }
+
+    /// 使通知源收到一个通知，但不唤醒等待的协程；通知源未被占用时返回`false`
+    pub(crate) fn post(&self, id: u64) -> bool {
+        match self.slot(id) {
+            Some(slot) if slot.state.load(Ordering::Acquire) == SLOT_USED => {
+                slot.pending.fetch_add(1, Ordering::AcqRel);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 唤醒在通知源上等待的协程
+    pub(crate) fn wake(&self, id: u64) {
+        if let Some(slot) = self.slot(id) {
+            slot.waker.wake();
+        }
+    }
+
+    /// 消费一个通知；没有通知时注册`cx`中的waker。通知源未被占用时立即返回`Poll::Ready(())`
+    pub fn poll_wait(&self, id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(slot) = self.slot(id) else {
+            return Poll::Ready(());
+        };
+        let take = || {
+            slot.pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+                || slot.state.load(Ordering::Acquire) != SLOT_USED
+        };
+        if take() {
+            return Poll::Ready(());
+        }
+        slot.waker.register(cx.waker());
+        // 注册之前到达的通知
+        if take() {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// 注册waker；已有未被消费的通知或通知源未被占用时立即唤醒，不消费通知
+    pub fn register_waker(&self, id: u64, waker: &Waker) {
+        let ready = |slot: &Slot| {
+            slot.pending.load(Ordering::Acquire) > 0
+                || slot.state.load(Ordering::Acquire) != SLOT_USED
+        };
+        match self.slot(id) {
+            Some(slot) if !ready(slot) => {
+                slot.waker.register(waker);
+                if ready(slot) {
+                    waker.wake_by_ref();
+                }
+            }
+            _ => waker.wake_by_ref(),
+        }
+    }
+}
+
+impl<const N: usize> Default for StaticPool<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 定义一个使用静态[`StaticPool`](crate::fixed::StaticPool)、实现了`NotificationIf`的通知机制
+///
+/// ```
+/// async_notification::static_notification!(
+///     /// 由中断处理程序通知的通知机制
+///     pub IrqNotification, 16
+/// );
+/// ```
+#[macro_export]
+macro_rules! static_notification {
+    ($(#[$meta:meta])* $vis:vis $name:ident, $capacity:expr) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $name {
+            /// 保存通知源状态的静态池
+            pub fn pool() -> &'static $crate::fixed::StaticPool<{ $capacity }> {
+                static POOL: $crate::fixed::StaticPool<{ $capacity }> =
+                    $crate::fixed::StaticPool::new();
+                &POOL
+            }
+        }
+
+        impl $crate::interface::NotificationIf for $name {
+            fn new_id() -> ::core::option::Option<u64> {
+                Self::pool().alloc()
+            }
+
+            fn poll_wait(
+                id: u64,
+                cx: &mut ::core::task::Context<'_>,
+            ) -> ::core::task::Poll<()> {
+                Self::pool().poll_wait(id, cx)
+            }
+
+            fn register_waker(id: u64, waker: &::core::task::Waker) {
+                Self::pool().register_waker(id, waker)
+            }
+
+            unsafe fn release_id(id: u64) {
+                Self::pool().release(id);
+            }
+
+            /// `process`被忽略，只能在同一地址空间内通知
+            fn notify(_process: u64, id: u64) {
+                Self::pool().notify(id);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    extern crate std;
+
+    crate::static_notification!(TestNotification, 2);
+
+    #[test]
+    fn test_static_pool() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let pool = TestNotification::pool();
+        assert_eq!(pool.capacity(), 2);
+        let a = TestNotification::new_id().unwrap();
+        let b = TestNotification::new_id().unwrap();
+        assert_eq!(TestNotification::new_id(), None);
+        assert_eq!(pool.available(), 0);
+
+        assert!(TestNotification::poll_wait(a, &mut cx).is_pending());
+        TestNotification::notify(0, a);
+        TestNotification::notify(0, a);
+        assert!(TestNotification::poll_wait(a, &mut cx).is_ready());
+        assert!(TestNotification::poll_wait(a, &mut cx).is_ready());
+        assert!(TestNotification::poll_wait(a, &mut cx).is_pending());
+        assert!(!pool.notify(2));
+
+        unsafe { TestNotification::release_id(a) };
+        assert!(TestNotification::poll_wait(a, &mut cx).is_ready());
+        assert!(!pool.notify(a) && !pool.release(a));
+        assert_eq!(TestNotification::new_id(), Some(a));
+        assert!(TestNotification::poll_wait(a, &mut cx).is_pending());
+        unsafe {
+            TestNotification::release_id(a);
+            TestNotification::release_id(b);
+        }
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn test_static_waker_threads() {
+        use std::sync::Arc;
+
+        struct Flag(core::sync::atomic::AtomicBool);
+        impl std::task::Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        static POOL: StaticPool<1> = StaticPool::new();
+        let id = POOL.alloc().unwrap();
+        for _ in 0..1000 {
+            let flag = Arc::new(Flag(Default::default()));
+            let waker = Waker::from(flag.clone());
+            let mut cx = Context::from_waker(&waker);
+            let notifier = std::thread::spawn(move || assert!(POOL.notify(id)));
+            // 通知要么在轮询中被消费，要么唤醒已注册的waker
+            if POOL.poll_wait(id, &mut cx).is_pending() {
+                notifier.join().unwrap();
+                assert!(flag.0.load(Ordering::Acquire));
+                assert!(POOL.poll_wait(id, &mut cx).is_ready());
+            } else {
+                notifier.join().unwrap();
+            }
+        }
+        assert!(POOL.release(id));
+    }
+}
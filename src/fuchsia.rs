@@ -0,0 +1,312 @@
+//! 使用zircon eventpair的通知机制，用于Fuchsia
+//!
+//! 每个通知源对应一个eventpair：接收方持有其中一端，id即为该端的句柄；另一端由接收方通过channel等方式交给发送方，
+//! 发送方调用[`FuchsiaNotification::adopt_peer`]将其登记为发送用的id。`notify`在对端上置位`ZX_USER_SIGNAL_0`，
+//! 因此不需要知道接收方进程，`process`参数被忽略。
+//!
+//! 等待通过port进行：本模块在首次等待时启动一个线程，在port上等待所有通知源的信号，并唤醒相应的等待者。
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+#[allow(non_camel_case_types)]
+type zx_status_t = i32;
+#[allow(non_camel_case_types)]
+type zx_handle_t = u32;
+#[allow(non_camel_case_types)]
+type zx_signals_t = u32;
+#[allow(non_camel_case_types)]
+type zx_time_t = i64;
+
+const ZX_OK: zx_status_t = 0;
+const ZX_HANDLE_INVALID: zx_handle_t = 0;
+const ZX_TIME_INFINITE: zx_time_t = i64::MAX;
+const ZX_TIME_INFINITE_PAST: zx_time_t = i64::MIN;
+const ZX_EVENTPAIR_PEER_CLOSED: zx_signals_t = 1 << 2;
+const ZX_USER_SIGNAL_0: zx_signals_t = 1 << 24;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct zx_port_packet_t {
+    key: u64,
+    packet_type: u32,
+    status: zx_status_t,
+    payload: [u64; 4],
+}
+
+#[link(name = "zircon")]
+extern "C" {
+    fn zx_eventpair_create(
+        options: u32,
+        out0: *mut zx_handle_t,
+        out1: *mut zx_handle_t,
+    ) -> zx_status_t;
+    fn zx_object_signal(handle: zx_handle_t, clear: zx_signals_t, set: zx_signals_t)
+    -> zx_status_t;
+    fn zx_object_signal_peer(
+        handle: zx_handle_t,
+        clear: zx_signals_t,
+        set: zx_signals_t,
+    ) -> zx_status_t;
+    fn zx_object_wait_one(
+        handle: zx_handle_t,
+        signals: zx_signals_t,
+        deadline: zx_time_t,
+        observed: *mut zx_signals_t,
+    ) -> zx_status_t;
+    fn zx_object_wait_async(
+        handle: zx_handle_t,
+        port: zx_handle_t,
+        key: u64,
+        signals: zx_signals_t,
+        options: u32,
+    ) -> zx_status_t;
+    fn zx_port_create(options: u32, out: *mut zx_handle_t) -> zx_status_t;
+    fn zx_port_wait(
+        port: zx_handle_t,
+        deadline: zx_time_t,
+        packet: *mut zx_port_packet_t,
+    ) -> zx_status_t;
+    fn zx_handle_close(handle: zx_handle_t) -> zx_status_t;
+}
+
+/// 使用zircon eventpair的通知机制
+pub struct FuchsiaNotification;
+
+struct EventSlot {
+    /// 是否已在port上登记了等待
+    armed: AtomicBool,
+    /// 最近一次等待该通知源的waker
+    waker: SpinMutex<Option<Waker>>,
+}
+
+/// 接收方持有的eventpair端，以句柄为key
+static EVENTS: SpinMutex<BTreeMap<u64, Arc<EventSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 等待线程使用的port，`ZX_HANDLE_INVALID`表示尚未创建
+static PORT: SpinMutex<zx_handle_t> = SpinMutex::new(ZX_HANDLE_INVALID);
+
+impl NotificationIf for FuchsiaNotification {
+    /// 返回`None`
+    ///
+    /// 需要同时得到交给发送方的另一端，应使用`new_id_with_peer`。
+    fn new_id() -> Option<u64> {
+        None
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若对端已被关闭或通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        let Some(slot) = Self::slot(id) else {
+            return Poll::Ready(WakeReason::Shutdown);
+        };
+        *slot.waker.lock() = Some(cx.waker().clone());
+        if let Some(reason) = Self::take_signal(id) {
+            return Poll::Ready(reason);
+        }
+        Self::arm(id, &slot);
+        // 登记等待之前到达的信号不会产生port上的事件，因此再检查一次
+        if let Some(reason) = Self::take_signal(id) {
+            return Poll::Ready(reason);
+        }
+        Poll::Pending
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let Some(slot) = Self::slot(id) else {
+            waker.wake_by_ref();
+            return;
+        };
+        *slot.waker.lock() = Some(waker.clone());
+        if Self::observe(id) != 0 {
+            waker.wake_by_ref();
+            return;
+        }
+        Self::arm(id, &slot);
+    }
+
+    /// 关闭接收方持有的eventpair端
+    unsafe fn release_id(id: u64) {
+        let Some(slot) = EVENTS.lock().remove(&id) else {
+            fail!(return, "release_id: id 0x{:016x} is not allocated", id)
+        };
+        unsafe { zx_handle_close(id as zx_handle_t) };
+        let waker = slot.waker.lock().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// 在eventpair的对端置位`ZX_USER_SIGNAL_0`，`process`被忽略
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl FuchsiaNotification {
+    /// 在eventpair的对端置位`ZX_USER_SIGNAL_0`，`process`被忽略；失败时以`NotifyError::Os`返回zircon的状态码
+    pub fn try_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let res = unsafe { zx_object_signal_peer(id as zx_handle_t, 0, ZX_USER_SIGNAL_0) };
+        if res == ZX_OK {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(res))
+        }
+    }
+
+    /// 检查能否通过发送用的id发送通知，但不置位信号；`process`被忽略
+    ///
+    /// 以不改变任何信号的`zx_object_signal_peer`检查句柄是否有效、对端是否仍未关闭，失败时以`NotifyError::Os`返回zircon的状态码。
+    pub fn can_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let res = unsafe { zx_object_signal_peer(id as zx_handle_t, 0, 0) };
+        if res == ZX_OK {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(res))
+        }
+    }
+
+    /// 新建eventpair，返回接收方的id与应交给发送方的另一端句柄
+    pub fn new_id_with_peer() -> Option<(u64, u32)> {
+        let (mut local, mut peer) = (ZX_HANDLE_INVALID, ZX_HANDLE_INVALID);
+        if unsafe { zx_eventpair_create(0, &mut local, &mut peer) } != ZX_OK {
+            return None;
+        }
+        let slot = EventSlot {
+            armed: AtomicBool::new(false),
+            waker: SpinMutex::new(None),
+        };
+        EVENTS.lock().insert(local as u64, Arc::new(slot));
+        Some((local as u64, peer))
+    }
+
+    /// 将从接收方得到的eventpair端登记为发送用的id，之后应以该id调用`notify`
+    ///
+    /// 发送方不再发送通知时，应调用`release_peer`关闭该句柄，接收方的等待会因此结束。
+    pub fn adopt_peer(handle: u32) -> u64 {
+        handle as u64
+    }
+
+    /// 关闭发送方持有的eventpair端
+    pub fn release_peer(id: u64) {
+        unsafe { zx_handle_close(id as zx_handle_t) };
+    }
+
+    /// 通知源上当前被置位的信号
+    fn observe(id: u64) -> zx_signals_t {
+        let mut observed = 0;
+        unsafe {
+            zx_object_wait_one(
+                id as zx_handle_t,
+                ZX_USER_SIGNAL_0 | ZX_EVENTPAIR_PEER_CLOSED,
+                ZX_TIME_INFINITE_PAST,
+                &mut observed,
+            )
+        };
+        observed & (ZX_USER_SIGNAL_0 | ZX_EVENTPAIR_PEER_CLOSED)
+    }
+
+    /// 若通知源上有通知，则清除信号并返回`Notified`；对端被关闭且没有剩余的通知时返回`Shutdown`
+    fn take_signal(id: u64) -> Option<WakeReason> {
+        let observed = Self::observe(id);
+        if observed & ZX_USER_SIGNAL_0 != 0 {
+            unsafe { zx_object_signal(id as zx_handle_t, ZX_USER_SIGNAL_0, 0) };
+            Some(WakeReason::NOTIFIED)
+        } else if observed & ZX_EVENTPAIR_PEER_CLOSED != 0 {
+            Some(WakeReason::Shutdown)
+        } else {
+            None
+        }
+    }
+
+    /// 在port上登记一次对通知源的等待
+    fn arm(id: u64, slot: &EventSlot) {
+        if slot.armed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let port = Self::port();
+        let res = unsafe {
+            zx_object_wait_async(
+                id as zx_handle_t,
+                port,
+                id,
+                ZX_USER_SIGNAL_0 | ZX_EVENTPAIR_PEER_CLOSED,
+                0,
+            )
+        };
+        check!(res == ZX_OK, "zx_object_wait_async returned {}", res);
+    }
+
+    /// 获取port，首次调用时创建port并启动等待线程
+    fn port() -> zx_handle_t {
+        let mut port = PORT.lock();
+        if *port == ZX_HANDLE_INVALID {
+            let res = unsafe { zx_port_create(0, &mut *port) };
+            check!(res == ZX_OK, "zx_port_create returned {}", res);
+            let handle = *port;
+            std::thread::spawn(move || Self::run(handle));
+        }
+        *port
+    }
+
+    /// 等待线程：在port上等待，并唤醒收到信号的通知源的等待者
+    fn run(port: zx_handle_t) {
+        loop {
+            let mut packet = zx_port_packet_t {
+                key: 0,
+                packet_type: 0,
+                status: 0,
+                payload: [0; 4],
+            };
+            if unsafe { zx_port_wait(port, ZX_TIME_INFINITE, &mut packet) } != ZX_OK {
+                continue;
+            }
+            let Some(slot) = Self::slot(packet.key) else {
+                continue;
+            };
+            // 等待是一次性的，之后的等待需要重新登记
+            slot.armed.store(false, Ordering::Release);
+            let waker = slot.waker.lock().take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    fn slot(id: u64) -> Option<Arc<EventSlot>> {
+        EVENTS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+
+    #[test]
+    fn test_fuchsia_eventpair() {
+        futures::executor::block_on(async move {
+            let (id, peer) = Notification::new_id_fuchsia().unwrap();
+            let sender = Notification::adopt_fuchsia_peer(peer);
+            Notification::notify(0, sender);
+            Notification::wait_on(id).await;
+            Notification::release_fuchsia_peer(sender);
+            // 对端被关闭后，等待立即结束
+            Notification::wait_on(id).await;
+            unsafe { Notification::release_id(id) };
+        });
+    }
+}
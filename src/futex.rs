@@ -0,0 +1,296 @@
+//! 以`futex_waitv`同时等待多个futex字
+//!
+//! [`FutexWord`]是位于共享内存中的门铃：发送方以[`FutexWord::notify`]增加其值并唤醒等待者，不需要为每个接收方创建通知源。
+//! [`FutexSet`]记录至多[`MAX_WAITERS`]个futex字上次被观察到的值，[`FutexSet::wait_any`]阻塞调用线程直到其中任意一个改变，
+//! 由一个线程等待所有futex字，而不必为每个字各占用一个任务或线程。
+//!
+//! 内核（5.16及以后）支持`futex_waitv`时一次系统调用等待全部futex字；否则轮流在每个futex字上以`FUTEX_WAIT`等待一小段时间，
+//! 唤醒的延迟因此至多增加一轮。futex字不带`FUTEX_PRIVATE_FLAG`，可由其他进程经共享内存唤醒。
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicU8, AtomicU32, Ordering},
+    time::Duration,
+};
+
+use crate::interface::NotifyError;
+
+/// 一次`futex_waitv`最多等待的futex字数
+pub const MAX_WAITERS: usize = 128;
+
+/// `futex_waitv`的`flags`：32位的futex字
+const FUTEX2_SIZE_U32: u32 = 0x02;
+
+/// 不支持`futex_waitv`时在每个futex字上等待的时间
+const FALLBACK_SLICE: Duration = Duration::from_millis(1);
+
+/// 内核接口`struct futex_waitv`
+#[repr(C)]
+struct FutexWaitv {
+    val: u64,
+    uaddr: u64,
+    flags: u32,
+    reserved: u32,
+}
+
+/// 是否支持`futex_waitv`：0为未知，1为支持，2为不支持
+static WAITV: AtomicU8 = AtomicU8::new(0);
+
+fn errno() -> i32 {
+    unsafe { *libc::__errno_location() }
+}
+
+/// 当前时刻加上`timeout`，以`CLOCK_MONOTONIC`表示
+fn deadline(timeout: Duration) -> libc::timespec {
+    let mut now: libc::timespec = unsafe { core::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    let nanos = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+    libc::timespec {
+        tv_sec: now
+            .tv_sec
+            .saturating_add(timeout.as_secs().min(i32::MAX as u64) as libc::time_t)
+            + (nanos / 1_000_000_000) as libc::time_t,
+        tv_nsec: (nanos % 1_000_000_000) as _,
+    }
+}
+
+fn expired(deadline: &libc::timespec) -> bool {
+    let mut now: libc::timespec = unsafe { core::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    (now.tv_sec, now.tv_nsec) >= (deadline.tv_sec, deadline.tv_nsec)
+}
+
+/// 用作门铃的futex字
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FutexWord(AtomicU32);
+
+impl FutexWord {
+    /// 新建futex字
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// 将共享内存中的一个`u32`视为futex字
+    ///
+    /// # Safety
+    ///
+    /// `ptr`必须非空、按4字节对齐，且在`'a`内有效，并只被以原子操作访问。
+    pub unsafe fn from_ptr<'a>(ptr: *mut u32) -> &'a Self {
+        unsafe { &*(ptr as *const Self) }
+    }
+
+    /// 当前的值，每次`notify`加1
+    pub fn value(&self) -> u32 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// 增加futex字的值并唤醒所有等待者，返回被唤醒的线程数
+    pub fn notify(&self) -> Result<usize, NotifyError> {
+        self.0.fetch_add(1, Ordering::AcqRel);
+        let res =
+            unsafe { libc::syscall(libc::SYS_futex, self.0.as_ptr(), libc::FUTEX_WAKE, i32::MAX) };
+        if res < 0 {
+            return Err(NotifyError::Os(errno()));
+        }
+        Ok(res as usize)
+    }
+}
+
+/// 同时等待的一组futex字
+#[derive(Default)]
+pub struct FutexSet<'a> {
+    /// futex字与上次被观察到的值
+    words: Vec<(&'a FutexWord, u32)>,
+}
+
+impl<'a> FutexSet<'a> {
+    /// 新建空的集合
+    pub fn new() -> Self {
+        Self { words: Vec::new() }
+    }
+
+    /// 加入futex字，以其当前的值为已观察到的值，返回其在集合中的下标；已有[`MAX_WAITERS`]个时返回`NotifyError::Overflow`
+    pub fn push(&mut self, word: &'a FutexWord) -> Result<usize, NotifyError> {
+        if self.words.len() >= MAX_WAITERS {
+            return Err(NotifyError::Overflow);
+        }
+        self.words.push((word, word.value()));
+        Ok(self.words.len() - 1)
+    }
+
+    /// 集合中futex字的数量
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// 集合是否为空
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// 取走下标最小的、值自上次被观察以来改变了的futex字，不阻塞
+    pub fn ready(&mut self) -> Option<usize> {
+        self.words.iter_mut().position(|(word, seen)| {
+            let value = word.value();
+            value != core::mem::replace(seen, value)
+        })
+    }
+
+    /// 阻塞直到任意一个futex字的值改变，返回其下标；`timeout`内没有改变时返回`Ok(None)`，`None`表示不限时
+    ///
+    /// 多个futex字都已改变时返回下标最小的，其余的在之后的调用中返回。
+    pub fn wait_any(&mut self, timeout: Option<Duration>) -> Result<Option<usize>, NotifyError> {
+        let deadline = timeout.map(deadline);
+        loop {
+            if let Some(index) = self.ready() {
+                return Ok(Some(index));
+            }
+            if self.words.is_empty() || deadline.as_ref().is_some_and(expired) {
+                return Ok(None);
+            }
+            if WAITV.load(Ordering::Relaxed) != 2 {
+                match self.waitv(deadline.as_ref()) {
+                    Err(NotifyError::Os(libc::ENOSYS)) => WAITV.store(2, Ordering::Relaxed),
+                    result => {
+                        WAITV.store(1, Ordering::Relaxed);
+                        result?;
+                        continue;
+                    }
+                }
+            }
+            self.wait_each(deadline.as_ref())?;
+        }
+    }
+
+    /// 以一次`futex_waitv`等待所有futex字，被唤醒、值已改变或超时时返回`Ok(())`
+    fn waitv(&self, deadline: Option<&libc::timespec>) -> Result<(), NotifyError> {
+        let waiters: Vec<FutexWaitv> = self
+            .words
+            .iter()
+            .map(|(word, seen)| FutexWaitv {
+                val: *seen as u64,
+                uaddr: word.0.as_ptr() as u64,
+                flags: FUTEX2_SIZE_U32,
+                reserved: 0,
+            })
+            .collect();
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_futex_waitv,
+                waiters.as_ptr(),
+                waiters.len() as libc::c_uint,
+                0 as libc::c_uint,
+                deadline.map_or(core::ptr::null(), |deadline| deadline as *const _),
+                libc::CLOCK_MONOTONIC,
+            )
+        };
+        match (res, errno()) {
+            (res, _) if res >= 0 => Ok(()),
+            (_, libc::EAGAIN | libc::EINTR | libc::ETIMEDOUT) => Ok(()),
+            (_, errno) => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 轮流在每个futex字上等待一小段时间
+    fn wait_each(&self, deadline: Option<&libc::timespec>) -> Result<(), NotifyError> {
+        let slice = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: FALLBACK_SLICE.as_nanos() as _,
+        };
+        for (word, seen) in &self.words {
+            if word.value() != *seen || deadline.is_some_and(expired) {
+                return Ok(());
+            }
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    word.0.as_ptr(),
+                    libc::FUTEX_WAIT,
+                    *seen,
+                    &slice as *const libc::timespec,
+                )
+            };
+            if res == 0 {
+                return Ok(());
+            }
+            match errno() {
+                libc::EAGAIN | libc::EINTR | libc::ETIMEDOUT => {}
+                errno => return Err(NotifyError::Os(errno)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 当前内核是否支持`futex_waitv`；尚未调用过[`FutexSet::wait_any`]时进行一次探测
+pub fn waitv_supported() -> bool {
+    if WAITV.load(Ordering::Relaxed) == 0 {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_futex_waitv,
+                core::ptr::null::<FutexWaitv>(),
+                0 as libc::c_uint,
+                0 as libc::c_uint,
+                core::ptr::null::<libc::timespec>(),
+                libc::CLOCK_MONOTONIC,
+            )
+        };
+        let supported = res >= 0 || errno() != libc::ENOSYS;
+        WAITV.store(if supported { 1 } else { 2 }, Ordering::Relaxed);
+    }
+    WAITV.load(Ordering::Relaxed) == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+
+    #[test]
+    fn test_futex_wait_any() {
+        static WORDS: [FutexWord; 3] = [FutexWord::new(), FutexWord::new(), FutexWord::new()];
+        let mut set = FutexSet::new();
+        for word in &WORDS {
+            set.push(word).unwrap();
+        }
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.wait_any(Some(Duration::from_millis(5))), Ok(None));
+
+        let notifier = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(10));
+            WORDS[2].notify().unwrap();
+        });
+        assert_eq!(set.wait_any(None), Ok(Some(2)));
+        notifier.join().unwrap();
+
+        // 已改变的futex字按下标依次返回
+        WORDS[1].notify().unwrap();
+        WORDS[0].notify().unwrap();
+        assert_eq!(set.wait_any(None), Ok(Some(0)));
+        assert_eq!(set.wait_any(None), Ok(Some(1)));
+        assert_eq!(set.ready(), None);
+
+        // 不支持`futex_waitv`时退回逐个等待
+        let notifier = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(10));
+            WORDS[1].notify().unwrap();
+        });
+        while set.ready().is_none() {
+            set.wait_each(None).unwrap();
+        }
+        notifier.join().unwrap();
+        let _ = waitv_supported();
+    }
+
+    #[test]
+    fn test_futex_set_capacity() {
+        let word = FutexWord::new();
+        let mut set = FutexSet::new();
+        for _ in 0..MAX_WAITERS {
+            set.push(&word).unwrap();
+        }
+        assert_eq!(set.push(&word), Err(NotifyError::Overflow));
+    }
+}
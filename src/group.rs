@@ -0,0 +1,110 @@
+//! 向一组进程发送通知
+//!
+//! 配置重载、关闭等场景需要唤醒多个进程。[`NotificationGroup`]记录一组`(process, id)`目标，
+//! `notify_all`向每个目标发送通知，并报告发送失败的目标，而不会因部分目标失败而中止。
+
+use alloc::vec::Vec;
+
+use crate::interface::{Delivery, Notification, NotifyError};
+
+/// 一组通知目标
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationGroup {
+    /// `(process, id)`，不含重复项
+    targets: Vec<(u64, u64)>,
+}
+
+impl NotificationGroup {
+    /// 新建空的通知组
+    pub const fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+        }
+    }
+
+    /// 添加通知目标，若其已在组中，则返回`false`
+    pub fn add(&mut self, process: u64, id: u64) -> bool {
+        if self.targets.contains(&(process, id)) {
+            return false;
+        }
+        self.targets.push((process, id));
+        true
+    }
+
+    /// 移除通知目标，若其不在组中，则返回`false`
+    pub fn remove(&mut self, process: u64, id: u64) -> bool {
+        let len = self.targets.len();
+        self.targets.retain(|&target| target != (process, id));
+        self.targets.len() != len
+    }
+
+    /// 移除进程`process`的所有通知目标，返回被移除的数量
+    pub fn remove_process(&mut self, process: u64) -> usize {
+        let len = self.targets.len();
+        self.targets.retain(|&(p, _)| p != process);
+        len - self.targets.len()
+    }
+
+    /// 组中的所有通知目标，按添加顺序排列
+    pub fn targets(&self) -> &[(u64, u64)] {
+        &self.targets
+    }
+
+    /// 组中通知目标的数量
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// 组是否为空
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// 以可靠投递向组中的每个目标发送通知，返回发送失败的目标及其原因
+    pub fn notify_all(&self) -> Vec<((u64, u64), NotifyError)> {
+        self.notify_all_with(Delivery::Reliable)
+    }
+
+    /// 以指定的投递类别向组中的每个目标发送通知，返回发送失败的目标及其原因
+    ///
+    /// 每个目标都会被尝试一次，与其他目标是否失败无关。
+    pub fn notify_all_with(&self, delivery: Delivery) -> Vec<((u64, u64), NotifyError)> {
+        self.targets
+            .iter()
+            .filter_map(|&(process, id)| {
+                Notification::notify_with(process, id, delivery)
+                    .err()
+                    .map(|err| ((process, id), err))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_membership_and_errors() {
+        let mut group = NotificationGroup::new();
+        assert!(group.add(1, 0));
+        assert!(!group.add(1, 0));
+        assert!(group.add(1, 1));
+        assert!(group.add(2, 0));
+        assert!(group.remove(1, 1));
+        assert!(!group.remove(1, 1));
+        assert_eq!(group.targets(), &[(1, 0), (2, 0)]);
+
+        // 高8位为0的id不属于任何通知机制，每个目标都单独报告失败
+        let failures = group.notify_all();
+        assert_eq!(
+            failures,
+            alloc::vec![
+                ((1, 0), NotifyError::Unsupported),
+                ((2, 0), NotifyError::Unsupported)
+            ]
+        );
+        assert_eq!(group.remove_process(1), 1);
+        assert_eq!(group.len(), 1);
+    }
+}
@@ -0,0 +1,330 @@
+//! 基于内核原语的通知机制
+//!
+//! [`SyscallIf`]抽象了申请、释放、发送与非阻塞接收通知对象等原语，[`HalNotification`]在其上实现[`NotificationIf`]。
+//! 内核与微内核环境（例如seL4的notification对象、自定义的hypercall）只需实现[`SyscallIf`]，而无需修改已有的通知机制。
+//!
+//! 以seL4为例：`send`对应`seL4_Signal`，`try_recv`对应`seL4_Poll`；
+//! 一个专门的线程在绑定的notification上`seL4_Wait`，收到通知后调用[`HalNotification::on_notify`]唤醒等待者。
+
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    fixed::StaticWaker,
+    interface::{NotificationIf, NotifyError},
+};
+
+/// [`HalSlots`]能同时容纳的通知对象数
+pub const HAL_SLOTS: usize = 64;
+
+/// 未被占用的槽位，通知对象编号的高8位被保留，不会与之冲突
+const EMPTY: u64 = u64::MAX;
+
+struct HalSlot {
+    obj: AtomicU64,
+    waker: StaticWaker,
+}
+
+/// 每个通知对象的等待者，由[`SyscallIf`]的实现者以静态变量的形式提供
+///
+/// 槽位预先分配，查找与唤醒都不加锁、不分配内存，因此[`HalNotification::on_notify`]可在中断上下文中调用。
+/// 通知对象在第一次等待时占用一个槽位，直到被释放；槽位用尽时等待者退化为忙轮询。
+pub struct HalSlots {
+    slots: [HalSlot; HAL_SLOTS],
+}
+
+impl Default for HalSlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HalSlots {
+    /// 新建空的等待者表
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const SLOT: HalSlot = HalSlot {
+            obj: AtomicU64::new(EMPTY),
+            waker: StaticWaker::new(),
+        };
+        Self {
+            slots: [SLOT; HAL_SLOTS],
+        }
+    }
+
+    fn find(&self, obj: u64) -> Option<&HalSlot> {
+        self.slots
+            .iter()
+            .find(|slot| slot.obj.load(Ordering::Acquire) == obj)
+    }
+
+    fn register(&self, obj: u64, waker: &Waker) {
+        loop {
+            let Some(slot) = self.find(obj).or_else(|| self.claim(obj)) else {
+                // 槽位用尽：立即唤醒，由之后的轮询检查
+                waker.wake_by_ref();
+                return;
+            };
+            slot.waker.register(waker);
+            // 登记期间槽位可能已被并发的首次登记让出，此时waker不会再被唤醒，需重新登记
+            if self
+                .find(obj)
+                .is_some_and(|found| core::ptr::eq(found, slot))
+            {
+                return;
+            }
+        }
+    }
+
+    /// 为`obj`占用一个空槽位
+    ///
+    /// 查找与占用不是原子的，并发的首次登记可能各自占用一个槽位；
+    /// 此时只保留最靠前的槽位，因为`wake`与`remove`只处理第一个匹配的槽位。
+    fn claim(&self, obj: u64) -> Option<&HalSlot> {
+        let index = self.slots.iter().position(|slot| {
+            slot.obj
+                .compare_exchange(EMPTY, obj, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        })?;
+        match self.slots[..index]
+            .iter()
+            .find(|slot| slot.obj.load(Ordering::Acquire) == obj)
+        {
+            Some(earlier) => {
+                self.slots[index].obj.store(EMPTY, Ordering::Release);
+                Some(earlier)
+            }
+            None => Some(&self.slots[index]),
+        }
+    }
+
+    fn wake(&self, obj: u64) {
+        if let Some(slot) = self.find(obj) {
+            slot.waker.wake();
+        }
+    }
+
+    fn remove(&self, obj: u64) {
+        if let Some(slot) = self.find(obj) {
+            slot.obj.store(EMPTY, Ordering::Release);
+            slot.waker.wake();
+        }
+    }
+}
+
+/// 通知对象的内核原语
+///
+/// 通知对象由`u64`编号表示，其高8位需被保留。
+pub trait SyscallIf {
+    /// 申请一个通知对象
+    fn alloc() -> Option<u64>;
+    /// 释放通知对象
+    ///
+    /// # Safety
+    ///
+    /// 调用者需满足[`NotificationIf::release_id`]的安全条件。
+    unsafe fn free(obj: u64);
+    /// 向进程`process`的通知对象`obj`发送通知，失败时返回错误
+    fn send(process: u64, obj: u64) -> Result<(), NotifyError>;
+    /// 检查能否向进程`process`的通知对象`obj`发送通知，但不发送通知
+    ///
+    /// 默认实现总是返回`Ok(())`，适用于内核没有不发送通知的检查手段的环境。
+    fn can_send(process: u64, obj: u64) -> Result<(), NotifyError> {
+        let _ = (process, obj);
+        Ok(())
+    }
+    /// 非阻塞地检查通知对象，若有通知则消费一个通知并返回`true`
+    fn try_recv(obj: u64) -> bool;
+    /// 请求内核在通知对象收到通知时回调[`HalNotification::on_notify`]
+    ///
+    /// 每次回调之后都会在需要时重新调用本函数。默认实现为空，适用于内核在每次收到通知时都会回调的环境。
+    fn arm(obj: u64) {
+        let _ = obj;
+    }
+    /// 存放等待者的表
+    fn slots() -> &'static HalSlots;
+}
+
+/// 基于[`SyscallIf`]的通知机制
+pub struct HalNotification<S: SyscallIf> {
+    _marker: PhantomData<fn() -> S>,
+}
+
+impl<S: SyscallIf> HalNotification<S> {
+    /// 通知对象`obj`收到通知时由内核回调（例如在中断处理或upcall中），唤醒其等待者
+    ///
+    /// 该函数不消费通知，通知在等待者被唤醒后通过`try_recv`消费。不加锁、不分配内存，可在中断上下文中调用，
+    /// 但等待者的waker本身需能在中断上下文中唤醒。
+    pub fn on_notify(obj: u64) {
+        S::slots().wake(obj);
+    }
+
+    /// 向进程`process`的通知对象`id`发送通知，失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        S::send(process, id)
+    }
+
+    /// 检查能否向进程`process`的通知对象`id`发送通知，但不发送通知
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        S::can_send(process, id)
+    }
+}
+
+impl<S: SyscallIf> NotificationIf for HalNotification<S> {
+    fn new_id() -> Option<u64> {
+        S::alloc()
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        if S::try_recv(id) {
+            return Poll::Ready(());
+        }
+        S::slots().register(id, cx.waker());
+        S::arm(id);
+        // 登记等待者之前到达的通知不会触发回调，因此再检查一次
+        if S::try_recv(id) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// `try_recv`会消费通知，无法在不消费通知的情况下检查通知对象，因此总是立即唤醒waker，由之后的`poll_wait`检查
+    fn register_waker(id: u64, waker: &Waker) {
+        S::slots().register(id, waker);
+        S::arm(id);
+        waker.wake_by_ref();
+    }
+
+    /// 释放通知对象，并唤醒正在等待的协程
+    unsafe fn release_id(id: u64) {
+        unsafe { S::free(id) };
+        S::slots().remove(id);
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = S::send(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+
+    use alloc::collections::btree_map::BTreeMap;
+
+    use crate::sync::SpinMutex;
+
+    /// 测试用的内核：通知对象为计数器，发送时立即回调
+    struct TestSyscall;
+
+    static COUNTS: SpinMutex<BTreeMap<u64, usize>> = SpinMutex::new(BTreeMap::new());
+    static SLOTS: HalSlots = HalSlots::new();
+
+    impl SyscallIf for TestSyscall {
+        fn alloc() -> Option<u64> {
+            let mut counts = COUNTS.lock();
+            let obj = counts.keys().next_back().map_or(0, |obj| obj + 1);
+            counts.insert(obj, 0);
+            Some(obj)
+        }
+
+        unsafe fn free(obj: u64) {
+            COUNTS.lock().remove(&obj);
+        }
+
+        fn send(_process: u64, obj: u64) -> Result<(), NotifyError> {
+            *COUNTS.lock().get_mut(&obj).ok_or(NotifyError::Os(9))? += 1;
+            HalNotification::<TestSyscall>::on_notify(obj);
+            Ok(())
+        }
+
+        fn try_recv(obj: u64) -> bool {
+            let mut counts = COUNTS.lock();
+            match counts.get_mut(&obj) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                Some(_) => false,
+                // 已被释放
+                None => true,
+            }
+        }
+
+        fn slots() -> &'static HalSlots {
+            &SLOTS
+        }
+    }
+
+    type TestNotification = HalNotification<TestSyscall>;
+
+    #[test]
+    fn test_hal_notification() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = TestNotification::new_id().unwrap();
+                let waiter = tokio::spawn(TestNotification::wait_on(id));
+                tokio::task::yield_now().await;
+                assert!(!waiter.is_finished());
+                TestNotification::notify(0, id);
+                tokio::time::timeout(core::time::Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                let waker = futures::task::noop_waker();
+                let mut cx = Context::from_waker(&waker);
+                assert!(TestNotification::poll_wait(id, &mut cx).is_pending());
+                unsafe { TestNotification::release_id(id) };
+                assert!(TestNotification::poll_wait(id, &mut cx).is_ready());
+            });
+    }
+
+    /// 释放通知对象后其槽位可被之后的通知对象复用
+    #[test]
+    fn test_hal_slot_reuse() {
+        let notified = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        struct Flag(std::sync::Arc<std::sync::atomic::AtomicBool>);
+        impl std::task::Wake for Flag {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let waker = Waker::from(std::sync::Arc::new(Flag(notified.clone())));
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..HAL_SLOTS * 2 {
+            let id = TestNotification::new_id().unwrap();
+            assert!(TestNotification::poll_wait(id, &mut cx).is_pending());
+            notified.store(false, Ordering::SeqCst);
+            TestNotification::notify(0, id);
+            assert!(notified.load(Ordering::SeqCst));
+            assert!(TestNotification::poll_wait(id, &mut cx).is_ready());
+            unsafe { TestNotification::release_id(id) };
+        }
+    }
+
+    /// 并发的首次登记只占用一个槽位
+    #[test]
+    fn test_hal_concurrent_claim() {
+        let slots = HalSlots::new();
+        // 另一线程的首次登记在本线程`find`之后占用了槽位，本线程随后占用下一个空槽位
+        slots.slots[0].obj.store(7, Ordering::Release);
+        let slot = slots.claim(7).unwrap();
+        assert!(core::ptr::eq(slot, &slots.slots[0]));
+        let claimed = slots
+            .slots
+            .iter()
+            .filter(|slot| slot.obj.load(Ordering::Acquire) == 7)
+            .count();
+        assert_eq!(claimed, 1);
+    }
+}
@@ -0,0 +1,184 @@
+//! 在进程之间移交通知源
+//!
+//! 滚动升级时，接收方的角色需要从旧进程迁移到新进程，而发送方不应察觉。旧进程调用[`Notification::handoff`]
+//! 释放通知源并取出其底层资源，经一个已连接的unix域套接字（例如升级时继承的`socketpair`）以[`Handoff::send`]交给新进程；
+//! 新进程以[`Handoff::recv`]收取，再以[`Notification::takeover`]重新建立通知源。
+//!
+//! 消息由8字节的原id与至多一个经`SCM_RIGHTS`传递的文件描述符组成。各类型的行为：
+//!
+//! - `kvm`（eventfd）：文件描述符被传递，计数器中尚未被读出的通知被保留。已持有该eventfd（例如注册为ioeventfd）的发送方不受影响；
+//! - `uds`：已绑定地址的套接字被传递，地址中仍是旧进程的进程号与原id，因此发送方的`(process, id)`保持有效，
+//!   接收队列中尚未被读出的数据报也被保留，但已读出而未被取出的文件描述符被关闭；
+//! - `signal`：信号无法被传递，消息只携带信号编号作为接管凭据，新进程占用同一信号。信号是发给进程的，
+//!   发送方需改为以新进程的进程号（或包含两者的进程组）发送；移交期间到达的信号被丢弃。
+//!
+//! 其他类型不支持移交。
+
+extern crate std;
+
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+
+use crate::{
+    interface::{Notification, NotifyError},
+    kind::NotificationKind,
+};
+
+/// 一个已被释放、等待由另一个进程接管的通知源
+#[derive(Debug)]
+pub struct Handoff {
+    id: u64,
+    fd: Option<OwnedFd>,
+}
+
+impl Handoff {
+    /// 在原进程中的id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 通知源的类型
+    pub fn kind(&self) -> Option<NotificationKind> {
+        Notification::kind_of(self.id)
+    }
+
+    /// 经已连接的unix域套接字`socket`发送，文件描述符在本进程中的副本在`self`被丢弃时关闭
+    ///
+    /// `socket`为阻塞的时阻塞直到发送完成；失败时可以重试。
+    pub fn send(&self, socket: RawFd) -> Result<(), NotifyError> {
+        crate::scm::send(socket, self.id, self.fd.as_ref().map(AsRawFd::as_raw_fd))
+    }
+
+    /// 从已连接的unix域套接字`socket`收取一个由[`send`](Self::send)发出的通知源
+    ///
+    /// `socket`为阻塞的时阻塞直到收到消息。对端关闭或消息不完整时返回`NotifyError::Os(EPROTO)`。
+    pub fn recv(socket: RawFd) -> Result<Self, NotifyError> {
+        let (id, fd) = crate::scm::recv(socket)?;
+        Ok(Self { id, fd })
+    }
+}
+
+impl Notification {
+    /// 释放通知源，并取出其底层资源以交给另一个进程
+    ///
+    /// 支持`signal`、`kvm`（eventfd）与`uds`，不支持的类型返回`None`且不释放通知源；
+    /// 若通知源正被其他线程使用（例如正在轮询），也返回`None`，且不释放通知源。
+    ///
+    /// # Safety
+    ///
+    /// 与`release_id`相同：返回`Some`之后，id不能再被使用。
+    pub unsafe fn handoff(id: u64) -> Option<Handoff> {
+        let fd = match Self::kind_of(id)? {
+            #[cfg(feature = "signal")]
+            NotificationKind::Signal => {
+                unsafe { <Self as crate::interface::NotificationIf>::release_id(id) };
+                None
+            }
+            #[cfg(feature = "kvm")]
+            NotificationKind::Kvm => Some(unsafe { Self::into_raw_fd(id) }?),
+            #[cfg(feature = "uds")]
+            NotificationKind::Uds => Some(unsafe { Self::into_raw_fd(id) }?),
+            _ => return None,
+        };
+        Some(Handoff { id, fd })
+    }
+
+    /// 在本进程中重新建立由另一个进程移交的通知源，并返回其在本进程中的id
+    ///
+    /// 新的id可能与原id不同，只用于在本进程中等待；`uds`的发送方仍使用原进程号与原id。
+    /// 类型不被本进程支持、消息中缺少文件描述符或信号已被占用时返回`None`。
+    /// 该函数需要在tokio运行时内部调用。
+    pub fn takeover(handoff: Handoff) -> Option<u64> {
+        let kind = handoff.kind()?;
+        match (kind, handoff.fd) {
+            #[cfg(feature = "signal")]
+            (NotificationKind::Signal, None) => {
+                Self::from_raw_signal(Self::as_raw_signal(handoff.id)?)
+            }
+            #[cfg(feature = "kvm")]
+            (NotificationKind::Kvm, Some(fd)) => Self::from_raw_eventfd(fd),
+            #[cfg(feature = "uds")]
+            (NotificationKind::Uds, Some(fd)) => crate::uds::UdsNotification::new_id_from_fd(fd)
+                .map(|id| Self::tagged(id, (kind.tag() as u64) << 56)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    fn socketpair() -> (OwnedFd, OwnedFd) {
+        let mut fds = [0; 2];
+        let res = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        assert_eq!(res, 0);
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+    }
+
+    #[test]
+    fn test_handoff() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (old, new) = socketpair();
+                let pid = unsafe { libc::getpid() } as u64;
+                let ids = [
+                    #[cfg(feature = "kvm")]
+                    Notification::new_id_kvm_ioeventfd().unwrap(),
+                    #[cfg(feature = "uds")]
+                    Notification::new_id_uds().unwrap(),
+                ];
+                for id in ids {
+                    // 移交前发出的通知被保留
+                    Notification::notify(pid, id);
+                    let handoff = unsafe { Notification::handoff(id) }.unwrap();
+                    assert_eq!(handoff.kind(), Notification::kind_of(id));
+                    handoff.send(old.as_raw_fd()).unwrap();
+                    drop(handoff);
+
+                    let handoff = Handoff::recv(new.as_raw_fd()).unwrap();
+                    assert_eq!(handoff.id(), id);
+                    let taken = Notification::takeover(handoff).unwrap();
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(taken))
+                        .await
+                        .unwrap();
+                    // unix域套接字的发送方仍使用原id，eventfd的发送方持有的是eventfd本身
+                    let target = if Notification::kind_of(id) == Some(NotificationKind::Uds) {
+                        id
+                    } else {
+                        taken
+                    };
+                    Notification::notify(pid, target);
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(taken))
+                        .await
+                        .unwrap();
+                    unsafe { Notification::release_id(taken) };
+                }
+
+                #[cfg(feature = "mock")]
+                {
+                    let id = Notification::new_id_mock().unwrap();
+                    assert!(unsafe { Notification::handoff(id) }.is_none());
+                    unsafe { Notification::release_id(id) };
+                }
+                drop(old);
+                assert_eq!(
+                    Handoff::recv(new.as_raw_fd()).unwrap_err(),
+                    NotifyError::Os(libc::EPROTO)
+                );
+            });
+    }
+}
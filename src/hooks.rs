@@ -0,0 +1,159 @@
+//! 发送与唤醒的钩子
+//!
+//! 应用可以通过[`on_notify`]与[`on_wake`]注册钩子，在不修改本crate的情况下加入跟踪、限流或权限检查等逻辑。
+//! 钩子为函数指针加上一个由注册者解释的`usize`上下文（可以是指针或表的下标），因此可在`no_std`环境下使用。
+//!
+//! - 发送钩子在[`Notification`](crate::interface::Notification)的`notify`、`notify_with`、`notify_to`
+//!   （以及基于其的函数）发送之前被调用；任一钩子返回`false`时通知不被发送，
+//!   返回`Result`的函数返回`NotifyError::Rejected`，`notify`则直接返回。
+//! - 唤醒钩子在`Notification::poll_wait`消费了一个通知（返回`Poll::Ready`）之后被调用。
+//!
+//! 直接使用各通知机制（例如`SignalNotification`）时不调用钩子。钩子在发送或轮询的线程上同步执行，应尽量简短，
+//! 且不能在其中注册或移除钩子。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{interface::ProcessRef, sync::SpinMutex};
+
+/// 每种钩子最多可注册的数量
+pub const MAX_HOOKS: usize = 8;
+
+/// 发送钩子：参数为注册时的上下文、接收者与id，返回是否允许发送
+pub type NotifyHook = fn(ctx: usize, target: ProcessRef, id: u64) -> bool;
+
+/// 唤醒钩子：参数为注册时的上下文与被消费通知的id
+pub type WakeHook = fn(ctx: usize, id: u64);
+
+/// 已注册的钩子，用于移除
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookHandle {
+    wake: bool,
+    index: usize,
+}
+
+struct Hooks<F: Copy> {
+    slots: SpinMutex<[Option<(F, usize)>; MAX_HOOKS]>,
+    /// 已注册的钩子数量，为0时跳过加锁
+    count: AtomicUsize,
+}
+
+impl<F: Copy> Hooks<F> {
+    const fn new() -> Self {
+        Self {
+            slots: SpinMutex::new([None; MAX_HOOKS]),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, hook: F, ctx: usize) -> Option<usize> {
+        let mut slots = self.slots.lock();
+        let index = slots.iter().position(Option::is_none)?;
+        slots[index] = Some((hook, ctx));
+        self.count.fetch_add(1, Ordering::Release);
+        Some(index)
+    }
+
+    fn remove(&self, index: usize) -> bool {
+        let removed = self.slots.lock()[index].take().is_some();
+        if removed {
+            self.count.fetch_sub(1, Ordering::Release);
+        }
+        removed
+    }
+
+    /// 已注册的钩子的副本，使钩子在不持有锁时执行
+    fn snapshot(&self) -> Option<[Option<(F, usize)>; MAX_HOOKS]> {
+        if self.count.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        Some(*self.slots.lock())
+    }
+}
+
+static NOTIFY_HOOKS: Hooks<NotifyHook> = Hooks::new();
+static WAKE_HOOKS: Hooks<WakeHook> = Hooks::new();
+
+/// 注册发送钩子，已注册[`MAX_HOOKS`]个时返回`None`
+pub fn on_notify(hook: NotifyHook, ctx: usize) -> Option<HookHandle> {
+    NOTIFY_HOOKS
+        .add(hook, ctx)
+        .map(|index| HookHandle { wake: false, index })
+}
+
+/// 注册唤醒钩子，已注册[`MAX_HOOKS`]个时返回`None`
+pub fn on_wake(hook: WakeHook, ctx: usize) -> Option<HookHandle> {
+    WAKE_HOOKS
+        .add(hook, ctx)
+        .map(|index| HookHandle { wake: true, index })
+}
+
+/// 移除钩子，返回其是否仍被注册
+pub fn remove_hook(handle: HookHandle) -> bool {
+    if handle.wake {
+        WAKE_HOOKS.remove(handle.index)
+    } else {
+        NOTIFY_HOOKS.remove(handle.index)
+    }
+}
+
+/// 依次调用发送钩子，返回是否允许发送
+pub(crate) fn run_notify(target: ProcessRef, id: u64) -> bool {
+    let Some(hooks) = NOTIFY_HOOKS.snapshot() else {
+        return true;
+    };
+    hooks
+        .iter()
+        .flatten()
+        .all(|&(hook, ctx)| hook(ctx, target, id))
+}
+
+/// 依次调用唤醒钩子
+pub(crate) fn run_wake(id: u64) {
+    let Some(hooks) = WAKE_HOOKS.snapshot() else {
+        return;
+    };
+    for &(hook, ctx) in hooks.iter().flatten() {
+        hook(ctx, id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU64;
+
+    static WOKEN: AtomicU64 = AtomicU64::new(0);
+
+    #[test]
+    fn test_hooks() {
+        // 只拒绝上下文中给出的id
+        fn deny(ctx: usize, _target: ProcessRef, id: u64) -> bool {
+            id != ctx as u64
+        }
+        // 其他测试中的唤醒也会调用该钩子，因此只统计测试用的id
+        fn count(ctx: usize, id: u64) {
+            if id == 5 {
+                WOKEN.fetch_add(ctx as u64, Ordering::AcqRel);
+            }
+        }
+
+        let denied = 0x0300_0000_dead_0001;
+        let handle = on_notify(deny, denied as usize).unwrap();
+        assert!(!run_notify(ProcessRef::Process(1), denied));
+        assert!(run_notify(ProcessRef::Process(1), denied + 1));
+        // 带有负载的发送同样经过钩子
+        assert_eq!(
+            crate::interface::Notification::notify_with_payload(1, denied, b"denied"),
+            Err(crate::interface::NotifyError::Rejected)
+        );
+        assert!(remove_hook(handle));
+        assert!(!remove_hook(handle));
+        assert!(run_notify(ProcessRef::Process(1), denied));
+
+        let handle = on_wake(count, 2).unwrap();
+        run_wake(5);
+        assert!(remove_hook(handle));
+        run_wake(5);
+        assert_eq!(WOKEN.load(Ordering::Acquire), 2);
+    }
+}
@@ -0,0 +1,204 @@
+//! 两阶段通知：先写共享内存中的标志，只在接收方挂起时才发送通知
+//!
+//! 接收方忙于处理时，每个通知都进行一次系统调用（`kill`、`senduipi`、写eventfd等）是不必要的。启用本机制后，
+//! 每个通知源带有一个位于共享内存中的`pending`计数与`sleeping`标志：
+//!
+//! - 接收方以[`enable`]为自己的通知源创建它们，发送方以[`attach`]映射；
+//! - 发送方经由[`Notification`](crate::interface::Notification)的`notify`、`notify_with`、`notify_to`发送通知时先增加`pending`，
+//!   只在`sleeping`被置位时才经由通知机制实际发送，否则直接返回成功；
+//! - 接收方的`poll_wait`先取走`pending`，没有通知时置位`sleeping`、再检查一次`pending`，之后才在通知机制上挂起。
+//!
+//! 两侧都先写自己的字段、再读对方的字段，因此不会出现发送方认为接收方醒着、接收方又已挂起的情况。
+//! 接收方在挂起后经由共享内存收到通知时，发送方可能已经（或正要）经由通知机制发送，这一通知之后会造成一次多余的唤醒。
+//!
+//! 发往进程组的通知与`notify_with_token`总是经由通知机制发送。共享内存在通知源被释放时删除。
+
+use alloc::{collections::btree_map::BTreeMap, ffi::CString, sync::Arc};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    interface::{ProcessRef, WakeReason},
+    shm,
+    sync::SpinMutex,
+};
+
+/// 位于共享内存中的状态
+#[repr(C)]
+struct Shared {
+    /// 尚未被接收方取走的通知数，由发送方增加
+    pending: AtomicU64,
+    /// 接收方是否已在通知机制上挂起，不为0时发送方需经由通知机制发送
+    sleeping: AtomicU64,
+    /// 只写共享内存、未经由通知机制发送的通知数，由发送方增加
+    skipped: AtomicU64,
+}
+
+impl Shared {
+    fn take(&self) -> bool {
+        self.pending.swap(0, Ordering::SeqCst) != 0
+    }
+}
+
+type Mapping = shm::Mapping<Shared>;
+
+/// 本进程作为接收方的状态，以id为key
+static RECEIVERS: SpinMutex<BTreeMap<u64, Arc<Mapping>>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程作为发送方映射的状态，以接收方的进程号与id为key
+static SENDERS: SpinMutex<BTreeMap<(u64, u64), Mapping>> = SpinMutex::new(BTreeMap::new());
+
+fn name(pid: u64, id: u64) -> CString {
+    shm::name("hybrid", pid, id)
+}
+
+fn getpid() -> u64 {
+    unsafe { libc::getpid() as u64 }
+}
+
+/// 为本进程的通知源`id`启用两阶段通知，返回是否成功；已启用时返回`false`
+pub fn enable(id: u64) -> bool {
+    let mut receivers = RECEIVERS.lock();
+    if receivers.contains_key(&id) {
+        return false;
+    }
+    let Ok(mapping) = Mapping::map(&name(getpid(), id), true) else {
+        return false;
+    };
+    receivers.insert(id, Arc::new(mapping));
+    true
+}
+
+/// 映射进程`process`的通知源`id`的状态，之后向其发送的通知先写共享内存；返回是否成功
+///
+/// 接收方需已调用[`enable`]。重复映射时返回`true`。
+pub fn attach(process: u64, id: u64) -> bool {
+    let mut senders = SENDERS.lock();
+    if senders.contains_key(&(process, id)) {
+        return true;
+    }
+    let Ok(mapping) = Mapping::map(&name(process, id), false) else {
+        return false;
+    };
+    senders.insert((process, id), mapping);
+    true
+}
+
+/// 取消对进程`process`的通知源`id`的状态的映射，返回其是否曾被映射
+pub fn detach(process: u64, id: u64) -> bool {
+    SENDERS.lock().remove(&(process, id)).is_some()
+}
+
+/// 进程`process`的通知源`id`上只写了共享内存、省去了系统调用的通知数；未映射其状态时返回`None`
+pub fn skipped(process: u64, id: u64) -> Option<u64> {
+    let senders = SENDERS.lock();
+    Some(
+        senders
+            .get(&(process, id))?
+            .get()
+            .skipped
+            .load(Ordering::Acquire),
+    )
+}
+
+/// 即将向`target`的通知源`id`发送通知，返回通知是否已经由共享内存送达、不需要再经由通知机制发送
+pub(crate) fn on_notify(target: ProcessRef, id: u64) -> bool {
+    let ProcessRef::Process(process) = target else {
+        return false;
+    };
+    let senders = SENDERS.lock();
+    let Some(mapping) = senders.get(&(process, id)) else {
+        return false;
+    };
+    let shared = mapping.get();
+    shared.pending.fetch_add(1, Ordering::SeqCst);
+    if shared.sleeping.load(Ordering::SeqCst) != 0 {
+        return false;
+    }
+    shared.skipped.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// 先从共享内存取走通知，没有通知时标记为挂起并以`cx`轮询`inner`；未启用两阶段通知时直接以`cx`轮询
+pub(crate) fn poll(
+    id: u64,
+    cx: &mut Context<'_>,
+    mut inner: impl FnMut(&mut Context<'_>) -> Poll<WakeReason>,
+) -> Poll<WakeReason> {
+    let Some(mapping) = RECEIVERS.lock().get(&id).cloned() else {
+        return inner(cx);
+    };
+    let shared = mapping.get();
+    // 挂起期间经由通知机制发出的通知与共享内存中的通知是同一个，取走它以免之后多余的唤醒
+    let mut drain = || {
+        if shared.sleeping.swap(0, Ordering::SeqCst) != 0 {
+            let waker = futures::task::noop_waker();
+            let _ = inner(&mut Context::from_waker(&waker));
+        }
+    };
+    if shared.take() {
+        drain();
+        return Poll::Ready(WakeReason::NOTIFIED);
+    }
+    shared.sleeping.store(1, Ordering::SeqCst);
+    if shared.take() {
+        drain();
+        return Poll::Ready(WakeReason::NOTIFIED);
+    }
+    let poll = inner(cx);
+    if poll.is_ready() {
+        shared.sleeping.store(0, Ordering::SeqCst);
+        shared.take();
+    }
+    poll
+}
+
+/// 通知源`id`即将被释放，删除其共享内存
+pub(crate) fn release(id: u64) {
+    if RECEIVERS.lock().remove(&id).is_some() {
+        shm::unlink(&name(getpid(), id));
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::{Notification, NotificationIf};
+
+    #[test]
+    fn test_hybrid_notify() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let pid = getpid();
+        let id = Notification::new_id_mock().unwrap();
+        assert!(!attach(pid, id));
+        assert!(enable(id));
+        assert!(!enable(id));
+        assert!(attach(pid, id));
+        assert_eq!(skipped(pid, id), Some(0));
+
+        // 接收方未挂起时只写共享内存
+        Notification::notify(pid, id);
+        Notification::notify_to(ProcessRef::Process(pid), id).unwrap();
+        assert_eq!(skipped(pid, id), Some(2));
+        assert_eq!(crate::mock::pending(id), Some(0));
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+
+        // 挂起后经由通知机制发送
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        Notification::notify(pid, id);
+        assert_eq!(skipped(pid, id), Some(2));
+        assert_eq!(crate::mock::pending(id), Some(1));
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        assert_eq!(crate::mock::pending(id), Some(0));
+        Notification::notify(pid, id);
+        assert_eq!(skipped(pid, id), Some(3));
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+
+        unsafe { Notification::release_id(id) };
+        assert!(detach(pid, id));
+        assert!(!attach(pid, id));
+    }
+}
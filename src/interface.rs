@@ -1,13 +1,64 @@
 //! 统一的通知接口
 
-use core::ops::Not;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+#[cfg(any(feature = "uds", feature = "net"))]
+extern crate std;
 
 #[cfg(feature = "signal")]
 use crate::signal::SignalNotification;
 
+#[cfg(feature = "arceos")]
+use crate::arceos::ArceosNotification;
+#[cfg(feature = "child")]
+use crate::child::ChildNotification;
+#[cfg(feature = "dbus")]
+use crate::dbus::DbusNotification;
+#[cfg(feature = "fuchsia")]
+use crate::fuchsia::FuchsiaNotification;
+#[cfg(feature = "kqueue")]
+use crate::kqueue::KqueueNotification;
+#[cfg(feature = "kvm")]
+use crate::kvm::KvmNotification;
+#[cfg(feature = "mock")]
+use crate::mock::MockNotification;
+#[cfg(feature = "mqueue")]
+use crate::mqueue::MqueueNotification;
+#[cfg(feature = "net")]
+use crate::net::NetNotification;
+#[cfg(feature = "netlink")]
+use crate::netlink::NetlinkNotification;
+#[cfg(feature = "pipe")]
+use crate::pipe::PipeNotification;
+#[cfg(feature = "signalfd")]
+use crate::signalfd::SignalfdNotification;
+#[cfg(feature = "sim")]
+use crate::sim::SimNotification;
+#[cfg(feature = "timer")]
+use crate::timer::TimerNotification;
+#[cfg(feature = "uds")]
+use crate::uds::UdsNotification;
+#[cfg(feature = "uintr")]
 use crate::uintr::UIntrNotification;
+#[cfg(feature = "uring")]
+use crate::uring::UringNotification;
+#[cfg(feature = "vsock")]
+use crate::vsock::VsockNotification;
+
+#[cfg(feature = "alloc")]
+use crate::owner::OwnerInfo;
 
 /// 统一的通知接口
+///
+/// 为兼容不支持trait中`async fn`的编译器，`wait_on`返回具名的[`WaitOn`] future，而非使用`async fn`。
 pub trait NotificationIf {
     /// 在本进程申请一个新的通知源（例如中断向量或信号编号）
     ///
@@ -16,10 +67,42 @@ pub trait NotificationIf {
     /// 通知源从申请开始即开始接收和缓存通知，以保证在等待通知时不会漏掉之前的通知。
     fn new_id() -> Option<u64>;
     /// 在一个通知源上等待
-    async fn wait_on(id: u64);
+    ///
+    /// 返回的future基于`poll_wait`实现，是取消安全的：在其完成之前丢弃它（例如在`select!`中其他分支先完成）不会消费任何通知，
+    /// 之后的`wait_on`仍能收到此前的通知。
+    fn wait_on(id: u64) -> WaitOn<Self>
+    where
+        Self: Sized,
+    {
+        WaitOn::new(id)
+    }
+    /// 轮询一个通知源
+    ///
+    /// 若通知源上有未被消费的通知，则消费一个通知并返回`Poll::Ready(())`；
+    /// 否则注册`cx`中的waker，在收到通知时唤醒，并返回`Poll::Pending`。
+    ///
+    /// 实现必须保证只在返回`Poll::Ready(())`时消费通知，且返回`Poll::Pending`后不再需要被轮询也不会丢失状态，
+    /// 从而使基于该函数的future是取消安全的。
+    ///
+    /// 该函数不依赖具体的执行器，可供自定义执行器（包括裸机环境下的执行器）直接使用，而无需将`wait_on`的future装箱。
+    ///
+    /// 每个通知源只保存最近一次轮询的waker，需要多个协程同时等待时应使用[`Broadcast`](crate::broadcast::Broadcast)。
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()>;
+    /// 轮询一个通知源，并报告等待结束的原因
+    ///
+    /// 与`poll_wait`相同，但`Poll::Ready`中带有[`WakeReason`]：通知源已被释放或已关闭时为`Shutdown`，
+    /// 一次消费了多个通知时`Notified`中带有其数量。默认实现由`poll_wait`得到，总是报告`WakeReason::NOTIFIED`。
+    /// `wait_on`返回的future基于该函数实现。
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        Self::poll_wait(id, cx).map(|()| WakeReason::NOTIFIED)
+    }
+    /// 注册waker，使其在通知源收到通知时被唤醒
+    ///
+    /// 若通知源上已有未被消费的通知，则立即唤醒waker。该函数本身不消费通知，通知需通过`poll_wait`或`wait_on`消费。
+    fn register_waker(id: u64, waker: &Waker);
     /// 释放通知源
     ///
-    /// SAFETY:
+    /// # Safety
     ///
     /// - 在调用`release_id`时，不能有相应id上的`wait_on`还在执行中。
     /// - 在调用`release_id`之后、使用`new_id`分配到相同id之前，不能在该id上调用`wait_on`
@@ -28,12 +111,235 @@ pub trait NotificationIf {
     fn notify(process: u64, id: u64);
 }
 
+/// 等待结束的原因
+///
+/// `wait_on`等在收到通知时返回`Notified`，在通知源已被释放或关闭时返回`Shutdown`；
+/// 带超时的等待与组合了对端存活、关闭等条件的等待以其余的取值告知调用者被唤醒的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WakeReason {
+    /// 收到了通知，`count`为本次等待消费的通知数
+    Notified {
+        /// 消费的通知数
+        count: usize,
+    },
+    /// 等待超时
+    Timeout,
+    /// 对端进程已退出或不再响应
+    PeerDied,
+    /// 通知源或其所属的服务已关闭，之后不会再有通知
+    Shutdown,
+    /// 被唤醒，但没有可报告的事件，调用者应重新检查其条件
+    Spurious,
+}
+
+impl WakeReason {
+    /// 消费了一个通知的等待结果
+    pub const NOTIFIED: Self = Self::Notified { count: 1 };
+
+    /// 是否因收到通知而结束
+    pub const fn is_notified(self) -> bool {
+        matches!(self, Self::Notified { .. })
+    }
+
+    /// 本次等待消费的通知数，未收到通知时为0
+    pub const fn count(self) -> usize {
+        match self {
+            Self::Notified { count } => count,
+            _ => 0,
+        }
+    }
+}
+
+/// `NotificationIf::wait_on`返回的future
+///
+/// 每次被轮询时调用`N::poll_wait_reason`，直到通知源上有通知或通知源已关闭，此时以其报告的[`WakeReason`]结束。
+///
+/// 除id外，该future只在启用`tracing`或`stats`时记录首次被轮询的时刻，用于统计等待时间；
+/// 通知只在其结束时被消费，因此它是取消安全的。未启用这两个feature时，其大小与`u64`相同。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitOn<N: NotificationIf> {
+    id: u64,
+    #[cfg(any(feature = "tracing", feature = "stats"))]
+    start: crate::trace::WaitStart,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<N: NotificationIf> WaitOn<N> {
+    /// 新建在`id`上等待的future
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            #[cfg(any(feature = "tracing", feature = "stats"))]
+            start: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 所等待的通知源id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<N: NotificationIf> Future for WaitOn<N> {
+    type Output = WakeReason;
+
+    #[cfg(not(any(feature = "tracing", feature = "stats")))]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        N::poll_wait_reason(self.id, cx)
+    }
+
+    #[cfg(any(feature = "tracing", feature = "stats"))]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        let this = self.get_mut();
+        let poll = N::poll_wait_reason(this.id, cx);
+        let backend = core::any::type_name::<N>();
+        crate::trace::wait_on(backend, this.id, &mut this.start, poll.is_ready());
+        poll
+    }
+}
+
+/// 通知的投递类别
+///
+/// 同一对通知源之间可以混合使用不同类别的通知，例如控制消息使用`Reliable`、高频的周期性通知使用`BestEffort`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// 尽力投递：通知可能与尚未被接收的通知合并，在发送队列已满时被丢弃，发送总是成功
+    BestEffort,
+    /// 可靠投递：通知在内核的发送队列中排队，队列已满时重试，重试后仍失败则返回错误
+    Reliable,
+}
+
+impl Delivery {
+    /// 随通知一同发送的类别标记，接收方可从通知的附加信息中读出
+    pub const fn tag(self) -> usize {
+        match self {
+            Delivery::BestEffort => 0,
+            Delivery::Reliable => 1,
+        }
+    }
+}
+
+/// 通知的接收者
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessRef {
+    /// 进程
+    Process(u64),
+    /// 进程组中的所有进程
+    Group(u64),
+    /// 进程中的特定线程，需要接收通知的协程固定在某一线程上时使用
+    Thread {
+        /// 线程所属的进程
+        pid: u64,
+        /// 线程id
+        tid: u64,
+    },
+}
+
+impl From<u64> for ProcessRef {
+    fn from(pid: u64) -> Self {
+        ProcessRef::Process(pid)
+    }
+}
+
+/// 发送通知失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyError {
+    /// 发送队列已满，重试后仍无法发送
+    Overflow,
+    /// 该类型的通知源不能由其他进程发送通知
+    Unsupported,
+    /// 发送失败，附带errno
+    Os(i32),
+    /// 被发送钩子拒绝，见[`hooks`](crate::hooks)
+    Rejected,
+    /// 接收方尚未消费的通知已达上限，见[`seq::enable_with_credit`](crate::seq::enable_with_credit)
+    Full,
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyError::Overflow => write!(f, "notification queue overflow"),
+            NotifyError::Unsupported => write!(f, "notification type cannot be notified"),
+            NotifyError::Os(errno) => write!(f, "notify failed with errno {}", errno),
+            NotifyError::Rejected => write!(f, "notification rejected by hook"),
+            NotifyError::Full => write!(f, "receiver has too many pending notifications"),
+        }
+    }
+}
+
+#[cfg(feature = "signal")]
 const SIGNAL_HIGH8: u64 = 0x01 << 56;
+#[cfg(feature = "uintr")]
 const UINTR_HIGH8: u64 = 0x02 << 56;
+#[cfg(feature = "timer")]
+const TIMER_HIGH8: u64 = 0x03 << 56;
+#[cfg(feature = "child")]
+const CHILD_HIGH8: u64 = 0x04 << 56;
+#[cfg(feature = "uring")]
+const URING_HIGH8: u64 = 0x05 << 56;
+#[cfg(feature = "kqueue")]
+const KQUEUE_HIGH8: u64 = 0x06 << 56;
+#[cfg(feature = "fuchsia")]
+const FUCHSIA_HIGH8: u64 = 0x07 << 56;
+#[cfg(feature = "arceos")]
+const ARCEOS_HIGH8: u64 = 0x08 << 56;
+#[cfg(feature = "vsock")]
+const VSOCK_HIGH8: u64 = 0x09 << 56;
+#[cfg(feature = "kvm")]
+const KVM_HIGH8: u64 = 0x0a << 56;
+#[cfg(feature = "uds")]
+const UDS_HIGH8: u64 = 0x0b << 56;
+#[cfg(feature = "signalfd")]
+const SIGNALFD_HIGH8: u64 = 0x0c << 56;
+#[cfg(feature = "mqueue")]
+const MQUEUE_HIGH8: u64 = 0x0d << 56;
+#[cfg(feature = "netlink")]
+const NETLINK_HIGH8: u64 = 0x0e << 56;
+#[cfg(feature = "dbus")]
+const DBUS_HIGH8: u64 = 0x0f << 56;
+#[cfg(feature = "net")]
+const NET_HIGH8: u64 = 0x10 << 56;
+#[cfg(feature = "pipe")]
+const PIPE_HIGH8: u64 = 0x11 << 56;
+#[cfg(feature = "mock")]
+const MOCK_HIGH8: u64 = 0x12 << 56;
+#[cfg(feature = "sim")]
+const SIM_HIGH8: u64 = 0x13 << 56;
 
 /// 封装不同类型的通知，在id上增加高8位以区分不同类型的通知源，并在接口函数中根据高8位分发到不同的实现。
+///
+/// 未启用相应feature的通知源类型被视为未知类型。
+#[cfg(feature = "alloc")]
 pub struct Notification;
 
+// 未启用任何通知机制时，分发函数的参数均未被使用
+#[cfg(feature = "alloc")]
+#[cfg_attr(
+    not(any(
+        feature = "signal",
+        feature = "uintr",
+        feature = "timer",
+        feature = "child",
+        feature = "uring",
+        feature = "kqueue",
+        feature = "fuchsia",
+        feature = "arceos",
+        feature = "vsock",
+        feature = "kvm",
+        feature = "uds",
+        feature = "signalfd",
+        feature = "mqueue",
+        feature = "netlink",
+        feature = "dbus",
+        feature = "net",
+        feature = "pipe",
+        feature = "mock",
+        feature = "sim"
+    )),
+    allow(unused_variables)
+)]
 impl NotificationIf for Notification {
     /// 返回`None`
     ///
@@ -42,25 +348,143 @@ impl NotificationIf for Notification {
         None
     }
 
-    async fn wait_on(id: u64) {
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        #[cfg(feature = "wake-coalesce")]
+        let poll = crate::coalesce::poll(id, cx, |cx| Self::spin_poll_wait(id, cx));
+        #[cfg(not(feature = "wake-coalesce"))]
+        let poll = Self::spin_poll_wait(id, cx);
+        crate::snapshot::polled(id, poll.is_ready());
+        if poll.is_ready() {
+            #[cfg(feature = "seq")]
+            crate::seq::on_wake(id);
+            crate::trace::wake(id);
+            crate::hooks::run_wake(id);
+        }
+        poll
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
         let high8 = id & 0xFF00_0000_0000_0000;
         let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
         match high8 {
             #[cfg(feature = "signal")]
-            SIGNAL_HIGH8 => SignalNotification::wait_on(id_inner).await,
-            UINTR_HIGH8 => UIntrNotification::wait_on(id_inner).await,
-            _ => panic!("wait_on: Unknown notification type with id: 0x{:016x}", id),
+            SIGNAL_HIGH8 => SignalNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "uintr")]
+            UINTR_HIGH8 => UIntrNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "timer")]
+            TIMER_HIGH8 => TimerNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "child")]
+            CHILD_HIGH8 => ChildNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "uring")]
+            URING_HIGH8 => UringNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "kqueue")]
+            KQUEUE_HIGH8 => KqueueNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "fuchsia")]
+            FUCHSIA_HIGH8 => FuchsiaNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "arceos")]
+            ARCEOS_HIGH8 => ArceosNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "vsock")]
+            VSOCK_HIGH8 => VsockNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "kvm")]
+            KVM_HIGH8 => KvmNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "mqueue")]
+            MQUEUE_HIGH8 => MqueueNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "netlink")]
+            NETLINK_HIGH8 => NetlinkNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "dbus")]
+            DBUS_HIGH8 => DbusNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "net")]
+            NET_HIGH8 => NetNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "pipe")]
+            PIPE_HIGH8 => PipeNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => MockNotification::register_waker(id_inner, waker),
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => SimNotification::register_waker(id_inner, waker),
+            // 未知类型的通知源视为已被释放
+            _ => fail!(
+                waker.wake_by_ref(),
+                "register_waker: Unknown notification type with id: 0x{:016x}",
+                id
+            ),
         }
     }
 
+    /// 若通知源被`dup_id`复制过，则只减少其持有者的数量，由最后一个持有者真正释放
     unsafe fn release_id(id: u64) {
+        if crate::shared::release(id) {
+            return;
+        }
+        crate::trace::release_id(id);
+        crate::snapshot::release(id);
+        #[cfg(feature = "debug-leaks")]
+        crate::leaks::release(id);
+        crate::set::release(id);
+        #[cfg(feature = "seq")]
+        crate::seq::release(id);
+        #[cfg(feature = "hybrid")]
+        crate::hybrid::release(id);
+        #[cfg(feature = "payload")]
+        crate::payload::release(id);
+        #[cfg(feature = "receive-policy")]
+        crate::receive::release(id);
+        #[cfg(feature = "lease")]
+        crate::lease::release(id);
+        #[cfg(feature = "spin-wait")]
+        crate::spin::release(id);
+        #[cfg(feature = "wake-coalesce")]
+        crate::coalesce::release(id);
         let high8 = id & 0xFF00_0000_0000_0000;
         let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
         match high8 {
             #[cfg(feature = "signal")]
-            SIGNAL_HIGH8 => SignalNotification::release_id(id_inner),
-            UINTR_HIGH8 => UIntrNotification::release_id(id_inner),
-            _ => panic!(
+            SIGNAL_HIGH8 => unsafe { SignalNotification::release_id(id_inner) },
+            #[cfg(feature = "uintr")]
+            UINTR_HIGH8 => unsafe { UIntrNotification::release_id(id_inner) },
+            #[cfg(feature = "timer")]
+            TIMER_HIGH8 => unsafe { TimerNotification::release_id(id_inner) },
+            #[cfg(feature = "child")]
+            CHILD_HIGH8 => unsafe { ChildNotification::release_id(id_inner) },
+            #[cfg(feature = "uring")]
+            URING_HIGH8 => unsafe { UringNotification::release_id(id_inner) },
+            #[cfg(feature = "kqueue")]
+            KQUEUE_HIGH8 => unsafe { KqueueNotification::release_id(id_inner) },
+            #[cfg(feature = "fuchsia")]
+            FUCHSIA_HIGH8 => unsafe { FuchsiaNotification::release_id(id_inner) },
+            #[cfg(feature = "arceos")]
+            ARCEOS_HIGH8 => unsafe { ArceosNotification::release_id(id_inner) },
+            #[cfg(feature = "vsock")]
+            VSOCK_HIGH8 => unsafe { VsockNotification::release_id(id_inner) },
+            #[cfg(feature = "kvm")]
+            KVM_HIGH8 => unsafe { KvmNotification::release_id(id_inner) },
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => unsafe { UdsNotification::release_id(id_inner) },
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => unsafe { SignalfdNotification::release_id(id_inner) },
+            #[cfg(feature = "mqueue")]
+            MQUEUE_HIGH8 => unsafe { MqueueNotification::release_id(id_inner) },
+            #[cfg(feature = "netlink")]
+            NETLINK_HIGH8 => unsafe { NetlinkNotification::release_id(id_inner) },
+            #[cfg(feature = "dbus")]
+            DBUS_HIGH8 => unsafe { DbusNotification::release_id(id_inner) },
+            #[cfg(feature = "net")]
+            NET_HIGH8 => unsafe { NetNotification::release_id(id_inner) },
+            #[cfg(feature = "pipe")]
+            PIPE_HIGH8 => unsafe { PipeNotification::release_id(id_inner) },
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => unsafe { MockNotification::release_id(id_inner) },
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => unsafe { SimNotification::release_id(id_inner) },
+            _ => fail!(
+                (),
                 "release_id: Unknown notification type with id: 0x{:016x}",
                 id
             ),
@@ -68,23 +492,936 @@ impl NotificationIf for Notification {
     }
 
     fn notify(process: u64, id: u64) {
+        let target = ProcessRef::Process(process);
+        if !crate::hooks::run_notify(target, id) {
+            return;
+        }
+        #[cfg(feature = "seq")]
+        let reserved = crate::seq::on_notify(target, id);
+        #[cfg(not(feature = "seq"))]
+        let reserved: Result<(), NotifyError> = Ok(());
+        let result = reserved.and_then(|()| {
+            #[cfg(feature = "hybrid")]
+            if crate::hybrid::on_notify(target, id) {
+                return Ok(());
+            }
+            let result = Self::dispatch_try_notify(process, id);
+            // 与`notify_with`相同，失败的发送不占用额度
+            #[cfg(feature = "seq")]
+            if result.is_err() {
+                crate::seq::undo_notify(target, id);
+            }
+            result
+        });
+        // 在发送之后记录，使失败的发送（例如启用`no-panic`时）不被计为成功
+        crate::trace::notify(target, id, result);
+        if let Err(err) = result {
+            fail!(
+                (),
+                "notify: failed to notify id 0x{:016x} of process {}: {:?}",
+                id,
+                process,
+                err
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Notification {
+    /// 分发到各通知机制返回错误的发送，不调用钩子；未知类型的id返回`NotifyError::Unsupported`
+    #[cfg_attr(
+        not(any(
+            feature = "signal",
+            feature = "uintr",
+            feature = "uring",
+            feature = "kqueue",
+            feature = "fuchsia",
+            feature = "arceos",
+            feature = "vsock",
+            feature = "kvm",
+            feature = "uds",
+            feature = "signalfd",
+            feature = "mqueue",
+            feature = "netlink",
+            feature = "dbus",
+            feature = "net",
+            feature = "pipe",
+            feature = "mock",
+            feature = "sim"
+        )),
+        allow(unused_variables)
+    )]
+    fn dispatch_try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signal")]
+            SIGNAL_HIGH8 => SignalNotification::notify_to(ProcessRef::Process(process), id_inner),
+            #[cfg(feature = "uintr")]
+            UINTR_HIGH8 => UIntrNotification::try_notify(process, id_inner),
+            #[cfg(feature = "uring")]
+            URING_HIGH8 => UringNotification::try_notify(process, id_inner),
+            #[cfg(feature = "kqueue")]
+            KQUEUE_HIGH8 => KqueueNotification::try_notify(process, id_inner),
+            #[cfg(feature = "fuchsia")]
+            FUCHSIA_HIGH8 => FuchsiaNotification::try_notify(process, id_inner),
+            #[cfg(feature = "arceos")]
+            ARCEOS_HIGH8 => ArceosNotification::try_notify(process, id_inner),
+            #[cfg(feature = "vsock")]
+            VSOCK_HIGH8 => VsockNotification::try_notify(process, id_inner),
+            #[cfg(feature = "kvm")]
+            KVM_HIGH8 => KvmNotification::try_notify(process, id_inner),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::try_notify(process, id_inner),
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::try_notify(process, id_inner),
+            #[cfg(feature = "mqueue")]
+            MQUEUE_HIGH8 => MqueueNotification::try_notify(process, id_inner),
+            #[cfg(feature = "netlink")]
+            NETLINK_HIGH8 => NetlinkNotification::try_notify(process, id_inner),
+            #[cfg(feature = "dbus")]
+            DBUS_HIGH8 => DbusNotification::try_notify(process, id_inner),
+            #[cfg(feature = "net")]
+            NET_HIGH8 => NetNotification::try_notify(process, id_inner),
+            #[cfg(feature = "pipe")]
+            PIPE_HIGH8 => PipeNotification::try_notify(process, id_inner),
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => MockNotification::try_notify(process, id_inner),
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => SimNotification::try_notify(process, id_inner),
+            // 定时器与子进程退出不能由其他进程通知
+            _ => Err(NotifyError::Unsupported),
+        }
+    }
+
+    /// 分发发送给进程组或线程的通知，不调用钩子；只有基于信号的类型与不区分接收者的模拟类型支持
+    #[cfg_attr(
+        not(any(feature = "signal", feature = "signalfd")),
+        allow(unused_variables)
+    )]
+    fn dispatch_try_notify_group(target: ProcessRef, id: u64) -> Result<(), NotifyError> {
         let high8 = id & 0xFF00_0000_0000_0000;
         let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
         match high8 {
             #[cfg(feature = "signal")]
-            SIGNAL_HIGH8 => SignalNotification::notify(process, id_inner),
-            UINTR_HIGH8 => UIntrNotification::notify(process, id_inner),
-            _ => panic!("notify: Unknown notification type with id: 0x{:016x}", id),
+            SIGNAL_HIGH8 => SignalNotification::notify_to(target, id_inner),
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::notify_to(target, id_inner),
+            // 模拟的通知源不区分接收者
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => MockNotification::try_notify(0, id_inner),
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => SimNotification::try_notify(0, id_inner),
+            _ => Err(NotifyError::Unsupported),
         }
     }
+
+    /// 以`send`向进程`process`的通知源`id`发送一个通知，并与`notify_with`相同地调用钩子、维护`seq`的计数并记录
+    ///
+    /// 用于带有负载、令牌或文件描述符等不经过`dispatch_try_notify`的发送。
+    fn notify_via(
+        process: u64,
+        id: u64,
+        send: impl FnOnce() -> Result<(), NotifyError>,
+    ) -> Result<(), NotifyError> {
+        let target = ProcessRef::Process(process);
+        if !crate::hooks::run_notify(target, id) {
+            return Err(NotifyError::Rejected);
+        }
+        // 额度不足的通知与其他失败的发送相同地被记录
+        #[cfg(feature = "seq")]
+        if let Err(err) = crate::seq::on_notify(target, id) {
+            crate::trace::notify(target, id, Err(err));
+            return Err(err);
+        }
+        let result = send();
+        #[cfg(feature = "seq")]
+        if result.is_err() {
+            crate::seq::undo_notify(target, id);
+        }
+        crate::trace::notify(target, id, result);
+        result
+    }
 }
 
+#[cfg(feature = "alloc")]
 impl Notification {
+    /// 按自旋等待的策略（启用`spin-wait`时）轮询通知源，不调用钩子
+    fn spin_poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        #[cfg(feature = "spin-wait")]
+        let poll = crate::spin::poll(id, cx, |cx| Self::receive_poll_wait(id, cx));
+        #[cfg(not(feature = "spin-wait"))]
+        let poll = Self::receive_poll_wait(id, cx);
+        poll
+    }
+
+    /// 按接收方的策略（启用`receive-policy`时）轮询通知源，不调用钩子
+    fn receive_poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        #[cfg(feature = "receive-policy")]
+        let poll = crate::receive::poll(id, cx, |cx| Self::hybrid_poll_wait(id, cx))
+            .unwrap_or_else(|| Self::hybrid_poll_wait(id, cx));
+        #[cfg(not(feature = "receive-policy"))]
+        let poll = Self::hybrid_poll_wait(id, cx);
+        poll
+    }
+
+    /// 先读取共享内存中的通知（启用`hybrid`时）再轮询通知源，不调用钩子
+    fn hybrid_poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        #[cfg(feature = "hybrid")]
+        let poll = crate::hybrid::poll(id, cx, |cx| Self::dispatch_poll_wait(id, cx));
+        #[cfg(not(feature = "hybrid"))]
+        let poll = Self::dispatch_poll_wait(id, cx);
+        poll
+    }
+
+    /// `poll_wait`的分发部分，不调用钩子
+    #[cfg_attr(
+        not(any(
+            feature = "signal",
+            feature = "uintr",
+            feature = "timer",
+            feature = "child",
+            feature = "uring",
+            feature = "kqueue",
+            feature = "fuchsia",
+            feature = "arceos",
+            feature = "vsock",
+            feature = "kvm",
+            feature = "uds",
+            feature = "signalfd",
+            feature = "mqueue",
+            feature = "netlink",
+            feature = "dbus",
+            feature = "net",
+            feature = "pipe",
+            feature = "mock",
+            feature = "sim"
+        )),
+        allow(unused_variables)
+    )]
+    fn dispatch_poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signal")]
+            SIGNAL_HIGH8 => SignalNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "uintr")]
+            UINTR_HIGH8 => UIntrNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "timer")]
+            TIMER_HIGH8 => TimerNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "child")]
+            CHILD_HIGH8 => ChildNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "uring")]
+            URING_HIGH8 => UringNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "kqueue")]
+            KQUEUE_HIGH8 => KqueueNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "fuchsia")]
+            FUCHSIA_HIGH8 => FuchsiaNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "arceos")]
+            ARCEOS_HIGH8 => ArceosNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "vsock")]
+            VSOCK_HIGH8 => VsockNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "kvm")]
+            KVM_HIGH8 => KvmNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "mqueue")]
+            MQUEUE_HIGH8 => MqueueNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "netlink")]
+            NETLINK_HIGH8 => NetlinkNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "dbus")]
+            DBUS_HIGH8 => DbusNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "net")]
+            NET_HIGH8 => NetNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "pipe")]
+            PIPE_HIGH8 => PipeNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => MockNotification::poll_wait_reason(id_inner, cx),
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => SimNotification::poll_wait_reason(id_inner, cx),
+            // 未知类型的通知源视为已被释放
+            _ => fail!(
+                Poll::Ready(WakeReason::Shutdown),
+                "poll_wait: Unknown notification type with id: 0x{:016x}",
+                id
+            ),
+        }
+    }
+
+    /// 为新申请的通知源的id加上类型标签`high8`
+    #[cfg_attr(
+        not(any(
+            feature = "signal",
+            feature = "timer",
+            feature = "child",
+            feature = "uring",
+            feature = "kqueue",
+            feature = "fuchsia",
+            feature = "arceos",
+            feature = "vsock",
+            feature = "kvm",
+            feature = "uds",
+            feature = "signalfd",
+            feature = "mqueue",
+            feature = "netlink",
+            feature = "dbus",
+            feature = "net",
+            feature = "pipe"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn tagged(id: u64, high8: u64) -> u64 {
+        let id = (id & 0x00FF_FFFF_FFFF_FFFF) | high8;
+        crate::trace::new_id(id);
+        crate::snapshot::new_id(id);
+        #[cfg(feature = "debug-leaks")]
+        crate::leaks::new_id(id);
+        #[cfg(feature = "lease")]
+        crate::lease::new_id(id);
+        id
+    }
+
     /// 申请一个使用信号的通知源，并返回其id
     ///
+    /// 实时信号已被占用完时，按[`policy::set_overflow_policy`](crate::policy::set_overflow_policy)设置的机制申请，
+    /// 可通过`kind_of`得知实际使用的机制；未设置时返回`None`。
+    ///
     /// 该函数需要在tokio运行时内部调用，因为其会同时开始信号的接收。
     #[cfg(feature = "signal")]
     pub fn new_id_signal() -> Option<u64> {
-        SignalNotification::new_id().map(|id| (id & 0x00FF_FFFF_FFFF_FFFF) | SIGNAL_HIGH8)
+        SignalNotification::new_id()
+            .map(|id| Self::tagged(id, SIGNAL_HIGH8))
+            .or_else(crate::policy::new_id_overflow)
+    }
+
+    /// 申请一个每隔`period`到期一次的定时器通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将定时器注册到tokio的reactor中。
+    #[cfg(feature = "timer")]
+    pub fn new_id_timer(period: core::time::Duration) -> Option<u64> {
+        TimerNotification::new_id_with_period(period).map(|id| Self::tagged(id, TIMER_HIGH8))
+    }
+
+    /// 申请一个在进程`pid`退出时被触发的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将pidfd注册到tokio的reactor中。
+    #[cfg(feature = "child")]
+    pub fn new_id_child(pid: u64) -> Option<u64> {
+        ChildNotification::new_id_with_pid(pid).map(|id| Self::tagged(id, CHILD_HIGH8))
+    }
+
+    /// 以指定的投递类别向另一进程的通知源发送通知
+    ///
+    /// 与`notify`不同，发送失败时返回错误而非panic。不能由其他进程发送通知的类型（定时器、子进程退出）返回`NotifyError::Unsupported`。
+    ///
+    /// `Delivery::Reliable`只保证通知不会在发送端被丢弃；接收端在两次等待之间收到的多个通知仍可能被合并为一次唤醒。
+    /// 只有信号区分两种投递类别：其他类型或由内核暂存通知（io_uring的完成队列、消息队列），
+    /// 或在缓冲区满时返回`Overflow`而不丢弃（vsock），或本身不保证投递（UDP），两种投递类别相同。
+    #[cfg_attr(not(feature = "signal"), allow(unused_variables))]
+    pub fn notify_with(process: u64, id: u64, delivery: Delivery) -> Result<(), NotifyError> {
+        if !crate::hooks::run_notify(ProcessRef::Process(process), id) {
+            return Err(NotifyError::Rejected);
+        }
+        // 额度不足的通知与其他失败的发送相同地被记录
+        #[cfg(feature = "seq")]
+        if let Err(err) = crate::seq::on_notify(ProcessRef::Process(process), id) {
+            crate::trace::notify(ProcessRef::Process(process), id, Err(err));
+            return Err(err);
+        }
+        #[cfg(feature = "hybrid")]
+        if crate::hybrid::on_notify(ProcessRef::Process(process), id) {
+            crate::trace::notify(ProcessRef::Process(process), id, Ok(()));
+            return Ok(());
+        }
+        let result = match id & 0xFF00_0000_0000_0000 {
+            #[cfg(feature = "signal")]
+            SIGNAL_HIGH8 => {
+                SignalNotification::notify_with(process, id & 0x00FF_FFFF_FFFF_FFFF, delivery)
+            }
+            _ => Self::dispatch_try_notify(process, id),
+        };
+        #[cfg(feature = "seq")]
+        if result.is_err() {
+            crate::seq::undo_notify(ProcessRef::Process(process), id);
+        }
+        crate::trace::notify(ProcessRef::Process(process), id, result);
+        result
+    }
+
+    /// 检查能否向另一进程的通知源发送通知，但不发送通知，用于在建立连接时尽早发现错误
+    ///
+    /// 返回的错误与`notify_with`发送失败时相同，例如对端进程不存在时为`NotifyError::Os(ESRCH)`，
+    /// 无权向其发送信号时为`NotifyError::Os(EPERM)`，不能由其他进程发送通知的类型为`NotifyError::Unsupported`。
+    /// 检查的方式因类型而异：信号使用空信号，io_uring使用`pidfd_getfd`，unix域套接字检查地址是否已被绑定，
+    /// 管道与消息队列以写方式打开后关闭，eventpair检查对端是否仍未关闭。不经过[`hooks`](crate::hooks)，也不检查`seq`的额度。
+    ///
+    /// 内核原语（ArceOS）由[`SyscallIf::can_send`](crate::hal::SyscallIf::can_send)检查，内核不提供检查时总是通过。
+    /// 无连接的数据报（vsock、netlink、UDP）与D-Bus无法在不发送的情况下确认接收方存在，只检查本地的条件。
+    /// 检查通过不保证之后的发送成功，例如对端可能在此期间退出。
+    #[cfg_attr(not(feature = "signal"), allow(unused_variables))]
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signal")]
+            SIGNAL_HIGH8 => SignalNotification::can_notify(process, id_inner),
+            #[cfg(feature = "uintr")]
+            UINTR_HIGH8 => UIntrNotification::can_notify(process, id_inner),
+            #[cfg(feature = "uring")]
+            URING_HIGH8 => UringNotification::can_notify(process, id_inner),
+            #[cfg(feature = "kqueue")]
+            KQUEUE_HIGH8 => {
+                if process == unsafe { libc::getpid() } as u64 {
+                    Ok(())
+                } else {
+                    Err(NotifyError::Unsupported)
+                }
+            }
+            #[cfg(feature = "fuchsia")]
+            FUCHSIA_HIGH8 => FuchsiaNotification::can_notify(process, id_inner),
+            #[cfg(feature = "arceos")]
+            ARCEOS_HIGH8 => ArceosNotification::can_notify(process, id_inner),
+            #[cfg(feature = "vsock")]
+            VSOCK_HIGH8 => Ok(()),
+            #[cfg(feature = "kvm")]
+            KVM_HIGH8 => KvmNotification::eventfd(id_inner)
+                .map(drop)
+                .ok_or(NotifyError::Os(libc::EBADF)),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::can_notify(process, id_inner),
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::can_notify(process, id_inner),
+            #[cfg(feature = "mqueue")]
+            MQUEUE_HIGH8 => MqueueNotification::can_notify(process, id_inner),
+            #[cfg(feature = "netlink")]
+            NETLINK_HIGH8 => Ok(()),
+            #[cfg(feature = "dbus")]
+            DBUS_HIGH8 => Ok(()),
+            #[cfg(feature = "net")]
+            NET_HIGH8 => NetNotification::can_notify(process, id_inner),
+            #[cfg(feature = "pipe")]
+            PIPE_HIGH8 => PipeNotification::can_notify(process, id_inner),
+            #[cfg(feature = "mock")]
+            MOCK_HIGH8 => MockNotification::can_notify(process, id_inner),
+            #[cfg(feature = "sim")]
+            SIM_HIGH8 => SimNotification::can_notify(process, id_inner),
+            _ => Err(NotifyError::Unsupported),
+        }
+    }
+
+    /// 向`target`所指的进程、进程组或线程的通知源发送通知
+    ///
+    /// 发送失败时返回错误而非panic。不支持该接收者的通知类型返回`NotifyError::Unsupported`。
+    pub fn notify_to(target: ProcessRef, id: u64) -> Result<(), NotifyError> {
+        if !crate::hooks::run_notify(target, id) {
+            return Err(NotifyError::Rejected);
+        }
+        // 额度不足的通知与其他失败的发送相同地被记录
+        #[cfg(feature = "seq")]
+        if let Err(err) = crate::seq::on_notify(target, id) {
+            crate::trace::notify(target, id, Err(err));
+            return Err(err);
+        }
+        #[cfg(feature = "hybrid")]
+        if crate::hybrid::on_notify(target, id) {
+            crate::trace::notify(target, id, Ok(()));
+            return Ok(());
+        }
+        let result = match target {
+            // 发送给进程的通知与`notify`使用同一分发，使各通知机制只需在一处接入
+            ProcessRef::Process(process) => Self::dispatch_try_notify(process, id),
+            _ => Self::dispatch_try_notify_group(target, id),
+        };
+        #[cfg(feature = "seq")]
+        if result.is_err() {
+            crate::seq::undo_notify(target, id);
+        }
+        crate::trace::notify(target, id, result);
+        result
+    }
+
+    /// 依次向每个`(process, id)`发送通知，返回发送失败的目标在`targets`中的下标及失败原因
+    ///
+    /// 与逐个调用`notify`不同，部分目标失败不会中止发送，也不会panic。
+    pub fn notify_batch(targets: &[(u64, u64)]) -> Vec<(usize, NotifyError)> {
+        targets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &(process, id))| {
+                Self::notify_to(ProcessRef::Process(process), id)
+                    .err()
+                    .map(|err| (index, err))
+            })
+            .collect()
+    }
+
+    /// 向进程组`pgid`中的所有进程发送通知
+    pub fn notify_pgroup(pgid: u64, id: u64) -> Result<(), NotifyError> {
+        Self::notify_to(ProcessRef::Group(pgid), id)
+    }
+
+    /// 向进程`pid`中的线程`tid`发送通知
+    pub fn notify_thread(pid: u64, tid: u64, id: u64) -> Result<(), NotifyError> {
+        Self::notify_to(ProcessRef::Thread { pid, tid }, id)
+    }
+
+    /// 申请一个使用io_uring的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将io_uring注册到tokio的reactor中。
+    #[cfg(feature = "uring")]
+    pub fn new_id_uring() -> Option<u64> {
+        UringNotification::new_id().map(|id| Self::tagged(id, URING_HIGH8))
+    }
+
+    /// 申请一个使用kqueue的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将kqueue注册到tokio的reactor中。
+    #[cfg(feature = "kqueue")]
+    pub fn new_id_kqueue() -> Option<u64> {
+        KqueueNotification::new_id().map(|id| Self::tagged(id, KQUEUE_HIGH8))
+    }
+
+    /// 申请一个使用zircon eventpair的通知源，返回其id与应交给发送方的另一端句柄
+    #[cfg(feature = "fuchsia")]
+    pub fn new_id_fuchsia() -> Option<(u64, u32)> {
+        FuchsiaNotification::new_id_with_peer()
+            .map(|(id, peer)| (Self::tagged(id, FUCHSIA_HIGH8), peer))
+    }
+
+    /// 将从接收方得到的eventpair端登记为发送用的id
+    #[cfg(feature = "fuchsia")]
+    pub fn adopt_fuchsia_peer(handle: u32) -> u64 {
+        (FuchsiaNotification::adopt_peer(handle) & 0x00FF_FFFF_FFFF_FFFF) | FUCHSIA_HIGH8
+    }
+
+    /// 关闭由`adopt_fuchsia_peer`登记的发送用的id
+    ///
+    /// `id`不是eventpair通知源时panic（启用`no-panic`时不做任何事）。
+    #[cfg(feature = "fuchsia")]
+    pub fn release_fuchsia_peer(id: u64) {
+        if id & 0xFF00_0000_0000_0000 != FUCHSIA_HIGH8 {
+            fail!(
+                return,
+                "release_fuchsia_peer: not a fuchsia id: 0x{:016x}",
+                id
+            )
+        }
+        FuchsiaNotification::release_peer(id & 0x00FF_FFFF_FFFF_FFFF);
+    }
+
+    /// 申请一个使用ArceOS内核通知对象的通知源，并返回其id
+    #[cfg(feature = "arceos")]
+    pub fn new_id_arceos() -> Option<u64> {
+        ArceosNotification::new_id().map(|id| Self::tagged(id, ARCEOS_HIGH8))
+    }
+
+    /// 申请一个使用vsock数据报的通知源，并返回其id
+    ///
+    /// 向该通知源发送通知时，`process`参数为本机的CID（见`VsockNotification::local_cid`）。
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "vsock")]
+    pub fn new_id_vsock() -> Option<u64> {
+        VsockNotification::new_id().map(|id| Self::tagged(id, VSOCK_HIGH8))
+    }
+
+    /// 申请一个可用作KVM ioeventfd的eventfd通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将eventfd注册到tokio的reactor中。
+    #[cfg(feature = "kvm")]
+    pub fn new_id_kvm_ioeventfd() -> Option<u64> {
+        KvmNotification::new_id().map(|id| Self::tagged(id, KVM_HIGH8))
+    }
+
+    /// 申请一个可用作KVM irqfd的eventfd通知源，并返回其id；该通知源只能用于发送
+    #[cfg(feature = "kvm")]
+    pub fn new_id_kvm_irqfd() -> Option<u64> {
+        KvmNotification::new_id_irqfd().map(|id| Self::tagged(id, KVM_HIGH8))
+    }
+
+    /// 由`new_id_kvm_ioeventfd`或`new_id_kvm_irqfd`申请的通知源对应的eventfd，用于注册到KVM中
+    #[cfg(feature = "kvm")]
+    pub fn kvm_eventfd(id: u64) -> Option<i32> {
+        if id & 0xFF00_0000_0000_0000 != KVM_HIGH8 {
+            return None;
+        }
+        KvmNotification::eventfd(id & 0x00FF_FFFF_FFFF_FFFF)
+    }
+
+    /// 申请一个使用unix域套接字的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "uds")]
+    pub fn new_id_uds() -> Option<u64> {
+        UdsNotification::new_id().map(|id| Self::tagged(id, UDS_HIGH8))
+    }
+
+    /// 经`SCM_RIGHTS`向进程`process`的unix域套接字通知源传递文件描述符，同时发送一个通知
+    ///
+    /// 其他类型的通知源返回`NotifyError::Unsupported`。
+    #[cfg(feature = "uds")]
+    pub fn uds_send_fds(process: u64, id: u64, fds: &[i32]) -> Result<(), NotifyError> {
+        Self::notify_via(process, id, || {
+            if id & 0xFF00_0000_0000_0000 != UDS_HIGH8 {
+                return Err(NotifyError::Unsupported);
+            }
+            UdsNotification::send_fds(process, id & 0x00FF_FFFF_FFFF_FFFF, fds)
+        })
+    }
+
+    /// 接收方：为用户态中断通知源创建uintr fd，并经已连接的unix域套接字`socket`交给发送方
+    ///
+    /// 需在通知源所在的接收线程中调用。其他类型的通知源返回`NotifyError::Unsupported`。
+    #[cfg(feature = "uintr")]
+    pub fn uintr_serve_sender(id: u64, socket: i32) -> Result<(), NotifyError> {
+        if id & 0xFF00_0000_0000_0000 != UINTR_HIGH8 {
+            return Err(NotifyError::Unsupported);
+        }
+        UIntrNotification::serve_sender(id & 0x00FF_FFFF_FFFF_FFFF, socket)
+    }
+
+    /// 发送方：从已连接的unix域套接字`socket`收取进程`process`以`uintr_serve_sender`交出的uintr fd并登记，
+    /// 返回通知源的id，之后即可以`notify(process, id)`发送通知
+    #[cfg(feature = "uintr")]
+    pub fn uintr_connect_sender(process: u64, socket: i32) -> Result<u64, NotifyError> {
+        UIntrNotification::connect_sender(process, socket).map(|id| id | UINTR_HIGH8)
+    }
+
+    /// 取出unix域套接字通知源上已收到的文件描述符，应在等待结束后调用
+    #[cfg(feature = "uds")]
+    pub fn uds_take_fds(id: u64) -> Vec<std::os::fd::OwnedFd> {
+        if id & 0xFF00_0000_0000_0000 != UDS_HIGH8 {
+            return Vec::new();
+        }
+        UdsNotification::take_fds(id & 0x00FF_FFFF_FFFF_FFFF)
+    }
+
+    /// 申请一个使用signalfd接收信号的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将signalfd注册到tokio的reactor中。
+    #[cfg(feature = "signalfd")]
+    pub fn new_id_signalfd() -> Option<u64> {
+        SignalfdNotification::new_id().map(|id| Self::tagged(id, SIGNALFD_HIGH8))
+    }
+
+    /// 在使用signalfd的通知源上等待，并返回随通知到达的发送方进程号与附加值
+    ///
+    /// `id`不是signalfd通知源时panic（启用`no-panic`时返回的future立即以`None`完成，与已被释放的通知源相同）。
+    #[cfg(feature = "signalfd")]
+    pub fn wait_on_signalfd_info(id: u64) -> crate::signalfd::WaitInfo {
+        if id & 0xFF00_0000_0000_0000 != SIGNALFD_HIGH8 {
+            // 内部id不超过56位，`u64::MAX`不会被占用
+            fail!(
+                return SignalfdNotification::wait_on_info(u64::MAX),
+                "wait_on_signalfd_info: not a signalfd id: 0x{:016x}",
+                id
+            )
+        }
+        SignalfdNotification::wait_on_info(id & 0x00FF_FFFF_FFFF_FFFF)
+    }
+
+    /// 申请一个可缓存`max_msgs`条、每条负载不超过`msg_size`字节的POSIX消息队列通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将消息队列注册到tokio的reactor中。
+    #[cfg(feature = "mqueue")]
+    pub fn new_id_mqueue(max_msgs: usize, msg_size: usize) -> Option<u64> {
+        MqueueNotification::new_id_with_capacity(max_msgs, msg_size)
+            .map(|id| Self::tagged(id, MQUEUE_HIGH8))
+    }
+
+    /// 向进程`process`的通知源发送一个带有负载的通知
+    ///
+    /// 只有POSIX消息队列通知源支持负载，其他类型的通知源返回`NotifyError::Unsupported`。
+    #[cfg_attr(not(feature = "mqueue"), allow(unused_variables))]
+    pub fn notify_with_payload(process: u64, id: u64, payload: &[u8]) -> Result<(), NotifyError> {
+        Self::notify_via(process, id, || {
+            let high8 = id & 0xFF00_0000_0000_0000;
+            let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+            match high8 {
+                #[cfg(feature = "mqueue")]
+                MQUEUE_HIGH8 => MqueueNotification::notify_with(process, id_inner, payload),
+                _ => Err(NotifyError::Unsupported),
+            }
+        })
+    }
+
+    /// 在POSIX消息队列通知源上等待，并返回消息的负载
+    ///
+    /// `id`不是消息队列通知源时panic（启用`no-panic`时返回的future立即以`None`完成，与已被释放的通知源相同）。
+    #[cfg(feature = "mqueue")]
+    pub fn wait_on_mqueue_info(id: u64) -> crate::mqueue::WaitInfo {
+        if id & 0xFF00_0000_0000_0000 != MQUEUE_HIGH8 {
+            // 内部id不超过56位，`u64::MAX`不会被占用
+            fail!(
+                return MqueueNotification::wait_on_info(u64::MAX),
+                "wait_on_mqueue_info: not a mqueue id: 0x{:016x}",
+                id
+            )
+        }
+        MqueueNotification::wait_on_info(id & 0x00FF_FFFF_FFFF_FFFF)
+    }
+
+    /// 申请一个使用`NETLINK_USERSOCK`套接字的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "netlink")]
+    pub fn new_id_netlink() -> Option<u64> {
+        NetlinkNotification::new_id().map(|id| Self::tagged(id, NETLINK_HIGH8))
+    }
+
+    /// 申请一个使用指定netlink协议的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "netlink")]
+    pub fn new_id_netlink_with_protocol(protocol: i32) -> Option<u64> {
+        NetlinkNotification::new_id_with_protocol(protocol)
+            .map(|id| Self::tagged(id, NETLINK_HIGH8))
+    }
+
+    /// 申请一个转发D-Bus信号的通知源，并返回其id；`notify`发出该通知源所匹配的信号
+    #[cfg(feature = "dbus")]
+    pub fn new_id_dbus() -> Option<u64> {
+        DbusNotification::new_id().map(|id| Self::tagged(id, DBUS_HIGH8))
+    }
+
+    /// 申请一个转发指定D-Bus信号的通知源，并返回其id，`None`表示不限
+    #[cfg(feature = "dbus")]
+    pub fn new_id_dbus_match(
+        path: Option<&str>,
+        interface: Option<&str>,
+        member: Option<&str>,
+    ) -> Option<u64> {
+        DbusNotification::new_id_with_match(path, interface, member)
+            .map(|id| Self::tagged(id, DBUS_HIGH8))
+    }
+
+    /// 申请一个在回环地址上接收UDP数据报的通知源，并返回其id
+    ///
+    /// 向该通知源发送通知时，`process`参数为由`NetNotification::register_endpoint`登记的端点句柄。
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "net")]
+    pub fn new_id_net() -> Option<u64> {
+        NetNotification::new_id().map(|id| Self::tagged(id, NET_HIGH8))
+    }
+
+    /// 申请一个在地址`addr`上接收UDP数据报的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    #[cfg(feature = "net")]
+    pub fn new_id_net_with_addr(addr: std::net::SocketAddr) -> Option<u64> {
+        NetNotification::new_id_with_addr(addr).map(|id| Self::tagged(id, NET_HIGH8))
+    }
+
+    /// 申请一个使用管道的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将管道注册到tokio的reactor中。
+    #[cfg(feature = "pipe")]
+    pub fn new_id_pipe() -> Option<u64> {
+        PipeNotification::new_id().map(|id| Self::tagged(id, PIPE_HIGH8))
+    }
+
+    /// 申请一个模拟的通知源，并返回其id，用于测试
+    ///
+    /// 通知只能由[`crate::mock::trigger`]或`notify`在本进程中触发，不需要异步运行时。
+    #[cfg(feature = "mock")]
+    pub fn new_id_mock() -> Option<u64> {
+        MockNotification::new_id().map(|id| Self::tagged(id, MOCK_HIGH8))
+    }
+
+    /// 申请一个使用虚拟时间的模拟通知源，并返回其id，用于测试
+    ///
+    /// 通知的投递由[`crate::sim`]中的调度器控制，不需要异步运行时。
+    #[cfg(feature = "sim")]
+    pub fn new_id_sim() -> Option<u64> {
+        SimNotification::new_id().map(|id| Self::tagged(id, SIM_HIGH8))
+    }
+
+    /// 申请一个使用信号的通知源并为其记录标签，返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会同时开始信号的接收。
+    #[cfg(feature = "signal")]
+    pub fn new_id_signal_with_label(label: &'static str) -> Option<u64> {
+        SignalNotification::new_id_with_label(label).map(|id| Self::tagged(id, SIGNAL_HIGH8))
+    }
+
+    /// 按优先级申请一个使用信号的通知源并为其设置该优先级，返回其id
+    ///
+    /// 优先级越高，信号编号越小，见[`SignalNotification::new_id_with_priority`]。
+    /// 该函数需要在tokio运行时内部调用，因为其会同时开始信号的接收。
+    #[cfg(feature = "signal")]
+    pub fn new_id_signal_with_priority(priority: crate::set::Priority) -> Option<u64> {
+        let id = SignalNotification::new_id_with_priority(priority)
+            .map(|id| Self::tagged(id, SIGNAL_HIGH8))?;
+        Self::set_priority(id, priority);
+        Some(id)
+    }
+
+    /// 查询通知源的占用者信息，若通知源未被占用或该类型的通知源不记录占用者信息，则返回`None`
+    #[cfg_attr(not(feature = "signal"), allow(unused_variables))]
+    pub fn owner(id: u64) -> Option<OwnerInfo> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signal")]
+            SIGNAL_HIGH8 => SignalNotification::owner(id_inner),
+            _ => None,
+        }
+    }
+
+    /// 列出所有被占用的通知源及其占用者信息
+    pub fn owners() -> Vec<(u64, OwnerInfo)> {
+        #[cfg_attr(not(feature = "signal"), allow(unused_mut))]
+        let mut owners = Vec::new();
+        #[cfg(feature = "signal")]
+        owners.extend(
+            SignalNotification::owners()
+                .into_iter()
+                .map(|(id, owner)| (id | SIGNAL_HIGH8, owner)),
+        );
+        owners
+    }
+
+    /// 只接受`filter`允许的发送方发送到通知源的通知，其他通知被丢弃并计数；`filter`为`None`时清除过滤器
+    ///
+    /// 只有`signalfd`与`uds`通知源支持，见[`filter`](crate::filter)。返回是否设置成功，
+    /// 不支持过滤的通知源或未被占用的通知源返回`false`。
+    #[cfg(any(feature = "signalfd", feature = "uds"))]
+    pub fn set_sender_filter(id: u64, filter: Option<crate::filter::SenderFilter>) -> bool {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::set_sender_filter(id_inner, filter),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::set_sender_filter(id_inner, filter),
+            _ => false,
+        }
+    }
+
+    /// 通知源上被发送方过滤器丢弃的通知数；不支持过滤的通知源或未被占用的通知源返回`None`
+    #[cfg(any(feature = "signalfd", feature = "uds"))]
+    pub fn dropped_by_filter(id: u64) -> Option<u64> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::dropped_by_filter(id_inner),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::dropped_by_filter(id_inner),
+            _ => None,
+        }
+    }
+
+    /// 为通知源生成一个随机的令牌，之后只接受带有该令牌的通知，其他通知被丢弃并计数
+    ///
+    /// 接收方应将令牌告知被授权的发送方，由其通过`notify_with_token`发送通知；再次调用时生成新的令牌，旧的令牌失效。
+    /// 只有`signalfd`与`uds`通知源支持，见[`filter`](crate::filter)。不支持的通知源、未被占用的通知源
+    /// 或无法取得随机数时返回`None`。
+    #[cfg(any(feature = "signalfd", feature = "uds"))]
+    pub fn mint_token(id: u64) -> Option<u64> {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::mint_token(id_inner),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::mint_token(id_inner),
+            _ => None,
+        }
+    }
+
+    /// 不再要求通知源上的通知带有令牌，返回是否成功
+    #[cfg(any(feature = "signalfd", feature = "uds"))]
+    pub fn clear_token(id: u64) -> bool {
+        let high8 = id & 0xFF00_0000_0000_0000;
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match high8 {
+            #[cfg(feature = "signalfd")]
+            SIGNALFD_HIGH8 => SignalfdNotification::clear_token(id_inner),
+            #[cfg(feature = "uds")]
+            UDS_HIGH8 => UdsNotification::clear_token(id_inner),
+            _ => false,
+        }
+    }
+
+    /// 向另一进程的通知源发送携带令牌`token`的通知，令牌由接收方的`mint_token`生成
+    ///
+    /// 令牌不正确的通知在接收方被丢弃，发送方无法得知。不支持令牌的通知源返回`NotifyError::Unsupported`。
+    #[cfg(any(feature = "signalfd", feature = "uds"))]
+    pub fn notify_with_token(process: u64, id: u64, token: u64) -> Result<(), NotifyError> {
+        Self::notify_via(process, id, || {
+            let high8 = id & 0xFF00_0000_0000_0000;
+            let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+            match high8 {
+                #[cfg(feature = "signalfd")]
+                SIGNALFD_HIGH8 => SignalfdNotification::notify_token(process, id_inner, token),
+                #[cfg(feature = "uds")]
+                UDS_HIGH8 => UdsNotification::notify_token(process, id_inner, token),
+                _ => Err(NotifyError::Unsupported),
+            }
+        })
+    }
+
+    /// 为进程检查点（例如CRIU）做准备：暂停信号通知源的接收，并关闭接收信号所用的文件描述符
+    ///
+    /// 只能暂停`signal`通知源。存在其他尚未被释放的通知源时不暂停任何通知源，返回[`Self::checkpoint_blockers`]列出的这些通知源：
+    /// io_uring与用户态中断等内核状态无法被检查点工具保存，其他通知机制的状态也未经本函数处理，调用者应先释放它们再重试。
+    ///
+    /// 暂停期间在信号通知源上的等待保持挂起，发送到本进程的信号通知可能丢失。
+    /// 成功后需调用`resume_after_restore`恢复接收，无论进程是从检查点恢复还是继续运行。
+    #[cfg(feature = "signal")]
+    pub fn prepare_checkpoint() -> Result<(), Vec<u64>> {
+        let blockers = Self::checkpoint_blockers();
+        if !blockers.is_empty() {
+            return Err(blockers);
+        }
+        SignalNotification::prepare_checkpoint();
+        Ok(())
+    }
+
+    /// 本进程中尚未被释放、使[`Self::prepare_checkpoint`]失败的非`signal`通知源，按id排序
+    ///
+    /// 与[`Self::list_ids`]相同，只包括经[`Notification`]申请的通知源。
+    #[cfg(feature = "signal")]
+    pub fn checkpoint_blockers() -> Vec<u64> {
+        Self::list_ids()
+            .into_iter()
+            .filter(|info| info.kind != Some(crate::kind::NotificationKind::Signal))
+            .map(|info| info.id)
+            .collect()
+    }
+
+    /// 在从检查点恢复之后（或检查点完成之后）重新开始信号通知源的接收
+    ///
+    /// 由于暂停期间的通知可能丢失，每个信号通知源的等待者都会被唤醒一次，以重新检查其等待的条件。
+    /// 需要在tokio运行时内部调用。
+    #[cfg(feature = "signal")]
+    pub fn resume_after_restore() {
+        SignalNotification::resume_after_restore();
+    }
+
+    /// 检查对端进程是否仍然存在，返回其中已不存在的进程
+    ///
+    /// 用于在从检查点恢复后重新验证对端进程的pid。
+    #[cfg(feature = "signal")]
+    pub fn revalidate_peers(processes: &[u64]) -> Vec<u64> {
+        processes
+            .iter()
+            .copied()
+            .filter(|&process| {
+                let res = unsafe { libc::kill(process as libc::pid_t, 0) };
+                res != 0 && unsafe { *libc::__errno_location() } == libc::ESRCH
+            })
+            .collect()
+    }
+
+    /// 输出所有被占用的通知源及其占用者信息，每行一个通知源
+    pub fn dump_owners(w: &mut impl fmt::Write) -> fmt::Result {
+        for (id, owner) in Self::owners() {
+            writeln!(w, "{:#018x} {}", id, owner)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,54 @@
+//! 基于共享内存与通知的进程间同步原语
+//!
+//! 每个原语的状态位于一个POSIX共享内存对象中，以创建者的进程号与通知源命名，等待的一方在自己的通知源上挂起。
+//! 创建者申请通知源并新建原语，把进程号与id通过其他途径告知其他进程，其他进程以此打开同一原语。
+//! 通知源由调用者申请与释放，不应被用于其他用途；共享内存在创建者的句柄被drop时删除。
+//!
+//! - [`oneshot`]：一次性传递一个值
+//! - [`channel`]：有界的环形缓冲区，只在接收方等待时发送通知
+//! - [`Semaphore`]：按等待顺序分配许可的信号量
+//! - [`Barrier`]：等待所有参与者到达的屏障，可重复使用
+//! - [`Condvar`]：在共享内存中的条件成立期间等待的条件变量
+
+use crate::shm;
+
+mod barrier;
+pub mod channel;
+mod condvar;
+pub mod oneshot;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWait, MAX_PARTIES};
+pub use condvar::{Condvar, WaitWhile};
+pub use semaphore::{Acquire, MAX_WAITERS, Semaphore};
+
+/// 可以按字节在进程之间复制的类型
+///
+/// # Safety
+///
+/// 类型的任意字节都必须是有效的值，且不能包含指针或引用等只在本进程中有意义的内容。
+pub unsafe trait Pod: Copy + Send + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty)*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+fn getpid() -> u64 {
+    unsafe { libc::getpid() as u64 }
+}
+
+/// 新建或打开进程`process`的通知源`id`在原语`kind`下的共享状态
+fn map<T>(kind: &str, process: u64, id: u64, create: bool) -> Option<shm::Mapping<T>> {
+    shm::Mapping::map(&shm::name(kind, process, id), create).ok()
+}
+
+/// 删除本进程的通知源`id`在原语`kind`下的共享状态
+fn unlink(kind: &str, id: u64) {
+    shm::unlink(&shm::name(kind, getpid(), id));
+}
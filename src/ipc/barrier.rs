@@ -0,0 +1,208 @@
+//! 进程间的屏障
+//!
+//! `n`个参与者各持有一个[`Barrier`]句柄，在自己的通知源上等待。[`Barrier::wait`]记录到达，最后到达的一方开始新的一代，
+//! 并通知本代所有已登记的等待者。屏障可以重复使用，每一代恰有一个参与者的`wait`返回`true`。
+//!
+//! 到达后被drop的`wait`仍被计为已到达。
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf, ProcessRef},
+    shm,
+};
+
+const KIND: &str = "barrier";
+
+/// 参与者数量的上限
+pub const MAX_PARTIES: usize = 64;
+
+/// 一个已登记的等待者，`generation`为其等待的代数加1
+#[repr(C)]
+struct Waiter {
+    generation: AtomicU64,
+    process: AtomicU64,
+    id: AtomicU64,
+}
+
+/// 位于共享内存中的状态
+#[repr(C)]
+struct Shared {
+    parties: AtomicU64,
+    /// 高32位为当前的代数，低32位为本代已到达的数量
+    state: AtomicU64,
+    /// 相邻的两代交替使用；下下一代开始之前，本代的等待者都已到达下一代，不再需要被通知
+    waiters: [[Waiter; MAX_PARTIES]; 2],
+}
+
+/// 进程间的屏障的一个句柄
+pub struct Barrier {
+    id: u64,
+    owner: bool,
+    shared: shm::Mapping<Shared>,
+}
+
+impl Barrier {
+    /// 新建有`n`个参与者的屏障，本句柄在本进程的通知源`key`上等待；`n`为0或超过[`MAX_PARTIES`]、
+    /// 状态已存在或无法创建时返回`None`
+    ///
+    /// 其他参与者以本进程的进程号与`key`打开同一屏障。本句柄被drop时删除屏障的共享内存。
+    pub fn new(n: usize, key: u64) -> Option<Self> {
+        if n == 0 || n > MAX_PARTIES {
+            return None;
+        }
+        let shared: shm::Mapping<Shared> = super::map(KIND, super::getpid(), key, true)?;
+        shared.get().parties.store(n as u64, Ordering::SeqCst);
+        Some(Self {
+            id: key,
+            owner: true,
+            shared,
+        })
+    }
+
+    /// 打开进程`process`以通知源`key`新建的屏障，本句柄在本进程的通知源`id`上等待；屏障不存在时返回`None`
+    pub fn open(process: u64, key: u64, id: u64) -> Option<Self> {
+        Some(Self {
+            id,
+            owner: false,
+            shared: super::map(KIND, process, key, false)?,
+        })
+    }
+
+    /// 参与者的数量
+    pub fn parties(&self) -> usize {
+        self.shared.get().parties.load(Ordering::SeqCst) as usize
+    }
+
+    /// 到达屏障，等待所有参与者到达；最后到达的一方返回`true`
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            generation: None,
+        }
+    }
+
+    /// 记录到达，返回到达时的代数，以及是否是最后到达的一方
+    fn arrive(&self) -> (u64, bool) {
+        let shared = self.shared.get();
+        let parties = shared.parties.load(Ordering::SeqCst);
+        let mut state = shared.state.load(Ordering::SeqCst);
+        loop {
+            let (generation, arrived) = (state >> 32, state & 0xFFFF_FFFF);
+            let last = arrived + 1 >= parties;
+            let next = if last {
+                ((generation + 1) & 0xFFFF_FFFF) << 32
+            } else {
+                state + 1
+            };
+            match shared
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) if last => {
+                    let waiters = &shared.waiters[(generation % 2) as usize];
+                    for waiter in &waiters[..arrived as usize] {
+                        // 尚未登记的等待者在登记之后会发现代数已改变
+                        if waiter.generation.load(Ordering::SeqCst) == generation + 1 {
+                            let process = waiter.process.load(Ordering::Relaxed);
+                            let id = waiter.id.load(Ordering::Relaxed);
+                            let _ = Notification::notify_to(ProcessRef::Process(process), id);
+                        }
+                    }
+                    return (generation, true);
+                }
+                Ok(_) => {
+                    let waiter = &shared.waiters[(generation % 2) as usize][arrived as usize];
+                    waiter.process.store(super::getpid(), Ordering::Relaxed);
+                    waiter.id.store(self.id, Ordering::Relaxed);
+                    waiter.generation.store(generation + 1, Ordering::SeqCst);
+                    return (generation, false);
+                }
+                Err(current) => state = current,
+            }
+        }
+    }
+}
+
+impl Drop for Barrier {
+    fn drop(&mut self) {
+        if self.owner {
+            super::unlink(KIND, self.id);
+        }
+    }
+}
+
+/// [`Barrier::wait`]返回的future
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    generation: Option<u64>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let generation = match self.generation {
+            Some(generation) => generation,
+            None => match self.barrier.arrive() {
+                (_, true) => return Poll::Ready(true),
+                (generation, false) => {
+                    self.generation = Some(generation);
+                    generation
+                }
+            },
+        };
+        let shared = self.barrier.shared.get();
+        loop {
+            if shared.state.load(Ordering::SeqCst) >> 32 != generation {
+                return Poll::Ready(false);
+            }
+            // 被唤醒后重新检查，通知源上遗留的通知只会造成一次多余的检查
+            if Notification::poll_wait(self.barrier.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_barrier() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let ids = [(); 3].map(|_| Notification::new_id_mock().unwrap());
+        let pid = super::super::getpid();
+        assert!(Barrier::new(0, ids[0]).is_none());
+        let owner = Barrier::new(3, ids[0]).unwrap();
+        let a = Barrier::open(pid, ids[0], ids[1]).unwrap();
+        let b = Barrier::open(pid, ids[0], ids[2]).unwrap();
+        assert_eq!(b.parties(), 3);
+
+        for _ in 0..3 {
+            let mut first = owner.wait();
+            let mut second = a.wait();
+            assert!(Pin::new(&mut first).poll(&mut cx).is_pending());
+            assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+            assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+            assert_eq!(Pin::new(&mut b.wait()).poll(&mut cx), Poll::Ready(true));
+            assert_eq!(crate::mock::pending(ids[0]), Some(1));
+            assert_eq!(crate::mock::pending(ids[1]), Some(1));
+            assert_eq!(Pin::new(&mut first).poll(&mut cx), Poll::Ready(false));
+            assert_eq!(Pin::new(&mut second).poll(&mut cx), Poll::Ready(false));
+        }
+
+        drop((a, b, owner));
+        assert!(Barrier::open(pid, ids[0], ids[1]).is_none());
+        for id in ids {
+            unsafe { Notification::release_id(id) };
+        }
+    }
+}
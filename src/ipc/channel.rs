@@ -0,0 +1,341 @@
+//! 进程间的有界通道
+//!
+//! 通道是位于共享内存中的环形缓冲区，容量`N`在编译时确定。接收方在本进程新建通道：[`spsc`]只允许同时打开一个发送端，
+//! 发送时不需要比较并交换；[`mpsc`]允许任意多个发送端，它们以比较并交换争用写入位置。其他进程以[`Sender::open`]打开发送端。
+//!
+//! 发送方不会为每个值都发送通知：接收方发现缓冲区为空、准备在通知源上挂起时先在共享内存中标记，
+//! 发送方写入后只在该标记被置位时（即缓冲区由空变为非空、且接收方正在等待时）才向接收方发送通知。
+//!
+//! 缓冲区已满时[`Sender::try_send`]立即返回，不等待空位，需要时可配合`retry`模块重试。
+//! 接收端被drop时删除共享内存，已打开的发送端此后的发送不再被接收。
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering, fence},
+    task::{Context, Poll},
+};
+
+use super::Pod;
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    shm,
+};
+
+const KIND: &str = "channel";
+
+/// 只允许一个发送端
+const SPSC: u32 = 1;
+/// 允许多个发送端
+const MPSC: u32 = 2;
+
+/// 缓冲区中的一个位置
+///
+/// 第`lap`圈时，`stamp`为`2 * lap`表示可写入，为`2 * lap + 1`表示已写入、可读取。
+#[repr(C)]
+struct Slot<T> {
+    stamp: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// 位于共享内存中的状态，全零时为空的缓冲区
+#[repr(C)]
+struct Ring<T, const N: usize> {
+    /// `SPSC`或`MPSC`，由接收方在新建时写入
+    mode: AtomicU32,
+    /// `SPSC`下是否已有打开的发送端
+    claimed: AtomicU32,
+    /// 接收方是否正在等待，不为0时发送方需发送通知
+    sleeping: AtomicU32,
+    /// 下一个读取的位置，只由接收方修改
+    head: AtomicU64,
+    /// 下一个写入的位置
+    tail: AtomicU64,
+    slots: [Slot<T>; N],
+}
+
+// 每个位置的`value`只在取得写入权的发送方写入之后、由`stamp`发布给接收方
+unsafe impl<T: Pod, const N: usize> Sync for Ring<T, N> {}
+
+impl<T: Pod, const N: usize> Ring<T, N> {
+    fn slot(&self, pos: u64) -> (&Slot<T>, u64) {
+        (&self.slots[(pos % N as u64) as usize], pos / N as u64)
+    }
+
+    /// 取得写入位置`pos`的发送方写入值并发布
+    fn publish(&self, slot: &Slot<T>, lap: u64, value: T) {
+        unsafe { (*slot.value.get()).write(value) };
+        slot.stamp.store(2 * lap + 1, Ordering::Release);
+    }
+}
+
+/// 发送失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// 缓冲区已满，附带未被发送的值
+    Full(T),
+    /// 值已写入，但通知接收方失败；接收方之后的轮询仍能取走它
+    Notify(NotifyError),
+}
+
+fn create<T: Pod, const N: usize>(id: u64, mode: u32) -> Option<(Sender<T, N>, Receiver<T, N>)> {
+    assert!(N > 0, "channel capacity must be positive");
+    let process = super::getpid();
+    let receiver = Receiver {
+        id,
+        ring: super::map(KIND, process, id, true)?,
+    };
+    receiver.ring.get().mode.store(mode, Ordering::Release);
+    let sender = Sender::open(process, id)?;
+    Some((sender, receiver))
+}
+
+/// 新建只允许一个发送端的通道，接收方在本进程的通知源`id`上等待；状态已存在或无法创建时返回`None`
+///
+/// 返回的发送端被drop之后，才能以[`Sender::open`]打开另一个发送端。
+pub fn spsc<T: Pod, const N: usize>(id: u64) -> Option<(Sender<T, N>, Receiver<T, N>)> {
+    create(id, SPSC)
+}
+
+/// 新建允许多个发送端的通道，接收方在本进程的通知源`id`上等待；状态已存在或无法创建时返回`None`
+pub fn mpsc<T: Pod, const N: usize>(id: u64) -> Option<(Sender<T, N>, Receiver<T, N>)> {
+    create(id, MPSC)
+}
+
+/// 通道的发送端
+pub struct Sender<T: Pod, const N: usize> {
+    process: u64,
+    id: u64,
+    ring: shm::Mapping<Ring<T, N>>,
+}
+
+impl<T: Pod, const N: usize> Sender<T, N> {
+    /// 打开进程`process`以通知源`id`新建的通道；通道不存在，或为`spsc`且已有打开的发送端时返回`None`
+    ///
+    /// `T`与`N`必须与接收方的相同。
+    pub fn open(process: u64, id: u64) -> Option<Self> {
+        let ring: shm::Mapping<Ring<T, N>> = super::map(KIND, process, id, false)?;
+        match ring.get().mode.load(Ordering::Acquire) {
+            SPSC => ring
+                .get()
+                .claimed
+                .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                .ok()?,
+            MPSC => 0,
+            _ => return None,
+        };
+        Some(Self { process, id, ring })
+    }
+
+    /// 写入`value`，缓冲区由空变为非空且接收方正在等待时通知接收方
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let ring = self.ring.get();
+        if ring.mode.load(Ordering::Relaxed) == SPSC {
+            let pos = ring.tail.load(Ordering::Relaxed);
+            let (slot, lap) = ring.slot(pos);
+            if slot.stamp.load(Ordering::Acquire) != 2 * lap {
+                return Err(TrySendError::Full(value));
+            }
+            ring.publish(slot, lap, value);
+            ring.tail.store(pos + 1, Ordering::Relaxed);
+        } else {
+            let mut pos = ring.tail.load(Ordering::Relaxed);
+            loop {
+                let (slot, lap) = ring.slot(pos);
+                let stamp = slot.stamp.load(Ordering::Acquire);
+                if stamp == 2 * lap {
+                    match ring.tail.compare_exchange_weak(
+                        pos,
+                        pos + 1,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            ring.publish(slot, lap, value);
+                            break;
+                        }
+                        Err(current) => pos = current,
+                    }
+                } else if stamp < 2 * lap {
+                    // 上一圈写入的值尚未被取走
+                    return Err(TrySendError::Full(value));
+                } else {
+                    pos = ring.tail.load(Ordering::Relaxed);
+                }
+            }
+        }
+        fence(Ordering::SeqCst);
+        if ring.sleeping.swap(0, Ordering::SeqCst) != 0 {
+            Notification::notify_to(ProcessRef::Process(self.process), self.id)
+                .map_err(TrySendError::Notify)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Pod, const N: usize> Drop for Sender<T, N> {
+    fn drop(&mut self) {
+        let ring = self.ring.get();
+        if ring.mode.load(Ordering::Relaxed) == SPSC {
+            ring.claimed.store(0, Ordering::Release);
+        }
+    }
+}
+
+/// 通道的接收端，被drop时删除通道的共享内存
+pub struct Receiver<T: Pod, const N: usize> {
+    id: u64,
+    ring: shm::Mapping<Ring<T, N>>,
+}
+
+impl<T: Pod, const N: usize> Receiver<T, N> {
+    /// 接收方的通知源，发送方以本进程的进程号与它打开通道
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 缓冲区的容量
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 取走最早写入的值，不阻塞
+    pub fn try_recv(&self) -> Option<T> {
+        let ring = self.ring.get();
+        let pos = ring.head.load(Ordering::Relaxed);
+        let (slot, lap) = ring.slot(pos);
+        if slot.stamp.load(Ordering::Acquire) != 2 * lap + 1 {
+            return None;
+        }
+        let value = unsafe { (*slot.value.get()).assume_init() };
+        slot.stamp.store(2 * lap + 2, Ordering::Release);
+        ring.head.store(pos + 1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// 轮询通道
+    ///
+    /// 缓冲区非空时取走一个值并返回`Poll::Ready`；否则标记等待，并在通知源上等待。
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        let ring = self.ring.get();
+        loop {
+            if let Some(value) = self.try_recv() {
+                return Poll::Ready(value);
+            }
+            ring.sleeping.store(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            if let Some(value) = self.try_recv() {
+                ring.sleeping.store(0, Ordering::Relaxed);
+                return Poll::Ready(value);
+            }
+            // 被唤醒后重新检查，通知源上遗留的通知只会造成一次多余的检查
+            if Notification::poll_wait(self.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// 等待并取走最早写入的值
+    pub fn recv(&self) -> Recv<'_, T, N> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T: Pod, const N: usize> Drop for Receiver<T, N> {
+    fn drop(&mut self) {
+        super::unlink(KIND, self.id);
+    }
+}
+
+/// [`Receiver::recv`]返回的future
+pub struct Recv<'a, T: Pod, const N: usize> {
+    receiver: &'a Receiver<T, N>,
+}
+
+impl<T: Pod, const N: usize> Future for Recv<'_, T, N> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    extern crate std;
+
+    #[test]
+    fn test_spsc() {
+        let id = Notification::new_id_mock().unwrap();
+        let pid = super::super::getpid();
+        let (sender, receiver) = spsc::<u64, 2>(id).unwrap();
+        assert!(Sender::<u64, 2>::open(pid, id).is_none());
+        assert_eq!(receiver.try_recv(), None);
+
+        // 接收方未在等待时不发送通知
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+        assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(crate::mock::pending(id), Some(0));
+        assert_eq!(receiver.try_recv(), Some(1));
+        sender.try_send(3).unwrap();
+        assert_eq!(receiver.try_recv(), Some(2));
+        assert_eq!(receiver.try_recv(), Some(3));
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(receiver.poll_recv(&mut cx).is_pending());
+        drop(sender);
+        let sender = Sender::<u64, 2>::open(pid, id).unwrap();
+        sender.try_send(4).unwrap();
+        assert_eq!(crate::mock::pending(id), Some(1));
+        assert_eq!(receiver.poll_recv(&mut cx), Poll::Ready(4));
+
+        drop(receiver);
+        drop(sender);
+        assert!(Sender::<u64, 2>::open(pid, id).is_none());
+        unsafe { Notification::release_id(id) };
+    }
+
+    #[test]
+    fn test_mpsc() {
+        const PRODUCERS: u64 = 4;
+        const COUNT: u64 = 200;
+        let id = Notification::new_id_mock().unwrap();
+        let pid = super::super::getpid();
+        let (sender, receiver) = mpsc::<[u64; 2], 8>(id).unwrap();
+        let handles: std::vec::Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let sender = Sender::<[u64; 2], 8>::open(pid, id).unwrap();
+                std::thread::spawn(move || {
+                    for i in 0..COUNT {
+                        while let Err(TrySendError::Full(_)) = sender.try_send([producer, i]) {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        // 同一发送端的值按发送的顺序到达
+        let mut next = [0; PRODUCERS as usize];
+        futures::executor::block_on(async {
+            for _ in 0..PRODUCERS * COUNT {
+                let [producer, i] = receiver.recv().await;
+                assert_eq!(next[producer as usize], i);
+                next[producer as usize] += 1;
+            }
+        });
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(receiver.try_recv(), None);
+        drop(receiver);
+        unsafe { Notification::release_id(id) };
+    }
+}
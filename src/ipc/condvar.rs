@@ -0,0 +1,279 @@
+//! 进程间的条件变量
+//!
+//! 条件是调用者自己放在共享内存中的数据，[`Condvar::wait_while`]在条件成立期间等待：它先在共享内存中登记自己的进程号与通知源，
+//! 再检查一次条件，之后才在通知源上挂起。修改条件的一方在修改之后调用[`Condvar::notify_one`]或[`Condvar::notify_all`]，
+//! 前者通知最早登记的等待者，后者通知所有等待者。修改与检查条件都应使用`SeqCst`的原子操作，否则等待者可能错过通知。
+//!
+//! 被通知的等待者的登记被移除，它重新检查条件，仍成立时重新登记，因此每次`notify_one`都唤醒一个不同的等待者。
+//! 至多[`MAX_WAITERS`](super::MAX_WAITERS)个等待者同时登记，超出时`wait_while`返回`NotifyError::Overflow`。
+//! 通知时等待者所在的进程已退出或其通知源已被释放时，移除其登记并跳过它。
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use super::MAX_WAITERS;
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    shm,
+};
+
+const KIND: &str = "condvar";
+
+/// 正在写入登记的位置的`state`
+const CLAIMED: u64 = 1;
+
+/// 一个登记的位置
+///
+/// `state`为0时空闲，为`CLAIMED`时正在被写入，否则为登记的序号。
+#[repr(C)]
+struct Waiter {
+    state: AtomicU64,
+    process: AtomicU64,
+    id: AtomicU64,
+}
+
+/// 位于共享内存中的状态
+#[repr(C)]
+struct Shared {
+    /// 下一个登记的序号减2
+    next_token: AtomicU64,
+    waiters: [Waiter; MAX_WAITERS],
+}
+
+impl Shared {
+    /// 移除序号为`token`的登记并通知它，返回通知是否送达
+    fn wake(&self, waiter: &Waiter, token: u64) -> bool {
+        if waiter
+            .state
+            .compare_exchange(token, 0, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        let process = waiter.process.load(Ordering::Relaxed);
+        let id = waiter.id.load(Ordering::Relaxed);
+        !matches!(
+            Notification::notify_to(ProcessRef::Process(process), id),
+            Err(NotifyError::Os(libc::ESRCH | libc::EBADF))
+        )
+    }
+}
+
+/// 进程间的条件变量的一个句柄
+pub struct Condvar {
+    id: u64,
+    owner: bool,
+    shared: shm::Mapping<Shared>,
+}
+
+impl Condvar {
+    /// 新建条件变量，本句柄在本进程的通知源`id`上等待；状态已存在或无法创建时返回`None`
+    ///
+    /// 其他进程以本进程的进程号与`id`打开同一条件变量。本句柄被drop时删除条件变量的共享内存。
+    pub fn new(id: u64) -> Option<Self> {
+        Some(Self {
+            id,
+            owner: true,
+            shared: super::map(KIND, super::getpid(), id, true)?,
+        })
+    }
+
+    /// 打开进程`process`以通知源`key`新建的条件变量，本句柄在本进程的通知源`id`上等待；条件变量不存在时返回`None`
+    pub fn open(process: u64, key: u64, id: u64) -> Option<Self> {
+        Some(Self {
+            id,
+            owner: false,
+            shared: super::map(KIND, process, key, false)?,
+        })
+    }
+
+    /// 在`condition`返回`true`期间等待
+    ///
+    /// 同一句柄同时只应有一个正在进行的`wait_while`。
+    pub fn wait_while<F: FnMut() -> bool + Unpin>(&self, condition: F) -> WaitWhile<'_, F> {
+        WaitWhile {
+            condvar: self,
+            condition,
+            registered: None,
+        }
+    }
+
+    /// 通知最早登记的等待者，返回是否通知了一个等待者
+    pub fn notify_one(&self) -> bool {
+        let shared = self.shared.get();
+        loop {
+            let earliest = shared
+                .waiters
+                .iter()
+                .map(|waiter| (waiter, waiter.state.load(Ordering::SeqCst)))
+                .filter(|&(_, state)| state > CLAIMED)
+                .min_by_key(|&(_, state)| state);
+            match earliest {
+                Some((waiter, token)) => {
+                    if shared.wake(waiter, token) {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// 通知所有登记的等待者，返回通知的等待者数
+    pub fn notify_all(&self) -> usize {
+        let shared = self.shared.get();
+        shared
+            .waiters
+            .iter()
+            .filter(|waiter| {
+                let token = waiter.state.load(Ordering::SeqCst);
+                token > CLAIMED && shared.wake(waiter, token)
+            })
+            .count()
+    }
+}
+
+impl Drop for Condvar {
+    fn drop(&mut self) {
+        if self.owner {
+            super::unlink(KIND, self.id);
+        }
+    }
+}
+
+/// [`Condvar::wait_while`]返回的future
+pub struct WaitWhile<'a, F> {
+    condvar: &'a Condvar,
+    condition: F,
+    /// 登记的位置与序号
+    registered: Option<(usize, u64)>,
+}
+
+impl<F> WaitWhile<'_, F> {
+    fn register(&mut self) -> Result<(), NotifyError> {
+        let shared = self.condvar.shared.get();
+        let index = shared
+            .waiters
+            .iter()
+            .position(|waiter| {
+                waiter
+                    .state
+                    .compare_exchange(0, CLAIMED, Ordering::SeqCst, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or(NotifyError::Overflow)?;
+        let waiter = &shared.waiters[index];
+        let token = shared.next_token.fetch_add(1, Ordering::SeqCst) + 2;
+        waiter.process.store(super::getpid(), Ordering::Relaxed);
+        waiter.id.store(self.condvar.id, Ordering::Relaxed);
+        waiter.state.store(token, Ordering::SeqCst);
+        self.registered = Some((index, token));
+        Ok(())
+    }
+
+    /// 登记是否仍在，即尚未被通知
+    fn is_registered(&self) -> bool {
+        self.registered.is_some_and(|(index, token)| {
+            self.condvar.shared.get().waiters[index]
+                .state
+                .load(Ordering::SeqCst)
+                == token
+        })
+    }
+
+    fn unregister(&mut self) {
+        if let Some((index, token)) = self.registered.take() {
+            let _ = self.condvar.shared.get().waiters[index]
+                .state
+                .compare_exchange(token, 0, Ordering::SeqCst, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<F: FnMut() -> bool + Unpin> Future for WaitWhile<'_, F> {
+    type Output = Result<(), NotifyError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if !(this.condition)() {
+                this.unregister();
+                return Poll::Ready(Ok(()));
+            }
+            if !this.is_registered() {
+                // 登记之后再检查一次条件，之后的修改一定会通知本等待者
+                this.registered = None;
+                if let Err(err) = this.register() {
+                    return Poll::Ready(Err(err));
+                }
+                continue;
+            }
+            // 被唤醒后重新检查，通知源上遗留的通知只会造成一次多余的检查
+            if Notification::poll_wait(this.condvar.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<F> Drop for WaitWhile<'_, F> {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU32;
+
+    #[test]
+    fn test_condvar() {
+        static READY: AtomicU32 = AtomicU32::new(0);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let ids = [(); 3].map(|_| Notification::new_id_mock().unwrap());
+        let pid = super::super::getpid();
+        let owner = Condvar::new(ids[0]).unwrap();
+        let a = Condvar::open(pid, ids[0], ids[1]).unwrap();
+        let b = Condvar::open(pid, ids[0], ids[2]).unwrap();
+        assert!(!owner.notify_one());
+
+        let mut first = a.wait_while(|| READY.load(Ordering::SeqCst) < 1);
+        let mut second = b.wait_while(|| READY.load(Ordering::SeqCst) < 2);
+        assert!(Pin::new(&mut first).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+
+        // 只通知最早登记的等待者
+        READY.store(1, Ordering::SeqCst);
+        assert!(owner.notify_one());
+        assert_eq!(crate::mock::pending(ids[1]), Some(1));
+        assert_eq!(crate::mock::pending(ids[2]), Some(0));
+        assert_eq!(Pin::new(&mut first).poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+
+        // 条件仍成立的等待者被通知后重新登记
+        assert_eq!(owner.notify_all(), 1);
+        assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+        READY.store(2, Ordering::SeqCst);
+        assert_eq!(owner.notify_all(), 1);
+        assert_eq!(Pin::new(&mut second).poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(owner.notify_all(), 0);
+
+        // 被drop的等待者不再被通知
+        let mut dropped = a.wait_while(|| true);
+        assert!(Pin::new(&mut dropped).poll(&mut cx).is_pending());
+        drop(dropped);
+        assert!(!owner.notify_one());
+
+        drop((first, second));
+        drop((a, b, owner));
+        for id in ids {
+            unsafe { Notification::release_id(id) };
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! 进程间的一次性通道
+//!
+//! [`oneshot`]在本进程新建通道并返回两端；另一个进程以[`Sender::open`]打开通道的发送端。发送方写入值后向接收方的通知源发送通知，
+//! 接收方被唤醒后读取。通道只能传递一个值，之后的发送返回`SendError::AlreadySent`。
+//!
+//! 发送方在发送之前退出时，接收方无法察觉，需要时可用超时等待。
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll},
+};
+
+use super::Pod;
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    shm,
+};
+
+const KIND: &str = "oneshot";
+
+/// 尚未发送
+const EMPTY: u32 = 0;
+/// 发送方正在写入
+const WRITING: u32 = 1;
+/// 值已写入，尚未被取走
+const FULL: u32 = 2;
+/// 值已被接收方取走
+const TAKEN: u32 = 3;
+
+/// 位于共享内存中的状态
+#[repr(C)]
+struct Slot<T> {
+    state: AtomicU32,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// `value`只在`state`为`WRITING`时由唯一的发送方写入，为`FULL`之后才被读取
+unsafe impl<T: Pod> Sync for Slot<T> {}
+
+/// 发送失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError<T> {
+    /// 通道已经传递过一个值，附带未被发送的值
+    AlreadySent(T),
+    /// 值已写入，但通知接收方失败；接收方之后的轮询仍能取走它
+    Notify(NotifyError),
+}
+
+/// 新建通道，接收方在本进程的通知源`id`上等待；状态已存在或无法创建时返回`None`
+pub fn oneshot<T: Pod>(id: u64) -> Option<(Sender<T>, Receiver<T>)> {
+    let process = super::getpid();
+    let receiver = Receiver {
+        id,
+        slot: super::map(KIND, process, id, true)?,
+    };
+    let sender = Sender::open(process, id)?;
+    Some((sender, receiver))
+}
+
+/// 通道的发送端
+pub struct Sender<T: Pod> {
+    process: u64,
+    id: u64,
+    slot: shm::Mapping<Slot<T>>,
+}
+
+impl<T: Pod> Sender<T> {
+    /// 打开进程`process`以通知源`id`新建的通道；通道不存在时返回`None`
+    ///
+    /// `T`必须与接收方的类型相同。
+    pub fn open(process: u64, id: u64) -> Option<Self> {
+        Some(Self {
+            process,
+            id,
+            slot: super::map(KIND, process, id, false)?,
+        })
+    }
+
+    /// 发送`value`并通知接收方
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let slot = self.slot.get();
+        if slot
+            .state
+            .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(SendError::AlreadySent(value));
+        }
+        unsafe { (*slot.value.get()).write(value) };
+        slot.state.store(FULL, Ordering::Release);
+        Notification::notify_to(ProcessRef::Process(self.process), self.id)
+            .map_err(SendError::Notify)
+    }
+}
+
+/// 通道的接收端，被drop时删除通道的共享内存
+pub struct Receiver<T: Pod> {
+    id: u64,
+    slot: shm::Mapping<Slot<T>>,
+}
+
+impl<T: Pod> Receiver<T> {
+    /// 接收方的通知源，发送方以本进程的进程号与它打开通道
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 取走已发送的值，不阻塞
+    pub fn try_recv(&self) -> Option<T> {
+        let slot = self.slot.get();
+        slot.state
+            .compare_exchange(FULL, TAKEN, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(unsafe { (*slot.value.get()).assume_init() })
+    }
+
+    /// 等待并取走发送的值
+    pub fn recv(&self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T: Pod> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        super::unlink(KIND, self.id);
+    }
+}
+
+/// [`Receiver::recv`]返回的future
+pub struct Recv<'a, T: Pod> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T: Pod> Future for Recv<'_, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        loop {
+            if let Some(value) = self.receiver.try_recv() {
+                return Poll::Ready(value);
+            }
+            // 被唤醒后重新检查，通知源上遗留的通知只会造成一次多余的检查
+            if Notification::poll_wait(self.receiver.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    extern crate std;
+
+    #[test]
+    fn test_oneshot() {
+        let id = Notification::new_id_mock().unwrap();
+        let (sender, receiver) = oneshot::<[u32; 4]>(id).unwrap();
+        assert!(oneshot::<[u32; 4]>(id).is_none());
+        assert_eq!(receiver.try_recv(), None);
+
+        let other = Sender::<[u32; 4]>::open(crate::ipc::getpid(), receiver.id()).unwrap();
+        let handle = std::thread::spawn(move || sender.send([1, 2, 3, 4]));
+        let value = futures::executor::block_on(receiver.recv());
+        assert_eq!(value, [1, 2, 3, 4]);
+        assert!(handle.join().unwrap().is_ok());
+        assert_eq!(other.send([5; 4]), Err(SendError::AlreadySent([5; 4])));
+        assert_eq!(receiver.try_recv(), None);
+
+        drop(receiver);
+        assert!(Sender::<[u32; 4]>::open(crate::ipc::getpid(), id).is_none());
+        unsafe { Notification::release_id(id) };
+    }
+}
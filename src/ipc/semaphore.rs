@@ -0,0 +1,345 @@
+//! 进程间的公平信号量
+//!
+//! 许可数与等待队列位于共享内存中。[`Semaphore::acquire`]先领取一个递增的号码，在队列中登记自己的进程号与通知源，
+//! 再按号码的顺序取得许可，因此先开始等待的一方先取得许可。[`Semaphore::release`]归还许可，并通知队首的等待者。
+//!
+//! 至多[`MAX_WAITERS`]个等待者同时排队，超出时`acquire`返回`NotifyError::Overflow`。等待中被drop的`acquire`
+//! 放弃它的号码，不会阻塞之后的等待者；队首的等待者所在的进程已退出或其通知源已被释放时，同样视其为已放弃。
+//!
+//! 每个句柄在自己的通知源上等待，同一句柄同时只应有一个正在进行的`acquire`。
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    shm,
+};
+
+const KIND: &str = "semaphore";
+
+/// 同时排队的等待者的上限
+pub const MAX_WAITERS: usize = 64;
+
+/// 队列中的一个位置
+///
+/// `state`为0时空闲；为`2 * (ticket + 1)`时登记了号码`ticket`，加1表示该号码已被放弃。
+#[repr(C)]
+struct Waiter {
+    state: AtomicU64,
+    process: AtomicU64,
+    id: AtomicU64,
+}
+
+/// 位于共享内存中的状态
+#[repr(C)]
+struct Shared {
+    permits: AtomicU64,
+    /// 下一个领取的号码
+    next_ticket: AtomicU64,
+    /// 正在被服务的号码，只有持有它的等待者能取得许可
+    serving: AtomicU64,
+    waiters: [Waiter; MAX_WAITERS],
+}
+
+impl Shared {
+    fn waiter(&self, ticket: u64) -> &Waiter {
+        &self.waiters[(ticket % MAX_WAITERS as u64) as usize]
+    }
+
+    /// 跳过队首已被放弃的号码，并通知队首的等待者
+    fn wake_head(&self) {
+        loop {
+            let serving = self.serving.load(Ordering::SeqCst);
+            if serving == self.next_ticket.load(Ordering::SeqCst) {
+                return;
+            }
+            let waiter = self.waiter(serving);
+            let state = waiter.state.load(Ordering::SeqCst);
+            let registered = 2 * (serving + 1);
+            if state == registered + 1 {
+                if self
+                    .serving
+                    .compare_exchange(serving, serving + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    let _ = waiter.state.compare_exchange(
+                        state,
+                        0,
+                        Ordering::SeqCst,
+                        Ordering::Relaxed,
+                    );
+                }
+                continue;
+            }
+            // 尚未登记的等待者在登记之后会自行检查
+            if state != registered {
+                return;
+            }
+            let process = waiter.process.load(Ordering::Relaxed);
+            let id = waiter.id.load(Ordering::Relaxed);
+            match Notification::notify_to(ProcessRef::Process(process), id) {
+                // 等待者所在的进程已退出，或其通知源已被释放
+                Err(NotifyError::Os(libc::ESRCH | libc::EBADF)) => {}
+                _ => return,
+            }
+            let _ = waiter.state.compare_exchange(
+                registered,
+                registered + 1,
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// 取得一个许可
+    fn take_permit(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+/// 进程间的信号量的一个句柄
+pub struct Semaphore {
+    id: u64,
+    owner: bool,
+    shared: shm::Mapping<Shared>,
+}
+
+impl Semaphore {
+    /// 新建有`permits`个许可的信号量，本句柄在本进程的通知源`id`上等待；状态已存在或无法创建时返回`None`
+    ///
+    /// 其他进程以本进程的进程号与`id`打开同一信号量。本句柄被drop时删除信号量的共享内存。
+    pub fn new(id: u64, permits: u64) -> Option<Self> {
+        let shared: shm::Mapping<Shared> = super::map(KIND, super::getpid(), id, true)?;
+        shared.get().permits.store(permits, Ordering::SeqCst);
+        Some(Self {
+            id,
+            owner: true,
+            shared,
+        })
+    }
+
+    /// 打开进程`process`以通知源`key`新建的信号量，本句柄在本进程的通知源`id`上等待；信号量不存在时返回`None`
+    pub fn open(process: u64, key: u64, id: u64) -> Option<Self> {
+        Some(Self {
+            id,
+            owner: false,
+            shared: super::map(KIND, process, key, false)?,
+        })
+    }
+
+    /// 当前可用的许可数
+    pub fn available(&self) -> u64 {
+        self.shared.get().permits.load(Ordering::SeqCst)
+    }
+
+    /// 没有等待者且有可用的许可时取得一个许可，不阻塞
+    pub fn try_acquire(&self) -> bool {
+        let shared = self.shared.get();
+        shared.serving.load(Ordering::SeqCst) == shared.next_ticket.load(Ordering::SeqCst)
+            && shared.take_permit()
+    }
+
+    /// 按开始等待的顺序取得一个许可
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            ticket: None,
+            done: false,
+        }
+    }
+
+    /// 归还一个许可，并通知队首的等待者
+    pub fn release(&self) {
+        let shared = self.shared.get();
+        shared.permits.fetch_add(1, Ordering::SeqCst);
+        shared.wake_head();
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        if self.owner {
+            super::unlink(KIND, self.id);
+        }
+    }
+}
+
+/// [`Semaphore::acquire`]返回的future
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    ticket: Option<u64>,
+    done: bool,
+}
+
+impl Acquire<'_> {
+    /// 领取号码并登记
+    fn enqueue(&self) -> Result<u64, NotifyError> {
+        let shared = self.semaphore.shared.get();
+        let mut ticket = shared.next_ticket.load(Ordering::SeqCst);
+        loop {
+            if ticket - shared.serving.load(Ordering::SeqCst) >= MAX_WAITERS as u64 {
+                return Err(NotifyError::Overflow);
+            }
+            match shared.next_ticket.compare_exchange(
+                ticket,
+                ticket + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => break,
+                Err(current) => ticket = current,
+            }
+        }
+        let waiter = shared.waiter(ticket);
+        waiter.process.store(super::getpid(), Ordering::Relaxed);
+        waiter.id.store(self.semaphore.id, Ordering::Relaxed);
+        waiter.state.store(2 * (ticket + 1), Ordering::SeqCst);
+        Ok(ticket)
+    }
+}
+
+impl Future for Acquire<'_> {
+    type Output = Result<(), NotifyError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+        let ticket = match self.ticket {
+            Some(ticket) => ticket,
+            None => {
+                let ticket = match self.enqueue() {
+                    Ok(ticket) => ticket,
+                    Err(err) => return Poll::Ready(Err(err)),
+                };
+                self.ticket = Some(ticket);
+                ticket
+            }
+        };
+        let shared = self.semaphore.shared.get();
+        loop {
+            if shared.serving.load(Ordering::SeqCst) == ticket && shared.take_permit() {
+                let _ = shared.serving.compare_exchange(
+                    ticket,
+                    ticket + 1,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+                let _ = shared.waiter(ticket).state.compare_exchange(
+                    2 * (ticket + 1),
+                    0,
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                );
+                self.done = true;
+                // 还有许可时下一个等待者也可以继续
+                shared.wake_head();
+                return Poll::Ready(Ok(()));
+            }
+            // 被唤醒后重新检查，通知源上遗留的通知只会造成一次多余的检查
+            if Notification::poll_wait(self.semaphore.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket.filter(|_| !self.done) else {
+            return;
+        };
+        let shared = self.semaphore.shared.get();
+        let registered = 2 * (ticket + 1);
+        let _ = shared.waiter(ticket).state.compare_exchange(
+            registered,
+            registered + 1,
+            Ordering::SeqCst,
+            Ordering::Relaxed,
+        );
+        shared.wake_head();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, vec::Vec};
+
+    #[test]
+    fn test_semaphore() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let ids = [(); 4].map(|_| Notification::new_id_mock().unwrap());
+        let pid = super::super::getpid();
+        let owner = Semaphore::new(ids[0], 1).unwrap();
+        let [a, b, c] = [1, 2, 3].map(|i| Semaphore::open(pid, ids[0], ids[i]).unwrap());
+        assert!(Semaphore::new(ids[0], 1).is_none());
+
+        assert!(owner.try_acquire());
+        assert_eq!(owner.available(), 0);
+        let mut first = Box::pin(a.acquire());
+        let mut abandoned = Box::pin(b.acquire());
+        let mut second = Box::pin(c.acquire());
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(abandoned.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(!owner.try_acquire());
+
+        // 按开始等待的顺序取得许可，放弃的等待者被跳过
+        owner.release();
+        assert_eq!(crate::mock::pending(ids[1]), Some(1));
+        assert_eq!(crate::mock::pending(ids[3]), Some(0));
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        drop(abandoned);
+        assert_eq!(crate::mock::pending(ids[3]), Some(1));
+        // 已是队首，但没有可用的许可
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        a.release();
+        assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        c.release();
+        assert_eq!(owner.available(), 1);
+
+        drop((first, second));
+        drop((a, b, c, owner));
+        assert!(Semaphore::open(pid, ids[0], ids[1]).is_none());
+        for id in ids {
+            unsafe { Notification::release_id(id) };
+        }
+    }
+
+    #[test]
+    fn test_semaphore_overflow() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let id = Notification::new_id_mock().unwrap();
+        let semaphore = Semaphore::new(id, 0).unwrap();
+        let mut waiters: Vec<_> = (0..MAX_WAITERS).map(|_| semaphore.acquire()).collect();
+        for waiter in &mut waiters {
+            assert!(Pin::new(waiter).poll(&mut cx).is_pending());
+        }
+        assert_eq!(
+            Pin::new(&mut semaphore.acquire()).poll(&mut cx),
+            Poll::Ready(Err(NotifyError::Overflow))
+        );
+        drop(waiters);
+        assert!(
+            Pin::new(&mut semaphore.acquire())
+                .poll(&mut cx)
+                .is_pending()
+        );
+        semaphore.release();
+        drop(semaphore);
+        unsafe { Notification::release_id(id) };
+    }
+}
@@ -0,0 +1,206 @@
+//! 使用软件核间中断的通知机制，用于裸机与内核中的多核心
+//!
+//! 与[`fixed`](crate::fixed)一样不需要分配内存。发送核间中断与取得当前核心编号的方式由[`IpiIf`]的实现者提供，
+//! [`ipi_notification!`](crate::ipi_notification)在其上定义实现了`NotificationIf`的通知机制，`notify`的`process`参数为目标核心的编号：
+//!
+//! - 目标核心即为当前核心时，直接唤醒等待的协程，不发送核间中断；
+//! - 否则标记通知源并向目标核心发送核间中断，目标核心的中断处理程序调用`on_ipi`唤醒被标记的通知源的等待者，
+//!   之后调用[`IpiIf::executor_hook`]，使执行器得知有任务就绪（例如从`wfi`返回后重新检查运行队列）。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::fixed::StaticPool;
+
+/// 核间中断的硬件原语
+pub trait IpiIf {
+    /// 当前核心的编号
+    fn current_core() -> u32;
+    /// 向核心`core`发送软件核间中断，可以在中断上下文中调用
+    fn send_ipi(core: u32);
+    /// 核间中断处理程序唤醒了`woken`个通知源的等待者之后在核心`core`上调用，默认不进行任何操作
+    fn executor_hook(core: u32, woken: usize) {
+        let _ = (core, woken);
+    }
+}
+
+/// 容量为`N`的通知源池，并记录等待核间中断处理程序唤醒的通知源
+pub struct IpiPool<const N: usize> {
+    pool: StaticPool<N>,
+    raised: [AtomicBool; N],
+}
+
+impl<const N: usize> IpiPool<N> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NOT_RAISED: AtomicBool = AtomicBool::new(false);
+
+    /// 新建所有通知源均未被占用的池，可用于初始化静态变量
+    pub const fn new() -> Self {
+        Self {
+            pool: StaticPool::new(),
+            raised: [Self::NOT_RAISED; N],
+        }
+    }
+
+    /// 保存通知源状态的池
+    pub fn pool(&self) -> &StaticPool<N> {
+        &self.pool
+    }
+
+    /// 使核心`core`上的通知源`id`收到一个通知；通知源未被占用时返回`false`
+    pub fn notify<I: IpiIf>(&self, core: u32, id: u64) -> bool {
+        if !self.pool.post(id) {
+            return false;
+        }
+        if core == I::current_core() {
+            self.pool.wake(id);
+        } else {
+            self.raised[id as usize].store(true, Ordering::Release);
+            I::send_ipi(core);
+        }
+        true
+    }
+
+    /// 由核间中断处理程序调用，唤醒被标记的通知源的等待者，返回唤醒的通知源数量
+    pub fn on_ipi<I: IpiIf>(&self) -> usize {
+        let mut woken = 0;
+        for (id, raised) in self.raised.iter().enumerate() {
+            if raised.swap(false, Ordering::AcqRel) {
+                self.pool.wake(id as u64);
+                woken += 1;
+            }
+        }
+        I::executor_hook(I::current_core(), woken);
+        woken
+    }
+}
+
+impl<const N: usize> Default for IpiPool<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 定义一个使用静态[`IpiPool`](crate::ipi::IpiPool)、以`$ipi`发送核间中断、实现了`NotificationIf`的通知机制
+///
+/// 生成的类型另有`on_ipi()`，需由核间中断处理程序调用。
+///
+/// ```
+/// struct Board;
+///
+/// impl async_notification::ipi::IpiIf for Board {
+///     fn current_core() -> u32 {
+///         0
+///     }
+///
+///     fn send_ipi(_core: u32) {}
+/// }
+///
+/// async_notification::ipi_notification!(
+///     /// 多核心之间的通知机制
+///     pub CoreNotification, Board, 32
+/// );
+/// ```
+#[macro_export]
+macro_rules! ipi_notification {
+    ($(#[$meta:meta])* $vis:vis $name:ident, $ipi:ty, $capacity:expr) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $name {
+            /// 保存通知源状态的静态池
+            pub fn pool() -> &'static $crate::ipi::IpiPool<{ $capacity }> {
+                static POOL: $crate::ipi::IpiPool<{ $capacity }> = $crate::ipi::IpiPool::new();
+                &POOL
+            }
+
+            /// 由核间中断处理程序调用，返回唤醒的通知源数量
+            pub fn on_ipi() -> usize {
+                Self::pool().on_ipi::<$ipi>()
+            }
+        }
+
+        impl $crate::interface::NotificationIf for $name {
+            fn new_id() -> ::core::option::Option<u64> {
+                Self::pool().pool().alloc()
+            }
+
+            fn poll_wait(
+                id: u64,
+                cx: &mut ::core::task::Context<'_>,
+            ) -> ::core::task::Poll<()> {
+                Self::pool().pool().poll_wait(id, cx)
+            }
+
+            fn register_waker(id: u64, waker: &::core::task::Waker) {
+                Self::pool().pool().register_waker(id, waker)
+            }
+
+            unsafe fn release_id(id: u64) {
+                Self::pool().pool().release(id);
+            }
+
+            /// `process`为目标核心的编号
+            fn notify(process: u64, id: u64) {
+                Self::pool().notify::<$ipi>(process as u32, id);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+    use core::{
+        sync::atomic::{AtomicU32, AtomicUsize},
+        task::Context,
+    };
+
+    static CURRENT: AtomicU32 = AtomicU32::new(0);
+    static SENT: AtomicU32 = AtomicU32::new(u32::MAX);
+    static HOOKED: AtomicUsize = AtomicUsize::new(0);
+
+    struct TestIpi;
+
+    impl IpiIf for TestIpi {
+        fn current_core() -> u32 {
+            CURRENT.load(Ordering::Acquire)
+        }
+
+        fn send_ipi(core: u32) {
+            SENT.store(core, Ordering::Release);
+        }
+
+        fn executor_hook(_core: u32, woken: usize) {
+            HOOKED.fetch_add(woken, Ordering::AcqRel);
+        }
+    }
+
+    crate::ipi_notification!(TestNotification, TestIpi, 4);
+
+    #[test]
+    fn test_ipi_notification() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let id = TestNotification::new_id().unwrap();
+
+        // 同一核心上的通知不发送核间中断
+        assert!(TestNotification::poll_wait(id, &mut cx).is_pending());
+        TestNotification::notify(0, id);
+        assert_eq!(SENT.load(Ordering::Acquire), u32::MAX);
+        assert!(TestNotification::poll_wait(id, &mut cx).is_ready());
+
+        // 其他核心上的通知经核间中断唤醒
+        assert!(TestNotification::poll_wait(id, &mut cx).is_pending());
+        TestNotification::notify(1, id);
+        assert_eq!(SENT.load(Ordering::Acquire), 1);
+        CURRENT.store(1, Ordering::Release);
+        assert_eq!(TestNotification::on_ipi(), 1);
+        assert_eq!(HOOKED.load(Ordering::Acquire), 1);
+        assert_eq!(TestNotification::on_ipi(), 0);
+        assert!(TestNotification::poll_wait(id, &mut cx).is_ready());
+
+        unsafe { TestNotification::release_id(id) };
+        assert!(!TestNotification::pool().notify::<TestIpi>(0, id));
+    }
+}
@@ -0,0 +1,248 @@
+//! 仿照virtio的队列通知抑制
+//!
+//! 共享内存中的队列的生产者发布新的索引（virtio的avail或used索引）后，不必每次都通知消费者。收发双方共享一个[`KickIndex`]，
+//! 其中记录生产者发布的索引，以及消费者希望被通知的位置（virtio的`avail_event`/`used_event`）与抑制标志（`NO_NOTIFY`）：
+//!
+//! - 消费者处理完索引`seen`之前的项、准备挂起时，以`seen`为希望被通知的位置并清除抑制标志，再检查一次索引；
+//! - 生产者将索引从`old`推进到`new`后，只有抑制标志未被置位、且`seen`位于`[old, new)`中时才发送通知，
+//!   即本批发布的项中包含消费者尚未见过的第一项。
+//!
+//! 两侧都先写自己的字段、再读对方的字段，因此不会出现生产者不通知、消费者又已挂起的情况。索引按`u16`回绕，
+//! 与virtio相同。双向的队列（例如avail与used）各使用一个`KickIndex`。
+
+use core::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicU16, Ordering, fence},
+    task::{Context, Poll},
+};
+
+use crate::interface::{Notification, NotificationIf};
+
+/// 抑制标志：消费者不需要通知
+const NO_NOTIFY: u16 = 1;
+
+/// 索引从`old`推进到`new`时，希望在`event`处被通知的消费者是否需要通知，与virtio的`vring_need_event`相同
+pub fn need_event(event: u16, new: u16, old: u16) -> bool {
+    new.wrapping_sub(event).wrapping_sub(1) < new.wrapping_sub(old)
+}
+
+/// 收发双方共享的索引
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct KickIndex {
+    /// 生产者发布的索引
+    idx: AtomicU16,
+    /// 消费者希望被通知的位置
+    event: AtomicU16,
+    /// 消费者的抑制标志
+    flags: AtomicU16,
+    _reserved: AtomicU16,
+}
+
+impl KickIndex {
+    /// 新建索引
+    pub const fn new() -> Self {
+        Self {
+            idx: AtomicU16::new(0),
+            event: AtomicU16::new(0),
+            flags: AtomicU16::new(0),
+            _reserved: AtomicU16::new(0),
+        }
+    }
+
+    /// 将共享内存中的4个连续的`u16`视为索引，全零时为初始状态
+    ///
+    /// # Safety
+    ///
+    /// `ptr`必须非空、按2字节对齐，且在`'a`内有效，并只被以原子操作访问。
+    pub unsafe fn from_ptr<'a>(ptr: *mut u16) -> &'a Self {
+        unsafe { &*(ptr as *const Self) }
+    }
+
+    /// 生产者发布的索引
+    pub fn idx(&self) -> u16 {
+        self.idx.load(Ordering::Acquire)
+    }
+}
+
+/// 队列的生产者
+pub struct Kicker<'a, N: NotificationIf = Notification> {
+    index: &'a KickIndex,
+    process: u64,
+    id: u64,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<'a, N: NotificationIf> Kicker<'a, N> {
+    /// 新建生产者，需要时向进程`process`的通知源`id`发送通知
+    pub fn new(index: &'a KickIndex, process: u64, id: u64) -> Self {
+        Self {
+            index,
+            process,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 发布新的索引`new`，返回是否通知了消费者
+    ///
+    /// 队列中的项应在调用之前写入。每个`KickIndex`只应有一个生产者。
+    pub fn publish(&self, new: u16) -> bool {
+        let old = self.index.idx.swap(new, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        if self.index.flags.load(Ordering::SeqCst) & NO_NOTIFY != 0
+            || !need_event(self.index.event.load(Ordering::SeqCst), new, old)
+        {
+            return false;
+        }
+        N::notify(self.process, self.id);
+        true
+    }
+}
+
+/// 队列的消费者
+///
+/// 队列只支持一个消费者，`id`应为消费者专用的通知源。
+pub struct KickWaiter<'a, N: NotificationIf = Notification> {
+    index: &'a KickIndex,
+    id: u64,
+    _marker: PhantomData<fn() -> N>,
+}
+
+impl<'a, N: NotificationIf> KickWaiter<'a, N> {
+    /// 新建消费者，挂起时在本进程的通知源`id`上等待
+    pub fn new(index: &'a KickIndex, id: u64) -> Self {
+        Self {
+            index,
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 置位抑制标志，忙于处理时生产者之后的发布不再发送通知
+    pub fn disable_notify(&self) {
+        self.index.flags.fetch_or(NO_NOTIFY, Ordering::SeqCst);
+    }
+
+    /// 请求在索引越过`seen`时被通知，返回索引是否已经越过`seen`（此时不应挂起）
+    pub fn enable_notify(&self, seen: u16) -> bool {
+        self.index.event.store(seen, Ordering::SeqCst);
+        self.index.flags.fetch_and(!NO_NOTIFY, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        self.index.idx.load(Ordering::SeqCst) != seen
+    }
+
+    /// 轮询索引
+    ///
+    /// 索引已越过`seen`时置位抑制标志并返回`Poll::Ready`与当前的索引；否则请求通知，并在通知源上等待。
+    pub fn poll_wait(&self, seen: u16, cx: &mut Context<'_>) -> Poll<u16> {
+        loop {
+            let idx = self.index.idx();
+            if idx != seen || self.enable_notify(seen) {
+                self.disable_notify();
+                return Poll::Ready(self.index.idx());
+            }
+            // 被唤醒后重新检查索引；索引未变（例如此前遗留的通知）时重新挂起
+            if N::poll_wait(self.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// 等待索引越过`seen`
+    pub fn wait(&self, seen: u16) -> KickWait<'_, 'a, N> {
+        KickWait { waiter: self, seen }
+    }
+}
+
+/// `KickWaiter::wait`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct KickWait<'w, 'a, N: NotificationIf> {
+    waiter: &'w KickWaiter<'a, N>,
+    seen: u16,
+}
+
+impl<N: NotificationIf> Future for KickWait<'_, '_, N> {
+    type Output = u16;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u16> {
+        self.waiter.poll_wait(self.seen, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::SpinMutex;
+    use core::task::Waker;
+
+    /// 测试用的通知源，记录发送次数
+    struct TestNotification;
+
+    /// 未被消费的通知数量，发送次数
+    static STATE: SpinMutex<(usize, usize)> = SpinMutex::new((0, 0));
+
+    impl NotificationIf for TestNotification {
+        fn new_id() -> Option<u64> {
+            Some(0)
+        }
+
+        fn poll_wait(_id: u64, _cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = STATE.lock();
+            if state.0 > 0 {
+                state.0 -= 1;
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn register_waker(_id: u64, _waker: &Waker) {}
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, _id: u64) {
+            let mut state = STATE.lock();
+            state.0 += 1;
+            state.1 += 1;
+        }
+    }
+
+    #[test]
+    fn test_need_event() {
+        assert!(need_event(0, 1, 0));
+        assert!(need_event(2, 5, 0));
+        assert!(!need_event(5, 5, 0));
+        assert!(!need_event(1, 3, 2));
+        // 索引回绕
+        assert!(need_event(u16::MAX, 1, u16::MAX - 1));
+        assert!(!need_event(2, 1, u16::MAX - 1));
+    }
+
+    #[test]
+    fn test_kick_suppression() {
+        let index = KickIndex::new();
+        let kicker = Kicker::<TestNotification>::new(&index, 0, 0);
+        let waiter = KickWaiter::<TestNotification>::new(&index, 0);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // 消费者位于0，第一批发布需要通知
+        assert!(kicker.publish(2));
+        assert_eq!(waiter.poll_wait(0, &mut cx), Poll::Ready(2));
+        // 忙于处理时抑制通知
+        assert!(!kicker.publish(3));
+        assert_eq!(waiter.poll_wait(2, &mut cx), Poll::Ready(3));
+        assert_eq!(STATE.lock().1, 1);
+
+        // 挂起后只有越过`seen`的第一批发布发送通知
+        assert!(waiter.poll_wait(3, &mut cx).is_pending());
+        assert!(kicker.publish(4));
+        assert_eq!(STATE.lock().1, 2);
+        assert_eq!(waiter.poll_wait(3, &mut cx), Poll::Ready(4));
+        assert!(!kicker.publish(5));
+        assert_eq!(STATE.lock().1, 2);
+    }
+}
@@ -0,0 +1,304 @@
+//! 通知源类型的查询
+//!
+//! id的高8位标识了通知源的类型。[`NotificationKind`]列出所有类型（无论相应的feature是否启用），
+//! [`Notification::display`]将id显示为`signal:42`的形式，便于在日志中阅读。
+
+use core::fmt;
+
+use crate::interface::Notification;
+
+/// 通知源的类型，与id的高8位一一对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NotificationKind {
+    /// 信号（`signal`）
+    Signal,
+    /// 用户态中断（`uintr`）
+    Uintr,
+    /// timerfd定时器（`timer`）
+    Timer,
+    /// pidfd子进程退出（`child`）
+    Child,
+    /// io_uring（`uring`）
+    Uring,
+    /// kqueue（`kqueue`）
+    Kqueue,
+    /// zircon eventpair（`fuchsia`）
+    Fuchsia,
+    /// ArceOS内核通知对象（`arceos`）
+    Arceos,
+    /// vsock数据报（`vsock`）
+    Vsock,
+    /// eventfd（`kvm`）
+    Kvm,
+    /// unix域套接字（`uds`）
+    Uds,
+    /// 使用signalfd接收的信号（`signalfd`）
+    Signalfd,
+    /// POSIX消息队列（`mqueue`）
+    Mqueue,
+    /// netlink套接字（`netlink`）
+    Netlink,
+    /// D-Bus信号（`dbus`）
+    Dbus,
+    /// UDP数据报（`net`）
+    Net,
+    /// 管道（`pipe`）
+    Pipe,
+    /// 用于测试的模拟通知源（`mock`）
+    Mock,
+    /// 使用虚拟时间的模拟通知源（`sim`）
+    Sim,
+}
+
+impl NotificationKind {
+    /// 所有类型，按高8位的顺序排列
+    pub const ALL: &'static [NotificationKind] = &[
+        NotificationKind::Signal,
+        NotificationKind::Uintr,
+        NotificationKind::Timer,
+        NotificationKind::Child,
+        NotificationKind::Uring,
+        NotificationKind::Kqueue,
+        NotificationKind::Fuchsia,
+        NotificationKind::Arceos,
+        NotificationKind::Vsock,
+        NotificationKind::Kvm,
+        NotificationKind::Uds,
+        NotificationKind::Signalfd,
+        NotificationKind::Mqueue,
+        NotificationKind::Netlink,
+        NotificationKind::Dbus,
+        NotificationKind::Net,
+        NotificationKind::Pipe,
+        NotificationKind::Mock,
+        NotificationKind::Sim,
+    ];
+
+    /// 该类型的id的高8位（未移位）
+    pub const fn tag(self) -> u8 {
+        match self {
+            NotificationKind::Signal => 0x01,
+            NotificationKind::Uintr => 0x02,
+            NotificationKind::Timer => 0x03,
+            NotificationKind::Child => 0x04,
+            NotificationKind::Uring => 0x05,
+            NotificationKind::Kqueue => 0x06,
+            NotificationKind::Fuchsia => 0x07,
+            NotificationKind::Arceos => 0x08,
+            NotificationKind::Vsock => 0x09,
+            NotificationKind::Kvm => 0x0a,
+            NotificationKind::Uds => 0x0b,
+            NotificationKind::Signalfd => 0x0c,
+            NotificationKind::Mqueue => 0x0d,
+            NotificationKind::Netlink => 0x0e,
+            NotificationKind::Dbus => 0x0f,
+            NotificationKind::Net => 0x10,
+            NotificationKind::Pipe => 0x11,
+            NotificationKind::Mock => 0x12,
+            NotificationKind::Sim => 0x13,
+        }
+    }
+
+    /// 由id的高8位得到类型，未知的值返回`None`
+    pub const fn from_tag(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0x01 => NotificationKind::Signal,
+            0x02 => NotificationKind::Uintr,
+            0x03 => NotificationKind::Timer,
+            0x04 => NotificationKind::Child,
+            0x05 => NotificationKind::Uring,
+            0x06 => NotificationKind::Kqueue,
+            0x07 => NotificationKind::Fuchsia,
+            0x08 => NotificationKind::Arceos,
+            0x09 => NotificationKind::Vsock,
+            0x0a => NotificationKind::Kvm,
+            0x0b => NotificationKind::Uds,
+            0x0c => NotificationKind::Signalfd,
+            0x0d => NotificationKind::Mqueue,
+            0x0e => NotificationKind::Netlink,
+            0x0f => NotificationKind::Dbus,
+            0x10 => NotificationKind::Net,
+            0x11 => NotificationKind::Pipe,
+            0x12 => NotificationKind::Mock,
+            0x13 => NotificationKind::Sim,
+            _ => return None,
+        })
+    }
+
+    /// 类型的名称，与启用它的feature同名
+    pub const fn name(self) -> &'static str {
+        match self {
+            NotificationKind::Signal => "signal",
+            NotificationKind::Uintr => "uintr",
+            NotificationKind::Timer => "timer",
+            NotificationKind::Child => "child",
+            NotificationKind::Uring => "uring",
+            NotificationKind::Kqueue => "kqueue",
+            NotificationKind::Fuchsia => "fuchsia",
+            NotificationKind::Arceos => "arceos",
+            NotificationKind::Vsock => "vsock",
+            NotificationKind::Kvm => "kvm",
+            NotificationKind::Uds => "uds",
+            NotificationKind::Signalfd => "signalfd",
+            NotificationKind::Mqueue => "mqueue",
+            NotificationKind::Netlink => "netlink",
+            NotificationKind::Dbus => "dbus",
+            NotificationKind::Net => "net",
+            NotificationKind::Pipe => "pipe",
+            NotificationKind::Mock => "mock",
+            NotificationKind::Sim => "sim",
+        }
+    }
+
+    /// 该类型的feature是否已启用；未启用的类型的id不能被分发
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            NotificationKind::Signal => cfg!(feature = "signal"),
+            NotificationKind::Uintr => cfg!(feature = "uintr"),
+            NotificationKind::Timer => cfg!(feature = "timer"),
+            NotificationKind::Child => cfg!(feature = "child"),
+            NotificationKind::Uring => cfg!(feature = "uring"),
+            NotificationKind::Kqueue => cfg!(feature = "kqueue"),
+            NotificationKind::Fuchsia => cfg!(feature = "fuchsia"),
+            NotificationKind::Arceos => cfg!(feature = "arceos"),
+            NotificationKind::Vsock => cfg!(feature = "vsock"),
+            NotificationKind::Kvm => cfg!(feature = "kvm"),
+            NotificationKind::Uds => cfg!(feature = "uds"),
+            NotificationKind::Signalfd => cfg!(feature = "signalfd"),
+            NotificationKind::Mqueue => cfg!(feature = "mqueue"),
+            NotificationKind::Netlink => cfg!(feature = "netlink"),
+            NotificationKind::Dbus => cfg!(feature = "dbus"),
+            NotificationKind::Net => cfg!(feature = "net"),
+            NotificationKind::Pipe => cfg!(feature = "pipe"),
+            NotificationKind::Mock => cfg!(feature = "mock"),
+            NotificationKind::Sim => cfg!(feature = "sim"),
+        }
+    }
+}
+
+impl NotificationKind {
+    /// 该类型是否为每个通知分别排队，即成功发送的通知在被接收之前不会与其他通知合并
+    ///
+    /// 信号、eventfd与用户态中断等只记录“有通知”或一个计数，尚未被接收的多个通知被合并为一个。
+    pub const fn is_queued(self) -> bool {
+        matches!(
+            self,
+            NotificationKind::Uring
+                | NotificationKind::Vsock
+                | NotificationKind::Uds
+                | NotificationKind::Mqueue
+                | NotificationKind::Netlink
+                | NotificationKind::Dbus
+                | NotificationKind::Net
+                | NotificationKind::Pipe
+        )
+    }
+}
+
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 以`类型:id`的形式显示id，由[`Notification::display`]返回
+///
+/// 信号的id显示为`signal:<信号编号>`，被重新申请过的信号在其后附加`#<代数>`；
+/// 未知类型的id显示为`unknown(<高8位>):<低56位>`。
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IdDisplay(pub u64);
+
+impl fmt::Display for IdDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = (self.0 >> 56) as u8;
+        let inner = self.0 & 0x00FF_FFFF_FFFF_FFFF;
+        match NotificationKind::from_tag(tag) {
+            Some(NotificationKind::Signal) => {
+                let (signal, epoch) = (inner & 0xFF, inner >> 8);
+                write!(f, "signal:{}", signal)?;
+                if epoch != 0 {
+                    write!(f, "#{}", epoch)?;
+                }
+                Ok(())
+            }
+            Some(kind) => write!(f, "{}:{}", kind, inner),
+            None => write!(f, "unknown(0x{:02x}):{}", tag, inner),
+        }
+    }
+}
+
+impl fmt::Debug for IdDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Notification {
+    /// id所属的通知源类型，未知类型返回`None`
+    pub fn kind_of(id: u64) -> Option<NotificationKind> {
+        NotificationKind::from_tag((id >> 56) as u8)
+    }
+
+    /// id的类型是否已知且相应的feature已启用，即分发函数不会因未知类型而panic
+    ///
+    /// 不检查通知源是否仍被占用。
+    pub fn is_valid(id: u64) -> bool {
+        Self::kind_of(id).is_some_and(NotificationKind::is_enabled)
+    }
+
+    /// 以`类型:id`的形式显示id，用于日志等
+    pub fn display(id: u64) -> IdDisplay {
+        IdDisplay(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NotificationKind;
+    use crate::interface::Notification;
+    use alloc::format;
+
+    #[test]
+    fn test_kind_of() {
+        for &kind in NotificationKind::ALL {
+            assert_eq!(NotificationKind::from_tag(kind.tag()), Some(kind));
+            let id = ((kind.tag() as u64) << 56) | 7;
+            assert_eq!(Notification::kind_of(id), Some(kind));
+            assert_eq!(Notification::is_valid(id), kind.is_enabled());
+        }
+        assert_eq!(Notification::kind_of(0), None);
+        assert!(!Notification::is_valid(0xFF00_0000_0000_0001));
+
+        assert_eq!(
+            format!("{}", Notification::display(0x0100_0000_0000_002a)),
+            "signal:42"
+        );
+        assert_eq!(
+            format!("{:?}", Notification::display(0x0100_0000_0000_032a)),
+            "signal:42#3"
+        );
+        assert_eq!(
+            format!("{}", Notification::display(0x1100_0000_0000_0005)),
+            "pipe:5"
+        );
+        assert_eq!(
+            format!("{}", Notification::display(0xFF00_0000_0000_0001)),
+            "unknown(0xff):1"
+        );
+    }
+
+    #[cfg(feature = "signal")]
+    #[test]
+    fn test_kind_of_allocated() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal().unwrap();
+                assert_eq!(Notification::kind_of(id), Some(NotificationKind::Signal));
+                assert!(Notification::is_valid(id));
+                unsafe { <Notification as crate::interface::NotificationIf>::release_id(id) };
+            });
+    }
+}
@@ -0,0 +1,214 @@
+//! 使用kqueue `EVFILT_USER`的通知机制，用于macOS与FreeBSD等系统
+//!
+//! 每个通知源对应一个kqueue，其中注册了一个`EVFILT_USER`事件，`notify`通过`NOTE_TRIGGER`触发该事件。
+//!
+//! kqueue描述符不会被fork继承，在macOS上也不能通过`SCM_RIGHTS`传递给其他进程，
+//! 因此该通知机制只支持本进程内的通知，跨进程的通知应使用其他通知机制。
+//!
+//! 必须配合tokio运行时
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 每个kqueue中`EVFILT_USER`事件的ident
+const USER_IDENT: libc::uintptr_t = 0;
+
+/// 使用kqueue `EVFILT_USER`的通知机制
+pub struct KqueueNotification;
+
+struct KqueueSlot {
+    fd: AsyncFd<OwnedRawFd>,
+    /// 已从kqueue中取出、但尚未被`poll_wait`消费的通知数量
+    pending: AtomicU64,
+}
+
+/// 构造kqueue的变更项
+fn user_event(flags: u16, fflags: u32) -> libc::kevent {
+    // 各系统的`kevent`结构体的字段不同，因此不使用结构体字面量
+    let mut event: libc::kevent = unsafe { core::mem::zeroed() };
+    event.ident = USER_IDENT;
+    event.filter = libc::EVFILT_USER;
+    event.flags = flags;
+    event.fflags = fflags;
+    event
+}
+
+impl KqueueSlot {
+    /// 取出kqueue中被触发的事件，返回是否有新的通知
+    fn read_events(&self) -> bool {
+        let mut event = user_event(0, 0);
+        let timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let res =
+            unsafe { libc::kevent(self.fd.get_ref().0, ptr::null(), 0, &mut event, 1, &timeout) };
+        if res > 0 {
+            self.pending.fetch_add(1, Ordering::AcqRel);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if self
+                .pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+            {
+                return Poll::Ready(());
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(())),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.read_events() {
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+/// 所有被占用的kqueue，以fd为key
+static KQUEUES: SpinMutex<BTreeMap<u64, Arc<KqueueSlot>>> = SpinMutex::new(BTreeMap::new());
+
+impl NotificationIf for KqueueNotification {
+    /// 新建一个kqueue并注册`EVFILT_USER`事件，id即为kqueue的文件描述符
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将kqueue注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            return None;
+        }
+        let kq = OwnedRawFd(fd);
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        let change = user_event(libc::EV_ADD | libc::EV_CLEAR, 0);
+        let res = unsafe { libc::kevent(fd, &change, 1, ptr::null_mut(), 0, ptr::null()) };
+        if res < 0 {
+            return None;
+        }
+        let slot = KqueueSlot {
+            fd: AsyncFd::new(kq).ok()?,
+            pending: AtomicU64::new(0),
+        };
+        KQUEUES.lock().insert(fd as u64, Arc::new(slot));
+        Some(fd as u64)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 在两次等待之间的多次触发会被合并；若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_event(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let Some(slot) = Self::slot(id) else {
+            waker.wake_by_ref();
+            return;
+        };
+        if slot.pending.load(Ordering::Acquire) > 0 {
+            waker.wake_by_ref();
+            return;
+        }
+        let mut cx = Context::from_waker(waker);
+        if let Poll::Ready(guard) = slot.fd.poll_read_ready(&mut cx) {
+            let mut guard = ready_guard!(guard, return waker.wake_by_ref());
+            if slot.read_events() {
+                waker.wake_by_ref();
+            } else {
+                guard.clear_ready();
+            }
+        }
+    }
+
+    /// 关闭kqueue
+    unsafe fn release_id(id: u64) {
+        let slot = KQUEUES.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 只支持本进程内的通知，`process`不是本进程时panic
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl KqueueNotification {
+    /// 触发本进程中的通知源，`process`不是本进程时返回`NotifyError::Unsupported`
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        if process != unsafe { libc::getpid() } as u64 {
+            return Err(NotifyError::Unsupported);
+        }
+        let change = user_event(0, libc::NOTE_TRIGGER);
+        let res = unsafe {
+            libc::kevent(
+                id as libc::c_int,
+                &change,
+                1,
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+            )
+        };
+        if res < 0 {
+            return Err(NotifyError::Os(unsafe { *libc::__error() }));
+        }
+        Ok(())
+    }
+
+    fn slot(id: u64) -> Option<Arc<KqueueSlot>> {
+        KQUEUES.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[test]
+    fn test_kqueue_user_event() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_kqueue().unwrap();
+                let pid = unsafe { libc::getpid() } as u64;
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                tokio::task::yield_now().await;
+                Notification::notify(pid, id);
+                tokio::time::timeout(Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert!(Notification::notify_to((pid + 1).into(), id).is_err());
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
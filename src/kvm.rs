@@ -0,0 +1,298 @@
+//! 使用eventfd的通知机制，用于基于KVM的虚拟机监视器（VMM）
+//!
+//! 每个通知源对应一个eventfd，VMM可通过[`KvmNotification::eventfd`]取得其文件描述符并注册到KVM中：
+//!
+//! - ioeventfd：客户机写入指定的I/O端口或MMIO地址（kick）时，KVM使该eventfd可读，VMM在其上等待。
+//!   使用[`KvmNotification::new_id`]申请，也可由本进程调用`notify`发送普通的通知。
+//! - irqfd：VMM向该eventfd写入时，KVM向客户机注入中断。使用[`KvmNotification::new_id_irqfd`]申请，
+//!   由于eventfd由KVM消费，该通知源只能用于发送，不能在其上等待。
+//!
+//! eventfd只能在本进程（或继承了它的进程）中使用，因此`notify`的`process`参数被忽略。
+//!
+//! 可以用[`KvmNotification::expand`]预先创建eventfd，之后的申请优先使用它们，
+//! 从而在客户端数量增长时避免在申请时创建eventfd，并在达到文件描述符上限后仍能申请预留的数量。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use core::task::{Context, Poll, Waker};
+use std::os::fd::{IntoRawFd, OwnedFd, RawFd};
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用eventfd的通知机制
+pub struct KvmNotification;
+
+enum EventSlot {
+    /// 可在其上等待的eventfd，用作ioeventfd
+    Wait(ReadySlot),
+    /// 只用于发送的eventfd，用作irqfd
+    Send(OwnedRawFd),
+}
+
+impl EventSlot {
+    fn raw_fd(&self) -> RawFd {
+        match self {
+            EventSlot::Wait(slot) => slot.raw_fd(),
+            EventSlot::Send(fd) => fd.0,
+        }
+    }
+}
+
+/// 所有被占用的eventfd，以fd为key
+static EVENTS: SpinMutex<BTreeMap<u64, Arc<EventSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 由`expand`预先创建、尚未被申请的eventfd
+static POOL: SpinMutex<Vec<OwnedRawFd>> = SpinMutex::new(Vec::new());
+
+/// 取出一个预先创建的eventfd，没有时新建一个
+fn eventfd() -> Option<OwnedRawFd> {
+    POOL.lock().pop().or_else(create)
+}
+
+fn create() -> Option<OwnedRawFd> {
+    let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+    (fd >= 0).then_some(OwnedRawFd(fd))
+}
+
+/// 读出eventfd的计数，即此前到达的通知数量
+pub(crate) fn drain(fd: RawFd) -> u64 {
+    let mut count = 0u64;
+    let res = unsafe { libc::read(fd, &mut count as *mut u64 as *mut libc::c_void, 8) };
+    if res == 8 { count } else { 0 }
+}
+
+impl NotificationIf for KvmNotification {
+    /// 申请一个可在其上等待的eventfd（用作ioeventfd），id即为其文件描述符
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将eventfd注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let fd = eventfd()?;
+        let id = fd.0 as u64;
+        let slot = ReadySlot::new(fd, drain)?;
+        EVENTS.lock().insert(id, Arc::new(EventSlot::Wait(slot)));
+        Some(id)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 与读出eventfd的计数一样，一次等待消费所有已到达的通知，并以`WakeReason::Notified`报告其数量
+    ///
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束；在只用于发送的通知源上等待会panic（启用`no-panic`时等待立即结束）
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id).as_deref() {
+            Some(EventSlot::Wait(slot)) => slot.poll_wait_all(cx),
+            Some(EventSlot::Send(_)) => Self::send_only(id, Poll::Ready(WakeReason::Shutdown)),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id).as_deref() {
+            Some(EventSlot::Wait(slot)) => slot.register_waker(waker),
+            Some(EventSlot::Send(_)) => {
+                Self::send_only(id, ());
+                waker.wake_by_ref();
+            }
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭eventfd
+    ///
+    /// 若eventfd已被注册到KVM中，需先在KVM中注销它。
+    unsafe fn release_id(id: u64) {
+        let slot = EVENTS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 向eventfd写入1，`process`被忽略
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl KvmNotification {
+    /// 接管一个已有的eventfd（例如已被注册为ioeventfd），将其作为可在其上等待的通知源，id即为其文件描述符
+    ///
+    /// eventfd被设置为非阻塞的，并在`release_id`时被关闭。
+    /// 该函数需要在tokio运行时内部调用，因为其会将eventfd注册到tokio的reactor中。
+    pub fn new_id_from_fd(fd: OwnedFd) -> Option<u64> {
+        let fd = OwnedRawFd(fd.into_raw_fd());
+        let flags = unsafe { libc::fcntl(fd.0, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd.0, libc::F_SETFL, flags | libc::O_NONBLOCK) } != 0 {
+            return None;
+        }
+        let id = fd.0 as u64;
+        let slot = ReadySlot::new(fd, drain)?;
+        EVENTS.lock().insert(id, Arc::new(EventSlot::Wait(slot)));
+        Some(id)
+    }
+
+    /// 申请一个只用于发送的eventfd（用作irqfd），id即为其文件描述符
+    pub fn new_id_irqfd() -> Option<u64> {
+        let fd = eventfd()?;
+        let id = fd.0 as u64;
+        EVENTS.lock().insert(id, Arc::new(EventSlot::Send(fd)));
+        Some(id)
+    }
+
+    /// 预先创建`n`个eventfd，供之后的`new_id`与`new_id_irqfd`使用，返回实际创建的数量
+    ///
+    /// 达到文件描述符上限时停止创建。
+    pub fn expand(n: usize) -> usize {
+        let fds: Vec<OwnedRawFd> = (0..n).map_while(|_| create()).collect();
+        let count = fds.len();
+        POOL.lock().extend(fds);
+        count
+    }
+
+    /// 预先创建、尚未被申请的eventfd的数量
+    pub fn pooled() -> usize {
+        POOL.lock().len()
+    }
+
+    /// 通知源对应的eventfd，用于注册到KVM中（`KVM_IOEVENTFD`或`KVM_IRQFD`）
+    ///
+    /// 文件描述符仍由本模块持有，在`release_id`时被关闭。
+    pub fn eventfd(id: u64) -> Option<RawFd> {
+        Self::slot(id).map(|slot| slot.raw_fd())
+    }
+
+    /// 向eventfd写入1，`process`被忽略；失败时返回错误
+    pub fn try_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let fd = Self::eventfd(id).ok_or(NotifyError::Os(libc::EBADF))?;
+        let one = 1u64;
+        let res = unsafe { libc::write(fd, &one as *const u64 as *const libc::c_void, 8) };
+        if res == 8 {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            // 计数将要溢出
+            libc::EAGAIN => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        let fd = match crate::fd::take_unique(&EVENTS, id)? {
+            EventSlot::Wait(slot) => slot.into_fd(),
+            EventSlot::Send(fd) => fd,
+        };
+        Some(fd.into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    ///
+    /// 在只用于发送的通知源上等待会panic（启用`no-panic`时等待立即结束）。
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.raw_fd(),
+            |slot| match &**slot {
+                EventSlot::Wait(slot) => slot.take(),
+                EventSlot::Send(_) => Self::send_only(id, true),
+            },
+        )
+    }
+
+    /// 在只用于发送的通知源上等待，启用`no-panic`时返回`fallback`
+    #[cfg_attr(not(feature = "no-panic"), allow(unused_variables))]
+    fn send_only<T>(id: u64, fallback: T) -> T {
+        fail!(fallback, "kvm: irqfd id 0x{:016x} cannot be waited on", id)
+    }
+
+    fn slot(id: u64) -> Option<Arc<EventSlot>> {
+        EVENTS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf, WakeReason};
+    use core::time::Duration;
+
+    #[test]
+    fn test_kvm_eventfd() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // 模拟客户机的kick：直接写入eventfd
+                let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                let fd = Notification::kvm_eventfd(id).unwrap();
+                let two = 2u64;
+                unsafe { libc::write(fd, &two as *const u64 as *const libc::c_void, 8) };
+                let reason =
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                assert_eq!(reason, WakeReason::Notified { count: 2 });
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                unsafe { Notification::release_id(id) };
+
+                // 模拟KVM消费irqfd：直接读出eventfd
+                let id = Notification::new_id_kvm_irqfd().unwrap();
+                let fd = Notification::kvm_eventfd(id).unwrap();
+                Notification::notify(0, id);
+                Notification::notify(0, id);
+                assert_eq!(super::drain(fd), 2);
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_kvm_expand() {
+        use super::KvmNotification;
+
+        // 在子进程中测试，池不被其他测试并发使用
+        match unsafe { libc::fork() } {
+            0 => {
+                unsafe { libc::alarm(5) };
+                let ok = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap()
+                    .block_on(async move {
+                        let added = KvmNotification::expand(2);
+                        let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                        let pooled = KvmNotification::pooled();
+                        Notification::notify(0, id);
+                        let woken =
+                            tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                                .await
+                                .is_ok();
+                        unsafe { Notification::release_id(id) };
+                        added == 2 && pooled == 1 && woken
+                    });
+                unsafe { libc::_exit(!ok as libc::c_int) };
+            }
+            -1 => panic!("Fork failed!"),
+            child => {
+                let mut status = 0;
+                unsafe { libc::waitpid(child, &mut status, 0) };
+                assert_eq!(status, 0);
+            }
+        }
+    }
+}
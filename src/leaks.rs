@@ -0,0 +1,162 @@
+//! 检测从未被释放的通知源
+//!
+//! 启用`debug-leaks`后，经[`Notification`]申请的每个通知源都记录申请时的调用栈与时刻，
+//! 可再以[`set_label`]附加一个调用处的标签（信号通知源默认使用`new_id_signal_with_label`的标签）。
+//! [`leaks`]列出存在时间超过给定值的通知源；[`shutdown`]应在进程退出前调用，报告所有尚未被释放的通知源。
+//! 以[`set_max_age`]设置最长存在时间后，每次申请通知源时检查已存在过久的通知源，并在启用`log`时对每个输出一次警告。
+//!
+//! 记录调用栈的开销较大，只应在调试时启用。
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use core::{fmt, time::Duration};
+use std::{backtrace::Backtrace, time::Instant};
+
+use crate::{interface::Notification, sync::SpinMutex};
+
+/// 一个尚未被释放的通知源
+#[derive(Debug, Clone)]
+pub struct Leak {
+    /// 通知源的id
+    pub id: u64,
+    /// 自申请以来经过的时间
+    pub age: Duration,
+    /// 调用处的标签
+    pub label: Option<&'static str>,
+    /// 申请时的调用栈
+    pub backtrace: Arc<Backtrace>,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allocated {:?} ago label={}\n{}",
+            Notification::display(self.id),
+            self.age,
+            self.label.unwrap_or("-"),
+            self.backtrace
+        )
+    }
+}
+
+struct Allocation {
+    at: Instant,
+    label: Option<&'static str>,
+    backtrace: Arc<Backtrace>,
+    /// 是否已因存在过久而输出过警告
+    reported: bool,
+}
+
+/// 尚未被释放的通知源
+static ALLOCATIONS: SpinMutex<BTreeMap<u64, Allocation>> = SpinMutex::new(BTreeMap::new());
+
+/// 最长存在时间，`None`表示不检查
+static MAX_AGE: SpinMutex<Option<Duration>> = SpinMutex::new(None);
+
+/// 申请了通知源`id`，记录调用栈并检查已存在过久的通知源
+pub(crate) fn new_id(id: u64) {
+    let allocation = Allocation {
+        at: Instant::now(),
+        label: None,
+        backtrace: Arc::new(Backtrace::force_capture()),
+        reported: false,
+    };
+    let max_age = *MAX_AGE.lock();
+    let mut allocations = ALLOCATIONS.lock();
+    allocations.insert(id, allocation);
+    if let Some(max_age) = max_age {
+        for (&_id, allocation) in allocations.iter_mut() {
+            if !allocation.reported && allocation.at.elapsed() > max_age {
+                allocation.reported = true;
+                #[cfg(feature = "log")]
+                log::warn!("possible leak: {}", leak(_id, allocation));
+            }
+        }
+    }
+}
+
+/// 通知源`id`即将被释放
+pub(crate) fn release(id: u64) {
+    ALLOCATIONS.lock().remove(&id);
+}
+
+fn leak(id: u64, allocation: &Allocation) -> Leak {
+    let label = allocation
+        .label
+        .or_else(|| Notification::owner(id).and_then(|owner| owner.label));
+    Leak {
+        id,
+        age: allocation.at.elapsed(),
+        label,
+        backtrace: allocation.backtrace.clone(),
+    }
+}
+
+/// 设置通知源的最长存在时间，`None`表示不检查
+pub fn set_max_age(age: Option<Duration>) {
+    *MAX_AGE.lock() = age;
+}
+
+/// 为尚未被释放的通知源`id`附加调用处的标签；通知源未被记录时返回`false`
+pub fn set_label(id: u64, label: &'static str) -> bool {
+    match ALLOCATIONS.lock().get_mut(&id) {
+        Some(allocation) => {
+            allocation.label = Some(label);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 存在时间不短于`min_age`的尚未被释放的通知源，按id排序
+pub fn leaks(min_age: Duration) -> Vec<Leak> {
+    ALLOCATIONS
+        .lock()
+        .iter()
+        .filter(|(_, allocation)| allocation.at.elapsed() >= min_age)
+        .map(|(&id, allocation)| leak(id, allocation))
+        .collect()
+}
+
+/// 报告所有尚未被释放的通知源，应在进程退出前、释放完所有通知源之后调用
+///
+/// 启用`log`时对每个通知源输出一条警告。
+pub fn shutdown() -> Vec<Leak> {
+    let leaks = leaks(Duration::ZERO);
+    #[cfg(feature = "log")]
+    for leak in &leaks {
+        log::warn!("leaked at shutdown: {}", leak);
+    }
+    leaks
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    #[test]
+    fn test_leaks() {
+        let id = Notification::new_id_mock().unwrap();
+        assert!(set_label(id, "test_leaks"));
+        std::thread::sleep(Duration::from_millis(10));
+        let leak = leaks(Duration::from_millis(10))
+            .into_iter()
+            .find(|leak| leak.id == id)
+            .unwrap();
+        assert_eq!(leak.label, Some("test_leaks"));
+        assert!(leak.age >= Duration::from_millis(10));
+        assert!(
+            leaks(Duration::from_secs(3600))
+                .iter()
+                .all(|leak| leak.id != id)
+        );
+        assert!(shutdown().iter().any(|leak| leak.id == id));
+
+        unsafe { Notification::release_id(id) };
+        assert!(shutdown().iter().all(|leak| leak.id != id));
+        assert!(!set_label(id, "test_leaks"));
+    }
+}
@@ -0,0 +1,188 @@
+//! 带租期的通知源
+//!
+//! 服务端为每个客户端申请的通知源在客户端崩溃后不会被释放。[`Notification::new_id_leased`]申请的通知源带有租期，
+//! 持有者需在租期内调用[`Notification::renew`]续租（例如在每次收到客户端的消息时），否则通知源被自动释放，
+//! 在[`Notification::wait_on_leased`]上的等待以[`Expired`]结束。
+//!
+//! 租期由后台的tokio任务检查，因此需要在tokio运行时内部申请，并启用其时钟。
+//! 不要在通知源可能到期时手动释放它，否则可能与自动释放重复。
+
+use alloc::collections::{btree_map::BTreeMap, vec_deque::VecDeque};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::{
+    interface::{Notification, NotificationIf, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 最多记录的已到期的通知源数量，更早到期的通知源被遗忘
+const MAX_EXPIRED: usize = 256;
+
+/// 通知源因租期到期而被释放
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expired;
+
+impl fmt::Display for Expired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "notification id lease expired")
+    }
+}
+
+/// 到期的通知源之后不会再有通知
+impl From<Expired> for WakeReason {
+    fn from(_: Expired) -> Self {
+        WakeReason::Shutdown
+    }
+}
+
+struct Lease {
+    ttl: Duration,
+    deadline: Instant,
+    /// 最近一次在`wait_on_leased`上等待的waker，在到期时唤醒
+    waker: Option<Waker>,
+}
+
+/// 租期尚未到期的通知源，以id为key
+static LEASES: SpinMutex<BTreeMap<u64, Lease>> = SpinMutex::new(BTreeMap::new());
+
+/// 最近到期的通知源，按到期的顺序排列
+static EXPIRED: SpinMutex<VecDeque<u64>> = SpinMutex::new(VecDeque::new());
+
+/// 申请了通知源`id`，清除同一id此前到期的记录
+pub(crate) fn new_id(id: u64) {
+    EXPIRED.lock().retain(|&expired| expired != id);
+}
+
+/// 通知源`id`即将被释放，不再检查其租期
+pub(crate) fn release(id: u64) {
+    LEASES.lock().remove(&id);
+}
+
+fn is_expired(id: u64) -> bool {
+    EXPIRED.lock().contains(&id)
+}
+
+/// 在租期到期时释放通知源`id`，通知源被提前释放时结束
+async fn watch(id: u64) {
+    loop {
+        let Some(deadline) = LEASES.lock().get(&id).map(|lease| lease.deadline) else {
+            return;
+        };
+        tokio::time::sleep_until(deadline).await;
+        let waker = {
+            let mut leases = LEASES.lock();
+            match leases.get(&id) {
+                // 期间被续租
+                Some(lease) if lease.deadline > Instant::now() => continue,
+                Some(_) => leases.remove(&id).and_then(|lease| lease.waker),
+                None => return,
+            }
+        };
+        {
+            let mut expired = EXPIRED.lock();
+            if expired.len() == MAX_EXPIRED {
+                expired.pop_front();
+            }
+            expired.push_back(id);
+        }
+        unsafe { Notification::release_id(id) };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        return;
+    }
+}
+
+impl Notification {
+    /// 以`new_id`申请通知源，并为其设置租期`ttl`；在`ttl`内未被续租时通知源被自动释放
+    ///
+    /// 例如`Notification::new_id_leased(Notification::new_id_signal, Duration::from_secs(30))`。
+    /// 需要在tokio运行时内部调用。
+    pub fn new_id_leased(new_id: impl FnOnce() -> Option<u64>, ttl: Duration) -> Option<u64> {
+        let id = new_id()?;
+        let lease = Lease {
+            ttl,
+            deadline: Instant::now() + ttl,
+            waker: None,
+        };
+        LEASES.lock().insert(id, lease);
+        tokio::spawn(watch(id));
+        Some(id)
+    }
+
+    /// 续租通知源`id`，使其租期从现在起重新计算；通知源没有租期或已到期时返回`false`
+    pub fn renew(id: u64) -> bool {
+        match LEASES.lock().get_mut(&id) {
+            Some(lease) => {
+                lease.deadline = Instant::now() + lease.ttl;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 在通知源上等待；若通知源因租期到期而被释放，则返回`Err(Expired)`
+    ///
+    /// 对没有租期的通知源与`wait_on`相同。返回的future是取消安全的。
+    pub fn wait_on_leased(id: u64) -> WaitLease {
+        WaitLease { id }
+    }
+}
+
+/// `Notification::wait_on_leased`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitLease {
+    id: u64,
+}
+
+impl Future for WaitLease {
+    type Output = Result<(), Expired>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.id;
+        if let Some(lease) = LEASES.lock().get_mut(&id) {
+            lease.waker = Some(cx.waker().clone());
+        }
+        if is_expired(id) {
+            return Poll::Ready(Err(Expired));
+        }
+        Notification::poll_wait(id, cx).map(|()| if is_expired(id) { Err(Expired) } else { Ok(()) })
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_expiry() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let ttl = Duration::from_millis(50);
+                let id = Notification::new_id_leased(Notification::new_id_mock, ttl).unwrap();
+                // 续租期间不到期
+                for _ in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    assert!(Notification::renew(id));
+                }
+                Notification::notify(0, id);
+                assert_eq!(Notification::wait_on_leased(id).await, Ok(()));
+
+                let start = Instant::now();
+                assert_eq!(Notification::wait_on_leased(id).await, Err(Expired));
+                assert!(start.elapsed() >= Duration::from_millis(40));
+                assert!(!Notification::renew(id));
+                assert_eq!(crate::mock::pending(id), None);
+            });
+    }
+}
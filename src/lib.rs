@@ -1,10 +1,250 @@
 //! 使用统一的接口封装信号、用户态中断等通知机制，使其可用于IPC的通知中。
+//!
+//! 各通知机制分别由同名的feature启用，feature之间可任意组合：
+//!
+//! - `signal`：使用信号的通知机制
+//! - `signalfd`：使用信号的通知机制，但通过signalfd接收，并可取得发送方的进程号与附加值
+//! - `uintr`：使用用户态中断的通知机制
+//! - `uintr-hal`：由内核（写UIPI描述符或RISC-V的CSR）或用户态（系统调用）实现投递的用户态中断通知机制，不需要分配内存
+//! - `timer`：使用timerfd的周期性通知机制
+//! - `child`：使用pidfd的子进程退出通知机制，以及基于其的对端进程存活监视
+//! - `uring`：使用io_uring `IORING_OP_MSG_RING`的通知机制
+//! - `kqueue`：使用kqueue `EVFILT_USER`的通知机制，仅支持macOS与FreeBSD
+//! - `fuchsia`：使用zircon eventpair的通知机制，仅支持Fuchsia
+//! - `arceos`：用于ArceOS等unikernel的通知机制，通知原语由内核提供
+//! - `ipi`：使用软件核间中断的通知机制，用于裸机与内核中的多核心，不需要分配内存
+//! - `vsock`：使用vsock数据报的通知机制，用于虚拟机与宿主机之间的通知
+//! - `kvm`：使用eventfd的通知机制，可注册为KVM的ioeventfd或irqfd
+//! - `uds`：使用unix域套接字的通知机制，并可经`SCM_RIGHTS`传递文件描述符
+//! - `mqueue`：使用POSIX消息队列的通知机制，通知可带有负载
+//! - `netlink`：使用netlink套接字的通知机制，内核模块也可发送通知
+//! - `dbus`：转发D-Bus信号的通知机制，用于与桌面服务互通
+//! - `net`：使用UDP数据报的通知机制，用于跨主机的通知
+//! - `pipe`：使用管道的通知机制，只依赖POSIX接口，作为最后的选择
+//! - `mock`：用于测试的模拟通知机制，由`mock::trigger`确定性地触发
+//! - `sim`：使用虚拟时间的确定性模拟通知机制，投递顺序与延迟由带种子的调度器决定
+//! - `mio`：为eventfd、signalfd与管道通知源实现`mio::event::Source`，用于不使用异步运行时的事件循环
+//! - `ffi`：导出C语言接口，声明见`include/async_notification.h`
+//! - `component`：Wasm组件模型的宿主侧适配，接口定义见`wit/notification.wit`
+//! - `log`：输出日志
+//! - `no-panic`：运行时的失败（例如`kill`失败）不再panic，而是输出错误日志后继续执行
+//! - `ack`：带确认的通知投递，未收到接收方的确认时按退避时间重发，直到超时
+//! - `seq`：在共享内存中为通知源维护发送序号，接收方可得知一次唤醒对应的通知数，从而发现被合并或丢失的通知；并可限制尚未消费的通知数，使发送方在接收方跟不上时得到错误
+//! - `sentinel`：发送方周期性地发送经`seq`编号的心跳，接收方据此检查通知的连续性，分别统计被合并与疑似丢失的通知数
+//! - `receive-policy`：为通知源设置接收方的合并、防抖或限流策略，避免频繁的通知造成唤醒风暴
+//! - `lease`：带租期的通知源，在租期内未被续租时自动释放，避免崩溃的客户端泄漏通知源
+//! - `fault-inject`：包装任意通知机制，按带种子的策略丢弃、重复或延迟通知，用于检验协议的容错
+//! - `record`：录制接收到的通知，并在`mock`通知源上回放，用于在测试中重现生产环境中的事件序列
+//! - `event-log`：在环形缓冲区中记录最近的申请、发送、消费与释放事件，用于事后分析
+//! - `stats`：统计每个通知源的发送、消费与等待时间
+//! - `debug-leaks`：记录每个通知源申请时的调用栈，报告从未被释放或存在过久的通知源
+//! - `tracing`：使用`tracing`输出申请、发送、等待与释放通知源的事件
+//! - `spin-wait`：为通知源设置先自旋、再挂起的等待策略，减少快速交接时的唤醒开销；并提供从不挂起的忙等待
+//! - `payload`：随通知传递至多数百字节的数据，数据位于接收方的已封印的memfd环形缓冲区中，接收方以不复制的视图访问，视图被drop时释放其位置
+//! - `ipc`：基于共享内存与通知的进程间同步原语
+//! - `rpc`：请求与应答配对的通知，以共享槽位中的序号关联应答，并支持超时
+//! - `watchdog`：基于通知的心跳与看门狗，对方连续若干个间隔没有发送心跳时停止
+//! - `retry`：带重试策略的发送目标，暂时的发送失败时按退避时间重试
+//! - `delay`：延迟与定时发送的通知，可在到期之前取消
+//! - `futex`：以`futex_waitv`同时等待至多128个共享内存中的futex字，内核不支持时退回逐个等待
+//! - `hybrid`：两阶段通知，发送方先写共享内存中的标志，只在接收方挂起时才经由通知机制发送，减少忙碌接收方的系统调用
+//! - `wake-coalesce`：合并通知成批到达时对任务的多次唤醒，每轮轮询至多唤醒一次，并记录被合并的次数
+//! - `full`：启用以上除`kqueue`、`fuchsia`、`arceos`与`no-panic`外的全部feature；`no-panic`改变了运行时失败的行为，需单独启用
+//!
+//! 以上feature与`Notification`等工具都需要堆分配，由默认启用的`alloc`提供。
+//! 在没有堆的环境中以`default-features = false`使用时，只提供[`interface::NotificationIf`]等接口
+//! 与[`fixed`]中容量由const泛型给出、保存在静态数组中的通知机制。
 
 #![no_std]
 #![deny(missing_docs)]
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[macro_use]
+mod macros;
+
+#[cfg(all(
+    feature = "kqueue",
+    not(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))
+))]
+compile_error!("the `kqueue` feature is only supported on macOS, iOS and FreeBSD");
+#[cfg(all(feature = "fuchsia", not(target_os = "fuchsia")))]
+compile_error!("the `fuchsia` feature is only supported on Fuchsia");
+
+#[cfg(feature = "ack")]
+pub mod ack;
+#[cfg(feature = "arceos")]
+pub mod arceos;
+#[cfg(feature = "signal")]
+mod bitmap;
+#[cfg(any(
+    feature = "signal",
+    feature = "signalfd",
+    feature = "timer",
+    feature = "child",
+    feature = "uring",
+    feature = "kqueue",
+    feature = "fuchsia",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "mqueue",
+    feature = "netlink",
+    feature = "dbus",
+    feature = "net",
+    feature = "pipe",
+    feature = "ffi"
+))]
+pub mod blocking;
+#[cfg(feature = "alloc")]
+pub mod broadcast;
+#[cfg(feature = "child")]
+pub mod child;
+#[cfg(feature = "wake-coalesce")]
+pub mod coalesce;
+#[cfg(feature = "component")]
+pub mod component;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+#[cfg(feature = "delay")]
+pub mod delay;
+#[cfg(feature = "alloc")]
+pub mod doorbell;
+#[cfg(feature = "alloc")]
+pub mod dynamic;
+#[cfg(feature = "event-log")]
+pub mod event_log;
+#[cfg(feature = "fault-inject")]
+pub mod fault;
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "uring",
+    feature = "kqueue",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "signalfd",
+    feature = "mqueue",
+    feature = "netlink",
+    feature = "dbus",
+    feature = "net",
+    feature = "pipe"
+))]
+mod fd;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(any(feature = "signalfd", feature = "uds"))]
+pub mod filter;
+pub mod fixed;
+#[cfg(feature = "fuchsia")]
+pub mod fuchsia;
+#[cfg(feature = "futex")]
+pub mod futex;
+#[cfg(feature = "alloc")]
+pub mod group;
+#[cfg(feature = "alloc")]
+pub mod hal;
+#[cfg(any(feature = "kvm", feature = "uds"))]
+pub mod handoff;
+#[cfg(feature = "alloc")]
+pub mod hooks;
+#[cfg(feature = "hybrid")]
+pub mod hybrid;
 pub mod interface;
+#[cfg(feature = "ipc")]
+pub mod ipc;
+#[cfg(feature = "ipi")]
+pub mod ipi;
+#[cfg(feature = "alloc")]
+pub mod kick;
+#[cfg(feature = "alloc")]
+pub mod kind;
+#[cfg(feature = "kqueue")]
+pub mod kqueue;
+#[cfg(feature = "kvm")]
+pub mod kvm;
+#[cfg(feature = "debug-leaks")]
+pub mod leaks;
+#[cfg(feature = "lease")]
+pub mod lease;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "mqueue")]
+pub mod mqueue;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "netlink")]
+pub mod netlink;
+#[cfg(feature = "alloc")]
+pub mod owner;
+#[cfg(feature = "payload")]
+pub mod payload;
+#[cfg(feature = "pipe")]
+pub mod pipe;
+#[cfg(feature = "alloc")]
+pub mod policy;
+#[cfg(feature = "alloc")]
+pub mod raw;
+#[cfg(feature = "receive-policy")]
+pub mod receive;
+#[cfg(feature = "record")]
+pub mod record;
+#[cfg(feature = "retry")]
+pub mod retry;
+#[cfg(any(feature = "sim", feature = "fault-inject"))]
+mod rng;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(any(feature = "kvm", feature = "uds", feature = "uintr"))]
+mod scm;
+#[cfg(feature = "sentinel")]
+pub mod sentinel;
+#[cfg(feature = "seq")]
+pub mod seq;
+#[cfg(feature = "alloc")]
+pub mod set;
+#[cfg(feature = "alloc")]
+pub mod shared;
+#[cfg(any(feature = "seq", feature = "hybrid", feature = "ipc"))]
+mod shm;
 #[cfg(feature = "signal")]
 pub mod signal;
+#[cfg(feature = "signal")]
+mod signal_slot;
+#[cfg(feature = "signalfd")]
+pub mod signalfd;
+#[cfg(any(feature = "signal", feature = "signalfd"))]
+pub mod sigrt;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+#[cfg(any(feature = "kvm", feature = "signalfd", feature = "pipe"))]
+pub mod source;
+#[cfg(feature = "spin-wait")]
+pub mod spin;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "alloc")]
+mod sync;
+#[cfg(feature = "timer")]
+pub mod timer;
+#[cfg(feature = "alloc")]
+mod trace;
+#[cfg(feature = "alloc")]
+pub mod typed;
+#[cfg(feature = "uds")]
+pub mod uds;
+#[cfg(feature = "uintr")]
 pub mod uintr;
+#[cfg(feature = "uintr-hal")]
+pub mod uintr_hal;
+#[cfg(feature = "uring")]
+pub mod uring;
+#[cfg(feature = "vsock")]
+pub mod vsock;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
+#[cfg(feature = "child")]
+pub mod watcher;
@@ -0,0 +1,91 @@
+//! 运行时失败的处理
+//!
+//! 默认情况下，运行时的失败（例如`kill`失败、在已被释放的通知源上重复释放）会panic。
+//! 启用`no-panic` feature时，这些失败改为输出错误日志（若启用了`log`），并以给定的值继续执行，
+//! 用于不能因目标进程在存活检查与发送之间退出等情况而中止的进程。
+//!
+//! 参数检查（例如定时器的周期不能为0）与不可能失败的内部不变式仍使用`assert!`。
+
+/// 运行时的失败：默认以给定的消息panic；启用`no-panic`时输出错误日志，并求值为`$fallback`
+#[allow(unused_macros)]
+macro_rules! fail {
+    ($fallback:expr, $($arg:tt)+) => {{
+        #[cfg(not(feature = "no-panic"))]
+        {
+            panic!($($arg)+)
+        }
+        #[cfg(feature = "no-panic")]
+        {
+            #[cfg(feature = "log")]
+            log::error!($($arg)+);
+            #[cfg(not(feature = "log"))]
+            let _ = format_args!($($arg)+);
+            $fallback
+        }
+    }};
+}
+
+/// 检查运行时的条件：不满足时默认panic；启用`no-panic`时输出错误日志后继续执行
+#[allow(unused_macros)]
+macro_rules! check {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            fail!((), $($arg)+)
+        }
+    };
+    ($cond:expr) => {
+        check!($cond, concat!("check failed: ", stringify!($cond)))
+    };
+}
+
+/// 取出`AsyncFd::poll_read_ready`返回的guard，tokio的reactor已关闭时视为运行时的失败，并求值为`$fallback`
+#[allow(unused_macros)]
+macro_rules! ready_guard {
+    ($guard:expr, $fallback:expr) => {
+        match $guard {
+            Ok(guard) => guard,
+            Err(err) => fail!($fallback, "poll_read_ready: {}", err),
+        }
+    };
+}
+
+#[cfg(all(test, feature = "alloc", feature = "no-panic"))]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::task::Context;
+
+    #[test]
+    fn test_no_panic() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        // 未知类型的通知源视为已被释放
+        let id = 0xFF00_0000_0000_0326;
+        Notification::notify(0, id);
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        Notification::register_waker(id, &waker);
+        unsafe { Notification::release_id(id) };
+        let _ = fail!(1, "not a panic");
+        check!(1 + 1 == 3);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_no_panic_mock() {
+        let id = Notification::new_id_mock().unwrap();
+        unsafe { Notification::release_id(id) };
+        // 向已被释放的通知源发送通知、重复释放均不会panic
+        Notification::notify(0, id);
+        unsafe { Notification::release_id(id) };
+    }
+
+    /// 类型不符的id不会panic，而是与已被释放的通知源相同
+    #[cfg(any(feature = "signalfd", feature = "mqueue"))]
+    #[test]
+    fn test_no_panic_wrong_tag() {
+        let id = 0xFF00_0000_0000_0326;
+        #[cfg(feature = "signalfd")]
+        assert!(futures::executor::block_on(Notification::wait_on_signalfd_info(id)).is_none());
+        #[cfg(feature = "mqueue")]
+        assert!(futures::executor::block_on(Notification::wait_on_mqueue_info(id)).is_none());
+    }
+}
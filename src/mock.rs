@@ -0,0 +1,207 @@
+//! 用于测试的模拟通知机制
+//!
+//! 通知源只存在于内存中，不使用信号、文件描述符或子进程，也不需要异步运行时。
+//! 使用本库的crate可在单元测试中用[`Notification::new_id_mock`](crate::interface::Notification::new_id_mock)
+//! 申请通知源，交给被测的等待循环，再调用[`trigger`]确定性地使一次等待结束：
+//!
+//! ```
+//! use async_notification::interface::{Notification, NotificationIf};
+//! use core::task::Context;
+//!
+//! let id = Notification::new_id_mock().unwrap();
+//! let waker = futures::task::noop_waker();
+//! let mut cx = Context::from_waker(&waker);
+//! assert!(Notification::poll_wait(id, &mut cx).is_pending());
+//! async_notification::mock::trigger(id);
+//! assert!(Notification::poll_wait(id, &mut cx).is_ready());
+//! unsafe { Notification::release_id(id) };
+//! ```
+
+use alloc::collections::btree_map::BTreeMap;
+use core::task::{Context, Poll, Waker};
+
+use crate::{
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 模拟的通知机制
+pub struct MockNotification;
+
+struct MockSlot {
+    /// 尚未被`poll_wait`消费的通知数量
+    pending: u64,
+    waker: Option<Waker>,
+}
+
+/// 所有被占用的通知源
+static MOCKS: SpinMutex<BTreeMap<u64, MockSlot>> = SpinMutex::new(BTreeMap::new());
+
+/// 去掉id的高8位，使[`trigger`]等函数既接受分发层的id，也接受本模块的id
+fn inner(id: u64) -> u64 {
+    id & 0x00FF_FFFF_FFFF_FFFF
+}
+
+impl NotificationIf for MockNotification {
+    fn new_id() -> Option<u64> {
+        let mut mocks = MOCKS.lock();
+        let id = mocks.keys().next_back().map_or(0, |id| id + 1);
+        mocks.insert(
+            id,
+            MockSlot {
+                pending: 0,
+                waker: None,
+            },
+        );
+        Some(id)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        let mut mocks = MOCKS.lock();
+        let Some(slot) = mocks.get_mut(&id) else {
+            return Poll::Ready(WakeReason::Shutdown);
+        };
+        if slot.pending > 0 {
+            slot.pending -= 1;
+            return Poll::Ready(WakeReason::NOTIFIED);
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let mut mocks = MOCKS.lock();
+        match mocks.get_mut(&id) {
+            Some(slot) if slot.pending == 0 => slot.waker = Some(waker.clone()),
+            _ => waker.wake_by_ref(),
+        }
+    }
+
+    /// 释放通知源，并唤醒正在等待的协程
+    unsafe fn release_id(id: u64) {
+        let slot = MOCKS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+        if let Some(waker) = slot.and_then(|slot| slot.waker) {
+            waker.wake();
+        }
+    }
+
+    /// `process`被忽略
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl MockNotification {
+    /// 检查通知源是否被占用，`process`被忽略；通知源已被释放时返回`NotifyError::Os(EBADF)`
+    pub fn can_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        if MOCKS.lock().contains_key(&id) {
+            Ok(())
+        } else {
+            // EBADF
+            Err(NotifyError::Os(9))
+        }
+    }
+
+    /// 使通知源收到一个通知，`process`被忽略；通知源已被释放时返回`NotifyError::Os(EBADF)`
+    pub fn try_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let waker = {
+            let mut mocks = MOCKS.lock();
+            // EBADF
+            let slot = mocks.get_mut(&id).ok_or(NotifyError::Os(9))?;
+            slot.pending += 1;
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+/// 使通知源`id`收到一个通知，唤醒在其上等待的协程
+///
+/// 与`notify`不同，不经过[`hooks`](crate::hooks)与`tracing`，模拟的是通知从外部到达。通知源未被占用时panic。
+pub fn trigger(id: u64) {
+    let res = MockNotification::try_notify(0, inner(id));
+    assert!(
+        res.is_ok(),
+        "trigger: mock id 0x{:016x} is not allocated",
+        id
+    );
+}
+
+/// 通知源上尚未被消费的通知数量，通知源未被占用时返回`None`
+pub fn pending(id: u64) -> Option<u64> {
+    MOCKS.lock().get(&inner(id)).map(|slot| slot.pending)
+}
+
+/// 是否有协程正在通知源上等待，可用于在`trigger`之前确认被测代码已进入等待
+pub fn has_waiter(id: u64) -> bool {
+    MOCKS
+        .lock()
+        .get(&inner(id))
+        .is_some_and(|slot| slot.waker.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf, WakeReason};
+    use core::time::Duration;
+
+    #[test]
+    fn test_mock() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_mock().unwrap();
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                while !super::has_waiter(id) {
+                    tokio::task::yield_now().await;
+                }
+                super::trigger(id);
+                let reason = tokio::time::timeout(Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(reason, WakeReason::NOTIFIED);
+
+                // 未被等待的通知被累计
+                Notification::notify(0, id);
+                super::trigger(id);
+                assert_eq!(super::pending(id), Some(2));
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                while !super::has_waiter(id) {
+                    tokio::task::yield_now().await;
+                }
+                unsafe { Notification::release_id(id) };
+                let reason = tokio::time::timeout(Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(reason, WakeReason::Shutdown);
+                assert_eq!(super::pending(id), None);
+                assert!(
+                    Notification::notify_with(0, id, crate::interface::Delivery::Reliable).is_err()
+                );
+            });
+    }
+}
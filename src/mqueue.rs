@@ -0,0 +1,324 @@
+//! 使用POSIX消息队列的通知机制
+//!
+//! 每个通知源对应一个名为`/async_notification.<pid>.<id>`的消息队列，`notify`向其中发送一条消息。
+//! 与信号不同，消息由内核按发送顺序缓存，不会被合并；每条消息还可以带有不超过队列消息大小的负载，
+//! 通过[`MqueueNotification::notify_with`]发送，并由[`MqueueNotification::wait_on_info`]取得。
+//!
+//! Linux下消息队列描述符即文件描述符，因此直接将其注册到tokio的reactor中等待，而不使用`mq_notify`。
+//!
+//! 接收方进程在释放通知源之前退出时，消息队列会残留在`/dev/mqueue`中，需手动删除。
+//!
+//! 必须配合tokio运行时
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    ffi::CString,
+    format,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用POSIX消息队列的通知机制
+pub struct MqueueNotification;
+
+/// `new_id`使用的队列容量
+pub const DEFAULT_MAX_MSGS: usize = 10;
+/// `new_id`使用的最大负载大小
+pub const DEFAULT_MSG_SIZE: usize = 64;
+
+struct MqueueSlot {
+    /// 消息队列描述符，Linux下由`close`关闭
+    fd: AsyncFd<OwnedRawFd>,
+    /// 每条消息的最大大小
+    msg_size: usize,
+    /// 已从队列中读出、但尚未被消费的消息负载
+    pending: SpinMutex<VecDeque<Vec<u8>>>,
+}
+
+impl MqueueSlot {
+    /// 读出队列中的所有消息，返回是否有新的消息
+    fn drain(&self) -> bool {
+        let mut pending = self.pending.lock();
+        let mut received = false;
+        loop {
+            let mut buf = vec![0u8; self.msg_size];
+            let len = unsafe {
+                libc::mq_receive(
+                    self.fd.get_ref().0,
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                    core::ptr::null_mut(),
+                )
+            };
+            if len < 0 {
+                return received;
+            }
+            buf.truncate(len as usize);
+            pending.push_back(buf);
+            received = true;
+        }
+    }
+
+    /// `consume`为`true`时消费一条消息并返回其负载；否则只检查是否有消息
+    fn poll_payload(&self, cx: &mut Context<'_>, consume: bool) -> Poll<Option<Vec<u8>>> {
+        loop {
+            {
+                let mut pending = self.pending.lock();
+                if consume {
+                    if let Some(payload) = pending.pop_front() {
+                        return Poll::Ready(Some(payload));
+                    }
+                } else if !pending.is_empty() {
+                    return Poll::Ready(Some(Vec::new()));
+                }
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(None)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.drain() {
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+/// 所有被占用的消息队列，以id为key
+static QUEUES: SpinMutex<BTreeMap<u64, Arc<MqueueSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 下一个被分配的id
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 进程`pid`的通知源`id`对应的消息队列名
+fn name(pid: u64, id: u64) -> CString {
+    CString::new(format!("/async_notification.{}.{}", pid, id)).unwrap()
+}
+
+impl NotificationIf for MqueueNotification {
+    /// 新建一个可缓存`DEFAULT_MAX_MSGS`条、每条负载不超过`DEFAULT_MSG_SIZE`字节的消息队列
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将消息队列注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        Self::new_id_with_capacity(DEFAULT_MAX_MSGS, DEFAULT_MSG_SIZE)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot
+                .poll_payload(cx, true)
+                .map(|payload| payload.map_or(WakeReason::Shutdown, |_| WakeReason::NOTIFIED)),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let ready = match Self::slot(id) {
+            Some(slot) => slot
+                .poll_payload(&mut Context::from_waker(waker), false)
+                .is_ready(),
+            None => true,
+        };
+        if ready {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// 关闭并删除消息队列，队列中尚未被消费的消息被丢弃
+    unsafe fn release_id(id: u64) {
+        let slot = QUEUES.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+        let name = name(unsafe { libc::getpid() } as u64, id);
+        unsafe { libc::mq_unlink(name.as_ptr()) };
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl MqueueNotification {
+    /// 新建一个可缓存`max_msgs`条、每条负载不超过`msg_size`字节的消息队列
+    ///
+    /// 两者的上限分别由`/proc/sys/fs/mqueue/msg_max`与`/proc/sys/fs/mqueue/msgsize_max`决定，超出时返回`None`。
+    /// 该函数需要在tokio运行时内部调用，因为其会将消息队列注册到tokio的reactor中。
+    pub fn new_id_with_capacity(max_msgs: usize, msg_size: usize) -> Option<u64> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let name = name(unsafe { libc::getpid() } as u64, id);
+        let mut attr: libc::mq_attr = unsafe { mem::zeroed() };
+        attr.mq_maxmsg = max_msgs as _;
+        attr.mq_msgsize = msg_size.max(1) as _;
+        let fd = unsafe {
+            libc::mq_open(
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NONBLOCK | libc::O_CLOEXEC,
+                0o600 as libc::mode_t,
+                &attr as *const libc::mq_attr,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        let Ok(fd) = AsyncFd::new(OwnedRawFd(fd)) else {
+            unsafe { libc::mq_unlink(name.as_ptr()) };
+            return None;
+        };
+        let slot = MqueueSlot {
+            fd,
+            msg_size: msg_size.max(1),
+            pending: SpinMutex::new(VecDeque::new()),
+        };
+        QUEUES.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    /// 向进程`process`的通知源`id`发送一条不带负载的消息，失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        Self::notify_with(process, id, &[])
+    }
+
+    /// 向进程`process`的通知源`id`发送一条带有负载`payload`的消息
+    ///
+    /// 队列已满时返回`NotifyError::Overflow`；负载超过队列的消息大小时返回`NotifyError::Os(EMSGSIZE)`。
+    pub fn notify_with(process: u64, id: u64, payload: &[u8]) -> Result<(), NotifyError> {
+        let fd = Self::open(process, id)?;
+        let res = unsafe {
+            libc::mq_send(
+                fd.0,
+                payload.as_ptr() as *const libc::c_char,
+                payload.len(),
+                0,
+            )
+        };
+        if res == 0 {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            libc::EAGAIN => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 检查能否向进程`process`的通知源`id`发送消息，不发送消息
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        Self::open(process, id).map(drop)
+    }
+
+    /// 以写方式打开进程`process`的通知源`id`对应的消息队列
+    fn open(process: u64, id: u64) -> Result<OwnedRawFd, NotifyError> {
+        let name = name(process, id);
+        let fd = unsafe {
+            libc::mq_open(
+                name.as_ptr(),
+                libc::O_WRONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(NotifyError::Os(unsafe { *libc::__errno_location() }));
+        }
+        Ok(OwnedRawFd(fd))
+    }
+
+    /// 在通知源上等待，并返回消息的负载；若通知源已被释放，则返回`None`
+    ///
+    /// 与`wait_on`相同，返回的future是取消安全的。
+    pub fn wait_on_info(id: u64) -> WaitInfo {
+        WaitInfo { id }
+    }
+
+    /// 轮询通知源，收到消息时消费之并返回其负载；若通知源已被释放，则返回`Poll::Ready(None)`
+    pub fn poll_wait_info(id: u64, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        let poll = match Self::slot(id) {
+            Some(slot) => slot.poll_payload(cx, true),
+            None => return Poll::Ready(None),
+        };
+        #[cfg(feature = "record")]
+        if let Poll::Ready(Some(payload)) = &poll {
+            let tag = (crate::kind::NotificationKind::Mqueue.tag() as u64) << 56;
+            crate::record::received(id | tag, None, payload);
+        }
+        poll
+    }
+
+    fn slot(id: u64) -> Option<Arc<MqueueSlot>> {
+        QUEUES.lock().get(&id).cloned()
+    }
+}
+
+/// [`MqueueNotification::wait_on_info`]返回的future
+pub struct WaitInfo {
+    id: u64,
+}
+
+impl Future for WaitInfo {
+    type Output = Option<Vec<u8>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        MqueueNotification::poll_wait_info(self.id, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf, NotifyError};
+    use core::time::Duration;
+
+    #[test]
+    fn test_mqueue_payload() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_mqueue(2, 8).unwrap();
+                Notification::notify_with_payload(pid, id, b"first").unwrap();
+                Notification::notify(pid, id);
+                // 队列已满
+                assert_eq!(
+                    Notification::notify_with_payload(pid, id, b"third"),
+                    Err(NotifyError::Overflow)
+                );
+                // 消息按发送顺序到达
+                let wait = Notification::wait_on_mqueue_info(id);
+                let payload = tokio::time::timeout(Duration::from_secs(1), wait)
+                    .await
+                    .unwrap();
+                assert_eq!(payload.as_deref(), Some(&b"first"[..]));
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                // 负载过大
+                assert_eq!(
+                    Notification::notify_with_payload(pid, id, b"too large"),
+                    Err(NotifyError::Os(libc::EMSGSIZE))
+                );
+                unsafe { Notification::release_id(id) };
+                assert!(Notification::notify_with_payload(pid, id, b"").is_err());
+            });
+    }
+}
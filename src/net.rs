@@ -0,0 +1,242 @@
+//! 使用UDP数据报的通知机制，用于跨主机的通知
+//!
+//! 每个通知源对应一个UDP套接字，id即为其端口号。发送方需先通过[`NetNotification::register_endpoint`]
+//! 登记接收方主机的地址，得到一个端点句柄；`notify`的`process`参数即为该句柄，向该主机的端口`id`发送一个数据报。
+//!
+//! 数据报的内容为固定的`MAGIC`，其他内容的数据报被忽略。通知不经过任何认证，
+//! 因此[`NetNotification::new_id`]只绑定回环地址；需要接收其他主机的通知时，应使用[`NetNotification::new_id_with_addr`]。
+//! 只提供UDP：每个通知只是一次唤醒，不需要TCP的连接与重传，丢失的通知应由上层的协议处理。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    os::fd::{IntoRawFd, OwnedFd},
+};
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用UDP数据报的通知机制
+pub struct NetNotification;
+
+/// 通知数据报的内容
+pub const MAGIC: [u8; 8] = *b"anotify\0";
+
+/// 所有被占用的端口，以端口号为key
+static PORTS: SpinMutex<BTreeMap<u64, Arc<ReadySlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 已登记的端点，以句柄为key
+static ENDPOINTS: SpinMutex<BTreeMap<u64, IpAddr>> = SpinMutex::new(BTreeMap::new());
+
+/// 下一个被分配的端点句柄
+static NEXT_ENDPOINT: AtomicU64 = AtomicU64::new(1);
+
+/// 本进程用于发送通知的套接字，分别用于IPv4与IPv6
+static SENDERS: SpinMutex<[Option<UdpSocket>; 2]> = SpinMutex::new([None, None]);
+
+/// 读出套接字上的所有数据报，返回内容为`MAGIC`的数据报的数量
+fn drain(fd: libc::c_int) -> u64 {
+    let mut count = 0;
+    let mut buf = [0u8; 16];
+    loop {
+        let len = unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+        if len < 0 {
+            return count;
+        }
+        if buf[..len as usize] == MAGIC {
+            count += 1;
+        }
+    }
+}
+
+impl NotificationIf for NetNotification {
+    /// 在回环地址上绑定一个由内核分配的端口，id即为端口号
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        Self::new_id_with_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_wait(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id) {
+            Some(slot) => slot.register_waker(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭套接字
+    unsafe fn release_id(id: u64) {
+        let slot = PORTS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 向句柄为`process`的端点的端口`id`发送通知
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl NetNotification {
+    /// 在地址`addr`上绑定套接字，并返回其id；`addr`的端口为0时由内核分配
+    ///
+    /// 同一端口号在不同地址上只能被占用一次。
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    pub fn new_id_with_addr(addr: SocketAddr) -> Option<u64> {
+        let socket = UdpSocket::bind(addr).ok()?;
+        socket.set_nonblocking(true).ok()?;
+        let port = socket.local_addr().ok()?.port() as u64;
+        let slot = ReadySlot::new(OwnedRawFd(socket.into_raw_fd()), drain)?;
+        let mut ports = PORTS.lock();
+        if ports.contains_key(&port) {
+            return None;
+        }
+        ports.insert(port, Arc::new(slot));
+        Some(port)
+    }
+
+    /// 登记接收方主机的地址，返回用作`notify`的`process`参数的端点句柄
+    pub fn register_endpoint(ip: IpAddr) -> u64 {
+        let handle = NEXT_ENDPOINT.fetch_add(1, Ordering::Relaxed);
+        ENDPOINTS.lock().insert(handle, ip);
+        handle
+    }
+
+    /// 注销端点句柄，之后以其为参数的`notify`返回错误
+    pub fn unregister_endpoint(handle: u64) {
+        ENDPOINTS.lock().remove(&handle);
+    }
+
+    /// 检查端点已被注册且id是合法的端口号，不发送数据报；无法确认对端存在
+    pub fn can_notify(endpoint: u64, id: u64) -> Result<(), NotifyError> {
+        if !ENDPOINTS.lock().contains_key(&endpoint) {
+            return Err(NotifyError::Os(libc::EDESTADDRREQ));
+        }
+        u16::try_from(id)
+            .map(drop)
+            .map_err(|_| NotifyError::Os(libc::EINVAL))
+    }
+
+    /// 向句柄为`endpoint`的端点的端口`id`发送通知，失败时返回错误
+    ///
+    /// 句柄未被登记时返回`NotifyError::Os(EDESTADDRREQ)`。
+    pub fn try_notify(endpoint: u64, id: u64) -> Result<(), NotifyError> {
+        let ip = *ENDPOINTS
+            .lock()
+            .get(&endpoint)
+            .ok_or(NotifyError::Os(libc::EDESTADDRREQ))?;
+        let port = u16::try_from(id).map_err(|_| NotifyError::Os(libc::EINVAL))?;
+        let mut senders = SENDERS.lock();
+        let (index, any) = match ip {
+            IpAddr::V4(_) => (0, "0.0.0.0:0"),
+            IpAddr::V6(_) => (1, "[::]:0"),
+        };
+        if senders[index].is_none() {
+            senders[index] = Some(UdpSocket::bind(any).map_err(Self::error)?);
+        }
+        let sender = senders[index].as_ref().unwrap();
+        sender
+            .send_to(&MAGIC, SocketAddr::new(ip, port))
+            .map(|_| ())
+            .map_err(Self::error)
+    }
+
+    fn error(err: std::io::Error) -> NotifyError {
+        match err.raw_os_error() {
+            Some(libc::EAGAIN) | Some(libc::ENOBUFS) => NotifyError::Overflow,
+            Some(errno) => NotifyError::Os(errno),
+            None => NotifyError::Unsupported,
+        }
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        crate::fd::take_unique(&PORTS, id).map(|slot| slot.into_fd().into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.raw_fd(),
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<ReadySlot>> {
+        PORTS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NetNotification;
+    use crate::interface::{Notification, NotificationIf, NotifyError};
+    use core::time::Duration;
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+    extern crate std;
+
+    #[test]
+    fn test_net_udp() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let endpoint = NetNotification::register_endpoint(IpAddr::V4(Ipv4Addr::LOCALHOST));
+                let id = Notification::new_id_net().unwrap();
+                // 内容不是`MAGIC`的数据报被忽略
+                let port = (id & 0xFFFF) as u16;
+                let stray = UdpSocket::bind("127.0.0.1:0").unwrap();
+                stray.send_to(b"hello", ("127.0.0.1", port)).unwrap();
+                Notification::notify(endpoint, id);
+                Notification::notify(endpoint, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                NetNotification::unregister_endpoint(endpoint);
+                assert_eq!(
+                    Notification::notify_with(endpoint, id, crate::interface::Delivery::Reliable),
+                    Err(NotifyError::Os(libc::EDESTADDRREQ))
+                );
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
@@ -0,0 +1,251 @@
+//! 使用netlink套接字的通知机制
+//!
+//! 每个通知源对应一个netlink套接字，由内核为其分配端口号（`nl_pid`）。`notify`向该端口发送一条netlink消息，
+//! 因为端口号在整个系统内唯一，`process`参数被忽略。内核模块也可以通过`netlink_unicast`向同一端口发送任意消息，
+//! 从而参与同一套通知机制。
+//!
+//! 默认使用`NETLINK_USERSOCK`协议；内核模块使用自己注册的协议号时，应使用
+//! [`NetlinkNotification::new_id_with_protocol`]。id的高位为协议号，低32位为端口号。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{
+    collections::btree_map::{BTreeMap, Entry},
+    sync::Arc,
+};
+use core::{
+    mem,
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::OwnedFd;
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用netlink套接字的通知机制
+pub struct NetlinkNotification;
+
+/// `notify`发送的消息类型，即`NLMSG_MIN_TYPE`
+pub const NOTIFY_MSG_TYPE: u16 = 0x10;
+
+/// 所有被占用的套接字，以id为key
+static SOCKETS: SpinMutex<BTreeMap<u64, Arc<ReadySlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程用于发送通知的套接字，以协议号为key
+static SENDERS: SpinMutex<BTreeMap<i32, OwnedRawFd>> = SpinMutex::new(BTreeMap::new());
+
+fn socket(protocol: i32) -> Option<OwnedRawFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            protocol,
+        )
+    };
+    (fd >= 0).then_some(OwnedRawFd(fd))
+}
+
+fn addr(port: u32) -> libc::sockaddr_nl {
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_pid = port;
+    addr
+}
+
+/// 由协议号与端口号组成id
+fn join(protocol: i32, port: u32) -> u64 {
+    ((protocol as u64) << 32) | port as u64
+}
+
+/// 将id拆分为协议号与端口号
+fn split(id: u64) -> (i32, u32) {
+    ((id >> 32) as i32, id as u32)
+}
+
+/// 读出套接字上的所有消息，返回消息的数量
+fn drain(fd: libc::c_int) -> u64 {
+    let mut count = 0;
+    let mut buf = [0u8; 256];
+    while unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) } >= 0 {
+        count += 1;
+    }
+    count
+}
+
+impl NotificationIf for NetlinkNotification {
+    /// 使用`NETLINK_USERSOCK`协议新建一个套接字
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        Self::new_id_with_protocol(libc::NETLINK_USERSOCK)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_wait(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id) {
+            Some(slot) => slot.register_waker(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭套接字
+    unsafe fn release_id(id: u64) {
+        let slot = SOCKETS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 向端口发送一条类型为`NOTIFY_MSG_TYPE`的消息，`process`被忽略
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl NetlinkNotification {
+    /// 使用协议号为`protocol`的netlink协议新建一个套接字，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    pub fn new_id_with_protocol(protocol: i32) -> Option<u64> {
+        let fd = socket(protocol)?;
+        // 端口号为0时由内核分配
+        let mut local = addr(0);
+        let mut len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+        let res = unsafe {
+            libc::bind(
+                fd.0,
+                &local as *const libc::sockaddr_nl as *const libc::sockaddr,
+                len,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        let res = unsafe {
+            libc::getsockname(
+                fd.0,
+                &mut local as *mut libc::sockaddr_nl as *mut libc::sockaddr,
+                &mut len,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        let id = join(protocol, local.nl_pid);
+        let slot = ReadySlot::new(fd, drain)?;
+        SOCKETS.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    /// 向端口发送一条类型为`NOTIFY_MSG_TYPE`的消息，失败时返回错误
+    pub fn try_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let (protocol, port) = split(id);
+        let mut senders = SENDERS.lock();
+        let fd = match senders.entry(protocol) {
+            Entry::Occupied(entry) => entry.get().0,
+            Entry::Vacant(entry) => {
+                entry
+                    .insert(socket(protocol).ok_or(NotifyError::Unsupported)?)
+                    .0
+            }
+        };
+        let header = libc::nlmsghdr {
+            nlmsg_len: mem::size_of::<libc::nlmsghdr>() as u32,
+            nlmsg_type: NOTIFY_MSG_TYPE,
+            nlmsg_flags: 0,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+        };
+        let peer = addr(port);
+        let res = unsafe {
+            libc::sendto(
+                fd,
+                &header as *const libc::nlmsghdr as *const libc::c_void,
+                mem::size_of::<libc::nlmsghdr>(),
+                0,
+                &peer as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res >= 0 {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            // 接收缓冲区已满
+            libc::EAGAIN | libc::ENOBUFS => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        crate::fd::take_unique(&SOCKETS, id).map(|slot| slot.into_fd().into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.raw_fd(),
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<ReadySlot>> {
+        SOCKETS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[test]
+    fn test_netlink_usersock() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // 沙箱等环境可能不支持netlink
+                let Some(id) = Notification::new_id_netlink() else {
+                    return;
+                };
+                Notification::notify(0, id);
+                Notification::notify(0, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                unsafe { Notification::release_id(id) };
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+            });
+    }
+}
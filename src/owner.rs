@@ -0,0 +1,85 @@
+//! 通知源的占用者信息
+//!
+//! 各通知机制在申请通知源时记录占用者信息，用于调试输出，并在释放时检查通知源是否被持有过久。
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// 通知源的占用者信息，在申请通知源时记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnerInfo {
+    /// 申请通知源的线程id
+    pub tid: u64,
+    /// 申请通知源的时刻（单调时钟，纳秒）
+    pub allocated_at_ns: u64,
+    /// 申请时提供的标签
+    pub label: Option<&'static str>,
+}
+
+impl fmt::Display for OwnerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tid={} allocated_at={}ns label={}",
+            self.tid,
+            self.allocated_at_ns,
+            self.label.unwrap_or("-")
+        )
+    }
+}
+
+/// 持有时间超过该值（纳秒）的通知源在释放时被视为释放过晚，0表示不检查
+static HOLD_WARN_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 被释放过晚的通知源数量
+static LONG_HELD: AtomicU64 = AtomicU64::new(0);
+
+/// 设置通知源的最长持有时间（纳秒），超过该时间才被释放的通知源会被计数，并在启用`log` feature时输出警告
+///
+/// 设为0以关闭检查。
+pub fn set_hold_warning_ns(ns: u64) {
+    HOLD_WARN_NS.store(ns, Ordering::Release);
+}
+
+/// 获取被释放过晚的通知源数量
+pub fn long_held_count() -> u64 {
+    LONG_HELD.load(Ordering::Acquire)
+}
+
+/// 在释放通知源时检查其持有时间，返回是否释放过晚
+#[cfg_attr(not(feature = "log"), allow(unused_variables))]
+#[cfg_attr(not(feature = "signal"), allow(dead_code))]
+pub(crate) fn check_release(id: u64, owner: &OwnerInfo, now_ns: u64) -> bool {
+    let limit = HOLD_WARN_NS.load(Ordering::Acquire);
+    let held = now_ns.saturating_sub(owner.allocated_at_ns);
+    if limit == 0 || held <= limit {
+        return false;
+    }
+    LONG_HELD.fetch_add(1, Ordering::AcqRel);
+    #[cfg(feature = "log")]
+    log::warn!("id {:#018x} released after {}ns ({})", id, held, owner);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_release() {
+        let owner = OwnerInfo {
+            tid: 1,
+            allocated_at_ns: 100,
+            label: Some("test"),
+        };
+        assert!(!check_release(0, &owner, 1_000_000));
+        set_hold_warning_ns(1000);
+        assert!(!check_release(0, &owner, 1100));
+        let before = long_held_count();
+        assert!(check_release(0, &owner, 1101));
+        assert!(long_held_count() > before);
+        set_hold_warning_ns(0);
+    }
+}
@@ -0,0 +1,477 @@
+//! 随通知传递的字节数据
+//!
+//! 信号的`sigval`只能携带一个机器字，许多通知却需要几百字节的上下文。启用本机制后，接收方的通知源带有一个位于memfd中的环形缓冲区：
+//!
+//! - 接收方以[`enable`]创建缓冲区，memfd被封印为不可缩小、不可增大，发送方因此无法使接收方在访问时收到`SIGBUS`；
+//! - 发送方以[`attach`]映射接收方交给它的memfd（例如经`SCM_RIGHTS`传递，或由子进程继承）；
+//! - [`Notification::notify_with_bytes`]将数据写入缓冲区中的一个位置，再经由[`Notification::notify_to`]发送通知；
+//! - 接收方以[`Notification::wait_on_payload_info`]等待，得到直接指向缓冲区中位置的[`PayloadView`]，所占的位置在视图被drop时释放。
+//!
+//! 缓冲区以`MAP_SHARED`映射，发送方随时可能改写其中的任何位置，因此视图只以volatile读取访问数据（[`PayloadView::get`]、[`PayloadView::copy_to`]），
+//! 以`&[u8]`访问需调用者以[`PayloadView::as_bytes`]保证发送方可信。需要长期保存的数据应以[`PayloadView::to_payload`]复制为[`Payload`]后尽早drop视图，
+//! 视图存在期间其位置不能被写入，缓冲区可能因此被写满。不可信的发送方仍可能使数据不完整，接收方应自行校验其内容。
+//!
+//! 缓冲区由固定大小的位置组成，每个位置至多容纳[`MAX_PAYLOAD`]字节；数据按写入的顺序被取出，与通知是否被合并无关。
+//! 缓冲区在通知源被释放时删除，已映射的发送方此后的发送不再被接收。
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    sync::SpinMutex,
+};
+
+/// 每个位置的大小
+const SLOT_SIZE: usize = 512;
+
+/// 一次通知至多携带的字节数
+pub const MAX_PAYLOAD: usize = SLOT_SIZE - 16;
+
+/// 缓冲区头部的大小
+const HEADER_SIZE: usize = 64;
+
+/// memfd必须带有的封印
+const SEALS: libc::c_int = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_SEAL;
+
+/// 缓冲区头部
+#[repr(C)]
+struct Header {
+    /// 位置的数量，由接收方在创建时写入
+    slots: AtomicU64,
+    /// 下一个读取的位置，只由接收方修改
+    head: AtomicU64,
+    /// 下一个写入的位置
+    tail: AtomicU64,
+}
+
+/// 缓冲区中的一个位置
+///
+/// 第`lap`圈时，`stamp`为`2 * lap`表示可写入，为`2 * lap + 1`表示已写入、可读取。
+#[repr(C)]
+struct Slot {
+    stamp: AtomicU64,
+    len: AtomicU32,
+    _reserved: AtomicU32,
+    data: UnsafeCell<[u8; MAX_PAYLOAD]>,
+}
+
+/// 映射到本进程的缓冲区
+struct Ring {
+    ptr: NonNull<u8>,
+    len: usize,
+    slots: u64,
+    fd: OwnedFd,
+}
+
+// 缓冲区只通过原子操作与取得了写入或读取权的一方访问
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+fn errno() -> NotifyError {
+    NotifyError::Os(unsafe { *libc::__errno_location() })
+}
+
+impl Ring {
+    /// 映射`fd`，并检查其大小与封印
+    fn map(fd: OwnedFd) -> Result<Self, NotifyError> {
+        let mut stat: libc::stat = unsafe { core::mem::zeroed() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+            return Err(errno());
+        }
+        let seals = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GET_SEALS) };
+        let len = stat.st_size as usize;
+        if seals < 0 || seals & SEALS != SEALS || len < HEADER_SIZE + SLOT_SIZE {
+            return Err(NotifyError::Os(libc::EINVAL));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(errno());
+        }
+        Ok(Self {
+            ptr: NonNull::new(ptr as *mut u8).unwrap(),
+            len,
+            slots: ((len - HEADER_SIZE) / SLOT_SIZE) as u64,
+            fd,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.ptr.as_ptr() as *const Header) }
+    }
+
+    fn slot(&self, pos: u64) -> (&Slot, u64) {
+        let index = (pos % self.slots) as usize;
+        let slot =
+            unsafe { &*(self.ptr.as_ptr().add(HEADER_SIZE + index * SLOT_SIZE) as *const Slot) };
+        (slot, pos / self.slots)
+    }
+
+    /// 写入一个位置，缓冲区已满时返回`NotifyError::Full`
+    fn push(&self, bytes: &[u8]) -> Result<(), NotifyError> {
+        let header = self.header();
+        let mut pos = header.tail.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(pos);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == 2 * lap {
+                match header.tail.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (&mut *slot.data.get())[..bytes.len()].copy_from_slice(bytes) };
+                        slot.len.store(bytes.len() as u32, Ordering::Relaxed);
+                        slot.stamp.store(2 * lap + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if stamp < 2 * lap {
+                // 上一圈写入的位置尚未被释放
+                return Err(NotifyError::Full);
+            } else {
+                pos = header.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 取出最早写入的位置，该位置在返回的视图被drop时释放
+    fn pop(self: &Arc<Self>) -> Option<PayloadView> {
+        let header = self.header();
+        let mut pos = header.head.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(pos);
+            if slot.stamp.load(Ordering::Acquire) != 2 * lap + 1 {
+                return None;
+            }
+            // 接收方的多个线程可能同时取出
+            match header.head.compare_exchange_weak(
+                pos,
+                pos + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    let len = (slot.len.load(Ordering::Relaxed) as usize).min(MAX_PAYLOAD);
+                    return Some(PayloadView {
+                        ring: self.clone(),
+                        pos,
+                        len,
+                    });
+                }
+                Err(current) => pos = current,
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len) };
+    }
+}
+
+/// 本进程作为接收方的缓冲区，以id为key
+static RECEIVERS: SpinMutex<BTreeMap<u64, Arc<Ring>>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程作为发送方映射的缓冲区，以接收方的进程号与id为key
+static SENDERS: SpinMutex<BTreeMap<(u64, u64), Arc<Ring>>> = SpinMutex::new(BTreeMap::new());
+
+/// 为本进程的通知源`id`创建有`slots`个位置的缓冲区，返回其memfd供交给发送方
+///
+/// 返回的文件描述符在通知源被释放时关闭，交给其他进程时应传递其副本。已创建时返回`NotifyError::Os(EEXIST)`。
+pub fn enable(id: u64, slots: usize) -> Result<RawFd, NotifyError> {
+    let mut receivers = RECEIVERS.lock();
+    if receivers.contains_key(&id) {
+        return Err(NotifyError::Os(libc::EEXIST));
+    }
+    if slots == 0 {
+        return Err(NotifyError::Os(libc::EINVAL));
+    }
+    let fd = unsafe {
+        libc::memfd_create(
+            b"async_notification.payload\0".as_ptr() as *const libc::c_char,
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        )
+    };
+    if fd < 0 {
+        return Err(errno());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let len = HEADER_SIZE + slots * SLOT_SIZE;
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } < 0
+        || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, SEALS) } < 0
+    {
+        return Err(errno());
+    }
+    let ring = Ring::map(fd)?;
+    ring.header().slots.store(slots as u64, Ordering::Release);
+    let raw = ring.fd.as_raw_fd();
+    receivers.insert(id, Arc::new(ring));
+    Ok(raw)
+}
+
+/// 映射进程`process`的通知源`id`的缓冲区，`fd`为接收方的memfd在本进程中的副本；重复映射时替换原有的映射
+///
+/// memfd的大小与封印不符合要求时返回`NotifyError::Os(EINVAL)`。
+pub fn attach(process: u64, id: u64, fd: OwnedFd) -> Result<(), NotifyError> {
+    let ring = Ring::map(fd)?;
+    if ring.header().slots.load(Ordering::Acquire) != ring.slots {
+        return Err(NotifyError::Os(libc::EINVAL));
+    }
+    SENDERS.lock().insert((process, id), Arc::new(ring));
+    Ok(())
+}
+
+/// 取消对进程`process`的通知源`id`的缓冲区的映射，返回其是否曾被映射
+pub fn detach(process: u64, id: u64) -> bool {
+    SENDERS.lock().remove(&(process, id)).is_some()
+}
+
+/// 通知源`id`即将被释放，删除其缓冲区
+pub(crate) fn release(id: u64) {
+    RECEIVERS.lock().remove(&id);
+}
+
+/// 随通知收到的数据在缓冲区中的视图，drop时释放其所占的位置
+///
+/// 视图持有缓冲区的映射，通知源被释放后仍可访问。
+pub struct PayloadView {
+    ring: Arc<Ring>,
+    pos: u64,
+    len: usize,
+}
+
+impl PayloadView {
+    fn data(&self) -> *const u8 {
+        self.ring.slot(self.pos).0.data.get() as *const u8
+    }
+
+    /// 数据的字节数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 数据是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 以volatile读取第`index`个字节，越界时返回`None`
+    pub fn get(&self, index: usize) -> Option<u8> {
+        (index < self.len).then(|| unsafe { self.data().add(index).read_volatile() })
+    }
+
+    /// 以volatile读取将数据复制到`buf`，返回复制的字节数
+    pub fn copy_to(&self, buf: &mut [u8]) -> usize {
+        let len = self.len.min(buf.len());
+        let src = self.data();
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = unsafe { src.add(i).read_volatile() };
+        }
+        len
+    }
+
+    /// 将数据复制出缓冲区
+    pub fn to_payload(&self) -> Payload {
+        let mut payload = Payload {
+            data: [0; MAX_PAYLOAD],
+            len: self.len,
+        };
+        self.copy_to(&mut payload.data);
+        payload
+    }
+
+    /// 以`&[u8]`直接访问缓冲区中的数据
+    ///
+    /// # Safety
+    ///
+    /// 调用者需保证返回的引用存在期间没有发送方改写该位置，例如所有映射了缓冲区的发送方均可信。
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.data(), self.len) }
+    }
+}
+
+impl Drop for PayloadView {
+    fn drop(&mut self) {
+        let (slot, lap) = self.ring.slot(self.pos);
+        slot.stamp.store(2 * lap + 2, Ordering::Release);
+    }
+}
+
+impl core::fmt::Debug for PayloadView {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PayloadView")
+            .field("data", &&*self.to_payload())
+            .finish()
+    }
+}
+
+/// 随通知收到的数据，已从缓冲区中复制出
+pub struct Payload {
+    data: [u8; MAX_PAYLOAD],
+    len: usize,
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl core::fmt::Debug for Payload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Payload").field("data", &&**self).finish()
+    }
+}
+
+/// [`Notification::wait_on_payload_info`]返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitInfo {
+    id: u64,
+}
+
+impl Future for WaitInfo {
+    type Output = Option<PayloadView>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PayloadView>> {
+        let Some(ring) = RECEIVERS.lock().get(&self.id).cloned() else {
+            return Poll::Ready(None);
+        };
+        loop {
+            if let Some(payload) = ring.pop() {
+                return Poll::Ready(Some(payload));
+            }
+            // 被唤醒后重新检查；不携带数据的通知与被合并的通知只会造成一次多余的检查
+            if Notification::poll_wait(self.id, cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl Notification {
+    /// 将`bytes`写入进程`target`的通知源`id`的缓冲区，并向其发送通知
+    ///
+    /// `target`为进程组时返回`NotifyError::Unsupported`。需已以[`attach`]映射缓冲区，否则返回`NotifyError::Os(ENOTCONN)`；`bytes`超过[`MAX_PAYLOAD`]时返回
+    /// `NotifyError::Os(EMSGSIZE)`；缓冲区已满时返回`NotifyError::Full`。数据已写入而通知发送失败时返回发送的错误，
+    /// 数据仍会被接收方在之后的等待中取出。
+    pub fn notify_with_bytes(target: ProcessRef, id: u64, bytes: &[u8]) -> Result<(), NotifyError> {
+        let ProcessRef::Process(process) = target else {
+            return Err(NotifyError::Unsupported);
+        };
+        if bytes.len() > MAX_PAYLOAD {
+            return Err(NotifyError::Os(libc::EMSGSIZE));
+        }
+        let ring = SENDERS
+            .lock()
+            .get(&(process, id))
+            .cloned()
+            .ok_or(NotifyError::Os(libc::ENOTCONN))?;
+        ring.push(bytes)?;
+        Self::notify_to(target, id)
+    }
+
+    /// 取出通知源`id`上最早收到的数据，不阻塞；未以[`enable`]创建缓冲区时返回`None`
+    pub fn try_payload(id: u64) -> Option<PayloadView> {
+        RECEIVERS.lock().get(&id).cloned()?.pop()
+    }
+
+    /// 等待通知源`id`上的下一份数据；未以[`enable`]创建缓冲区时立即返回`None`
+    pub fn wait_on_payload_info(id: u64) -> WaitInfo {
+        WaitInfo { id }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    fn dup(fd: RawFd) -> OwnedFd {
+        unsafe { OwnedFd::from_raw_fd(libc::dup(fd)) }
+    }
+
+    #[test]
+    fn test_payload() {
+        let pid = unsafe { libc::getpid() as u64 };
+        let id = Notification::new_id_mock().unwrap();
+        assert_eq!(
+            Notification::notify_with_bytes(pid.into(), id, b"none"),
+            Err(NotifyError::Os(libc::ENOTCONN))
+        );
+        assert_eq!(
+            Notification::notify_with_bytes(ProcessRef::Group(pid), id, b"group"),
+            Err(NotifyError::Unsupported)
+        );
+        let fd = enable(id, 2).unwrap();
+        assert_eq!(enable(id, 2), Err(NotifyError::Os(libc::EEXIST)));
+        // 封印使memfd不能被改变大小
+        assert!(unsafe { libc::ftruncate(fd, 0) } < 0);
+        attach(pid, id, dup(fd)).unwrap();
+
+        Notification::notify_with_bytes(pid.into(), id, b"hello").unwrap();
+        Notification::notify_with_bytes(pid.into(), id, &[7; MAX_PAYLOAD]).unwrap();
+        assert_eq!(
+            Notification::notify_with_bytes(pid.into(), id, b"full"),
+            Err(NotifyError::Full)
+        );
+        assert_eq!(
+            Notification::notify_with_bytes(pid.into(), id, &[0; MAX_PAYLOAD + 1]),
+            Err(NotifyError::Os(libc::EMSGSIZE))
+        );
+
+        let first = futures::executor::block_on(Notification::wait_on_payload_info(id)).unwrap();
+        assert_eq!(first.len(), 5);
+        assert_eq!(first.get(4), Some(b'o'));
+        assert_eq!(first.get(5), None);
+        let mut buf = [0; 8];
+        assert_eq!(first.copy_to(&mut buf), 5);
+        assert_eq!(&buf[..5], b"hello");
+        // 视图存在期间其位置不能被写入
+        assert_eq!(
+            Notification::notify_with_bytes(pid.into(), id, b"again"),
+            Err(NotifyError::Full)
+        );
+        let copied = first.to_payload();
+        drop(first);
+        Notification::notify_with_bytes(pid.into(), id, b"again").unwrap();
+        assert_eq!(&*copied, b"hello");
+        let second = Notification::try_payload(id).unwrap();
+        assert_eq!(unsafe { second.as_bytes() }, &[7; MAX_PAYLOAD][..]);
+        let third = futures::executor::block_on(Notification::wait_on_payload_info(id)).unwrap();
+        assert_eq!(&*third.to_payload(), b"again");
+        assert!(Notification::try_payload(id).is_none());
+
+        // 视图持有缓冲区的映射，通知源被释放后仍可访问
+        unsafe { Notification::release_id(id) };
+        assert!(Notification::try_payload(id).is_none());
+        assert_eq!(second.get(MAX_PAYLOAD - 1), Some(7));
+        drop(second);
+        assert!(detach(pid, id));
+    }
+}
@@ -0,0 +1,250 @@
+//! 使用管道的通知机制
+//!
+//! 每个通知源对应一个管道，id即为其读端的文件描述符；`notify`向写端写入一个字节，每个字节对应一个通知。
+//! 该机制只依赖`pipe`与`fcntl`，可用于缺少实时信号、用户态中断与eventfd的POSIX系统，作为最后的选择。
+//!
+//! 本进程（以及fork得到的、继承了写端的子进程）直接写入写端；在Linux上，其他进程通过打开
+//! `/proc/<pid>/fd/<写端>`向其发送通知，其他系统上则需要自行将[`PipeNotification::write_fd`]传给发送方。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, format, sync::Arc};
+use core::task::{Context, Poll, Waker};
+use std::os::fd::{OwnedFd, RawFd};
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用管道的通知机制
+pub struct PipeNotification;
+
+struct PipeSlot {
+    read: ReadySlot,
+    write: OwnedRawFd,
+}
+
+/// 所有被占用的管道，以读端为key
+static PIPES: SpinMutex<BTreeMap<u64, Arc<PipeSlot>>> = SpinMutex::new(BTreeMap::new());
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// 设置`O_NONBLOCK`与`FD_CLOEXEC`
+fn setup(fd: RawFd) -> bool {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        flags >= 0
+            && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == 0
+            && libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == 0
+    }
+}
+
+/// 读出管道中的所有字节，返回字节数
+pub(crate) fn drain(fd: RawFd) -> u64 {
+    let mut count = 0;
+    let mut buf = [0u8; 64];
+    loop {
+        let len = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if len <= 0 {
+            return count;
+        }
+        count += len as u64;
+    }
+}
+
+/// 向写端写入一个字节
+fn write_one(fd: RawFd) -> Result<(), NotifyError> {
+    let byte = 1u8;
+    if unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) } == 1 {
+        return Ok(());
+    }
+    match errno() {
+        // 管道已满，接收方尚有大量未读出的通知
+        libc::EAGAIN => Err(NotifyError::Overflow),
+        errno => Err(NotifyError::Os(errno)),
+    }
+}
+
+impl NotificationIf for PipeNotification {
+    /// 新建一个管道，id即为其读端
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将读端注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let (read, write) = (OwnedRawFd(fds[0]), OwnedRawFd(fds[1]));
+        if !setup(read.0) || !setup(write.0) {
+            return None;
+        }
+        let id = read.0 as u64;
+        let slot = PipeSlot {
+            read: ReadySlot::new(read, drain)?,
+            write,
+        };
+        PIPES.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.read.poll_wait(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id) {
+            Some(slot) => slot.read.register_waker(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭管道的两端
+    unsafe fn release_id(id: u64) {
+        let slot = PIPES.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl PipeNotification {
+    /// 通知源的读端，可注册到其他事件循环中；文件描述符仍由本模块持有
+    pub fn read_fd(id: u64) -> Option<RawFd> {
+        Self::slot(id).map(|slot| slot.read.raw_fd())
+    }
+
+    /// 通知源的写端，可传给其他进程用于发送通知；文件描述符仍由本模块持有
+    pub fn write_fd(id: u64) -> Option<RawFd> {
+        Self::slot(id).map(|slot| slot.write.0)
+    }
+
+    /// 向进程`process`的通知源`id`发送通知，失败时返回错误
+    ///
+    /// 本进程持有该通知源时直接写入写端；否则在Linux上通过`/proc`打开对端的写端，在其他系统上返回`NotifyError::Unsupported`。
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        if let Some(slot) = Self::slot(id) {
+            return write_one(slot.write.0);
+        }
+        write_one(Self::open_remote(process, id)?.0)
+    }
+
+    /// 检查能否向进程`process`的通知源`id`发送通知，不发送通知
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        if Self::slot(id).is_some() {
+            return Ok(());
+        }
+        Self::open_remote(process, id).map(drop)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn open_remote(process: u64, id: u64) -> Result<OwnedRawFd, NotifyError> {
+        // 读端可通过`/proc`以写方式打开，得到同一个管道的写端
+        let path = format!("/proc/{}/fd/{}\0", process, id);
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr() as *const libc::c_char,
+                libc::O_WRONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(NotifyError::Os(errno()));
+        }
+        Ok(OwnedRawFd(fd))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open_remote(_process: u64, _id: u64) -> Result<OwnedRawFd, NotifyError> {
+        Err(NotifyError::Unsupported)
+    }
+
+    /// 释放通知源，但不关闭管道，而是将读端与写端的所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fds(id: u64) -> Option<(OwnedFd, OwnedFd)> {
+        crate::fd::take_unique(&PIPES, id)
+            .map(|slot| (slot.read.into_fd().into_owned(), slot.write.into_owned()))
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.read.raw_fd(),
+            |slot| slot.read.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<PipeSlot>> {
+        PIPES.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Delivery, Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[test]
+    fn test_pipe() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_pipe().unwrap();
+                Notification::notify(pid, id);
+                Notification::notify(pid, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                // fork得到的子进程继承了写端，可直接发送通知
+                match unsafe { libc::fork() } {
+                    0 => {
+                        let res = Notification::notify_with(pid, id, Delivery::Reliable);
+                        unsafe { libc::_exit(res.is_err() as libc::c_int) };
+                    }
+                    -1 => panic!("Fork failed!"),
+                    child => {
+                        tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                            .await
+                            .unwrap();
+                        let mut status = 0;
+                        unsafe { libc::waitpid(child, &mut status, 0) };
+                        assert_eq!(status, 0);
+                    }
+                }
+
+                unsafe { Notification::release_id(id) };
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+            });
+    }
+}
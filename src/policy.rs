@@ -0,0 +1,243 @@
+//! 通知机制的自动选择
+//!
+//! [`Notification::new_id_auto`]按照策略中的顺序依次尝试各通知机制，返回第一个申请成功的通知源，
+//! 从而使应用无需为不同平台编写不同的cfg分支。未启用相应feature或在当前系统上不可用的机制被跳过。
+//!
+//! 默认策略为`DEFAULT_POLICY`：用户态中断 > eventfd > 信号 > 管道。其中eventfd只能在本进程（及继承了它的进程）内发送通知，
+//! 需要向无关的进程发送通知时，应使用不含`Backend::Eventfd`的策略。
+//!
+//! 大部分机制需要在tokio运行时内部申请，因此`new_id_auto`也应在tokio运行时内部调用。
+//!
+//! 实时信号的数量有限。可以用[`set_overflow_policy`]设置信号耗尽时`new_id_signal`改用的机制，
+//! 并用[`Backend::remaining_capacity`]查询还能申请的信号数量。
+//!
+//! 长期运行的服务可以用[`Backend::expand`]在运行时向机制的池中加入预先创建的通知源。
+
+use alloc::vec::Vec;
+
+use crate::{interface::Notification, sync::SpinMutex};
+
+/// 可被自动选择的通知机制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// 用户态中断（`uintr`）
+    Uintr,
+    /// io_uring（`uring`）
+    Uring,
+    /// eventfd（`kvm`）
+    Eventfd,
+    /// 实时信号（`signal`）
+    Signal,
+    /// unix域套接字（`uds`）
+    Uds,
+    /// POSIX消息队列（`mqueue`），使用默认的队列容量
+    Mqueue,
+    /// 管道（`pipe`）
+    Pipe,
+}
+
+/// 默认的选择策略
+pub const DEFAULT_POLICY: &[Backend] = &[
+    Backend::Uintr,
+    Backend::Eventfd,
+    Backend::Signal,
+    Backend::Pipe,
+];
+
+/// 由`set_policy`设置的策略，为空时使用`DEFAULT_POLICY`
+static POLICY: SpinMutex<Vec<Backend>> = SpinMutex::new(Vec::new());
+
+/// 设置`new_id_auto`使用的策略；`policy`为空时恢复默认策略
+pub fn set_policy(policy: &[Backend]) {
+    *POLICY.lock() = policy.to_vec();
+}
+
+/// `new_id_auto`当前使用的策略
+pub fn policy() -> Vec<Backend> {
+    let policy = POLICY.lock();
+    if policy.is_empty() {
+        DEFAULT_POLICY.to_vec()
+    } else {
+        policy.clone()
+    }
+}
+
+/// 信号耗尽时`new_id_signal`依次尝试的机制，为空时`new_id_signal`返回`None`
+static OVERFLOW: SpinMutex<Vec<Backend>> = SpinMutex::new(Vec::new());
+
+/// 设置信号耗尽时`new_id_signal`依次尝试的机制，例如`&[Backend::Eventfd, Backend::Pipe]`；`fallback`为空时不再尝试
+///
+/// 其中的`Backend::Signal`被跳过。改用的机制与信号的发送方式不同，例如eventfd只能在本进程内发送通知，
+/// 应只在对端也通过id的类型发送通知（如`Notification::notify`）时使用。
+pub fn set_overflow_policy(fallback: &[Backend]) {
+    *OVERFLOW.lock() = fallback.to_vec();
+}
+
+/// 信号耗尽时`new_id_signal`当前依次尝试的机制
+pub fn overflow_policy() -> Vec<Backend> {
+    OVERFLOW.lock().clone()
+}
+
+/// 信号耗尽时按`OVERFLOW`申请通知源
+#[cfg(feature = "signal")]
+pub(crate) fn new_id_overflow() -> Option<u64> {
+    let fallback = overflow_policy();
+    let id = fallback
+        .into_iter()
+        .filter(|&backend| backend != Backend::Signal)
+        .find_map(Backend::new_id)?;
+    #[cfg(feature = "log")]
+    log::info!(
+        "new_id_signal: signals exhausted, allocated {} instead",
+        Notification::display(id)
+    );
+    Some(id)
+}
+
+impl Backend {
+    /// 尝试使用该机制申请通知源；未启用或不可用时返回`None`
+    pub fn new_id(self) -> Option<u64> {
+        match self {
+            // 用户态中断尚未实现
+            Backend::Uintr => None,
+            #[cfg(feature = "uring")]
+            Backend::Uring => Notification::new_id_uring(),
+            #[cfg(feature = "kvm")]
+            Backend::Eventfd => Notification::new_id_kvm_ioeventfd(),
+            #[cfg(feature = "signal")]
+            Backend::Signal => Notification::new_id_signal(),
+            #[cfg(feature = "uds")]
+            Backend::Uds => Notification::new_id_uds(),
+            #[cfg(feature = "mqueue")]
+            Backend::Mqueue => Notification::new_id_mqueue(
+                crate::mqueue::DEFAULT_MAX_MSGS,
+                crate::mqueue::DEFAULT_MSG_SIZE,
+            ),
+            #[cfg(feature = "pipe")]
+            Backend::Pipe => Notification::new_id_pipe(),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// 向该机制的池中加入`n`个预先创建的通知源，之后的申请优先使用它们；返回实际加入的数量
+    ///
+    /// 目前只有`Backend::Eventfd`支持（见[`KvmNotification::expand`](crate::kvm::KvmNotification::expand)）。
+    /// 实时信号的数量由内核决定，用户态中断尚未实现，其他机制在申请时按需创建，均返回0。
+    #[cfg_attr(not(feature = "kvm"), allow(unused_variables))]
+    pub fn expand(self, n: usize) -> usize {
+        match self {
+            #[cfg(feature = "kvm")]
+            Backend::Eventfd => crate::kvm::KvmNotification::expand(n),
+            _ => 0,
+        }
+    }
+
+    /// 该机制还能申请的通知源数量；数量只受文件描述符等系统资源限制时返回`None`
+    ///
+    /// 未启用或未实现的机制返回`Some(0)`。
+    pub fn remaining_capacity(self) -> Option<usize> {
+        match self {
+            Backend::Uintr => Some(0),
+            #[cfg(feature = "signal")]
+            Backend::Signal => Some(crate::signal::SignalNotification::remaining_capacity()),
+            #[cfg(feature = "uring")]
+            Backend::Uring => None,
+            #[cfg(feature = "kvm")]
+            Backend::Eventfd => None,
+            #[cfg(feature = "uds")]
+            Backend::Uds => None,
+            #[cfg(feature = "mqueue")]
+            Backend::Mqueue => None,
+            #[cfg(feature = "pipe")]
+            Backend::Pipe => None,
+            #[allow(unreachable_patterns)]
+            _ => Some(0),
+        }
+    }
+}
+
+impl Notification {
+    /// 按照`set_policy`设置的策略（默认为`DEFAULT_POLICY`）自动选择通知机制，申请一个通知源并返回其id
+    pub fn new_id_auto() -> Option<u64> {
+        Self::new_id_with_policy(&policy())
+    }
+
+    /// 按照`policy`中的顺序依次尝试各通知机制，返回第一个申请成功的通知源的id
+    pub fn new_id_with_policy(policy: &[Backend]) -> Option<u64> {
+        policy.iter().find_map(|backend| backend.new_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, DEFAULT_POLICY};
+    use crate::interface::{Notification, NotificationIf};
+
+    #[test]
+    fn test_policy_selection() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                assert_eq!(super::policy(), DEFAULT_POLICY);
+                // 未实现或未启用的机制被跳过
+                assert_eq!(Notification::new_id_with_policy(&[Backend::Uintr]), None);
+                assert_eq!(Notification::new_id_with_policy(&[]), None);
+
+                if let Some(id) = Notification::new_id_auto() {
+                    unsafe { Notification::release_id(id) };
+                }
+                #[cfg(feature = "pipe")]
+                {
+                    let id =
+                        Notification::new_id_with_policy(&[Backend::Uintr, Backend::Pipe]).unwrap();
+                    assert_eq!(id >> 56, 0x11);
+                    unsafe { Notification::release_id(id) };
+                }
+            });
+    }
+
+    #[cfg(all(feature = "signal", feature = "pipe"))]
+    #[test]
+    fn test_signal_overflow() {
+        use crate::kind::NotificationKind;
+
+        let _guard = crate::signal::tests::SIGNAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // 在子进程中耗尽信号，不影响其他测试
+        match unsafe { libc::fork() } {
+            0 => {
+                unsafe { libc::alarm(5) };
+                let ok = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap()
+                    .block_on(async move {
+                        let capacity = Backend::Signal.remaining_capacity().unwrap();
+                        let mut ids = alloc::vec::Vec::new();
+                        while let Some(id) = Notification::new_id_signal() {
+                            ids.push(id);
+                        }
+                        let exhausted = ids.len() == capacity
+                            && Backend::Signal.remaining_capacity() == Some(0)
+                            && Backend::Pipe.remaining_capacity().is_none();
+                        super::set_overflow_policy(&[Backend::Signal, Backend::Pipe]);
+                        let id = Notification::new_id_signal().unwrap();
+                        let kind = Notification::kind_of(id);
+                        unsafe { Notification::release_id(id) };
+                        exhausted && kind == Some(NotificationKind::Pipe)
+                    });
+                unsafe { libc::_exit(!ok as libc::c_int) };
+            }
+            -1 => panic!("Fork failed!"),
+            child => {
+                let mut status = 0;
+                unsafe { libc::waitpid(child, &mut status, 0) };
+                assert_eq!(status, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! 通知源与其底层资源之间的转换
+//!
+//! 用于与已持有底层资源（信号编号、文件描述符、中断向量）的代码互通。所有权的转移通过类型表达：
+//!
+//! - `from_raw_*`接管资源，之后资源由本crate持有，在`release_id`时被释放；
+//! - `into_raw_fd`释放通知源并将文件描述符以`OwnedFd`交给调用者，之后id不能再被使用；
+//! - `as_raw_*`只查询资源，所有权仍属于本crate，返回的值不应在`release_id`之后使用。
+
+#[cfg(any(
+    feature = "timer",
+    feature = "child",
+    feature = "vsock",
+    feature = "kvm",
+    feature = "uds",
+    feature = "netlink",
+    feature = "net",
+    feature = "pipe"
+))]
+extern crate std;
+
+use crate::{interface::Notification, kind::NotificationKind};
+
+impl Notification {
+    /// 占用编号为`signum`的信号，并返回其id
+    ///
+    /// 信号不在本crate使用的实时信号范围内或已被占用时返回`None`。
+    /// 该函数需要在tokio运行时内部调用，因为其会同时开始信号的接收。
+    #[cfg(feature = "signal")]
+    pub fn from_raw_signal(signum: i32) -> Option<u64> {
+        let id =
+            crate::signal::SignalNotification::new_id_with_signal(u32::try_from(signum).ok()?)?;
+        Some(Notification::tagged(
+            id,
+            (NotificationKind::Signal.tag() as u64) << 56,
+        ))
+    }
+
+    /// 接管一个已有的eventfd（例如已被注册为KVM的ioeventfd），将其作为可在其上等待的通知源，并返回其id
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将eventfd注册到tokio的reactor中。
+    #[cfg(feature = "kvm")]
+    pub fn from_raw_eventfd(fd: std::os::fd::OwnedFd) -> Option<u64> {
+        let id = crate::kvm::KvmNotification::new_id_from_fd(fd)?;
+        Some(Notification::tagged(
+            id,
+            (NotificationKind::Kvm.tag() as u64) << 56,
+        ))
+    }
+
+    /// 信号通知源所使用的信号编号，其他类型的通知源返回`None`
+    pub fn as_raw_signal(id: u64) -> Option<i32> {
+        (Self::kind_of(id)? == NotificationKind::Signal).then_some((id & 0xFF) as i32)
+    }
+
+    /// 用户态中断通知源所使用的中断向量，其他类型的通知源返回`None`
+    pub fn as_raw_vector(id: u64) -> Option<u32> {
+        (Self::kind_of(id)? == NotificationKind::Uintr).then_some((id & 0xFF) as u32)
+    }
+
+    /// 释放基于文件描述符的通知源，但不关闭文件描述符，而是将其所有权交给调用者
+    ///
+    /// 支持定时器、子进程、vsock、eventfd、unix域套接字、netlink、UDP与管道（返回读端，写端被关闭）。
+    /// 不支持的类型返回`None`；若通知源正被其他线程使用（例如正在轮询），也返回`None`，且不释放通知源。
+    ///
+    /// # Safety
+    ///
+    /// 与`release_id`相同：返回`Some`之后，id不能再被使用。
+    #[cfg(any(
+        feature = "timer",
+        feature = "child",
+        feature = "vsock",
+        feature = "kvm",
+        feature = "uds",
+        feature = "netlink",
+        feature = "net",
+        feature = "pipe"
+    ))]
+    pub unsafe fn into_raw_fd(id: u64) -> Option<std::os::fd::OwnedFd> {
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match Self::kind_of(id)? {
+            #[cfg(feature = "timer")]
+            NotificationKind::Timer => crate::timer::TimerNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "child")]
+            NotificationKind::Child => crate::child::ChildNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "vsock")]
+            NotificationKind::Vsock => crate::vsock::VsockNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "kvm")]
+            NotificationKind::Kvm => crate::kvm::KvmNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "uds")]
+            NotificationKind::Uds => crate::uds::UdsNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "netlink")]
+            NotificationKind::Netlink => crate::netlink::NetlinkNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "net")]
+            NotificationKind::Net => crate::net::NetNotification::into_raw_fd(id_inner),
+            #[cfg(feature = "pipe")]
+            NotificationKind::Pipe => {
+                crate::pipe::PipeNotification::into_raw_fds(id_inner).map(|(read, _)| read)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::Notification;
+
+    #[test]
+    fn test_as_raw() {
+        assert_eq!(Notification::as_raw_signal(0x0100_0000_0000_032a), Some(42));
+        assert_eq!(Notification::as_raw_signal(0x0200_0000_0000_0003), None);
+        assert_eq!(Notification::as_raw_vector(0x0200_0000_0000_0003), Some(3));
+        assert_eq!(Notification::as_raw_vector(0), None);
+    }
+
+    #[cfg(feature = "signal")]
+    #[test]
+    fn test_from_raw_signal() {
+        use crate::interface::NotificationIf;
+
+        let _guard = crate::signal::tests::SIGNAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                assert_eq!(Notification::from_raw_signal(libc::SIGINT), None);
+                // 选取一个当前未被占用的信号
+                let signum = (libc::SIGRTMIN()..libc::SIGRTMAX()).find_map(|signum| {
+                    Notification::from_raw_signal(signum).map(|id| (signum, id))
+                });
+                let (signum, id) = signum.unwrap();
+                assert_eq!(Notification::as_raw_signal(id), Some(signum));
+                assert_eq!(Notification::from_raw_signal(signum), None);
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[cfg(feature = "kvm")]
+    #[test]
+    fn test_eventfd_round_trip() {
+        use core::time::Duration;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        use crate::interface::NotificationIf;
+
+        extern crate std;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                let fd = unsafe { Notification::into_raw_fd(id) }.unwrap();
+                assert!(Notification::kvm_eventfd(id).is_none());
+                // 通知源被释放后，文件描述符仍然有效，且可以被重新接管
+                let id = Notification::from_raw_eventfd(fd).unwrap();
+                let raw = Notification::kvm_eventfd(id).unwrap();
+                let one = 1u64;
+                unsafe { libc::write(raw, &one as *const u64 as *const libc::c_void, 8) };
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                unsafe { Notification::release_id(id) };
+
+                let raw = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+                let id =
+                    Notification::from_raw_eventfd(unsafe { OwnedFd::from_raw_fd(raw) }).unwrap();
+                assert_eq!(Notification::kvm_eventfd(id), Some(raw));
+                let fd = unsafe { Notification::into_raw_fd(id) }.unwrap();
+                assert_eq!(fd.as_raw_fd(), raw);
+            });
+    }
+}
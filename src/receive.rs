@@ -0,0 +1,296 @@
+//! 接收方的合并、防抖与限流
+//!
+//! 频繁发送通知的对端会使等待的协程被反复唤醒，占用运行时的其他任务。
+//! 可以为通知源设置[`ReceivePolicy`]，在[`Notification`]的`poll_wait`（包括`wait_on`）返回之前应用：
+//!
+//! - `Coalesce`：一次唤醒消费所有已到达的通知；
+//! - `Debounce(d)`：通知到达后，直到`d`时间内没有新的通知到达才唤醒一次，期间到达的通知被合并；
+//! - `RateLimit(n)`：每秒最多唤醒`n`次（允许`n`次的突发），超出时通知留在通知源中，直到有可用的额度。
+//!
+//! 策略在通知源被释放时清除。`register_waker`不应用策略，因此可能产生多余的唤醒。
+//! 防抖与限流需要tokio运行时，并启用其时钟。
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+use crate::{
+    interface::{Notification, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 一次轮询中最多消费的通知数，避免在持续就绪的通知源上无限循环
+const MAX_DRAIN: usize = 1024;
+
+/// 接收方的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceivePolicy {
+    /// 一次唤醒消费所有已到达的通知
+    Coalesce,
+    /// 最后一个通知到达后经过给定的时间才唤醒
+    Debounce(Duration),
+    /// 每秒最多唤醒给定的次数，为0时视为1
+    RateLimit(u32),
+}
+
+/// 通知源的策略与状态
+struct ReceiveState {
+    policy: ReceivePolicy,
+    /// 防抖：收到通知后的唤醒时刻，以及期间合并的通知数
+    deadline: Option<Instant>,
+    merged: usize,
+    /// 限流：当前可用的唤醒次数，以及上次补充的时刻
+    tokens: u32,
+    refilled: Instant,
+    /// 等待到`deadline`或下一次补充的定时器
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl ReceiveState {
+    fn new(policy: ReceivePolicy) -> Self {
+        let tokens = match policy {
+            ReceivePolicy::RateLimit(rate) => rate.max(1),
+            _ => 0,
+        };
+        Self {
+            policy,
+            deadline: None,
+            merged: 0,
+            tokens,
+            refilled: Instant::now(),
+            sleep: None,
+        }
+    }
+
+    /// 按经过的时间补充限流的额度，返回下一次补充的时刻
+    fn refill(&mut self, rate: u32, now: Instant) -> Instant {
+        let rate = rate.max(1);
+        let interval = Duration::from_secs(1) / rate;
+        let elapsed = now.duration_since(self.refilled);
+        let added = (elapsed.as_nanos() / interval.as_nanos()) as u64;
+        if added > 0 {
+            self.tokens = (self.tokens as u64 + added).min(rate as u64) as u32;
+            self.refilled = if self.tokens == rate {
+                now
+            } else {
+                self.refilled + interval * added as u32
+            };
+        }
+        self.refilled + interval
+    }
+
+    /// 防抖结束，返回期间合并的通知
+    fn fire(&mut self) -> WakeReason {
+        self.deadline = None;
+        WakeReason::Notified {
+            count: core::mem::take(&mut self.merged),
+        }
+    }
+
+    /// 在`at`时刻唤醒`cx`中的waker
+    fn sleep_until(&mut self, at: Instant, cx: &mut Context<'_>) -> Poll<()> {
+        let sleep = match &mut self.sleep {
+            Some(sleep) => {
+                sleep.as_mut().reset(at);
+                sleep
+            }
+            None => self.sleep.insert(Box::pin(tokio::time::sleep_until(at))),
+        };
+        sleep.as_mut().poll(cx)
+    }
+}
+
+/// 设置了策略的通知源，以id为key
+static STATES: SpinMutex<BTreeMap<u64, ReceiveState>> = SpinMutex::new(BTreeMap::new());
+
+/// 为通知源`id`设置接收策略，替换已有的策略
+pub fn set_policy(id: u64, policy: ReceivePolicy) {
+    STATES.lock().insert(id, ReceiveState::new(policy));
+}
+
+/// 清除通知源`id`的接收策略，返回其原有的策略
+pub fn clear_policy(id: u64) -> Option<ReceivePolicy> {
+    STATES.lock().remove(&id).map(|state| state.policy)
+}
+
+/// 通知源`id`的接收策略
+pub fn policy(id: u64) -> Option<ReceivePolicy> {
+    STATES.lock().get(&id).map(|state| state.policy)
+}
+
+/// 通知源`id`即将被释放
+pub(crate) fn release(id: u64) {
+    STATES.lock().remove(&id);
+}
+
+/// 消费`id`上所有已到达的通知，返回消费的数量；最后一次轮询注册了`cx`中的waker
+///
+/// 通知源已关闭时返回`Err`，其中为关闭的原因。
+fn drain(
+    poll_wait: impl Fn(&mut Context<'_>) -> Poll<WakeReason>,
+    cx: &mut Context<'_>,
+) -> Result<usize, WakeReason> {
+    let mut count = 0;
+    while count < MAX_DRAIN {
+        match poll_wait(cx) {
+            Poll::Ready(WakeReason::Notified { count: n }) => count += n,
+            Poll::Ready(reason) => return Err(reason),
+            Poll::Pending => break,
+        }
+    }
+    Ok(count)
+}
+
+/// 对通知源`id`应用接收策略后轮询，`poll_wait`为底层的轮询；未设置策略时返回`None`
+///
+/// 合并与防抖以`WakeReason::Notified`报告合并的通知数。
+pub(crate) fn poll(
+    id: u64,
+    cx: &mut Context<'_>,
+    poll_wait: impl Fn(&mut Context<'_>) -> Poll<WakeReason>,
+) -> Option<Poll<WakeReason>> {
+    let policy = policy(id)?;
+    Some(match policy {
+        ReceivePolicy::Coalesce => {
+            let first = match poll_wait(cx) {
+                Poll::Ready(WakeReason::Notified { count }) => count,
+                poll => return Some(poll),
+            };
+            match drain(&poll_wait, cx) {
+                Ok(count) => Poll::Ready(WakeReason::Notified {
+                    count: first + count,
+                }),
+                Err(reason) => Poll::Ready(reason),
+            }
+        }
+        ReceivePolicy::Debounce(quiet) => {
+            let arrived = match drain(&poll_wait, cx) {
+                Ok(count) => count,
+                Err(reason) => return Some(Poll::Ready(reason)),
+            };
+            let mut states = STATES.lock();
+            let Some(state) = states.get_mut(&id) else {
+                return Some(Poll::Ready(WakeReason::Spurious));
+            };
+            let now = Instant::now();
+            if arrived > 0 {
+                state.deadline = Some(now + quiet);
+                state.merged += arrived;
+            }
+            match state.deadline {
+                Some(deadline) if deadline <= now => Poll::Ready(state.fire()),
+                Some(deadline) => state.sleep_until(deadline, cx).map(|()| state.fire()),
+                None => Poll::Pending,
+            }
+        }
+        ReceivePolicy::RateLimit(rate) => {
+            let mut states = STATES.lock();
+            let Some(state) = states.get_mut(&id) else {
+                return Some(Poll::Ready(WakeReason::Spurious));
+            };
+            let next = state.refill(rate, Instant::now());
+            if state.tokens == 0 {
+                // 没有额度时不轮询通知源，通知留在其中，由定时器唤醒
+                if state.sleep_until(next, cx).is_ready() {
+                    cx.waker().wake_by_ref();
+                }
+                return Some(Poll::Pending);
+            }
+            drop(states);
+            let poll = poll_wait(cx);
+            if poll.is_ready() {
+                if let Some(state) = STATES.lock().get_mut(&id) {
+                    state.tokens = state.tokens.saturating_sub(1);
+                }
+            }
+            poll
+        }
+    })
+}
+
+impl Notification {
+    /// 以`new_id`申请通知源，并为其设置接收策略
+    ///
+    /// 例如`Notification::new_id_with_receive_policy(Notification::new_id_signal, ReceivePolicy::Coalesce)`。
+    pub fn new_id_with_receive_policy(
+        new_id: impl FnOnce() -> Option<u64>,
+        policy: ReceivePolicy,
+    ) -> Option<u64> {
+        let id = new_id()?;
+        set_policy(id, policy);
+        Some(id)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    fn notify(id: u64, times: usize) {
+        for _ in 0..times {
+            Notification::notify(0, id);
+        }
+    }
+
+    #[test]
+    fn test_receive_policy() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let waker = futures::task::noop_waker();
+                let mut cx = Context::from_waker(&waker);
+
+                let id = Notification::new_id_with_receive_policy(
+                    Notification::new_id_mock,
+                    ReceivePolicy::Coalesce,
+                )
+                .unwrap();
+                notify(id, 3);
+                assert_eq!(
+                    Notification::poll_wait_reason(id, &mut cx),
+                    Poll::Ready(WakeReason::Notified { count: 3 })
+                );
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                // 防抖：持续通知期间不唤醒
+                set_policy(id, ReceivePolicy::Debounce(Duration::from_millis(50)));
+                let start = Instant::now();
+                for _ in 0..3 {
+                    notify(id, 1);
+                    assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                assert_eq!(
+                    Notification::wait_on(id).await,
+                    WakeReason::Notified { count: 3 }
+                );
+                assert!(start.elapsed() >= Duration::from_millis(90));
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                // 限流：突发之后按速率唤醒，未被唤醒的通知留在通知源中
+                assert_eq!(
+                    clear_policy(id),
+                    Some(ReceivePolicy::Debounce(Duration::from_millis(50)))
+                );
+                set_policy(id, ReceivePolicy::RateLimit(20));
+                notify(id, 25);
+                let start = Instant::now();
+                for _ in 0..22 {
+                    Notification::wait_on(id).await;
+                }
+                assert!(start.elapsed() >= Duration::from_millis(90));
+                assert_eq!(crate::mock::pending(id), Some(3));
+
+                unsafe { Notification::release_id(id) };
+                assert_eq!(policy(id), None);
+            });
+    }
+}
@@ -0,0 +1,261 @@
+//! 通知的录制与回放
+//!
+//! [`start`]之后，接收方消费的每个通知都被记录为一个[`RecordedEvent`]，包括到达的时刻、通知源id、
+//! 发送方与负载，直到[`stop`]返回录得的[`Trace`]。`Trace`可以文本形式保存（见其`Display`实现与[`Trace::parse`]），
+//! 之后由[`Replay`]将其中的通知依次投递到[`mock`](crate::mock)通知源上，使生产环境中的事件序列在测试中重现。
+//!
+//! 经由[`Notification::poll_wait`](crate::interface::NotificationIf::poll_wait)（包括`wait_on`）消费的通知不带发送方与负载；
+//! `signalfd`与`mqueue`的`wait_on_info`还记录发送方的进程号与附加值（`signalfd`）或消息的负载（`mqueue`）。需要std。
+
+extern crate std;
+
+use alloc::{
+    collections::btree_map::{BTreeMap, Entry},
+    vec::Vec,
+};
+use core::{fmt, time::Duration};
+use std::time::Instant;
+
+use crate::{interface::Notification, sync::SpinMutex};
+
+/// 一个被消费的通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedEvent {
+    /// 自[`start`]以来的时间
+    pub time: Duration,
+    /// 通知源id
+    pub id: u64,
+    /// 发送方，例如`signalfd`通知的发送方进程号；未知时为`None`
+    pub sender: Option<u64>,
+    /// 随通知到达的负载，例如`mqueue`消息的内容或`signalfd`的附加值；没有负载时为空
+    pub payload: Vec<u8>,
+}
+
+/// 录得的通知序列，按到达的顺序排列
+///
+/// 以文本形式显示时每行一个事件：`<纳秒> <id的十六进制> <发送方或-> <负载的十六进制或->`。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// 各事件
+    pub events: Vec<RecordedEvent>,
+}
+
+/// [`Trace::parse`]失败，附带出错的行号（从1开始）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseTraceError(pub usize);
+
+impl fmt::Display for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid trace at line {}", self.0)
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            write!(f, "{} {:016x} ", event.time.as_nanos(), event.id)?;
+            match event.sender {
+                Some(sender) => write!(f, "{} ", sender)?,
+                None => write!(f, "- ")?,
+            }
+            if event.payload.is_empty() {
+                write!(f, "-")?;
+            }
+            for byte in &event.payload {
+                write!(f, "{:02x}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Trace {
+    /// 解析由`Display`输出的文本，忽略空行
+    pub fn parse(text: &str) -> Result<Self, ParseTraceError> {
+        let mut events = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = Self::parse_line(line).ok_or(ParseTraceError(i + 1))?;
+            events.push(event);
+        }
+        Ok(Self { events })
+    }
+
+    fn parse_line(line: &str) -> Option<RecordedEvent> {
+        let mut fields = line.split_whitespace();
+        let time = Duration::from_nanos(fields.next()?.parse().ok()?);
+        let id = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let sender = match fields.next()? {
+            "-" => None,
+            sender => Some(sender.parse().ok()?),
+        };
+        let payload = match fields.next()? {
+            "-" => Vec::new(),
+            hex if hex.len() % 2 == 0 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(RecordedEvent {
+            time,
+            id,
+            sender,
+            payload,
+        })
+    }
+}
+
+struct Recording {
+    start: Instant,
+    events: Vec<RecordedEvent>,
+}
+
+static RECORDING: SpinMutex<Option<Recording>> = SpinMutex::new(None);
+
+/// 开始录制，丢弃此前未被[`stop`]取走的事件
+pub fn start() {
+    *RECORDING.lock() = Some(Recording {
+        start: Instant::now(),
+        events: Vec::new(),
+    });
+}
+
+/// 停止录制并返回录得的事件；未在录制时返回空的序列
+pub fn stop() -> Trace {
+    Trace {
+        events: RECORDING
+            .lock()
+            .take()
+            .map_or_else(Vec::new, |recording| recording.events),
+    }
+}
+
+/// 是否正在录制
+pub fn is_recording() -> bool {
+    RECORDING.lock().is_some()
+}
+
+/// 通知源`id`上的一个通知被消费
+pub(crate) fn received(id: u64, sender: Option<u64>, payload: &[u8]) {
+    let mut recording = RECORDING.lock();
+    if let Some(recording) = recording.as_mut() {
+        let time = recording.start.elapsed();
+        recording.events.push(RecordedEvent {
+            time,
+            id,
+            sender,
+            payload: payload.to_vec(),
+        });
+    }
+}
+
+/// 将[`Trace`]中的通知依次投递到`mock`通知源上
+///
+/// 为录得的每个不同的id申请一个`mock`通知源，被测代码通过[`Replay::id`]取得并在其上等待；
+/// [`Replay::step`]触发下一个通知并返回其事件，调用者可据此提供发送方与负载，或按`time`控制投递的节奏。
+/// 被丢弃时释放所有`mock`通知源。
+pub struct Replay {
+    events: alloc::vec::IntoIter<RecordedEvent>,
+    ids: BTreeMap<u64, u64>,
+}
+
+impl Replay {
+    /// 为`trace`中的每个id申请`mock`通知源
+    pub fn new(trace: Trace) -> Option<Self> {
+        let mut replay = Self {
+            events: Vec::new().into_iter(),
+            ids: BTreeMap::new(),
+        };
+        for event in &trace.events {
+            if let Entry::Vacant(entry) = replay.ids.entry(event.id) {
+                entry.insert(Notification::new_id_mock()?);
+            }
+        }
+        replay.events = trace.events.into_iter();
+        Some(replay)
+    }
+
+    /// 录得的id`recorded`对应的`mock`通知源
+    pub fn id(&self, recorded: u64) -> Option<u64> {
+        self.ids.get(&recorded).copied()
+    }
+
+    /// 尚未投递的事件数量
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+
+    /// 投递下一个通知，返回其事件；全部投递完毕时返回`None`
+    pub fn step(&mut self) -> Option<RecordedEvent> {
+        let event = self.events.next()?;
+        crate::mock::trigger(self.ids[&event.id]);
+        Some(event)
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        for &id in self.ids.values() {
+            unsafe { <Notification as crate::interface::NotificationIf>::release_id(id) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_record_replay() {
+        let waker = futures::task::noop_waker();
+        let mut cx = core::task::Context::from_waker(&waker);
+        let (a, b) = (
+            Notification::new_id_mock().unwrap(),
+            Notification::new_id_mock().unwrap(),
+        );
+        start();
+        for id in [a, b, a] {
+            crate::mock::trigger(id);
+            assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        }
+        // 其他测试也可能消费通知，因此只检查本测试的id
+        let mut trace = stop();
+        trace.events.retain(|event| event.id == a || event.id == b);
+        assert_eq!(
+            trace.events.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [a, b, a]
+        );
+        assert!(!is_recording());
+
+        trace.events[1].sender = Some(42);
+        trace.events[1].payload = alloc::vec![0xde, 0xad];
+        let text = trace.to_string();
+        assert_eq!(Trace::parse(&text), Ok(trace.clone()));
+        assert_eq!(Trace::parse("1 2 - zz"), Err(ParseTraceError(1)));
+
+        let mut replay = Replay::new(trace).unwrap();
+        let (ra, rb) = (replay.id(a).unwrap(), replay.id(b).unwrap());
+        assert_ne!(ra, a);
+        assert!(Notification::poll_wait(ra, &mut cx).is_pending());
+        assert_eq!(replay.step().unwrap().id, a);
+        assert!(Notification::poll_wait(ra, &mut cx).is_ready());
+        assert_eq!(replay.step().unwrap().sender, Some(42));
+        assert!(Notification::poll_wait(rb, &mut cx).is_ready());
+        assert_eq!(replay.remaining(), 1);
+        replay.step();
+        assert!(replay.step().is_none());
+        drop(replay);
+        assert_eq!(crate::mock::pending(ra), None);
+        unsafe { Notification::release_id(a) };
+        unsafe { Notification::release_id(b) };
+    }
+}
@@ -0,0 +1,233 @@
+//! 带重试策略的发送目标
+//!
+//! 发送通知可能因暂时的原因失败：信号队列已满（`EAGAIN`）、接收方尚未消费的通知已达上限（`NotifyError::Full`）、
+//! 数据报的发送缓冲区已满（`NotifyError::Overflow`）等。[`NotificationTarget`]记录发送目标与[`RetryPolicy`]，
+//! 其[`notify`](NotificationTarget::notify)按策略的退避时间重试，调用者不必各自编写重试的循环。
+//!
+//! `notify`在tokio的时钟上等待退避时间，需要在tokio运行时内部调用；[`NotificationTarget::notify_blocking`]阻塞调用线程。
+
+extern crate std;
+
+use core::time::Duration;
+
+use crate::interface::{Notification, NotifyError, ProcessRef};
+
+/// 两次尝试之间的退避时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// 立即重试
+    None,
+    /// 每次等待相同的时间
+    Fixed(Duration),
+    /// 从`initial`开始每次加倍，至多为`max`
+    Exponential {
+        /// 第一次重试之前等待的时间
+        initial: Duration,
+        /// 等待时间的上限
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// 第`retry`次重试（从0开始）之前等待的时间
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+}
+
+/// 发送失败是否是暂时的：`NotifyError::Overflow`、`NotifyError::Full`，以及`EAGAIN`、`EINTR`、`ENOBUFS`
+pub fn is_transient(err: &NotifyError) -> bool {
+    match err {
+        NotifyError::Overflow | NotifyError::Full => true,
+        NotifyError::Os(errno) => matches!(*errno, libc::EAGAIN | libc::EINTR | libc::ENOBUFS),
+        _ => false,
+    }
+}
+
+/// 发送失败时的重试策略
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 最多尝试的次数，包括首次发送；为0时与1相同
+    pub max_attempts: u32,
+    /// 两次尝试之间的退避时间
+    pub backoff: Backoff,
+    /// 对哪些错误重试，其余的错误立即返回
+    pub retry_on: fn(&NotifyError) -> bool,
+}
+
+impl RetryPolicy {
+    /// 最多尝试`max_attempts`次，退避时间从1毫秒加倍至100毫秒，只重试[`is_transient`]的错误
+    pub const fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(100),
+            },
+            retry_on: is_transient,
+        }
+    }
+
+    /// 设置退避时间
+    pub const fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// 设置对哪些错误重试
+    pub const fn retry_on(mut self, retry_on: fn(&NotifyError) -> bool) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// 第`attempt`次尝试（从1开始）以`err`失败后，返回重试之前需等待的时间；不再重试时返回`None`
+    fn next(&self, attempt: u32, err: &NotifyError) -> Option<Duration> {
+        (attempt < self.max_attempts && (self.retry_on)(err))
+            .then(|| self.backoff.delay(attempt - 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// 最多尝试5次
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+/// 一个发送目标：接收方与其通知源，以及发送失败时的重试策略
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationTarget {
+    target: ProcessRef,
+    id: u64,
+    retry: Option<RetryPolicy>,
+}
+
+impl NotificationTarget {
+    /// 向`target`的通知源`id`发送通知的目标，不重试
+    pub fn new(target: impl Into<ProcessRef>, id: u64) -> Self {
+        Self {
+            target: target.into(),
+            id,
+            retry: None,
+        }
+    }
+
+    /// 设置重试策略
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// 接收方
+    pub fn target(&self) -> ProcessRef {
+        self.target
+    }
+
+    /// 接收方的通知源
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 重试策略
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry
+    }
+
+    /// 只尝试一次，见[`Notification::notify_to`]
+    pub fn try_notify(&self) -> Result<(), NotifyError> {
+        Notification::notify_to(self.target, self.id)
+    }
+
+    /// 发送通知，失败时按重试策略在tokio的时钟上等待后重试；返回最后一次尝试的错误
+    pub async fn notify(&self) -> Result<(), NotifyError> {
+        let mut attempt = 1;
+        loop {
+            let err = match self.try_notify() {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            match self.retry.and_then(|policy| policy.next(attempt, &err)) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(err),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// 与[`notify`](Self::notify)相同，但阻塞调用线程等待退避时间
+    pub fn notify_blocking(&self) -> Result<(), NotifyError> {
+        let mut attempt = 1;
+        loop {
+            let err = match self.try_notify() {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            match self.retry.and_then(|policy| policy.next(attempt, &err)) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Err(err),
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(5),
+        };
+        let delays: std::vec::Vec<_> = (0..4).map(|retry| backoff.delay(retry)).collect();
+        assert_eq!(delays, [1, 2, 4, 5].map(Duration::from_millis).to_vec());
+        assert_eq!(backoff.delay(40), Duration::from_millis(5));
+        assert_eq!(Backoff::None.delay(3), Duration::ZERO);
+        assert!(is_transient(&NotifyError::Os(libc::EAGAIN)));
+        assert!(!is_transient(&NotifyError::Os(libc::ESRCH)));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+        fn counting(_: &NotifyError) -> bool {
+            ATTEMPTS.fetch_add(1, Ordering::AcqRel);
+            true
+        }
+
+        let id = Notification::new_id_mock().unwrap();
+        let target = NotificationTarget::new(0, id);
+        assert_eq!(target.target(), ProcessRef::Process(0));
+        assert_eq!(target.notify_blocking(), Ok(()));
+        unsafe { Notification::release_id(id) };
+
+        // 已被释放的通知源返回EBADF，默认策略不重试
+        let target = target.with_retry(RetryPolicy::new(3).backoff(Backoff::None));
+        assert_eq!(target.notify_blocking(), Err(NotifyError::Os(9)));
+
+        let target = target.with_retry(
+            RetryPolicy::new(3)
+                .backoff(Backoff::Fixed(Duration::from_millis(1)))
+                .retry_on(counting),
+        );
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                assert_eq!(target.notify().await, Err(NotifyError::Os(9)));
+            });
+        // 第3次尝试失败后不再询问是否重试
+        assert_eq!(ATTEMPTS.load(Ordering::Acquire), 2);
+    }
+}
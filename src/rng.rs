@@ -0,0 +1,35 @@
+//! 可由种子复现的伪随机数生成器
+
+/// SplitMix64生成器，种子相同时产生的序列相同
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// 以`seed`为种子新建生成器
+    pub(crate) const fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// 下一个随机数
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `[low, high]`中均匀分布的随机数
+    pub(crate) fn range(&mut self, low: u64, high: u64) -> u64 {
+        match (high - low).checked_add(1) {
+            Some(span) => low + self.next_u64() % span,
+            None => self.next_u64(),
+        }
+    }
+
+    /// 以概率`p`返回`true`
+    #[cfg(feature = "fault-inject")]
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        // 取高53位作为[0, 1)中的浮点数
+        ((self.next_u64() >> 11) as f64) / ((1u64 << 53) as f64) < p
+    }
+}
@@ -0,0 +1,266 @@
+//! 请求与应答配对的通知
+//!
+//! 调用方与服务方共享一个[`RpcSlot`]（例如位于`MAP_SHARED`映射的内存中），并各有一个通知源：服务方的`id_req`接收请求，
+//! 调用方的`id_resp`接收应答。
+//!
+//! - 调用方的[`call`]将请求的参数与新的序号写入槽位，向`id_req`发送通知，并在`id_resp`上等待序号相同的应答，直到超时；
+//! - 服务方以[`recv`]等待并取走请求，处理后以[`reply`]写入应答并向`id_resp`发送通知。
+//!
+//! 超时的调用之后到达的应答带有旧的序号，会被之后的调用忽略。请求与应答各只有一个写者：
+//! 每个槽位同时只应有一个正在进行的`call`，且只有一个服务方。参数与结果都是`u64`，更大的数据可放在另外的共享内存中，
+//! 以此传递其位置。
+//!
+//! 必须配合tokio运行时，并启用其时钟。
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering, fence},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::interface::{Notification, NotificationIf, NotifyError, ProcessRef};
+
+/// 以序号保护的一个值，序号为奇数时正在被写入
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Cell {
+    seq: AtomicU64,
+    value: AtomicU64,
+}
+
+impl Cell {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            value: AtomicU64::new(0),
+        }
+    }
+
+    /// 写入第`n`个值，只能由唯一的写者调用
+    fn write(&self, n: u64, value: u64) {
+        self.seq.store(2 * n - 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.value.store(value, Ordering::Relaxed);
+        self.seq.store(2 * n, Ordering::Release);
+    }
+
+    /// 读取最近写入的值及其序号；尚未写入过时序号为0
+    fn read(&self) -> (u64, u64) {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq % 2 == 0 {
+                let value = self.value.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return (seq / 2, value);
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 调用方与服务方共享的槽位
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct RpcSlot {
+    request: Cell,
+    response: Cell,
+    /// 服务方最近取走的请求的序号
+    taken: AtomicU64,
+}
+
+/// 服务方取走的一个请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    seq: u64,
+    payload: u64,
+}
+
+impl Request {
+    /// 请求的序号，每次`call`加1
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// 请求的参数
+    pub fn payload(&self) -> u64 {
+        self.payload
+    }
+}
+
+impl RpcSlot {
+    /// 新建槽位
+    pub const fn new() -> Self {
+        Self {
+            request: Cell::new(),
+            response: Cell::new(),
+            taken: AtomicU64::new(0),
+        }
+    }
+
+    /// 将共享内存中的5个连续的`u64`视为槽位，全零时为初始状态
+    ///
+    /// # Safety
+    ///
+    /// `ptr`必须非空、按8字节对齐，且在`'a`内有效，并只被以原子操作访问。
+    pub unsafe fn from_ptr<'a>(ptr: *mut u64) -> &'a Self {
+        unsafe { &*(ptr as *const Self) }
+    }
+
+    /// 取走尚未被取走的请求，不阻塞；只能由服务方调用
+    ///
+    /// 调用方在服务方取走之前发出了多个请求时（之前的调用已超时），只返回最新的一个。
+    pub fn take_request(&self) -> Option<Request> {
+        let (seq, payload) = self.request.read();
+        if seq == self.taken.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.taken.store(seq, Ordering::Relaxed);
+        Some(Request { seq, payload })
+    }
+
+    /// 写入下一个请求，返回其序号
+    fn post(&self, payload: u64) -> u64 {
+        let seq = self.request.read().0 + 1;
+        self.request.write(seq, payload);
+        seq
+    }
+
+    /// 序号为`seq`的请求的应答
+    fn response(&self, seq: u64) -> Option<u64> {
+        match self.response.read() {
+            (n, value) if n == seq => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// 调用失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallError {
+    /// 超时前未收到应答
+    Timeout,
+    /// 发送请求失败
+    Notify(NotifyError),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::Timeout => write!(f, "no response before timeout"),
+            CallError::Notify(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// 经由`slot`向`target`的通知源`id_req`发送参数为`payload`的请求，并在本进程的通知源`id_resp`上等待应答，返回服务方的结果
+///
+/// `id_resp`由调用者申请与释放，不应被用于其他用途；`timeout`内未收到应答时返回`CallError::Timeout`。
+pub async fn call(
+    slot: &RpcSlot,
+    target: ProcessRef,
+    id_req: u64,
+    id_resp: u64,
+    payload: u64,
+    timeout: Duration,
+) -> Result<u64, CallError> {
+    let deadline = Instant::now() + timeout;
+    let seq = slot.post(payload);
+    Notification::notify_to(target, id_req).map_err(CallError::Notify)?;
+    loop {
+        if let Some(value) = slot.response(seq) {
+            return Ok(value);
+        }
+        // 之前超时的调用的应答也会唤醒本次调用，重新检查序号即可
+        if tokio::time::timeout_at(deadline, Notification::wait_on(id_resp))
+            .await
+            .is_err()
+        {
+            return slot.response(seq).ok_or(CallError::Timeout);
+        }
+    }
+}
+
+/// 在本进程的通知源`id_req`上等待，直到取走`slot`中的一个请求
+pub async fn recv(slot: &RpcSlot, id_req: u64) -> Request {
+    loop {
+        if let Some(request) = slot.take_request() {
+            return request;
+        }
+        Notification::wait_on(id_req).await;
+    }
+}
+
+/// 将`request`的结果`value`写入`slot`，并向调用方`caller`的通知源`id_resp`发送通知
+///
+/// 请求已被调用方放弃时，应答仍被写入，但会被之后的调用忽略。
+pub fn reply(
+    slot: &RpcSlot,
+    caller: ProcessRef,
+    id_resp: u64,
+    request: Request,
+    value: u64,
+) -> Result<(), NotifyError> {
+    slot.response.write(request.seq, value);
+    Notification::notify_to(caller, id_resp)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call() {
+        static SLOT: RpcSlot = RpcSlot::new();
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let target = ProcessRef::Process(0);
+                let timeout = Duration::from_millis(100);
+                let id_req = Notification::new_id_mock().unwrap();
+                let id_resp = Notification::new_id_mock().unwrap();
+                let server = tokio::spawn(async move {
+                    loop {
+                        let request = recv(&SLOT, id_req).await;
+                        reply(&SLOT, target, id_resp, request, request.payload() * 2).unwrap();
+                    }
+                });
+                assert_eq!(
+                    call(&SLOT, target, id_req, id_resp, 21, timeout).await,
+                    Ok(42)
+                );
+                assert_eq!(
+                    call(&SLOT, target, id_req, id_resp, 5, timeout).await,
+                    Ok(10)
+                );
+                server.abort();
+                let _ = server.await;
+
+                // 无人应答时超时，之后迟到的应答不会被当作下一次调用的结果
+                let short = Duration::from_millis(10);
+                assert_eq!(
+                    call(&SLOT, target, id_req, id_resp, 1, short).await,
+                    Err(CallError::Timeout)
+                );
+                let late = SLOT.take_request().unwrap();
+                assert_eq!((late.seq(), late.payload()), (3, 1));
+                assert_eq!(SLOT.take_request(), None);
+                reply(&SLOT, target, id_resp, late, 2).unwrap();
+                assert_eq!(
+                    call(&SLOT, target, id_req, id_resp, 7, short).await,
+                    Err(CallError::Timeout)
+                );
+
+                unsafe { Notification::release_id(id_req) };
+                assert_eq!(
+                    call(&SLOT, target, id_req, id_resp, 7, short).await,
+                    Err(CallError::Notify(NotifyError::Os(9)))
+                );
+                unsafe { Notification::release_id(id_resp) };
+            });
+    }
+}
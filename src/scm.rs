@@ -0,0 +1,85 @@
+//! 经已连接的unix域套接字收发一个64位的值与至多一个文件描述符
+//!
+//! 用于在进程之间交接通知源（[`handoff`](crate::handoff)）与用户态中断的发送方登记。
+
+extern crate std;
+
+use core::mem;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+
+use crate::interface::NotifyError;
+
+/// 消息中值的长度
+const WORD_LEN: usize = mem::size_of::<u64>();
+
+/// 足以容纳一个`SCM_RIGHTS`控制消息的缓冲区，以`u64`对齐
+type CmsgBuf = [u64; 4];
+
+fn errno() -> NotifyError {
+    NotifyError::Os(unsafe { *libc::__errno_location() })
+}
+
+/// 发送`word`，`fd`不为`None`时经`SCM_RIGHTS`一并发送；`socket`为阻塞的时阻塞直到发送完成
+pub(crate) fn send(socket: RawFd, word: u64, fd: Option<RawFd>) -> Result<(), NotifyError> {
+    let data = word.to_le_bytes();
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: WORD_LEN,
+    };
+    let mut cmsg_buf: CmsgBuf = [0; 4];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if let Some(fd) = fd {
+        let data_len = mem::size_of::<RawFd>() as libc::c_uint;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(data_len) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+            core::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        }
+    }
+    if unsafe { libc::sendmsg(socket, &msg, libc::MSG_NOSIGNAL) } == WORD_LEN as isize {
+        Ok(())
+    } else {
+        Err(errno())
+    }
+}
+
+/// 收取一个由[`send`]发出的值与文件描述符；`socket`为阻塞的时阻塞直到收到消息
+///
+/// 对端关闭或消息不完整时返回`NotifyError::Os(EPROTO)`。
+pub(crate) fn recv(socket: RawFd) -> Result<(u64, Option<OwnedFd>), NotifyError> {
+    let mut data = [0u8; WORD_LEN];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: WORD_LEN,
+    };
+    let mut cmsg_buf: CmsgBuf = [0; 4];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of::<CmsgBuf>() as _;
+    let len =
+        unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC | libc::MSG_WAITALL) };
+    if len < 0 {
+        return Err(errno());
+    }
+    let mut fd = None;
+    let cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    if !cmsg.is_null() {
+        let header = unsafe { &*cmsg };
+        if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_RIGHTS {
+            let raw = unsafe { core::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd) };
+            fd = Some(unsafe { OwnedFd::from_raw_fd(raw) });
+        }
+    }
+    if len as usize != WORD_LEN {
+        return Err(NotifyError::Os(libc::EPROTO));
+    }
+    Ok((u64::from_le_bytes(data), fd))
+}
@@ -0,0 +1,262 @@
+//! 基于周期性哨兵通知的通知丢失检测
+//!
+//! 发送方（[`SentinelSender`]）按约定的起始时刻`epoch_ns`与周期`period_ns`，在每个周期的中点向目标通知源发送一个心跳。
+//! 心跳经由[`seq`](crate::seq)的共享计数器编号：每个心跳在发送之前将计数器加1，接收方因此能得知自上次唤醒以来发送了几个通知。
+//!
+//! 接收方以[`seq::enable`](crate::seq::enable)为通知源创建计数器，以[`Notification::wait_on_seq`]等待，
+//! 并将每次唤醒的[`SeqInfo`]交给[`SentinelMonitor`]。一次唤醒计入了多个通知时，多出的通知被合并或丢失：
+//!
+//! - 在为每个通知分别排队的通知源上（见[`NotificationKind::is_queued`]），通知不会在接收之前被合并，多出的通知计为疑似丢失；
+//! - 在其他通知源上（例如信号与eventfd），合并是正常的行为，多出的通知计为被合并，不计为丢失。
+//!
+//! 一个心跳的通知丢失后，由之后的心跳所引起的唤醒揭示，因此丢失至多在一个周期之后被统计。
+//! 启用`stats`时，两者还被累计到该通知源的[`IdStats`](crate::stats::IdStats)中。
+//!
+//! 普通通知与心跳可共用同一通知源，此时统计的是该通知源上经由[`Notification`]发送的所有通知。
+//!
+//! 本模块不依赖具体的时钟，时间戳（纳秒）由调用者传入。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    interface::{Notification, NotifyError, ProcessRef},
+    kind::NotificationKind,
+    seq::SeqInfo,
+};
+
+/// 向一个通知源周期性发送心跳的发送方
+pub struct SentinelSender {
+    process: u64,
+    id: u64,
+    epoch_ns: u64,
+    period_ns: u64,
+    /// 下一个待发送心跳的窗口编号
+    next_seq: u64,
+}
+
+impl SentinelSender {
+    /// 新建发送方，向`process`进程的`id`通知源发送心跳，并映射该通知源的计数器
+    ///
+    /// 接收方需已以[`seq::enable`](crate::seq::enable)创建计数器，否则返回`None`。
+    /// 心跳在周期`[epoch_ns + k * period_ns, epoch_ns + (k + 1) * period_ns)`的中点到期。
+    pub fn new(process: u64, id: u64, epoch_ns: u64, period_ns: u64) -> Option<Self> {
+        assert!(period_ns > 0);
+        if !crate::seq::attach(process, id) {
+            return None;
+        }
+        Some(Self {
+            process,
+            id,
+            epoch_ns,
+            period_ns,
+            next_seq: 0,
+        })
+    }
+
+    /// 下一个心跳应当发送的时刻
+    pub fn next_due_ns(&self) -> u64 {
+        self.epoch_ns + self.next_seq * self.period_ns + self.period_ns / 2
+    }
+
+    /// 若在`now_ns`时刻有到期的心跳，则返回其窗口编号，并推进到下一个心跳
+    ///
+    /// 若错过了若干窗口（例如发送方被长时间调度出去），只返回最新窗口的编号，被错过的窗口不再发送心跳。
+    fn take_due(&mut self, now_ns: u64) -> Option<u64> {
+        if now_ns < self.next_due_ns() {
+            return None;
+        }
+        let seq = (now_ns - self.epoch_ns) / self.period_ns;
+        self.next_seq = seq + 1;
+        Some(seq)
+    }
+
+    /// 若在`now_ns`时刻有到期的心跳，则发送该心跳并返回其窗口编号；没有到期的心跳时返回`Ok(None)`
+    ///
+    /// 发送失败时返回`notify_to`的错误，该心跳不会被重发。
+    pub fn beat(&mut self, now_ns: u64) -> Result<Option<u64>, NotifyError> {
+        let Some(seq) = self.take_due(now_ns) else {
+            return Ok(None);
+        };
+        Notification::notify_to(ProcessRef::Process(self.process), self.id)?;
+        Ok(Some(seq))
+    }
+}
+
+/// 哨兵统计信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentinelStats {
+    /// 截至最近一次唤醒，发送方累计发送的通知数
+    pub expected: u64,
+    /// 计入了至少一个通知的唤醒数
+    pub received: u64,
+    /// 在不排队的通知源上与其他通知合并为一次唤醒的通知数
+    pub coalesced: u64,
+    /// 在排队的通知源上疑似丢失的通知数
+    pub suspected_lost: u64,
+}
+
+/// 检查心跳连续性的接收方
+///
+/// 所有操作均为无锁的，可在等待通知的协程与读取统计信息的协程之间共享。
+pub struct SentinelMonitor {
+    id: u64,
+    /// 最近一次唤醒时计数器的值
+    last_seq: AtomicU64,
+    received: AtomicU64,
+    coalesced: AtomicU64,
+    lost: AtomicU64,
+}
+
+impl SentinelMonitor {
+    /// 新建监测通知源`id`的接收方
+    pub const fn new(id: u64) -> Self {
+        Self {
+            id,
+            last_seq: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            lost: AtomicU64::new(0),
+        }
+    }
+
+    /// 记录一次唤醒，应在每次从被监测的通知源`wait_on_seq`返回后调用
+    ///
+    /// 计数为0的唤醒（其通知已在之前的唤醒中被计入）不被统计。
+    pub fn on_wake(&self, info: SeqInfo) {
+        self.last_seq.fetch_max(info.seq, Ordering::AcqRel);
+        if info.count == 0 {
+            return;
+        }
+        self.received.fetch_add(1, Ordering::AcqRel);
+        let missed = info.missed();
+        let (coalesced, lost) =
+            if Notification::kind_of(self.id).is_some_and(NotificationKind::is_queued) {
+                (0, missed)
+            } else {
+                (missed, 0)
+            };
+        self.coalesced.fetch_add(coalesced, Ordering::AcqRel);
+        self.lost.fetch_add(lost, Ordering::AcqRel);
+        #[cfg(feature = "stats")]
+        crate::stats::record_sentinel(self.id, coalesced, lost);
+    }
+
+    /// 获取截至最近一次唤醒的统计信息
+    pub fn stats(&self) -> SentinelStats {
+        SentinelStats {
+            expected: self.last_seq.load(Ordering::Acquire),
+            received: self.received.load(Ordering::Acquire),
+            coalesced: self.coalesced.load(Ordering::Acquire),
+            suspected_lost: self.lost.load(Ordering::Acquire),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentinel_sender_due() {
+        let mut sender = SentinelSender {
+            process: 0,
+            id: 0,
+            epoch_ns: 1000,
+            period_ns: 100,
+            next_seq: 0,
+        };
+        assert_eq!(sender.take_due(1000), None);
+        assert_eq!(sender.take_due(1050), Some(0));
+        assert_eq!(sender.take_due(1099), None);
+        assert_eq!(sender.take_due(1150), Some(1));
+        // 错过窗口2、3
+        assert_eq!(sender.take_due(1460), Some(4));
+        assert_eq!(sender.next_due_ns(), 1550);
+    }
+
+    #[test]
+    fn test_sentinel_monitor_continuity() {
+        // 消息队列为每个通知分别排队，不与其他测试中的id冲突
+        let monitor =
+            SentinelMonitor::new(((NotificationKind::Mqueue.tag() as u64) << 56) | 0xfff0);
+        monitor.on_wake(SeqInfo { seq: 1, count: 1 });
+        monitor.on_wake(SeqInfo { seq: 2, count: 1 });
+        // 已在之前的唤醒中被计入
+        monitor.on_wake(SeqInfo { seq: 2, count: 0 });
+        assert_eq!(
+            monitor.stats(),
+            SentinelStats {
+                expected: 2,
+                received: 2,
+                coalesced: 0,
+                suspected_lost: 0
+            }
+        );
+
+        // 心跳3、4的唤醒被合并或丢失，由心跳5揭示
+        monitor.on_wake(SeqInfo { seq: 5, count: 3 });
+        assert_eq!(
+            monitor.stats(),
+            SentinelStats {
+                expected: 5,
+                received: 3,
+                coalesced: 0,
+                suspected_lost: 2
+            }
+        );
+
+        // 信号会合并尚未被接收的通知，多出的通知不计为丢失
+        let monitor =
+            SentinelMonitor::new(((NotificationKind::Signal.tag() as u64) << 56) | 0xfff0);
+        monitor.on_wake(SeqInfo { seq: 3, count: 3 });
+        assert_eq!(
+            monitor.stats(),
+            SentinelStats {
+                expected: 3,
+                received: 1,
+                coalesced: 2,
+                suspected_lost: 0
+            }
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_sentinel_mock() {
+        use crate::interface::NotificationIf;
+
+        let pid = unsafe { libc::getpid() as u64 };
+        let id = Notification::new_id_mock().unwrap();
+        // 接收方尚未创建计数器
+        assert!(SentinelSender::new(pid, id, 0, 100).is_none());
+        assert!(crate::seq::enable(id));
+        let mut sender = SentinelSender::new(pid, id, 0, 100).unwrap();
+        let monitor = SentinelMonitor::new(id);
+
+        assert_eq!(sender.beat(10), Ok(None));
+        assert_eq!(sender.beat(50), Ok(Some(0)));
+        monitor.on_wake(futures::executor::block_on(Notification::wait_on_seq(id)).unwrap());
+        // 两个心跳在接收方等待之前到达，被合并为一次唤醒
+        assert_eq!(sender.beat(150), Ok(Some(1)));
+        assert_eq!(sender.beat(250), Ok(Some(2)));
+        monitor.on_wake(futures::executor::block_on(Notification::wait_on_seq(id)).unwrap());
+        assert_eq!(
+            monitor.stats(),
+            SentinelStats {
+                expected: 3,
+                received: 2,
+                coalesced: 1,
+                suspected_lost: 0
+            }
+        );
+        #[cfg(feature = "stats")]
+        {
+            let stats = Notification::stats(id).unwrap();
+            assert_eq!((stats.sentinel_coalesced, stats.sentinel_lost), (1, 0));
+        }
+
+        unsafe { Notification::release_id(id) };
+        assert!(sender.beat(350).is_err());
+        assert!(crate::seq::detach(pid, id));
+    }
+}
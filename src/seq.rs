@@ -0,0 +1,350 @@
+//! 共享内存中的通知序号与额度
+//!
+//! 信号等通知机制会合并尚未被接收的通知，接收方无法得知一次唤醒对应几个通知。
+//! 启用本机制后，每个通知源带有一个位于共享内存中的计数器：
+//!
+//! - 接收方以[`enable`]为自己的通知源创建计数器；
+//! - 发送方以[`attach`]映射接收方的计数器，之后经由[`Notification`]的`notify`、`notify_with`、`notify_to`
+//!   向该通知源发送通知时，在发送之前将计数器加1；
+//! - 接收方以[`Notification::wait_on_seq`]等待，并得到自上次唤醒以来新发送的通知数。
+//!
+//! 接收方还可以[`enable_with_credit`]限制尚未消费的通知数，使发送方在接收方跟不上时得到`NotifyError::Full`。
+//! 计数与额度只对经由[`Notification`]的`poll_wait`（包括`wait_on`）消费的通知生效。
+//!
+//! 计数器在发送之前增加，因此一次唤醒可能计入了尚未到达的通知，使其到达时的唤醒计数为0。
+//! 发往进程组的通知不被计数。计数器在通知源被释放时删除。
+
+use alloc::{collections::btree_map::BTreeMap, ffi::CString};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf, NotifyError, ProcessRef},
+    shm,
+    sync::SpinMutex,
+};
+
+/// 一次唤醒的序号信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqInfo {
+    /// 唤醒时计数器的值，即发送方累计发送的通知数
+    pub seq: u64,
+    /// 自上次唤醒以来新发送的通知数
+    ///
+    /// 为1表示没有通知被合并；大于1时多出的通知被合并或丢失；为0表示本次唤醒的通知已在之前的唤醒中被计入。
+    pub count: u64,
+}
+
+impl SeqInfo {
+    /// 被合并或丢失的通知数
+    pub fn missed(&self) -> u64 {
+        self.count.saturating_sub(1)
+    }
+}
+
+/// 位于共享内存中的计数器
+#[repr(C)]
+struct Shared {
+    /// 累计发送的通知数，由发送方增加
+    sent: AtomicU64,
+    /// 最近一次唤醒时的`sent`，由接收方更新
+    consumed: AtomicU64,
+    /// 尚未消费的通知数的上限，为0表示不限制；由接收方在创建时写入
+    capacity: AtomicU64,
+}
+
+/// 映射到本进程的计数器
+type Mapping = shm::Mapping<Shared>;
+
+/// 接收方的计数器
+struct RecvSlot {
+    mapping: Mapping,
+    /// 最近一次唤醒的序号信息
+    last: Option<SeqInfo>,
+}
+
+/// 本进程作为接收方的计数器，以id为key
+static RECEIVERS: SpinMutex<BTreeMap<u64, RecvSlot>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程作为发送方映射的计数器，以接收方的进程号与id为key
+static SENDERS: SpinMutex<BTreeMap<(u64, u64), Mapping>> = SpinMutex::new(BTreeMap::new());
+
+/// 进程`pid`的通知源`id`对应的共享内存对象名
+fn name(pid: u64, id: u64) -> CString {
+    shm::name("seq", pid, id)
+}
+
+fn getpid() -> u64 {
+    unsafe { libc::getpid() as u64 }
+}
+
+/// 为本进程的通知源`id`创建计数器，返回是否成功；已创建时返回`false`
+pub fn enable(id: u64) -> bool {
+    enable_with_credit(id, 0)
+}
+
+/// 为本进程的通知源`id`创建计数器，并限制尚未消费的通知数不超过`capacity`，为0时不限制
+///
+/// 已发送但尚未被唤醒计入的通知数达到`capacity`时，发送方的`notify_with`与`notify_to`返回`NotifyError::Full`，
+/// `notify`则丢弃该通知，使生产者可以在消费者跟不上时减速，而不是让通知被合并。
+/// 一次唤醒计入此前发送的所有通知，因此被合并的通知同样归还额度。
+pub fn enable_with_credit(id: u64, capacity: u64) -> bool {
+    let mut receivers = RECEIVERS.lock();
+    if receivers.contains_key(&id) {
+        return false;
+    }
+    let Ok(mapping) = Mapping::map(&name(getpid(), id), true) else {
+        return false;
+    };
+    mapping.get().capacity.store(capacity, Ordering::Release);
+    receivers.insert(
+        id,
+        RecvSlot {
+            mapping,
+            last: None,
+        },
+    );
+    true
+}
+
+/// 映射进程`process`的通知源`id`的计数器，之后向其发送的通知将被计数；返回是否成功
+///
+/// 接收方需已调用[`enable`]或[`enable_with_credit`]。重复映射时返回`true`。
+pub fn attach(process: u64, id: u64) -> bool {
+    let mut senders = SENDERS.lock();
+    if senders.contains_key(&(process, id)) {
+        return true;
+    }
+    let Ok(mapping) = Mapping::map(&name(process, id), false) else {
+        return false;
+    };
+    senders.insert((process, id), mapping);
+    true
+}
+
+/// 取消对进程`process`的通知源`id`的计数器的映射，返回其是否曾被映射
+pub fn detach(process: u64, id: u64) -> bool {
+    SENDERS.lock().remove(&(process, id)).is_some()
+}
+
+/// 进程`process`的通知源`id`还可以接受的通知数；未映射其计数器或不限制时返回`None`
+pub fn credit(process: u64, id: u64) -> Option<u64> {
+    let senders = SENDERS.lock();
+    let shared = senders.get(&(process, id))?.get();
+    let capacity = shared.capacity.load(Ordering::Acquire);
+    if capacity == 0 {
+        return None;
+    }
+    let pending = shared
+        .sent
+        .load(Ordering::Acquire)
+        .wrapping_sub(shared.consumed.load(Ordering::Acquire));
+    Some(capacity.saturating_sub(pending))
+}
+
+/// 计数所用的接收方进程号，发往进程组的通知不被计数
+fn process_of(target: ProcessRef) -> Option<u64> {
+    match target {
+        ProcessRef::Process(pid) | ProcessRef::Thread { pid, .. } => Some(pid),
+        ProcessRef::Group(_) => None,
+    }
+}
+
+/// 即将向`target`的通知源`id`发送通知；接收方尚未消费的通知已达上限时返回`NotifyError::Full`
+pub(crate) fn on_notify(target: ProcessRef, id: u64) -> Result<(), NotifyError> {
+    let Some(process) = process_of(target) else {
+        return Ok(());
+    };
+    let senders = SENDERS.lock();
+    let Some(mapping) = senders.get(&(process, id)) else {
+        return Ok(());
+    };
+    let shared = mapping.get();
+    let capacity = shared.capacity.load(Ordering::Acquire);
+    shared
+        .sent
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |sent| {
+            let pending = sent.wrapping_sub(shared.consumed.load(Ordering::Acquire));
+            (capacity == 0 || pending < capacity).then(|| sent.wrapping_add(1))
+        })
+        .map(|_| ())
+        .map_err(|_| NotifyError::Full)
+}
+
+/// 向`target`的通知源`id`发送通知失败，撤销`on_notify`中的计数
+pub(crate) fn undo_notify(target: ProcessRef, id: u64) {
+    let Some(process) = process_of(target) else {
+        return;
+    };
+    if let Some(mapping) = SENDERS.lock().get(&(process, id)) {
+        let shared = mapping.get();
+        // 接收方已将该通知计入唤醒时不再撤销，避免`sent`小于`consumed`
+        let _ = shared
+            .sent
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |sent| {
+                (sent != shared.consumed.load(Ordering::Acquire)).then(|| sent.wrapping_sub(1))
+            });
+    }
+}
+
+/// 通知源`id`上的一个通知被消费，记录本次唤醒的序号信息，并归还额度
+pub(crate) fn on_wake(id: u64) {
+    let mut receivers = RECEIVERS.lock();
+    let Some(slot) = receivers.get_mut(&id) else {
+        return;
+    };
+    let shared = slot.mapping.get();
+    let seq = shared.sent.load(Ordering::Acquire);
+    let count = seq.wrapping_sub(shared.consumed.swap(seq, Ordering::AcqRel));
+    slot.last = Some(SeqInfo { seq, count });
+}
+
+/// 通知源`id`即将被释放，删除其计数器
+pub(crate) fn release(id: u64) {
+    if RECEIVERS.lock().remove(&id).is_some() {
+        shm::unlink(&name(getpid(), id));
+    }
+}
+
+/// 最近一次唤醒的序号信息
+fn last(id: u64) -> Option<SeqInfo> {
+    RECEIVERS.lock().get(&id)?.last
+}
+
+impl Notification {
+    /// 在通知源上等待，并返回本次唤醒的序号信息；若通知源未以[`enable`]创建计数器，则返回`None`
+    ///
+    /// 与`wait_on`相同，返回的future是取消安全的。
+    pub fn wait_on_seq(id: u64) -> WaitSeq {
+        WaitSeq { id }
+    }
+}
+
+/// `wait_on_seq`返回的future
+pub struct WaitSeq {
+    id: u64,
+}
+
+impl Future for WaitSeq {
+    type Output = Option<SeqInfo>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Notification::poll_wait(self.id, cx).map(|()| last(self.id))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_gap() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let pid = getpid();
+                let id = Notification::new_id_mock().unwrap();
+                assert!(enable(id));
+                assert!(!enable(id));
+                assert!(attach(pid, id));
+                for _ in 0..3 {
+                    Notification::notify(pid, id);
+                }
+                // 第一次唤醒即计入了全部三个通知
+                assert_eq!(
+                    Notification::wait_on_seq(id).await,
+                    Some(SeqInfo { seq: 3, count: 3 })
+                );
+                assert_eq!(
+                    Notification::wait_on_seq(id).await,
+                    Some(SeqInfo { seq: 3, count: 0 })
+                );
+                assert!(detach(pid, id));
+                Notification::notify(pid, id);
+                assert_eq!(Notification::wait_on_seq(id).await.unwrap().seq, 3);
+
+                unsafe { Notification::release_id(id) };
+                // 计数器随通知源被删除
+                assert!(!attach(pid, id));
+            });
+    }
+
+    #[test]
+    fn test_credit() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let pid = getpid();
+                let target = ProcessRef::Process(pid);
+                let id = Notification::new_id_mock().unwrap();
+                assert!(enable_with_credit(id, 2));
+                assert!(attach(pid, id));
+                assert_eq!(credit(pid, id), Some(2));
+                Notification::notify_to(target, id).unwrap();
+                Notification::notify_to(target, id).unwrap();
+                assert_eq!(Notification::notify_to(target, id), Err(NotifyError::Full));
+                assert_eq!(credit(pid, id), Some(0));
+                // 一次唤醒归还此前发送的所有通知的额度
+                Notification::wait_on(id).await;
+                assert_eq!(credit(pid, id), Some(2));
+                Notification::notify_to(target, id).unwrap();
+                assert_eq!(
+                    Notification::wait_on_seq(id).await,
+                    Some(SeqInfo { seq: 3, count: 1 })
+                );
+                unsafe { Notification::release_id(id) };
+                assert!(detach(pid, id));
+            });
+    }
+
+    /// 发送失败的通知不占用额度
+    #[test]
+    fn test_failed_notify_keeps_credit() {
+        let pid = getpid();
+        let target = ProcessRef::Process(pid);
+        let id = Notification::new_id_mock().unwrap();
+        assert!(enable_with_credit(id, 1));
+        assert!(attach(pid, id));
+        unsafe { Notification::release_id(id) };
+        // 通知源已被释放，发送失败
+        assert!(Notification::notify_to(target, id).is_err());
+        assert_eq!(credit(pid, id), Some(1));
+        assert!(detach(pid, id));
+    }
+
+    /// 带有负载的通知与普通通知共用计数
+    #[cfg(feature = "mqueue")]
+    #[test]
+    fn test_payload_counted() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let pid = getpid();
+                let id = Notification::new_id_mqueue(4, 8).unwrap();
+                assert!(enable_with_credit(id, 3));
+                assert!(attach(pid, id));
+                Notification::notify_with_payload(pid, id, b"one").unwrap();
+                Notification::notify_to(ProcessRef::Process(pid), id).unwrap();
+                assert_eq!(credit(pid, id), Some(1));
+                // 负载超过消息的大小，发送失败时归还额度
+                assert!(Notification::notify_with_payload(pid, id, &[0; 16]).is_err());
+                assert_eq!(credit(pid, id), Some(1));
+                assert_eq!(
+                    Notification::wait_on_seq(id).await,
+                    Some(SeqInfo { seq: 2, count: 2 })
+                );
+                unsafe { Notification::release_id(id) };
+                assert!(detach(pid, id));
+            });
+    }
+}
@@ -0,0 +1,392 @@
+//! 通知源的优先级与同时等待多个通知源
+//!
+//! 每个通知源带有一个[`Priority`]，默认为`Priority::Normal`，可通过[`Notification::set_priority`]修改。
+//! [`Notification::wait_any`]与[`NotificationSet`]同时等待多个通知源，在多个通知源均有通知时先返回优先级高的；
+//! 优先级相同的通知源轮流返回，避免其中一个持续有通知时饿死其他通知源。
+//!
+//! 信号通知源还可以用[`Notification::new_id_signal_with_priority`]申请，使优先级对应于内核投递实时信号的顺序。
+//!
+//! 通知源还可以带有一个指针大小的上下文，通过[`Notification::new_id_with_ctx`]申请或[`Notification::set_ctx`]设置。
+//! [`Notification::wait_any_with_ctx`]与[`NotificationSet::wait_next_with_ctx`]随通知源一同返回其上下文，
+//! 服务大量通知源时不必再以id查找对应的状态。
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf},
+    sync::SpinMutex,
+};
+
+/// 通知源的优先级
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// 低优先级，例如批量数据的门铃
+    Low,
+    /// 默认的优先级
+    #[default]
+    Normal,
+    /// 高优先级，例如控制消息
+    High,
+}
+
+/// 优先级不为`Priority::Normal`的通知源，以id为key
+static PRIORITIES: SpinMutex<BTreeMap<u64, Priority>> = SpinMutex::new(BTreeMap::new());
+
+/// 带有上下文的通知源，以id为key
+static CONTEXTS: SpinMutex<BTreeMap<u64, usize>> = SpinMutex::new(BTreeMap::new());
+
+impl Notification {
+    /// 设置通知源`id`的优先级，只影响之后加入`NotificationSet`或传给`wait_any`的等待
+    pub fn set_priority(id: u64, priority: Priority) {
+        let mut priorities = PRIORITIES.lock();
+        if priority == Priority::Normal {
+            priorities.remove(&id);
+        } else {
+            priorities.insert(id, priority);
+        }
+    }
+
+    /// 通知源`id`的优先级
+    pub fn priority(id: u64) -> Priority {
+        PRIORITIES.lock().get(&id).copied().unwrap_or_default()
+    }
+
+    /// 申请一个通知源，并为其设置上下文`ctx`，例如指向其对应状态的指针
+    pub fn new_id_with_ctx(ctx: usize) -> Option<u64> {
+        let id = Self::new_id()?;
+        Self::set_ctx(id, ctx);
+        Some(id)
+    }
+
+    /// 设置通知源`id`的上下文，只影响之后加入`NotificationSet`或传给`wait_any_with_ctx`的等待
+    pub fn set_ctx(id: u64, ctx: usize) {
+        CONTEXTS.lock().insert(id, ctx);
+    }
+
+    /// 通知源`id`的上下文，未设置时返回`None`
+    pub fn ctx(id: u64) -> Option<usize> {
+        CONTEXTS.lock().get(&id).copied()
+    }
+
+    /// 同时等待`ids`中的通知源，返回收到通知的通知源；多个通知源均有通知时返回优先级最高的
+    ///
+    /// 返回的future只消费所返回的通知源上的一个通知，是取消安全的。`ids`为空时永远不会完成。
+    pub fn wait_any(ids: &[u64]) -> WaitAny {
+        WaitAny {
+            set: NotificationSet::from_ids(ids),
+        }
+    }
+
+    /// 与`wait_any`相同，并随通知源返回其上下文；未设置上下文的通知源返回0
+    pub fn wait_any_with_ctx(ids: &[u64]) -> WaitAnyWithCtx {
+        WaitAnyWithCtx {
+            set: NotificationSet::from_ids(ids),
+        }
+    }
+}
+
+/// 通知源`id`即将被释放，清除其优先级与上下文
+pub(crate) fn release(id: u64) {
+    PRIORITIES.lock().remove(&id);
+    CONTEXTS.lock().remove(&id);
+}
+
+/// 一组同时等待的通知源
+#[derive(Debug, Clone, Default)]
+pub struct NotificationSet {
+    /// 按优先级从高到低排列，优先级相同的按加入的顺序排列
+    entries: Vec<(Priority, u64)>,
+    /// 与`entries`一一对应的上下文
+    contexts: Vec<usize>,
+    /// 优先级相同的通知源之间轮转的起点
+    cursor: usize,
+}
+
+impl NotificationSet {
+    /// 新建空的集合
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            contexts: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    fn from_ids(ids: &[u64]) -> Self {
+        let mut set = Self::new();
+        for &id in ids {
+            set.insert(id);
+        }
+        set
+    }
+
+    /// 以通知源当前的优先级与上下文将其加入集合，若其已在集合中，则返回`false`
+    pub fn insert(&mut self, id: u64) -> bool {
+        self.insert_with_ctx(id, Notification::ctx(id).unwrap_or(0))
+    }
+
+    /// 以通知源当前的优先级与给定的上下文`ctx`将其加入集合，若其已在集合中，则返回`false`
+    pub fn insert_with_ctx(&mut self, id: u64, ctx: usize) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        let priority = Notification::priority(id);
+        let index = self.entries.partition_point(|&(p, _)| p >= priority);
+        self.entries.insert(index, (priority, id));
+        self.contexts.insert(index, ctx);
+        true
+    }
+
+    /// 将通知源移出集合，若其不在集合中，则返回`false`
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.entries.iter().position(|&(_, i)| i == id) {
+            Some(index) => {
+                self.entries.remove(index);
+                self.contexts.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 通知源是否在集合中
+    pub fn contains(&self, id: u64) -> bool {
+        self.entries.iter().any(|&(_, i)| i == id)
+    }
+
+    /// 集合中的通知源及其优先级，按优先级从高到低排列
+    pub fn entries(&self) -> &[(Priority, u64)] {
+        &self.entries
+    }
+
+    /// 集合中通知源的数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 集合是否为空
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 轮询集合中的通知源，返回收到通知的通知源，并消费其一个通知
+    ///
+    /// 按优先级从高到低轮询，找到有通知的通知源即返回，不再轮询优先级更低的通知源。
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<u64> {
+        self.poll_next_with_ctx(cx).map(|(id, _)| id)
+    }
+
+    /// 与`poll_next`相同，并随通知源返回其加入集合时的上下文
+    pub fn poll_next_with_ctx(&mut self, cx: &mut Context<'_>) -> Poll<(u64, usize)> {
+        let mut start = 0;
+        while start < self.entries.len() {
+            let priority = self.entries[start].0;
+            let len = self.entries[start..].partition_point(|&(p, _)| p == priority);
+            let class = &self.entries[start..start + len];
+            for i in 0..len {
+                let index = (self.cursor + i) % len;
+                let id = class[index].1;
+                if Notification::poll_wait(id, cx).is_ready() {
+                    self.cursor = self.cursor.wrapping_add(i + 1);
+                    return Poll::Ready((id, self.contexts[start + index]));
+                }
+            }
+            start += len;
+        }
+        Poll::Pending
+    }
+
+    /// 等待集合中的任一通知源收到通知，返回该通知源
+    pub fn wait_next(&mut self) -> Next<'_> {
+        Next { set: self }
+    }
+
+    /// 等待集合中的任一通知源收到通知，返回该通知源及其上下文
+    pub fn wait_next_with_ctx(&mut self) -> NextWithCtx<'_> {
+        NextWithCtx { set: self }
+    }
+}
+
+/// `NotificationSet::wait_next`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Next<'a> {
+    set: &'a mut NotificationSet,
+}
+
+impl Future for Next<'_> {
+    type Output = u64;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        self.set.poll_next(cx)
+    }
+}
+
+/// `NotificationSet::wait_next_with_ctx`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct NextWithCtx<'a> {
+    set: &'a mut NotificationSet,
+}
+
+impl Future for NextWithCtx<'_> {
+    type Output = (u64, usize);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<(u64, usize)> {
+        self.set.poll_next_with_ctx(cx)
+    }
+}
+
+/// `Notification::wait_any`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitAny {
+    set: NotificationSet,
+}
+
+impl Future for WaitAny {
+    type Output = u64;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        self.set.poll_next(cx)
+    }
+}
+
+/// `Notification::wait_any_with_ctx`返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WaitAnyWithCtx {
+    set: NotificationSet,
+}
+
+impl Future for WaitAnyWithCtx {
+    type Output = (u64, usize);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<(u64, usize)> {
+        self.set.poll_next_with_ctx(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_order() {
+        let mut set = NotificationSet::new();
+        // 不与其他测试中的id冲突
+        let (low, normal, high) = (
+            0xff00_0000_0000_0331,
+            0xff00_0000_0000_0332,
+            0xff00_0000_0000_0333,
+        );
+        Notification::set_priority(low, Priority::Low);
+        Notification::set_priority(high, Priority::High);
+        assert!(set.insert(low));
+        assert!(set.insert(normal));
+        assert!(set.insert(high));
+        assert!(!set.insert(high));
+        assert_eq!(
+            set.entries(),
+            [
+                (Priority::High, high),
+                (Priority::Normal, normal),
+                (Priority::Low, low)
+            ]
+        );
+        assert!(set.remove(normal));
+        assert_eq!(set.len(), 2);
+        release(low);
+        release(high);
+        assert_eq!(Notification::priority(high), Priority::Normal);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_wait_any_priority() {
+        use crate::mock::trigger;
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let bulk: Vec<u64> = (0..2)
+            .map(|_| Notification::new_id_mock().unwrap())
+            .collect();
+        let control = Notification::new_id_mock().unwrap();
+        for &id in &bulk {
+            Notification::set_priority(id, Priority::Low);
+        }
+        Notification::set_priority(control, Priority::High);
+
+        let mut set = NotificationSet::new();
+        for &id in bulk.iter().chain([control].iter()) {
+            set.insert(id);
+        }
+        assert!(set.poll_next(&mut cx).is_pending());
+        for _ in 0..2 {
+            trigger(bulk[0]);
+            trigger(bulk[1]);
+        }
+        trigger(control);
+        // 高优先级的通知先被返回，之后优先级相同的通知源轮流返回
+        assert_eq!(set.poll_next(&mut cx), Poll::Ready(control));
+        let order: Vec<u64> = (0..4)
+            .map(|_| match set.poll_next(&mut cx) {
+                Poll::Ready(id) => id,
+                Poll::Pending => panic!("bulk notification lost"),
+            })
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] != w[1]));
+        assert!(set.poll_next(&mut cx).is_pending());
+
+        trigger(bulk[1]);
+        let mut wait = Notification::wait_any(&[bulk[0], bulk[1], control]);
+        assert_eq!(Pin::new(&mut wait).poll(&mut cx), Poll::Ready(bulk[1]));
+
+        for id in bulk.into_iter().chain([control]) {
+            unsafe { Notification::release_id(id) };
+            assert_eq!(Notification::priority(id), Priority::Normal);
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_wait_any_ctx() {
+        use crate::mock::trigger;
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let states = [11usize, 22];
+        let ids = states.map(|state| {
+            let id = Notification::new_id_mock().unwrap();
+            Notification::set_ctx(id, state);
+            id
+        });
+        let plain = Notification::new_id_mock().unwrap();
+        assert_eq!(Notification::ctx(ids[1]), Some(22));
+        assert_eq!(Notification::ctx(plain), None);
+
+        let mut set = NotificationSet::new();
+        set.insert(ids[0]);
+        set.insert(ids[1]);
+        assert!(set.insert_with_ctx(plain, 33));
+        trigger(ids[1]);
+        assert_eq!(set.poll_next_with_ctx(&mut cx), Poll::Ready((ids[1], 22)));
+        trigger(plain);
+        assert_eq!(set.poll_next_with_ctx(&mut cx), Poll::Ready((plain, 33)));
+        assert!(set.remove(ids[1]));
+        trigger(ids[0]);
+        let mut next = set.wait_next_with_ctx();
+        assert_eq!(Pin::new(&mut next).poll(&mut cx), Poll::Ready((ids[0], 11)));
+
+        // 未设置上下文的通知源返回0
+        trigger(plain);
+        let mut wait = Notification::wait_any_with_ctx(&[ids[0], plain]);
+        assert_eq!(Pin::new(&mut wait).poll(&mut cx), Poll::Ready((plain, 0)));
+
+        for id in ids.into_iter().chain([plain]) {
+            unsafe { Notification::release_id(id) };
+            assert_eq!(Notification::ctx(id), None);
+        }
+    }
+}
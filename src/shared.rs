@@ -0,0 +1,100 @@
+//! 在同一进程内共享通知源
+//!
+//! 多个子系统持有同一个通知源时，需要约定由谁调用`release_id`。[`Notification::dup_id`]增加通知源的持有者，
+//! 并返回一个[`SharedId`]；之后每次`Notification::release_id`（包括`SharedId`被drop）只减少一个持有者，
+//! 最后一个持有者释放时才真正释放通知源。
+
+use alloc::collections::btree_map::BTreeMap;
+
+use crate::{
+    interface::{Notification, NotificationIf, WaitOn},
+    sync::SpinMutex,
+};
+
+/// 被复制过的通知源除最初的持有者之外的持有者数量，以id为key
+static HOLDERS: SpinMutex<BTreeMap<u64, usize>> = SpinMutex::new(BTreeMap::new());
+
+/// 通知源`id`的一个持有者释放了它，返回是否还有其他持有者（此时不应真正释放通知源）
+pub(crate) fn release(id: u64) -> bool {
+    let mut holders = HOLDERS.lock();
+    match holders.get_mut(&id) {
+        Some(extra) => {
+            *extra -= 1;
+            if *extra == 0 {
+                holders.remove(&id);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+impl Notification {
+    /// 增加通知源`id`的一个持有者，返回代表该持有者的句柄
+    ///
+    /// 通知源必须已被申请。原有的持有者仍通过`release_id`释放，与句柄被drop的顺序无关。
+    pub fn dup_id(id: u64) -> SharedId {
+        *HOLDERS.lock().entry(id).or_insert(0) += 1;
+        SharedId { id }
+    }
+
+    /// 通知源`id`的持有者数量；未被复制过的通知源为1
+    pub fn holders(id: u64) -> usize {
+        HOLDERS.lock().get(&id).map_or(1, |extra| extra + 1)
+    }
+}
+
+/// 通知源的一个持有者，被drop时释放其持有，最后一个持有者被drop时释放通知源
+///
+/// 由[`Notification::dup_id`]或`clone`得到。
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct SharedId {
+    id: u64,
+}
+
+impl SharedId {
+    /// 通知源的id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 在通知源上等待，见[`NotificationIf::wait_on`]
+    pub fn wait_on(&self) -> WaitOn<Notification> {
+        Notification::wait_on(self.id)
+    }
+}
+
+impl Clone for SharedId {
+    fn clone(&self) -> Self {
+        Notification::dup_id(self.id)
+    }
+}
+
+impl Drop for SharedId {
+    fn drop(&mut self) {
+        unsafe { Notification::release_id(self.id) };
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dup_id() {
+        let id = Notification::new_id_mock().unwrap();
+        let a = Notification::dup_id(id);
+        let b = a.clone();
+        assert_eq!(Notification::holders(id), 3);
+
+        // 最初的持有者先释放，通知源仍可使用
+        unsafe { Notification::release_id(id) };
+        drop(a);
+        Notification::notify(0, id);
+        assert_eq!(crate::mock::pending(id), Some(1));
+        assert_eq!(Notification::holders(id), 1);
+
+        drop(b);
+        assert_eq!(crate::mock::pending(id), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! 以POSIX共享内存对象在进程之间共享一个结构体
+//!
+//! 结构体只能包含原子类型等可被多个进程同时访问的字段，且全零时为有效的初始值。
+
+use alloc::{ffi::CString, format};
+use core::{marker::PhantomData, ptr::NonNull};
+
+use crate::interface::NotifyError;
+
+/// 打开已有的对象时，等待创建者设置其大小的最大重试次数
+const SIZE_RETRIES: usize = 64;
+
+/// 映射到本进程的共享内存对象
+pub(crate) struct Mapping<T> {
+    ptr: NonNull<T>,
+    _marker: PhantomData<T>,
+}
+
+// 共享的结构体只通过原子操作访问
+unsafe impl<T: Sync> Send for Mapping<T> {}
+unsafe impl<T: Sync> Sync for Mapping<T> {}
+
+/// 进程`pid`的通知源`id`在功能`kind`下对应的共享内存对象名
+pub(crate) fn name(kind: &str, pid: u64, id: u64) -> CString {
+    CString::new(format!("/async_notification.{}.{}.{:x}", kind, pid, id)).unwrap()
+}
+
+impl<T> Mapping<T> {
+    /// 映射共享内存对象`name`，`create`为`true`时新建该对象
+    ///
+    /// 打开已有的对象时，创建者可能尚未设置其大小，此时映射的内存在访问时会引发`SIGBUS`；
+    /// 因此先等待对象达到结构体的大小，重试若干次后仍未达到时返回`NotifyError::Os(EAGAIN)`。
+    pub(crate) fn map(name: &CString, create: bool) -> Result<Self, NotifyError> {
+        let flags = if create {
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC
+        } else {
+            libc::O_RDWR | libc::O_CLOEXEC
+        };
+        let fd = unsafe { libc::shm_open(name.as_ptr(), flags, 0o600 as libc::mode_t) };
+        if fd < 0 {
+            return Err(errno());
+        }
+        let len = core::mem::size_of::<T>();
+        let sized = if create {
+            if unsafe { libc::ftruncate(fd, len as libc::off_t) } == 0 {
+                Ok(())
+            } else {
+                Err(errno())
+            }
+        } else {
+            wait_size(fd, len)
+        };
+        let ptr = if sized.is_ok() {
+            unsafe {
+                libc::mmap(
+                    core::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    fd,
+                    0,
+                )
+            }
+        } else {
+            libc::MAP_FAILED
+        };
+        let res = match sized {
+            Err(err) => Err(err),
+            Ok(()) if ptr == libc::MAP_FAILED => Err(errno()),
+            Ok(()) => Ok(()),
+        };
+        unsafe { libc::close(fd) };
+        if let Err(err) = res {
+            if create {
+                unsafe { libc::shm_unlink(name.as_ptr()) };
+            }
+            return Err(err);
+        }
+        Ok(Self {
+            ptr: NonNull::new(ptr as *mut T).unwrap(),
+            _marker: PhantomData,
+        })
+    }
+
+    pub(crate) fn get(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> Drop for Mapping<T> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(
+                self.ptr.as_ptr() as *mut libc::c_void,
+                core::mem::size_of::<T>(),
+            )
+        };
+    }
+}
+
+fn errno() -> NotifyError {
+    NotifyError::Os(unsafe { *libc::__errno_location() })
+}
+
+/// 等待`fd`所指的对象的大小达到`len`
+fn wait_size(fd: libc::c_int, len: usize) -> Result<(), NotifyError> {
+    for _ in 0..SIZE_RETRIES {
+        let mut stat: libc::stat = unsafe { core::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+            return Err(errno());
+        }
+        if stat.st_size as usize >= len {
+            return Ok(());
+        }
+        unsafe { libc::sched_yield() };
+    }
+    Err(NotifyError::Os(libc::EAGAIN))
+}
+
+/// 删除共享内存对象`name`
+pub(crate) fn unlink(name: &CString) {
+    unsafe { libc::shm_unlink(name.as_ptr()) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_map_unsized() {
+        let name = name("test", unsafe { libc::getpid() } as u64, 0x324);
+        // 模拟尚未设置大小的创建者
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+                0o600 as libc::mode_t,
+            )
+        };
+        assert!(fd >= 0);
+        assert_eq!(
+            Mapping::<AtomicU64>::map(&name, false).err(),
+            Some(NotifyError::Os(libc::EAGAIN))
+        );
+        assert_eq!(unsafe { libc::ftruncate(fd, 8) }, 0);
+        unsafe { libc::close(fd) };
+        let mapping = Mapping::<AtomicU64>::map(&name, false).unwrap();
+        assert_eq!(mapping.get().load(core::sync::atomic::Ordering::Relaxed), 0);
+        unlink(&name);
+        assert!(Mapping::<AtomicU64>::map(&name, false).is_err());
+    }
+}
@@ -2,159 +2,662 @@
 //!
 //! 必须配合tokio运行时
 
-use crate::interface::NotificationIf;
-use alloc::{boxed::Box, collections::btree_map::BTreeMap, vec::Vec};
+use crate::{
+    bitmap::IdBitmap,
+    interface::{Delivery, NotificationIf, NotifyError, ProcessRef, WakeReason},
+    owner::OwnerInfo,
+    set::Priority,
+    signal_slot,
+    sync::{CachePadded, SpinMutex},
+};
+use alloc::vec::Vec;
 use core::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    cell::RefCell,
+    hint, mem, ptr,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll, Waker},
 };
-use futures::stream::{StreamExt, iter};
 use lazyinit::LazyInit;
 use signal_hook_tokio::{Signals, SignalsInfo};
 
-/// 使用信号的通知机制
-pub struct SignalNotification;
-
-struct SignalsInfoWrapper {
-    used: AtomicBool,
-    info: UnsafeCell<Option<SignalsInfo>>,
-}
+extern crate std;
 
-unsafe impl Sync for SignalsInfoWrapper {}
+/// 每个信号的状态，见[`signal_slot::SignalSlot`]
+type SignalSlot = signal_slot::SignalSlot<SignalsInfo>;
 
-/// 用于本模块的信号数量
-static SIG_NUM: LazyInit<usize> = LazyInit::new();
-// const USED_CAPABILITY: usize = 65;
+/// 使用信号的通知机制
+pub struct SignalNotification;
 
 /// 用于本模块的信号
 ///
-/// Linux下的[SIGRTMIN, SIGRTMAX]（[34, 64]）
+/// 由[`sigrt::policy`](crate::sigrt::policy)给出的信号中去掉被排除的信号，按编号排序
 static SIGNALS: LazyInit<Vec<u32>> = LazyInit::new();
 
-/// 每个信号的占用情况及接收情况。
+/// 每个信号的接收情况，Vec的index对应信号编号
 ///
-/// - Vec的index对应信号编号
-/// - Some(SignalsInfo)代表该信号目前被占用
-/// - None代表该信号目前未被占用
-static USED: LazyInit<Vec<SignalsInfoWrapper>> = LazyInit::new();
+/// 每个信号的状态各占一个缓存行，等待与通知不同信号的线程之间不会伪共享。
+static USED: LazyInit<Vec<CachePadded<SignalSlot>>> = LazyInit::new();
+
+/// 信号的占用情况，位图的index对应信号在`SIGNALS`中的index
+static ALLOCATOR: LazyInit<IdBitmap> = LazyInit::new();
+
+/// 由`set_excluded_signals`设置的不使用的信号，`None`表示检测已被其他代码使用的信号
+static EXCLUSIONS: SpinMutex<Option<Vec<u32>>> = SpinMutex::new(None);
+
+/// 线程的本地信号缓存，见[`SignalNotification::set_thread_cache`]
+struct ThreadCache {
+    /// 已在`ALLOCATOR`中被占用、但未被申请的信号在`SIGNALS`中的index
+    indices: Vec<usize>,
+    /// 缓存的容量，为0时不缓存
+    capacity: usize,
+}
+
+impl Drop for ThreadCache {
+    /// 线程退出时将缓存的信号归还全局的池
+    fn drop(&mut self) {
+        if !self.indices.is_empty() {
+            ALLOCATOR.release_batch(&self.indices);
+        }
+    }
+}
 
-/// 下一个分配的信号在`SIGNALS`中的index；
-static NEXT: AtomicUsize = AtomicUsize::new(0);
+std::thread_local! {
+    static CACHE: RefCell<ThreadCache> = const {
+        RefCell::new(ThreadCache {
+            indices: Vec::new(),
+            capacity: 0,
+        })
+    };
+}
+
+/// 模块未初始化
+const MODULE_UNINIT: u8 = 0;
+/// 模块正在初始化
+const MODULE_INITING: u8 = 1;
+/// 模块已初始化
+const MODULE_INIT: u8 = 2;
 
-/// 模块是否初始化
-static IS_INIT: AtomicBool = AtomicBool::new(false);
+/// 模块的初始化状态
+static INIT_STATE: AtomicU8 = AtomicU8::new(MODULE_UNINIT);
 
 impl NotificationIf for SignalNotification {
-    /// id即为分配的信号编号，取值区间[34, 64]
+    /// id的低8位为分配的信号编号，取值在`sigrt::rt_range`内，其上的位为信号的代数
     fn new_id() -> Option<u64> {
-        if !IS_INIT.load(Ordering::Acquire) {
-            Self::init();
+        Self::alloc(None)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若信号在等待期间被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        Self::ensure_init();
+
+        let (signal, epoch) = Self::split(id);
+        match Self::slot(signal) {
+            Some(slot) => slot.poll_epoch(epoch, cx, true).map(|notified| {
+                if notified {
+                    WakeReason::NOTIFIED
+                } else {
+                    WakeReason::Shutdown
+                }
+            }),
+            None => Poll::Ready(WakeReason::Shutdown),
         }
+    }
 
-        // 求余操作的除数
-        let mod_: usize = SIG_NUM.next_power_of_two();
-        // 代替求余的与操作的mask
-        let mask: usize = mod_ - 1;
+    fn register_waker(id: u64, waker: &Waker) {
+        Self::ensure_init();
 
-        let mut curr_next = NEXT.fetch_add(1, Ordering::AcqRel);
-        for _ in 0..mod_ {
-            let index = curr_next & mask;
-            if index >= *SIG_NUM {
-                continue;
+        let (signal, epoch) = Self::split(id);
+        let mut cx = Context::from_waker(waker);
+        let ready = match Self::slot(signal) {
+            Some(slot) => slot.poll_epoch(epoch, &mut cx, false).is_ready(),
+            None => true,
+        };
+        if ready {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// 释放信号，并唤醒正在等待该信号的协程
+    ///
+    /// 若信号已被释放（包括已被释放后重新申请），则忽略本次释放。
+    unsafe fn release_id(id: u64) {
+        Self::ensure_init();
+
+        let (signal, epoch) = Self::split(id);
+        let Ok(index) = SIGNALS.binary_search(&signal) else {
+            fail!(return, "release_id: 0x{:x} is not a signal id", id)
+        };
+        let released = USED[signal as usize].release(epoch, |owner| {
+            crate::owner::check_release(id, owner, Self::now_ns());
+        });
+        if !released {
+            #[cfg(feature = "log")]
+            log::warn!("release_id: stale id {:#x} ignored", id);
+            return;
+        }
+        if Self::cache_release(index) {
+            return;
+        }
+        let res = ALLOCATOR.release(index);
+        check!(res, "release_id: signal {} is not allocated", signal);
+    }
+
+    /// 若`process`为本进程且信号仍被占用，则不发送信号，直接唤醒等待者
+    fn notify(process: u64, id: u64) {
+        if Self::notify_local(process, id) {
+            return;
+        }
+        let (signal, _) = Self::split(id);
+        let res = unsafe { libc::kill(process as libc::pid_t, signal as libc::c_int) };
+        check!(
+            res == 0,
+            "notify: kill({}, {}) failed with errno {}",
+            process,
+            signal,
+            unsafe { *libc::__errno_location() }
+        );
+    }
+}
+
+/// 可靠投递在发送队列已满时的最大重试次数
+const RELIABLE_RETRIES: usize = 64;
+
+impl SignalNotification {
+    /// 以空信号检查进程`process`是否存在且可被本进程发送信号，不发送信号
+    pub fn can_notify(process: u64, _id: u64) -> Result<(), NotifyError> {
+        if unsafe { libc::kill(process as libc::pid_t, 0) } == 0 {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(unsafe { *libc::__errno_location() }))
+        }
+    }
+
+    /// 以指定的投递类别发送信号
+    ///
+    /// 使用`sigqueue`发送实时信号，并将`Delivery::tag`作为信号的附加值。
+    /// 内核的实时信号队列已满时，尽力投递直接丢弃该通知，可靠投递让出CPU后重试，重试`RELIABLE_RETRIES`次后返回`NotifyError::Overflow`。
+    pub fn notify_with(process: u64, id: u64, delivery: Delivery) -> Result<(), NotifyError> {
+        if Self::notify_local(process, id) {
+            return Ok(());
+        }
+        let value = libc::sigval {
+            sival_ptr: delivery.tag() as *mut libc::c_void,
+        };
+        let retries = match delivery {
+            Delivery::BestEffort => 0,
+            Delivery::Reliable => RELIABLE_RETRIES,
+        };
+        let (signal, _) = Self::split(id);
+        for attempt in 0..=retries {
+            let res =
+                unsafe { libc::sigqueue(process as libc::pid_t, signal as libc::c_int, value) };
+            if res == 0 {
+                return Ok(());
             }
-            if USED[SIGNALS[index] as usize]
-                .used
-                .swap(true, Ordering::AcqRel)
-            {
-                // 该信号已被占用
-                curr_next = NEXT.fetch_add(1, Ordering::AcqRel);
-            } else {
-                // 该信号未被占用
-                unsafe {
-                    (&mut *(USED[SIGNALS[index] as usize].info.get()))
-                        .replace(Signals::new([SIGNALS[index] as i32]).unwrap())
-                };
-                return Some(SIGNALS[index] as u64);
+            match unsafe { *libc::__errno_location() } {
+                // 接收方的信号队列已满，通知与尚未被接收的通知合并
+                libc::EAGAIN if delivery == Delivery::BestEffort => {
+                    crate::trace::coalesced(
+                        id | ((crate::kind::NotificationKind::Signal.tag() as u64) << 56),
+                    );
+                    return Ok(());
+                }
+                libc::EAGAIN if attempt < retries => unsafe {
+                    libc::sched_yield();
+                },
+                libc::EAGAIN => {}
+                errno => return Err(NotifyError::Os(errno)),
             }
         }
-        None
+        Err(NotifyError::Overflow)
     }
 
-    async fn wait_on(id: u64) {
-        if !IS_INIT.load(Ordering::Acquire) {
-            Self::init();
+    /// 向进程、进程组或线程发送信号
+    ///
+    /// 分别使用`kill`、`killpg`与`tgkill`。
+    pub fn notify_to(target: ProcessRef, id: u64) -> Result<(), NotifyError> {
+        if let ProcessRef::Process(pid) = target {
+            if Self::notify_local(pid, id) {
+                return Ok(());
+            }
         }
+        let (signal, _) = Self::split(id);
+        let signal = signal as libc::c_int;
+        let res = match target {
+            ProcessRef::Process(pid) => unsafe { libc::kill(pid as libc::pid_t, signal) },
+            ProcessRef::Group(pgid) => unsafe { libc::killpg(pgid as libc::pid_t, signal) },
+            ProcessRef::Thread { pid, tid } => unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    pid as libc::pid_t,
+                    tid as libc::pid_t,
+                    signal,
+                ) as libc::c_int
+            },
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(unsafe { *libc::__errno_location() }))
+        }
+    }
 
-        assert!(SIGNALS.contains(&(id as u32)));
-        unsafe { &mut *(USED[id as usize].info.get()) }
-            .as_mut()
-            .unwrap()
-            .next()
-            .await;
+    /// 申请一个信号，并为其记录标签，标签可通过`owner`查询
+    pub fn new_id_with_label(label: &'static str) -> Option<u64> {
+        Self::alloc(Some(label))
     }
 
-    unsafe fn release_id(id: u64) {
-        if !IS_INIT.load(Ordering::Acquire) {
-            Self::init();
+    /// 查询被占用的信号的占用者信息，若信号未被占用或已被重新申请则返回`None`
+    pub fn owner(id: u64) -> Option<OwnerInfo> {
+        Self::ensure_init();
+
+        let (signal, epoch) = Self::split(id);
+        let slot = Self::slot(signal)?;
+        slot.owner().filter(|_| slot.epoch() == epoch)
+    }
+
+    /// 列出所有被占用的信号及其占用者信息
+    pub fn owners() -> Vec<(u64, OwnerInfo)> {
+        Self::ensure_init();
+
+        SIGNALS
+            .iter()
+            .filter_map(|&signal| {
+                let slot = &USED[signal as usize];
+                slot.owner()
+                    .map(|owner| (Self::join(signal, slot.epoch()), owner))
+            })
+            .collect()
+    }
+
+    /// 占用指定的信号，返回其id；信号不是本模块使用的信号或已被占用时返回`None`
+    ///
+    /// 用于与已约定使用某个信号编号的代码互通。该函数需要在tokio运行时内部调用，因为其会同时开始信号的接收。
+    pub fn new_id_with_signal(signal: u32) -> Option<u64> {
+        Self::ensure_init();
+
+        let index = SIGNALS.binary_search(&signal).ok()?;
+        if !ALLOCATOR.claim(index) && !Self::cache_take(index) {
+            return None;
         }
+        Self::start(index, None)
+    }
 
-        assert!(SIGNALS.contains(&(id as u32)));
-        unsafe { &mut *(USED[id as usize].info.get()) }.take();
-        let res = USED[id as usize].used.swap(false, Ordering::AcqRel);
-        assert!(res); // 释放某id前，其必须已被占用
+    /// 尚未被占用的信号的数量，包括调用线程的本地缓存中的信号，不包括被`signalfd`占用的信号
+    pub fn remaining_capacity() -> usize {
+        Self::ensure_init();
+
+        ALLOCATOR.available() + Self::cached()
     }
 
-    fn notify(process: u64, id: u64) {
-        let res = unsafe { libc::kill(process as libc::pid_t, id as libc::c_int) };
-        assert!(res == 0);
+    /// 设置调用线程的本地信号缓存的容量，0表示不缓存（默认）
+    ///
+    /// 开启后，调用线程申请信号时先从缓存中取，缓存为空时从全局的池中一次补充至多一半容量的信号；
+    /// 在调用线程中释放的信号先放回缓存，缓存已满时将一半归还全局的池。频繁申请、释放短期信号的线程因此很少访问全局的位图。
+    ///
+    /// 缓存中的信号对其他线程不可用，`new_id_with_priority`也不会选取它们；缓存在线程退出时归还全局的池，
+    /// 也可以以[`flush_thread_cache`](Self::flush_thread_cache)提前归还。缩小容量时归还多出的信号。
+    pub fn set_thread_cache(capacity: usize) {
+        let excess: Vec<usize> = CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache.capacity = capacity;
+            let keep = cache.indices.len().min(capacity);
+            cache.indices.split_off(keep)
+        });
+        if !excess.is_empty() {
+            ALLOCATOR.release_batch(&excess);
+        }
+    }
+
+    /// 将调用线程的本地缓存中的信号全部归还全局的池，不改变缓存的容量
+    pub fn flush_thread_cache() {
+        let indices = CACHE.with(|cache| mem::take(&mut cache.borrow_mut().indices));
+        if !indices.is_empty() {
+            ALLOCATOR.release_batch(&indices);
+        }
+    }
+
+    /// 调用线程的本地缓存中的信号数量；线程正在退出时为0
+    fn cached() -> usize {
+        CACHE
+            .try_with(|cache| cache.borrow().indices.len())
+            .unwrap_or(0)
+    }
+
+    /// 从调用线程的本地缓存中取一个信号，缓存为空时从全局的池中补充；未开启缓存时返回`None`
+    fn cache_alloc() -> Option<usize> {
+        CACHE
+            .try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                if cache.capacity == 0 {
+                    return None;
+                }
+                if cache.indices.is_empty() {
+                    let batch = (cache.capacity + 1) / 2;
+                    ALLOCATOR.alloc_batch(batch, &mut cache.indices);
+                }
+                cache.indices.pop()
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// 将已停止接收的信号放回调用线程的本地缓存，缓存已满时先将一半归还全局的池；未开启缓存时返回`false`
+    fn cache_release(index: usize) -> bool {
+        CACHE
+            .try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                if cache.capacity == 0 {
+                    return false;
+                }
+                if cache.indices.len() >= cache.capacity {
+                    let half = cache.indices.len() / 2;
+                    let returned: Vec<usize> = cache.indices.drain(..half.max(1)).collect();
+                    ALLOCATOR.release_batch(&returned);
+                }
+                cache.indices.push(index);
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// 从调用线程的本地缓存中取出指定的信号，其不在缓存中时返回`false`
+    fn cache_take(index: usize) -> bool {
+        CACHE
+            .try_with(|cache| {
+                let mut cache = cache.borrow_mut();
+                let position = cache.indices.iter().position(|&cached| cached == index);
+                position.map(|position| cache.indices.swap_remove(position))
+            })
+            .ok()
+            .flatten()
+            .is_some()
+    }
+
+    /// 按优先级申请一个信号，返回其id：优先级越高，信号编号越小
+    ///
+    /// 多个实时信号同时待处理时，内核先投递编号较小的信号，因此高优先级的通知先被接收。
+    /// `Priority::High`占用编号最小的空闲信号，`Priority::Low`占用编号最大的空闲信号，`Priority::Normal`与`new_id`相同。
+    pub fn new_id_with_priority(priority: Priority) -> Option<u64> {
+        Self::ensure_init();
+
+        let index = match priority {
+            Priority::High => (0..SIGNALS.len()).find(|&index| ALLOCATOR.claim(index))?,
+            Priority::Low => (0..SIGNALS.len())
+                .rev()
+                .find(|&index| ALLOCATOR.claim(index))?,
+            Priority::Normal => ALLOCATOR.alloc()?,
+        };
+        Self::start(index, None)
+    }
+
+    fn alloc(label: Option<&'static str>) -> Option<u64> {
+        Self::ensure_init();
+
+        let index = Self::cache_alloc().or_else(|| ALLOCATOR.alloc())?;
+        Self::start(index, label)
+    }
+
+    /// 开始接收已在`ALLOCATOR`中被占用的信号，返回其id
+    ///
+    /// 无法注册信号的接收或信号的状态不一致时（启用`no-panic`时）归还该信号并返回`None`。
+    fn start(index: usize, label: Option<&'static str>) -> Option<u64> {
+        let signal = SIGNALS[index];
+        let owner = OwnerInfo {
+            tid: unsafe { libc::gettid() } as u64,
+            allocated_at_ns: Self::now_ns(),
+            label,
+        };
+        let info = match Signals::new([signal as i32]) {
+            Ok(info) => info,
+            Err(err) => {
+                ALLOCATOR.release(index);
+                fail!(
+                    return None,
+                    "new_id: failed to receive signal {}: {}",
+                    signal,
+                    err
+                )
+            }
+        };
+        let Some(epoch) = USED[signal as usize].start(info, owner) else {
+            ALLOCATOR.release(index);
+            return None;
+        };
+        Some(Self::join(signal, epoch))
+    }
+
+    /// 为进程检查点暂停所有被占用信号的接收，并关闭接收所用的文件描述符
+    ///
+    /// 暂停期间，在这些信号上的等待保持挂起，不会因接收流被关闭而结束；暂停的信号仍可被释放。
+    ///
+    /// 调用时不应有正在进行的`new_id`，否则其申请的信号可能不会被暂停。
+    pub fn prepare_checkpoint() {
+        Self::ensure_init();
+
+        for &signal in SIGNALS.iter() {
+            USED[signal as usize].suspend();
+        }
+    }
+
+    /// 在从检查点恢复之后重新开始所有暂停信号的接收，需要在tokio运行时内部调用
+    ///
+    /// 暂停期间到达的信号无法被接收，因此每个信号都会被视为收到了一个通知，以唤醒其等待者重新检查状态。
+    /// 无法重新开始接收的信号（启用`no-panic`时）保持暂停，可再次调用该函数重试。
+    pub fn resume_after_restore() {
+        Self::ensure_init();
+
+        for &signal in SIGNALS.iter() {
+            USED[signal as usize].resume(|| match Signals::new([signal as i32]) {
+                Ok(info) => Some(info),
+                Err(err) => fail!(None, "resume: failed to receive signal {}: {}", signal, err),
+            });
+        }
+    }
+
+    /// 实时信号中不被本模块使用的信号，包括被策略保留的与被检测为已被其他代码使用的信号
+    pub fn excluded_signals() -> Vec<u32> {
+        Self::ensure_init();
+
+        crate::sigrt::rt_range()
+            .filter(|signal| SIGNALS.binary_search(signal).is_err())
+            .collect()
+    }
+
+    /// 以给定的信号代替检测结果，作为不使用的信号；`None`恢复检测
+    ///
+    /// 模块在首次使用时初始化，因此需要在申请第一个信号之前调用，否则不生效并返回`false`。
+    pub fn set_excluded_signals(signals: Option<&[u32]>) -> bool {
+        if INIT_STATE.load(Ordering::Acquire) != MODULE_UNINIT {
+            return false;
+        }
+        *EXCLUSIONS.lock() = signals.map(<[u32]>::to_vec);
+        true
+    }
+
+    /// 信号是否已被其他代码使用，即其处理方式已不是默认的
+    ///
+    /// glibc内部、其他crate或嵌入的Go、JVM等运行时可能已为部分实时信号设置了处理函数或将其忽略，
+    /// 在这些信号上发送通知会干扰它们。
+    fn claimed(signal: i32) -> bool {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        if unsafe { libc::sigaction(signal, ptr::null(), &mut action) } != 0 {
+            return true;
+        }
+        action.sa_sigaction != libc::SIG_DFL
+    }
+
+    /// 本模块使用的所有信号
+    #[cfg(feature = "signalfd")]
+    pub(crate) fn signals() -> Vec<u32> {
+        Self::ensure_init();
+        SIGNALS.clone()
+    }
+
+    /// 为signalfd占用一个信号，返回其编号；该信号在被归还之前不会被本模块分配
+    #[cfg(feature = "signalfd")]
+    pub(crate) fn reserve_raw() -> Option<u32> {
+        Self::ensure_init();
+        ALLOCATOR.alloc().map(|index| SIGNALS[index])
+    }
+
+    /// 归还由`reserve_raw`占用的信号
+    #[cfg(feature = "signalfd")]
+    pub(crate) fn release_raw(signal: u32) {
+        let Ok(index) = SIGNALS.binary_search(&signal) else {
+            fail!(
+                return,
+                "release_raw: {} is not a signal used by this module",
+                signal
+            )
+        };
+        let res = ALLOCATOR.release(index);
+        check!(res, "release_raw: signal {} is not reserved", signal);
+    }
+
+    /// 单调时钟的当前时刻（纳秒）
+    fn now_ns() -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    }
+
+    /// 一次轮询`ids`中的所有信号，返回有通知（或已被释放）的id，按在`ids`中的顺序，每个id消费一个通知
+    ///
+    /// 所有信号都没有通知时返回空的`Vec`，并在其中任一信号收到通知时唤醒`cx`的waker。
+    /// 与依次`poll_wait`相比，调用者可在一次唤醒中处理所有已到达的信号。
+    pub fn poll_batch(ids: &[u64], cx: &mut Context<'_>) -> Vec<u64> {
+        ids.iter()
+            .copied()
+            .filter(|&id| Self::poll_wait(id, cx).is_ready())
+            .collect()
+    }
+
+    /// 本进程内的通知：若`process`为本进程且`id`仍被占用，则直接记录一个通知并唤醒等待者，返回`true`
+    ///
+    /// 这样省去了信号的发送与处理，且不会与其他未被消费的通知合并。
+    /// 信号未被占用（或`id`已过期）时返回`false`，由调用者照常发送信号。
+    fn notify_local(process: u64, id: u64) -> bool {
+        if process != unsafe { libc::getpid() } as u64 {
+            return false;
+        }
+        Self::ensure_init();
+
+        let (signal, epoch) = Self::split(id);
+        if SIGNALS.binary_search(&signal).is_err() {
+            return false;
+        }
+        USED[signal as usize].notify_local(epoch)
+    }
+
+    /// 将id拆分为信号编号与代数
+    fn split(id: u64) -> (u32, u32) {
+        ((id & 0xFF) as u32, (id >> 8) as u32)
+    }
+
+    /// 由信号编号与代数组成id
+    fn join(signal: u32, epoch: u32) -> u64 {
+        ((epoch as u64) << 8) | signal as u64
+    }
+
+    /// 获取信号的状态
+    ///
+    /// 在已被释放的信号上等待是允许的（等待会立即结束），但信号必须是本模块使用的信号，
+    /// 否则panic（启用`no-panic`时返回`None`）。
+    fn slot(signal: u32) -> Option<&'static SignalSlot> {
+        if SIGNALS.binary_search(&signal).is_err() {
+            fail!(return None, "signal {} is not used by this module", signal)
+        }
+        Some(&USED[signal as usize])
+    }
+
+    /// 确保模块已初始化，可被多个线程同时调用
+    fn ensure_init() {
+        match INIT_STATE.compare_exchange(
+            MODULE_UNINIT,
+            MODULE_INITING,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                Self::init();
+                INIT_STATE.store(MODULE_INIT, Ordering::Release);
+            }
+            Err(_) => {
+                while INIT_STATE.load(Ordering::Acquire) != MODULE_INIT {
+                    hint::spin_loop();
+                }
+            }
+        }
     }
-}
 
-impl SignalNotification {
     fn init() {
-        assert!(!IS_INIT.swap(true, Ordering::AcqRel));
         #[cfg(feature = "log")]
         log::info!("SignalNotification init");
-        let mut signum: usize = 0;
+        let exclusions = EXCLUSIONS.lock().clone();
         let mut signals: Vec<u32> = Vec::new();
-        for i in libc::SIGRTMIN()..=libc::SIGRTMAX() {
-            if ![
-                0x3f, // sender panic，libc::kill返回非0
-                0x40, // sender panic，libc::kill返回非0
-            ]
-            .contains(&i)
-            {
-                signals.push(i as u32);
-                signum += 1;
+        for signal in crate::sigrt::signals() {
+            let excluded = match &exclusions {
+                Some(exclusions) => exclusions.contains(&signal),
+                None => Self::claimed(signal as i32),
+            };
+            if excluded {
+                #[cfg(feature = "log")]
+                log::info!("signal {} is excluded", signal);
+                continue;
             }
+            signals.push(signal);
         }
-        SIG_NUM.init_once(signum);
+        ALLOCATOR.init_once(IdBitmap::new(signals.len()));
 
         #[cfg(feature = "log")]
         log::info!("SIGNALS: {:?}", signals);
         SIGNALS.init_once(signals);
-        let mut used: Vec<SignalsInfoWrapper> = Vec::new();
+        let mut used: Vec<CachePadded<SignalSlot>> = Vec::new();
         for _ in 0..=libc::SIGRTMAX() {
-            used.push(SignalsInfoWrapper {
-                used: AtomicBool::new(false),
-                info: UnsafeCell::new(None),
-            });
+            used.push(CachePadded(SignalSlot::new()));
         }
         USED.init_once(used);
     }
 }
 
-mod tests {
-    use core::{ptr, task, time};
+#[cfg(test)]
+pub(crate) mod tests {
+    use core::{future::Future, ptr, time};
 
     // use super::*;
     use crate::interface::{Notification, NotificationIf};
-    use alloc::vec::Vec;
+    use alloc::{sync::Arc, vec::Vec};
 
     extern crate std;
 
+    /// 信号为进程级资源，使用信号的测试需串行执行
+    pub(crate) static SIGNAL_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// 记录被唤醒次数的waker
+    struct CountWaker(core::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, core::sync::atomic::Ordering::AcqRel);
+        }
+    }
+
     #[test]
+    #[ignore = "需要手动向测试进程发送信号"]
     fn test_signal_manual() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         use tokio::task::JoinHandle;
 
         tokio::runtime::Builder::new_current_thread()
@@ -182,8 +685,469 @@ mod tests {
             });
     }
 
+    #[test]
+    fn test_signal_register_waker() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal().unwrap();
+                let count = Arc::new(CountWaker(core::sync::atomic::AtomicUsize::new(0)));
+                let waker = core::task::Waker::from(count.clone());
+                Notification::register_waker(id, &waker);
+                assert_eq!(count.0.load(core::sync::atomic::Ordering::Acquire), 0);
+
+                Notification::notify(unsafe { libc::getpid() } as u64, id);
+                while count.0.load(core::sync::atomic::Ordering::Acquire) == 0 {
+                    tokio::time::sleep(time::Duration::from_millis(10)).await;
+                    Notification::register_waker(id, &waker);
+                }
+
+                // 通知未被register_waker消费
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_batch() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use super::SignalNotification;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let ids = [
+                    Notification::new_id_signal().unwrap(),
+                    Notification::new_id_signal().unwrap(),
+                    Notification::new_id_signal().unwrap(),
+                ];
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(SignalNotification::poll_batch(&ids, &mut cx).is_empty());
+
+                let pid = unsafe { libc::getpid() } as u64;
+                Notification::notify(pid, ids[2]);
+                Notification::notify(pid, ids[0]);
+                Notification::notify(pid, ids[0]);
+                assert_eq!(
+                    SignalNotification::poll_batch(&ids, &mut cx),
+                    [ids[0], ids[2]]
+                );
+                assert_eq!(SignalNotification::poll_batch(&ids, &mut cx), [ids[0]]);
+                assert!(SignalNotification::poll_batch(&ids, &mut cx).is_empty());
+                for id in ids {
+                    unsafe { Notification::release_id(id) };
+                }
+            });
+    }
+
+    #[test]
+    fn test_signal_thread_cache() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use super::{CACHE, SignalNotification};
+
+        fn cached() -> Vec<usize> {
+            CACHE.with(|cache| cache.borrow().indices.clone())
+        }
+
+        let leftover = std::thread::spawn(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    SignalNotification::set_thread_cache(4);
+                    // 一次补充两个信号，用掉其中一个
+                    let id = Notification::new_id_signal().unwrap();
+                    assert_eq!(cached().len(), 1);
+                    unsafe { Notification::release_id(id) };
+                    assert_eq!(cached().len(), 2);
+                    let reused = Notification::new_id_signal().unwrap();
+                    assert_eq!(reused & 0xFF, id & 0xFF);
+                    unsafe { Notification::release_id(reused) };
+
+                    // 指定编号申请缓存中的信号
+                    let signal = *cached().first().unwrap();
+                    let signal = super::SIGNALS[signal];
+                    let id = SignalNotification::new_id_with_signal(signal).unwrap();
+                    assert_eq!(cached().len(), 1);
+                    unsafe { SignalNotification::release_id(id) };
+                    assert_eq!(cached().len(), 2);
+
+                    SignalNotification::flush_thread_cache();
+                    assert!(cached().is_empty());
+                    let id = Notification::new_id_signal().unwrap();
+                    unsafe { Notification::release_id(id) };
+                    cached()
+                })
+        })
+        .join()
+        .unwrap();
+        // 线程退出时缓存已被归还
+        assert_eq!(leftover.len(), 2);
+        for index in leftover {
+            assert!(super::ALLOCATOR.claim(index));
+            assert!(super::ALLOCATOR.release(index));
+        }
+    }
+
+    #[test]
+    fn test_signal_release_while_waiting() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal().unwrap();
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                tokio::time::sleep(time::Duration::from_millis(50)).await;
+                assert!(!waiter.is_finished());
+                unsafe { Notification::release_id(id) };
+                tokio::time::timeout(time::Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            });
+    }
+
+    #[test]
+    fn test_signal_concurrent_lifecycle() {
+        const TASKS: usize = 8;
+        const ROUNDS: usize = 20;
+
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let tasks: Vec<_> = (0..TASKS)
+                    .map(|_| {
+                        tokio::spawn(async move {
+                            for _ in 0..ROUNDS {
+                                let id = loop {
+                                    match Notification::new_id_signal() {
+                                        Some(id) => break id,
+                                        None => tokio::task::yield_now().await,
+                                    }
+                                };
+                                let waiter = tokio::spawn(Notification::wait_on(id));
+                                Notification::notify(pid, id);
+                                tokio::time::timeout(time::Duration::from_secs(1), waiter)
+                                    .await
+                                    .unwrap()
+                                    .unwrap();
+                                unsafe { Notification::release_id(id) };
+                            }
+                        })
+                    })
+                    .collect();
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            });
+    }
+
+    #[test]
+    fn test_signal_cancel_safety() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_signal().unwrap();
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+
+                // 轮询一次后丢弃
+                let mut wait = Notification::wait_on(id);
+                assert!(core::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+                drop(wait);
+                Notification::notify(pid, id);
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+
+                // 通知到达后，未被轮询即丢弃
+                Notification::notify(pid, id);
+                tokio::time::sleep(time::Duration::from_millis(50)).await;
+                drop(Notification::wait_on(id));
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+
+                // 等待中多次因超时被取消
+                for _ in 0..3 {
+                    let res = tokio::time::timeout(
+                        time::Duration::from_millis(10),
+                        Notification::wait_on(id),
+                    )
+                    .await;
+                    assert!(res.is_err());
+                }
+                Notification::notify(pid, id);
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_exclusions() {
+        use super::SignalNotification;
+
+        // Rust的运行时将SIGPIPE设为忽略
+        assert!(SignalNotification::claimed(libc::SIGPIPE));
+        let excluded = SignalNotification::excluded_signals();
+        assert!(excluded.contains(&0x3f) && excluded.contains(&0x40));
+        assert!(
+            excluded
+                .iter()
+                .all(|&signal| SignalNotification::new_id_with_signal(signal).is_none())
+        );
+        // 模块已初始化，不再生效
+        assert!(!SignalNotification::set_excluded_signals(Some(&[])));
+    }
+
+    #[test]
+    fn test_signal_owner() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal_with_label("test_owner").unwrap();
+                let owner = Notification::owner(id).unwrap();
+                assert_eq!(owner.tid, unsafe { libc::gettid() } as u64);
+                assert_eq!(owner.label, Some("test_owner"));
+                assert!(Notification::owners().contains(&(id, owner)));
+
+                let mut dump = alloc::string::String::new();
+                Notification::dump_owners(&mut dump).unwrap();
+                assert!(dump.contains("label=test_owner"));
+
+                unsafe { Notification::release_id(id) };
+                assert_eq!(Notification::owner(id), None);
+            });
+    }
+
+    #[test]
+    fn test_signal_stale_epoch() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let stale = Notification::new_id_signal().unwrap();
+                unsafe { Notification::release_id(stale) };
+                // 申请是轮转的，反复申请直到重新得到同一信号
+                let id = loop {
+                    let id = Notification::new_id_signal().unwrap();
+                    if id & 0xFF == stale & 0xFF {
+                        break id;
+                    }
+                    unsafe { Notification::release_id(id) };
+                };
+                assert_ne!(id, stale);
+
+                // 旧id上的操作不影响新占用者
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                Notification::notify(pid, id);
+                tokio::time::sleep(time::Duration::from_millis(50)).await;
+                assert!(Notification::poll_wait(stale, &mut cx).is_ready());
+                unsafe { Notification::release_id(stale) };
+                assert_eq!(Notification::owner(stale), None);
+                assert!(Notification::owner(id).is_some());
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_delivery_classes() {
+        use crate::interface::{Delivery, NotifyError};
+
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal().unwrap();
+                let pid = unsafe { libc::getpid() } as u64;
+                for delivery in [Delivery::Reliable, Delivery::BestEffort] {
+                    Notification::notify_with(pid, id, delivery).unwrap();
+                    tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                // 不存在的进程
+                assert_eq!(
+                    Notification::notify_with(i32::MAX as u64, id, Delivery::Reliable),
+                    Err(NotifyError::Os(libc::ESRCH))
+                );
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_local_fast_path() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_signal().unwrap();
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                tokio::task::yield_now().await;
+                Notification::notify(pid, id);
+                tokio::time::timeout(time::Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                // 本进程内的通知不经过信号，因此不会被合并
+                for _ in 0..3 {
+                    Notification::notify(pid, id);
+                }
+                for _ in 0..3 {
+                    assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                }
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_notify_targets() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_signal().unwrap();
+                let pid = unsafe { libc::getpid() } as u64;
+                let tid = unsafe { libc::gettid() } as u64;
+                Notification::notify_thread(pid, tid, id).unwrap();
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                let failures = Notification::notify_batch(&[
+                    (pid, id),
+                    (i32::MAX as u64, id),
+                    (pid, id & !0xFF00_0000_0000_0000),
+                ]);
+                assert_eq!(
+                    failures,
+                    alloc::vec![
+                        (1, crate::interface::NotifyError::Os(libc::ESRCH)),
+                        (2, crate::interface::NotifyError::Unsupported)
+                    ]
+                );
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                // 测试进程的进程组中还有其他进程，只检查错误路径
+                assert_eq!(
+                    Notification::notify_pgroup(i32::MAX as u64, id),
+                    Err(crate::interface::NotifyError::Os(libc::ESRCH))
+                );
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[test]
+    fn test_signal_checkpoint() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_signal().unwrap();
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                tokio::time::sleep(time::Duration::from_millis(10)).await;
+
+                assert!(!Notification::checkpoint_blockers().contains(&id));
+                // 其他测试可能同时持有非信号通知源，因此直接暂停信号通知源
+                super::SignalNotification::prepare_checkpoint();
+                tokio::time::sleep(time::Duration::from_millis(50)).await;
+                assert!(!waiter.is_finished());
+
+                Notification::resume_after_restore();
+                tokio::time::timeout(time::Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                Notification::notify(pid, id);
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+
+                assert!(Notification::revalidate_peers(&[pid]).is_empty());
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_signal_checkpoint_blocked() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_signal().unwrap();
+                let mock = Notification::new_id_mock().unwrap();
+                // 存在非信号通知源时不暂停任何通知源
+                let blockers = Notification::prepare_checkpoint().unwrap_err();
+                assert!(blockers.contains(&mock));
+                assert!(!blockers.contains(&id));
+                Notification::notify(pid, id);
+                tokio::time::timeout(time::Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                unsafe { Notification::release_id(mock) };
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
     #[test]
     fn test_signal_wakeup() {
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         use tokio::task::JoinHandle;
         const SIGNAL_HIGH8: u64 = 0x01 << 56;
 
@@ -191,15 +1155,8 @@ mod tests {
         // while let Some(id) = Notification::new_id_signal() {
         //     ids.push(id);
         // }
-        for i in libc::SIGRTMIN()..=libc::SIGRTMAX() {
-            if ![
-                0x3f, // sender panic，libc::kill返回非0
-                0x40, // sender panic，libc::kill返回非0
-            ]
-            .contains(&i)
-            {
-                ids.push((i as u64) | SIGNAL_HIGH8);
-            }
+        for signal in crate::sigrt::policy().signals() {
+            ids.push((signal as u64) | SIGNAL_HIGH8);
         }
 
         for id in &ids {
@@ -220,14 +1177,19 @@ mod tests {
                         while let Some(id) = Notification::new_id_signal() {
                             actual_ids.push(id);
                         }
+                        // 信号在父进程中可能已被申请过，id中的代数不一定为0，只比较信号编号
+                        let signals: Vec<u64> = actual_ids
+                            .iter()
+                            .map(|id| id & 0xFF | SIGNAL_HIGH8)
+                            .collect();
                         ids_c.iter().for_each(|id| {
-                            assert!(actual_ids.contains(id));
+                            assert!(signals.contains(id));
                         });
-                        actual_ids.iter().for_each(|id| {
+                        signals.iter().for_each(|id| {
                             assert!(ids_c.contains(id));
                         });
                         let mut handles: Vec<JoinHandle<()>> = Vec::new();
-                        for id in ids_c {
+                        for id in actual_ids {
                             handles.push(tokio::spawn(async move {
                                 std::println!("before block on id {:#018x}", id);
                                 Notification::wait_on(id).await;
@@ -236,7 +1198,7 @@ mod tests {
                         }
 
                         for handle in handles {
-                            handle.await;
+                            handle.await.unwrap();
                         }
                     });
             }
@@ -249,9 +1211,33 @@ mod tests {
                 }
 
                 unsafe {
-                    libc::waitpid(child as i32, ptr::null_mut(), 0);
+                    libc::waitpid(child, ptr::null_mut(), 0);
                 }
             }
         }
     }
+
+    #[test]
+    fn test_signal_priority() {
+        use crate::set::Priority;
+
+        let _guard = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let high = Notification::new_id_signal_with_priority(Priority::High).unwrap();
+                let low = Notification::new_id_signal_with_priority(Priority::Low).unwrap();
+                // 高优先级的通知源使用编号较小的实时信号
+                assert!(high & 0xFF < low & 0xFF);
+                assert_eq!(Notification::priority(high), Priority::High);
+                assert_eq!(Notification::priority(low), Priority::Low);
+                unsafe {
+                    Notification::release_id(high);
+                    Notification::release_id(low);
+                }
+                assert_eq!(Notification::priority(high), Priority::Normal);
+            });
+    }
 }
@@ -0,0 +1,362 @@
+//! 信号通知机制中每个信号的共享状态
+//!
+//! 从[`signal`](crate::signal)中分离出来，不依赖具体的信号接收流，从而可以在`--cfg async_notification_loom`下
+//! 以模拟的接收流对申请、等待、本进程内通知与释放之间的并发进行模型检查（见本模块的`loom_tests`）。
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::stream::Stream;
+
+use crate::{
+    owner::OwnerInfo,
+    sync::{
+        AtomicWaker, Mutex,
+        atomic::{AtomicU8, AtomicU32, AtomicUsize, Ordering},
+    },
+};
+
+/// 信号未被占用
+pub(crate) const SLOT_FREE: u8 = 0;
+/// 信号已被占用，正在开始接收
+pub(crate) const SLOT_INIT: u8 = 1;
+/// 信号已被占用，正在接收通知
+pub(crate) const SLOT_READY: u8 = 2;
+/// 信号正在被释放
+pub(crate) const SLOT_RELEASING: u8 = 3;
+/// 信号已被占用，但为了进程检查点暂停接收
+pub(crate) const SLOT_SUSPENDED: u8 = 4;
+
+/// 一次轮询最多从接收流中取出的通知数，避免信号持续到达时一直持有锁
+const BATCH: usize = 64;
+
+/// 每个信号的状态，`R`为信号的接收流
+///
+/// 状态转换：`SLOT_FREE` -> `SLOT_INIT` -> `SLOT_READY` -> `SLOT_RELEASING` -> `SLOT_FREE`，
+/// 其中前两步由[`SignalSlot::start`]完成，后两步由[`SignalSlot::release`]完成。
+///
+/// 此外，[`SignalSlot::suspend`]将`SLOT_READY`转换为`SLOT_SUSPENDED`，[`SignalSlot::resume`]将其转换回`SLOT_READY`。
+///
+/// 每次释放都使`epoch`加一，id中记录了申请时的`epoch`。信号被释放并重新申请后，旧id上的操作因`epoch`不匹配而被忽略，
+/// 不会消费或影响新占用者的通知。
+pub(crate) struct SignalSlot<R> {
+    state: AtomicU8,
+    /// 信号的代数，在释放时加一；对`epoch`的检查与修改均在持有`info`锁时进行
+    epoch: AtomicU32,
+    /// 已从`info`中取出、但尚未被`poll_wait`消费的通知数量
+    ///
+    /// `register_waker`在检查通知时可能从`info`中取出通知，此时将其记录于此，以免丢失。
+    pending: AtomicUsize,
+    /// 最近一次等待该信号的waker，在信号被释放时唤醒
+    waker: AtomicWaker,
+    /// 信号的接收流，仅在`SLOT_READY`状态下为`Some`
+    ///
+    /// 离开`SLOT_INIT`之后的状态转换均在持有该锁时进行，以保证`info`与状态的一致性。
+    info: Mutex<Option<R>>,
+    /// 信号的占用者信息，仅在信号被占用时为`Some`
+    owner: Mutex<Option<OwnerInfo>>,
+}
+
+impl<R: Stream + Unpin> SignalSlot<R> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: AtomicU8::new(SLOT_FREE),
+            epoch: AtomicU32::new(0),
+            pending: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            info: Mutex::new(None),
+            owner: Mutex::new(None),
+        }
+    }
+
+    /// 当前的代数
+    pub(crate) fn epoch(&self) -> u32 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// 当前的占用者信息
+    pub(crate) fn owner(&self) -> Option<OwnerInfo> {
+        *self.owner.lock()
+    }
+
+    /// 开始接收已被占用的信号，返回其代数
+    ///
+    /// 调用者需已在分配器中占用了该信号，因此信号必须处于`SLOT_FREE`状态；
+    /// 否则panic（启用`no-panic`时返回`None`，信号的状态不变）。
+    pub(crate) fn start(&self, info: R, owner: OwnerInfo) -> Option<u32> {
+        let res =
+            self.state
+                .compare_exchange(SLOT_FREE, SLOT_INIT, Ordering::AcqRel, Ordering::Acquire);
+        if let Err(state) = res {
+            fail!(
+                return None,
+                "start: signal slot is not free (state {})",
+                state
+            )
+        }
+        self.owner.lock().replace(owner);
+        let mut slot_info = self.info.lock();
+        slot_info.replace(info);
+        let epoch = self.epoch.load(Ordering::Acquire);
+        self.state.store(SLOT_READY, Ordering::Release);
+        Some(epoch)
+    }
+
+    /// 轮询代数为`epoch`的信号
+    ///
+    /// `consume`为`true`时消费一个通知；否则收到的通知被记录于`pending`中，不被消费。
+    /// 接收流中已到达的其余通知（至多`BATCH`个）在同一次轮询中被取出并记录于`pending`。
+    /// 返回`Poll::Ready(true)`表示有通知；若信号已被释放（包括已被释放后重新申请），则返回`Poll::Ready(false)`；
+    /// 若信号暂停接收，则返回`Poll::Pending`，并在恢复接收时通过`waker`唤醒。
+    pub(crate) fn poll_epoch(&self, epoch: u32, cx: &mut Context<'_>, consume: bool) -> Poll<bool> {
+        let mut info = self.info.lock();
+        if self.epoch.load(Ordering::Acquire) != epoch {
+            return Poll::Ready(false);
+        }
+        let pending = if consume {
+            self.pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+        } else {
+            self.pending.load(Ordering::Acquire) > 0
+        };
+        if pending {
+            return Poll::Ready(true);
+        }
+        self.waker.register(cx.waker());
+        match info.as_mut() {
+            Some(info) => Pin::new(&mut *info).poll_next(cx).map(|sig| match sig {
+                Some(_) => {
+                    // 在同一次加锁中取出已到达的其余通知，记录于`pending`
+                    let mut batch = usize::from(!consume);
+                    while batch < BATCH {
+                        match Pin::new(&mut *info).poll_next(cx) {
+                            Poll::Ready(Some(_)) => batch += 1,
+                            _ => break,
+                        }
+                    }
+                    if batch > 0 {
+                        self.pending.fetch_add(batch, Ordering::AcqRel);
+                    }
+                    true
+                }
+                // 接收流不会结束，启用`no-panic`时视为信号已被释放
+                None => fail!(false, "signal stream ended"),
+            }),
+            None if self.state.load(Ordering::Acquire) == SLOT_SUSPENDED => Poll::Pending,
+            None => Poll::Ready(false),
+        }
+    }
+
+    /// 释放代数为`epoch`的信号，并唤醒正在等待的协程；`on_owner`在信号变为空闲之前以占用者信息被调用
+    ///
+    /// 若信号已被释放（包括已被释放后重新申请），则返回`false`且不做任何事。
+    /// 代数匹配而信号未被占用时panic（启用`no-panic`时同样返回`false`）。
+    pub(crate) fn release(&self, epoch: u32, on_owner: impl FnOnce(&OwnerInfo)) -> bool {
+        let mut info = self.info.lock();
+        if self.epoch.load(Ordering::Acquire) != epoch {
+            return false;
+        }
+        // 代数匹配的信号必然已被占用
+        let res = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                matches!(state, SLOT_READY | SLOT_SUSPENDED).then_some(SLOT_RELEASING)
+            });
+        if let Err(state) = res {
+            fail!(
+                return false,
+                "release: signal slot is not in use (state {})",
+                state
+            )
+        }
+        info.take();
+        self.pending.store(0, Ordering::Release);
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+        drop(info);
+        if let Some(owner) = self.owner.lock().take() {
+            on_owner(&owner);
+        }
+        self.state.store(SLOT_FREE, Ordering::Release);
+        self.waker.wake();
+        true
+    }
+
+    /// 若代数为`epoch`的信号仍被占用，则直接记录一个通知并唤醒等待者，返回`true`
+    pub(crate) fn notify_local(&self, epoch: u32) -> bool {
+        let info = self.info.lock();
+        if self.epoch.load(Ordering::Acquire) != epoch
+            || !matches!(
+                self.state.load(Ordering::Acquire),
+                SLOT_READY | SLOT_SUSPENDED
+            )
+        {
+            return false;
+        }
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        drop(info);
+        self.waker.wake();
+        true
+    }
+
+    /// 暂停接收，关闭接收流；信号不在接收状态时不做任何事
+    pub(crate) fn suspend(&self) {
+        let mut info = self.info.lock();
+        if self
+            .state
+            .compare_exchange(
+                SLOT_READY,
+                SLOT_SUSPENDED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            info.take();
+        }
+    }
+
+    /// 以`new_info`创建的接收流恢复暂停的接收，并视为收到了一个通知；信号未暂停时不做任何事
+    ///
+    /// `new_info`返回`None`时信号保持暂停。
+    pub(crate) fn resume(&self, new_info: impl FnOnce() -> Option<R>) {
+        let mut info = self.info.lock();
+        if self.state.load(Ordering::Acquire) == SLOT_SUSPENDED {
+            let Some(new_info) = new_info() else {
+                return;
+            };
+            info.replace(new_info);
+            self.pending.fetch_add(1, Ordering::AcqRel);
+            self.state.store(SLOT_READY, Ordering::Release);
+            drop(info);
+            self.waker.wake();
+        }
+    }
+}
+
+#[cfg(all(test, async_notification_loom))]
+mod loom_tests {
+    use super::*;
+    use core::future::poll_fn;
+    use loom::sync::Arc;
+
+    /// 模拟的信号接收流：`count`为已到达的信号数量
+    struct FakeSignals {
+        count: Arc<loom::sync::atomic::AtomicUsize>,
+        waker: Arc<loom::future::AtomicWaker>,
+    }
+
+    impl Stream for FakeSignals {
+        type Item = ();
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+            self.waker.register_by_ref(cx.waker());
+            match self
+                .count
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |c| c.checked_sub(1))
+            {
+                Ok(_) => Poll::Ready(Some(())),
+                Err(_) => Poll::Pending,
+            }
+        }
+    }
+
+    fn owner() -> OwnerInfo {
+        OwnerInfo {
+            tid: 0,
+            allocated_at_ns: 0,
+            label: None,
+        }
+    }
+
+    /// 返回已开始接收的信号，以及向其发送信号的函数
+    fn started() -> (
+        Arc<SignalSlot<FakeSignals>>,
+        u32,
+        impl Fn() + Send + 'static,
+    ) {
+        let count = Arc::new(loom::sync::atomic::AtomicUsize::new(0));
+        let waker = Arc::new(loom::future::AtomicWaker::new());
+        let slot = Arc::new(SignalSlot::new());
+        let epoch = slot
+            .start(
+                FakeSignals {
+                    count: count.clone(),
+                    waker: waker.clone(),
+                },
+                owner(),
+            )
+            .unwrap();
+        let kill = move || {
+            count.fetch_add(1, Ordering::AcqRel);
+            waker.wake();
+        };
+        (slot, epoch, kill)
+    }
+
+    #[test]
+    fn loom_wait_notify() {
+        // 本进程内的通知与外部信号均不会丢失唤醒
+        loom::model(|| {
+            let (slot, epoch, kill) = started();
+            let notifier = {
+                let slot = slot.clone();
+                loom::thread::spawn(move || assert!(slot.notify_local(epoch)))
+            };
+            let killer = loom::thread::spawn(kill);
+            for _ in 0..2 {
+                let got = loom::future::block_on(poll_fn(|cx| slot.poll_epoch(epoch, cx, true)));
+                assert!(got);
+            }
+            notifier.join().unwrap();
+            killer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn loom_wait_release() {
+        // 等待期间被释放的信号使等待结束，且释放后不再接受本进程内的通知
+        loom::model(|| {
+            let (slot, epoch, _kill) = started();
+            let releaser = {
+                let slot = slot.clone();
+                loom::thread::spawn(move || assert!(slot.release(epoch, |_| {})))
+            };
+            let notifier = {
+                let slot = slot.clone();
+                loom::thread::spawn(move || slot.notify_local(epoch))
+            };
+            loom::future::block_on(poll_fn(|cx| slot.poll_epoch(epoch, cx, true)));
+            releaser.join().unwrap();
+            notifier.join().unwrap();
+            assert_eq!(slot.epoch(), epoch + 1);
+            assert!(!slot.notify_local(epoch));
+            assert_eq!(slot.pending.load(Ordering::Acquire), 0);
+            assert_eq!(slot.state.load(Ordering::Acquire), SLOT_FREE);
+        });
+    }
+
+    #[test]
+    fn loom_release_reuse() {
+        // 重新申请之后，旧id上的等待与释放不影响新的占用者
+        loom::model(|| {
+            let (slot, epoch, _kill) = started();
+            assert!(slot.release(epoch, |_| {}));
+            let (count, waker) = (
+                Arc::new(loom::sync::atomic::AtomicUsize::new(0)),
+                Arc::new(loom::future::AtomicWaker::new()),
+            );
+            let new_epoch = slot.start(FakeSignals { count, waker }, owner()).unwrap();
+            let stale = {
+                let slot = slot.clone();
+                loom::thread::spawn(move || assert!(!slot.release(epoch, |_| {})))
+            };
+            assert!(slot.notify_local(new_epoch));
+            let got = loom::future::block_on(poll_fn(|cx| slot.poll_epoch(new_epoch, cx, true)));
+            assert!(got);
+            stale.join().unwrap();
+            assert_eq!(slot.epoch(), new_epoch);
+        });
+    }
+}
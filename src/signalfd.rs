@@ -0,0 +1,550 @@
+//! 使用signalfd接收信号的通知机制
+//!
+//! 与`signal`相同，每个通知源对应一个实时信号，发送方式也相同；不同的是接收方不安装信号处理函数，
+//! 而是阻塞该信号，并从注册到tokio的reactor中的signalfd读出信号。这样接收不受信号处理函数中异步信号安全的限制，
+//! 且每个通知都带有发送方的进程号与`sigqueue`的附加值，可通过[`SignalfdNotification::wait_on_info`]取得。
+//!
+//! signalfd只能读出被阻塞的信号。[`SignalfdNotification::new_id`]只在调用它的线程中阻塞信号，
+//! 其他线程若未阻塞该信号，发往本进程的信号可能被投递给这些线程而不能被读出。因此应在创建任何线程之前
+//! （包括创建tokio运行时之前）调用[`SignalfdNotification::block_signals`]，使之后创建的线程继承被阻塞的信号。
+//!
+//! 同时启用`signal`时，两者从同一信号池中分配信号，不会占用同一个信号。
+//!
+//! 必须配合tokio运行时
+
+use alloc::{collections::btree_map::BTreeMap, collections::vec_deque::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    filter::{FilterSlot, SenderFilter},
+    interface::{NotificationIf, NotifyError, ProcessRef, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用signalfd接收信号的通知机制
+pub struct SignalfdNotification;
+
+/// 随通知到达的信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalInfo {
+    /// 发送方的进程号
+    pub pid: u32,
+    /// 发送方的用户id
+    pub uid: u32,
+    /// `sigqueue`的附加值；由`kill`发送时为0
+    pub value: i32,
+}
+
+struct SignalfdSlot {
+    fd: AsyncFd<OwnedRawFd>,
+    /// 已从signalfd中读出、但尚未被消费的通知
+    pending: SpinMutex<VecDeque<SignalInfo>>,
+    /// 按发送方过滤读出的信号
+    filter: FilterSlot,
+}
+
+impl SignalfdSlot {
+    /// 读出signalfd上的所有信号，返回是否有新的通知
+    fn drain(&self) -> bool {
+        let mut pending = self.pending.lock();
+        let mut received = false;
+        loop {
+            let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+            let size = mem::size_of::<libc::signalfd_siginfo>();
+            let res = unsafe {
+                libc::read(
+                    self.fd.get_ref().0,
+                    &mut info as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                    size,
+                )
+            };
+            if res != size as isize {
+                return received;
+            }
+            let sender = Some((info.ssi_pid, info.ssi_uid));
+            if !self.filter.accept(sender, Some(info.ssi_ptr)) {
+                continue;
+            }
+            pending.push_back(SignalInfo {
+                pid: info.ssi_pid,
+                uid: info.ssi_uid,
+                value: info.ssi_int,
+            });
+            received = true;
+        }
+    }
+
+    /// 若有通知则消费一个，不阻塞
+    fn take(&self) -> bool {
+        self.pending.lock().pop_front().is_some() || (self.drain() && self.take())
+    }
+
+    /// `consume`为`true`时消费一个通知并返回其信息；否则只检查是否有通知
+    fn poll_info(&self, cx: &mut Context<'_>, consume: bool) -> Poll<Option<SignalInfo>> {
+        loop {
+            {
+                let mut pending = self.pending.lock();
+                if consume {
+                    if let Some(info) = pending.pop_front() {
+                        return Poll::Ready(Some(info));
+                    }
+                } else if let Some(&info) = pending.front() {
+                    return Poll::Ready(Some(info));
+                }
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(None)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.drain() {
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+/// 所有被占用的信号，以信号编号为key
+static SLOTS: SpinMutex<BTreeMap<u64, Arc<SignalfdSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 读出并丢弃signalfd上的所有信号，返回信号数量
+pub(crate) fn drain(fd: libc::c_int) -> u64 {
+    let mut count = 0;
+    loop {
+        let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+        let size = mem::size_of::<libc::signalfd_siginfo>();
+        let res = unsafe {
+            libc::read(
+                fd,
+                &mut info as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                size,
+            )
+        };
+        if res != size as isize {
+            return count;
+        }
+        count += 1;
+    }
+}
+
+impl NotificationIf for SignalfdNotification {
+    /// 分配一个实时信号并在调用线程中阻塞它，id即为信号编号
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将signalfd注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let signal = pool::reserve()?;
+        match Self::open(signal) {
+            Some(slot) => {
+                SLOTS.lock().insert(signal as u64, Arc::new(slot));
+                Some(signal as u64)
+            }
+            None => {
+                pool::release(signal);
+                None
+            }
+        }
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot
+                .poll_info(cx, true)
+                .map(|info| info.map_or(WakeReason::Shutdown, |_| WakeReason::NOTIFIED)),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let ready = match Self::slot(id) {
+            Some(slot) => slot
+                .poll_info(&mut Context::from_waker(waker), false)
+                .is_ready(),
+            None => true,
+        };
+        if ready {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// 关闭signalfd并归还信号
+    ///
+    /// 信号仍保持被阻塞，之后到达的该信号会一直挂起，直到其被重新申请。
+    unsafe fn release_id(id: u64) {
+        let slot = SLOTS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+        pool::release(id as u32);
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl SignalfdNotification {
+    /// 在调用线程中阻塞本模块可能使用的所有信号
+    ///
+    /// 应在创建任何线程之前调用，使之后创建的线程都继承被阻塞的信号。
+    pub fn block_signals() {
+        let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+        unsafe { libc::sigemptyset(&mut set) };
+        for signal in pool::signals() {
+            unsafe { libc::sigaddset(&mut set, signal as libc::c_int) };
+        }
+        let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, core::ptr::null_mut()) };
+        check!(res == 0, "block_signals: pthread_sigmask returned {}", res);
+    }
+
+    /// 在通知源上等待，并返回随通知到达的信息；若通知源已被释放，则返回`None`
+    ///
+    /// 与`wait_on`相同，返回的future是取消安全的。
+    pub fn wait_on_info(id: u64) -> WaitInfo {
+        WaitInfo { id }
+    }
+
+    /// 轮询通知源，收到通知时消费之并返回其信息；若通知源已被释放，则返回`Poll::Ready(None)`
+    pub fn poll_wait_info(id: u64, cx: &mut Context<'_>) -> Poll<Option<SignalInfo>> {
+        let poll = match Self::slot(id) {
+            Some(slot) => slot.poll_info(cx, true),
+            None => return Poll::Ready(None),
+        };
+        #[cfg(feature = "record")]
+        if let Poll::Ready(Some(info)) = poll {
+            let tag = (crate::kind::NotificationKind::Signalfd.tag() as u64) << 56;
+            crate::record::received(id | tag, Some(info.pid as u64), &info.value.to_le_bytes());
+        }
+        poll
+    }
+
+    /// 以空信号检查进程`process`是否存在且可被本进程发送信号，不发送信号
+    pub fn can_notify(process: u64, _id: u64) -> Result<(), NotifyError> {
+        if unsafe { libc::kill(process as libc::pid_t, 0) } == 0 {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(unsafe { *libc::__errno_location() }))
+        }
+    }
+
+    /// 使用`sigqueue`向进程`process`发送信号，附加值为0；失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        Self::notify_value(process, id, 0)
+    }
+
+    /// 使用`sigqueue`向进程`process`发送信号，并带上附加值`value`
+    pub fn notify_value(process: u64, id: u64, value: i32) -> Result<(), NotifyError> {
+        Self::sigqueue(process, id, value as isize as *mut libc::c_void)
+    }
+
+    fn sigqueue(process: u64, id: u64, sival_ptr: *mut libc::c_void) -> Result<(), NotifyError> {
+        let value = libc::sigval { sival_ptr };
+        let res = unsafe { libc::sigqueue(process as libc::pid_t, id as libc::c_int, value) };
+        if res == 0 {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            libc::EAGAIN => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 向进程、进程组或线程发送信号
+    ///
+    /// 分别使用`sigqueue`、`killpg`与`tgkill`。
+    pub fn notify_to(target: ProcessRef, id: u64) -> Result<(), NotifyError> {
+        let signal = id as libc::c_int;
+        let res = match target {
+            ProcessRef::Process(pid) => return Self::try_notify(pid, id),
+            ProcessRef::Group(pgid) => unsafe { libc::killpg(pgid as libc::pid_t, signal) },
+            ProcessRef::Thread { pid, tid } => unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    pid as libc::pid_t,
+                    tid as libc::pid_t,
+                    signal,
+                ) as libc::c_int
+            },
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(unsafe { *libc::__errno_location() }))
+        }
+    }
+
+    /// 阻塞信号并为其创建signalfd
+    fn open(signal: u32) -> Option<SignalfdSlot> {
+        let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+        unsafe {
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, signal as libc::c_int);
+        }
+        if unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, core::ptr::null_mut()) } != 0 {
+            return None;
+        }
+        // 实时信号的默认动作是终止进程。为防止信号被投递给未阻塞它的线程时终止进程，
+        // 在信号没有处理函数时安装一个空的处理函数。
+        unsafe {
+            let mut old: libc::sigaction = mem::zeroed();
+            libc::sigaction(signal as libc::c_int, core::ptr::null(), &mut old);
+            if old.sa_sigaction == libc::SIG_DFL {
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as usize;
+                libc::sigaction(signal as libc::c_int, &action, core::ptr::null_mut());
+            }
+        }
+        let fd = unsafe { libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        Some(SignalfdSlot {
+            fd: AsyncFd::new(OwnedRawFd(fd)).ok()?,
+            pending: SpinMutex::new(VecDeque::new()),
+            filter: FilterSlot::new(),
+        })
+    }
+
+    /// 只接受`filter`允许的发送方的信号，`None`表示接受所有信号；若通知源未被占用，则返回`false`
+    ///
+    /// 已读出但尚未被消费的通知不受影响。
+    pub fn set_sender_filter(id: u64, filter: Option<SenderFilter>) -> bool {
+        Self::slot(id).map(|slot| slot.filter.set(filter)).is_some()
+    }
+
+    /// 为通知源生成新的令牌，之后只接受带有该令牌的信号；若通知源未被占用或无法取得随机数，则返回`None`
+    pub fn mint_token(id: u64) -> Option<u64> {
+        let slot = Self::slot(id)?;
+        let token = crate::filter::mint()?;
+        slot.filter.set_token(Some(token));
+        Some(token)
+    }
+
+    /// 不再要求通知源上的信号带有令牌；若通知源未被占用，则返回`false`
+    pub fn clear_token(id: u64) -> bool {
+        Self::slot(id)
+            .map(|slot| slot.filter.set_token(None))
+            .is_some()
+    }
+
+    /// 使用`sigqueue`向进程`process`发送信号，以附加值携带令牌`token`
+    ///
+    /// 接收方读出的`SignalInfo::value`为令牌的低32位。
+    pub fn notify_token(process: u64, id: u64, token: u64) -> Result<(), NotifyError> {
+        Self::sigqueue(process, id, token as usize as *mut libc::c_void)
+    }
+
+    /// 被过滤器丢弃的通知数；若通知源未被占用，则返回`None`
+    pub fn dropped_by_filter(id: u64) -> Option<u64> {
+        Self::slot(id).map(|slot| slot.filter.dropped())
+    }
+
+    /// 通知源对应的signalfd，可注册到其他事件循环中；文件描述符仍由本模块持有，在`release_id`时被关闭
+    pub fn signalfd(id: u64) -> Option<libc::c_int> {
+        Self::slot(id).map(|slot| slot.fd.get_ref().0)
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    ///
+    /// 随通知到达的信息被丢弃。
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.fd.get_ref().0,
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<SignalfdSlot>> {
+        SLOTS.lock().get(&id).cloned()
+    }
+}
+
+extern "C" fn ignore(_signal: libc::c_int) {}
+
+/// [`SignalfdNotification::wait_on_info`]返回的future
+pub struct WaitInfo {
+    id: u64,
+}
+
+impl Future for WaitInfo {
+    type Output = Option<SignalInfo>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        SignalfdNotification::poll_wait_info(self.id, cx)
+    }
+}
+
+/// 启用`signal`时，与其共用信号池
+#[cfg(feature = "signal")]
+mod pool {
+    use crate::signal::SignalNotification;
+    use alloc::vec::Vec;
+
+    pub(super) fn signals() -> Vec<u32> {
+        SignalNotification::signals()
+    }
+
+    pub(super) fn reserve() -> Option<u32> {
+        SignalNotification::reserve_raw()
+    }
+
+    pub(super) fn release(signal: u32) {
+        SignalNotification::release_raw(signal);
+    }
+}
+
+/// 未启用`signal`时，使用自己的信号池
+#[cfg(not(feature = "signal"))]
+mod pool {
+    use crate::sync::SpinMutex;
+    use alloc::vec::Vec;
+
+    /// 已被占用的信号，第i位对应信号`SIGRTMIN + i`
+    static USED: SpinMutex<u64> = SpinMutex::new(0);
+
+    /// 可被分配的信号，由[`sigrt::policy`](crate::sigrt::policy)给出
+    pub(super) fn signals() -> Vec<u32> {
+        crate::sigrt::signals()
+    }
+
+    pub(super) fn reserve() -> Option<u32> {
+        let base = libc::SIGRTMIN() as u32;
+        let mut used = USED.lock();
+        let signal = signals()
+            .into_iter()
+            .find(|&signal| *used & (1 << (signal - base)) == 0)?;
+        *used |= 1 << (signal - base);
+        Some(signal)
+    }
+
+    pub(super) fn release(signal: u32) {
+        let bit = 1 << (signal - libc::SIGRTMIN() as u32);
+        let mut used = USED.lock();
+        check!(
+            *used & bit != 0,
+            "release: signal {} is not allocated",
+            signal
+        );
+        *used &= !bit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SignalInfo, SignalfdNotification};
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signalfd_thread() {
+        runtime().block_on(async move {
+            // 测试进程中有其他线程，因此只向本线程发送信号
+            let pid = unsafe { libc::getpid() } as u64;
+            let tid = unsafe { libc::gettid() } as u64;
+            let id = Notification::new_id_signalfd().unwrap();
+            assert_eq!(Notification::can_notify(pid, id), Ok(()));
+            assert_eq!(
+                Notification::can_notify(i32::MAX as u64, id),
+                Err(crate::interface::NotifyError::Os(libc::ESRCH))
+            );
+            Notification::notify_thread(pid, tid, id).unwrap();
+            Notification::notify_thread(pid, tid, id).unwrap();
+            for _ in 0..2 {
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+            }
+            let waker = futures::task::noop_waker();
+            let mut cx = core::task::Context::from_waker(&waker);
+            assert!(Notification::poll_wait(id, &mut cx).is_pending());
+            unsafe { Notification::release_id(id) };
+            assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        });
+    }
+
+    #[test]
+    fn test_signalfd_sender_filter() {
+        use crate::filter::SenderFilter;
+
+        runtime().block_on(async move {
+            let pid = unsafe { libc::getpid() } as u64;
+            let tid = unsafe { libc::gettid() } as u64;
+            let id = Notification::new_id_signalfd().unwrap();
+            let waker = futures::task::noop_waker();
+            let mut cx = core::task::Context::from_waker(&waker);
+
+            let other = SenderFilter::new().allow_pid(pid as u32 + 1);
+            assert!(Notification::set_sender_filter(id, Some(other)));
+            Notification::notify_thread(pid, tid, id).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(Notification::poll_wait(id, &mut cx).is_pending());
+            assert_eq!(Notification::dropped_by_filter(id), Some(1));
+
+            let own = SenderFilter::new().allow_pid(pid as u32);
+            assert!(Notification::set_sender_filter(id, Some(own)));
+            Notification::notify_thread(pid, tid, id).unwrap();
+            tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                .await
+                .unwrap();
+            assert_eq!(Notification::dropped_by_filter(id), Some(1));
+
+            // `tgkill`发送的信号不带令牌
+            let token = Notification::mint_token(id).unwrap();
+            Notification::notify_thread(pid, tid, id).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(Notification::poll_wait(id, &mut cx).is_pending());
+            assert_eq!(Notification::dropped_by_filter(id), Some(2));
+            assert!(Notification::clear_token(id));
+            assert_ne!(token, 0);
+            unsafe { Notification::release_id(id) };
+        });
+    }
+
+    #[test]
+    fn test_signalfd_info() {
+        // 在单线程的子进程中测试发往进程的信号
+        match unsafe { libc::fork() } {
+            0 => {
+                unsafe { libc::alarm(5) };
+                SignalfdNotification::block_signals();
+                let ok = runtime().block_on(async move {
+                    let pid = unsafe { libc::getpid() } as u64;
+                    let id = SignalfdNotification::new_id().unwrap();
+                    SignalfdNotification::notify_value(pid, id, 42).unwrap();
+                    let info = SignalfdNotification::wait_on_info(id).await;
+                    unsafe { SignalfdNotification::release_id(id) };
+                    matches!(info, Some(SignalInfo { pid: p, value: 42, .. }) if p as u64 == pid)
+                });
+                unsafe { libc::_exit(!ok as libc::c_int) };
+            }
+            -1 => panic!("Fork failed!"),
+            child => {
+                let mut status = 0;
+                unsafe { libc::waitpid(child, &mut status, 0) };
+                assert_eq!(status, 0);
+            }
+        }
+    }
+}
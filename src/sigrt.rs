@@ -0,0 +1,142 @@
+//! 实时信号的可用范围
+//!
+//! 实时信号的编号范围由C库在运行时给出：内核提供[32, 64]，glibc保留32、33供NPTL内部使用，`SIGRTMIN()`为34；
+//! musl保留32至34，`SIGRTMIN()`为35。因此可用的信号不应写死，而应由[`rt_range`]在运行时计算。
+//!
+//! `signal`与`signalfd`从[`SignalPolicy`]给出的信号中分配，默认保留最高的两个信号：
+//! 在部分环境中向它们发送信号时`kill`失败。可在申请第一个信号之前以[`set_policy`]修改。
+
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::sync::SpinMutex;
+
+/// 本进程的C库给出的实时信号范围`[SIGRTMIN(), SIGRTMAX()]`
+pub fn rt_range() -> RangeInclusive<u32> {
+    libc::SIGRTMIN() as u32..=libc::SIGRTMAX() as u32
+}
+
+/// 从实时信号中选取本crate使用的信号的策略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalPolicy {
+    /// 不使用的最低信号的数量
+    reserve_low: u32,
+    /// 不使用的最高信号的数量
+    reserve_high: u32,
+    /// 范围内不使用的信号
+    exclude: Vec<u32>,
+}
+
+impl SignalPolicy {
+    /// 默认的策略：保留最高的两个信号
+    pub const fn new() -> Self {
+        Self {
+            reserve_low: 0,
+            reserve_high: 2,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// 使用所有实时信号的策略
+    pub const fn all() -> Self {
+        Self {
+            reserve_low: 0,
+            reserve_high: 0,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// 不使用最低的`n`个信号，例如留给同一进程中另一个使用`SIGRTMIN + i`的库
+    pub fn reserve_low(mut self, n: u32) -> Self {
+        self.reserve_low = n;
+        self
+    }
+
+    /// 不使用最高的`n`个信号
+    pub fn reserve_high(mut self, n: u32) -> Self {
+        self.reserve_high = n;
+        self
+    }
+
+    /// 不使用信号`signal`
+    pub fn exclude(mut self, signal: u32) -> Self {
+        self.exclude.push(signal);
+        self
+    }
+
+    /// 去掉最低与最高的保留信号之后的范围，保留的信号过多时为空
+    pub fn range(&self) -> RangeInclusive<u32> {
+        let rt = rt_range();
+        let start = rt.start().saturating_add(self.reserve_low);
+        // 范围为空时`end < start`
+        let end = rt.end().saturating_sub(self.reserve_high);
+        start..=end
+    }
+
+    /// 按该策略可被使用的信号，按编号排序
+    pub fn signals(&self) -> Vec<u32> {
+        self.range()
+            .filter(|signal| !self.exclude.contains(signal))
+            .collect()
+    }
+}
+
+impl Default for SignalPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 当前的策略，以及其是否已被使用过
+static POLICY: SpinMutex<(SignalPolicy, bool)> = SpinMutex::new((SignalPolicy::new(), false));
+
+/// 设置选取信号的策略
+///
+/// 信号池在首次申请信号时按策略建立，之后不再改变，因此需要在此之前调用，否则不生效并返回`false`。
+pub fn set_policy(policy: SignalPolicy) -> bool {
+    let mut current = POLICY.lock();
+    if current.1 {
+        return false;
+    }
+    current.0 = policy;
+    true
+}
+
+/// 当前的策略
+pub fn policy() -> SignalPolicy {
+    POLICY.lock().0.clone()
+}
+
+/// 按当前的策略建立信号池，之后策略不能再被修改
+pub(crate) fn signals() -> Vec<u32> {
+    let mut current = POLICY.lock();
+    current.1 = true;
+    current.0.signals()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_policy() {
+        let rt = rt_range();
+        let (min, max) = (*rt.start(), *rt.end());
+        assert!(min >= 32 && max <= 64 && min < max);
+
+        let signals = SignalPolicy::new().signals();
+        assert_eq!(signals.first(), Some(&min));
+        assert_eq!(signals.last(), Some(&(max - 2)));
+        assert_eq!(SignalPolicy::all().signals().len() as u32, max - min + 1);
+
+        let policy = SignalPolicy::all()
+            .reserve_low(1)
+            .reserve_high(1)
+            .exclude(min + 2);
+        assert_eq!(policy.range(), min + 1..=max - 1);
+        let signals = policy.signals();
+        assert!(!signals.contains(&min) && !signals.contains(&max));
+        assert!(!signals.contains(&(min + 2)) && signals.contains(&(min + 3)));
+        assert!(SignalPolicy::all().reserve_low(64).signals().is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! 使用虚拟时间的确定性模拟通知机制
+//!
+//! 与[`mock`](crate::mock)不同，`notify`发出的通知不会立即到达，而是由模拟调度器按照虚拟时间投递：
+//! 每个通知的延迟在[`SimConfig`]给出的范围内由带种子的随机数生成器决定，延迟不同的通知因此可能乱序到达，
+//! 而种子相同时投递的顺序与时刻总是相同。测试通过[`step`]、[`advance`]与[`run_until_idle`]推进虚拟时间，
+//! 从而可复现地检验构建在本库之上的IPC协议在各种投递顺序下的行为。
+//!
+//! 调度器是全局的，使用它的测试之间需要串行执行，并在开始时调用[`reset`]。
+//! 不需要异步运行时；被唤醒的协程需在推进虚拟时间后由测试所用的执行器调度运行。
+
+use alloc::collections::btree_map::BTreeMap;
+use core::{
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{
+    interface::{NotificationIf, NotifyError},
+    kind::NotificationKind,
+    rng::Rng,
+    sync::SpinMutex,
+};
+
+/// 模拟调度器的配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimConfig {
+    /// 随机数生成器的种子
+    pub seed: u64,
+    /// 通知的最小投递延迟
+    pub min_delay: Duration,
+    /// 通知的最大投递延迟
+    pub max_delay: Duration,
+}
+
+impl Default for SimConfig {
+    /// 种子为0，延迟在0至1ms之间
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            min_delay: Duration::ZERO,
+            max_delay: Duration::from_millis(1),
+        }
+    }
+}
+
+/// 使用虚拟时间的模拟通知机制
+pub struct SimNotification;
+
+struct SimSlot {
+    /// 已到达、但尚未被`poll_wait`消费的通知数量
+    pending: u64,
+    waker: Option<Waker>,
+}
+
+struct Scheduler {
+    /// 当前的虚拟时间，单位为纳秒
+    now: u64,
+    min_delay: u64,
+    max_delay: u64,
+    rng: Rng,
+    /// 待投递的通知，以（到达时刻, 发送序号）为key，值为通知源id
+    queue: BTreeMap<(u64, u64), u64>,
+    /// 下一个发送序号
+    seq: u64,
+    slots: BTreeMap<u64, SimSlot>,
+}
+
+impl Scheduler {
+    const fn new() -> Self {
+        Self {
+            now: 0,
+            min_delay: 0,
+            max_delay: 1_000_000,
+            rng: Rng::new(0),
+            queue: BTreeMap::new(),
+            seq: 0,
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// 投递最早到达的通知，返回其id与要唤醒的waker
+    fn pop(&mut self, until: u64) -> Option<(u64, Option<Waker>)> {
+        let (&(time, seq), _) = self.queue.first_key_value()?;
+        if time > until {
+            return None;
+        }
+        let id = self.queue.remove(&(time, seq)).unwrap();
+        self.now = self.now.max(time);
+        // 已被释放的通知源上的通知被丢弃
+        let waker = self.slots.get_mut(&id).and_then(|slot| {
+            slot.pending += 1;
+            slot.waker.take()
+        });
+        Some((id, waker))
+    }
+}
+
+static SCHEDULER: SpinMutex<Scheduler> = SpinMutex::new(Scheduler::new());
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+fn tagged(id: u64) -> u64 {
+    id | ((NotificationKind::Sim.tag() as u64) << 56)
+}
+
+impl NotificationIf for SimNotification {
+    fn new_id() -> Option<u64> {
+        let mut scheduler = SCHEDULER.lock();
+        let id = scheduler.slots.keys().next_back().map_or(0, |id| id + 1);
+        scheduler.slots.insert(
+            id,
+            SimSlot {
+                pending: 0,
+                waker: None,
+            },
+        );
+        Some(id)
+    }
+
+    /// 若通知源已被释放，则等待立即结束
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        let mut scheduler = SCHEDULER.lock();
+        let Some(slot) = scheduler.slots.get_mut(&id) else {
+            return Poll::Ready(());
+        };
+        if slot.pending > 0 {
+            slot.pending -= 1;
+            return Poll::Ready(());
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let mut scheduler = SCHEDULER.lock();
+        match scheduler.slots.get_mut(&id) {
+            Some(slot) if slot.pending == 0 => slot.waker = Some(waker.clone()),
+            _ => waker.wake_by_ref(),
+        }
+    }
+
+    /// 释放通知源，并唤醒正在等待的协程；尚未到达的通知在到达时被丢弃
+    unsafe fn release_id(id: u64) {
+        let slot = SCHEDULER.lock().slots.remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+        if let Some(waker) = slot.and_then(|slot| slot.waker) {
+            waker.wake();
+        }
+    }
+
+    /// `process`被忽略
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl SimNotification {
+    /// 检查通知源是否被占用，`process`被忽略；通知源已被释放时返回`NotifyError::Os(EBADF)`
+    pub fn can_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        if SCHEDULER.lock().slots.contains_key(&id) {
+            Ok(())
+        } else {
+            // EBADF
+            Err(NotifyError::Os(9))
+        }
+    }
+
+    /// 将通知加入调度器，在随机的延迟之后到达，`process`被忽略；通知源已被释放时返回`NotifyError::Os(EBADF)`
+    pub fn try_notify(_process: u64, id: u64) -> Result<(), NotifyError> {
+        let mut scheduler = SCHEDULER.lock();
+        if !scheduler.slots.contains_key(&id) {
+            // EBADF
+            return Err(NotifyError::Os(9));
+        }
+        let (min, max) = (scheduler.min_delay, scheduler.max_delay);
+        let delay = scheduler.rng.range(min, max);
+        let key = (scheduler.now.saturating_add(delay), scheduler.seq);
+        scheduler.seq += 1;
+        scheduler.queue.insert(key, id);
+        Ok(())
+    }
+}
+
+/// 以`config`重置调度器：虚拟时间回到0，丢弃所有尚未到达的通知，并以新的种子重建随机数生成器
+///
+/// 已申请的通知源及其上已到达的通知不受影响。
+pub fn reset(config: SimConfig) {
+    assert!(config.min_delay <= config.max_delay);
+    let mut scheduler = SCHEDULER.lock();
+    scheduler.now = 0;
+    scheduler.min_delay = nanos(config.min_delay);
+    scheduler.max_delay = nanos(config.max_delay);
+    scheduler.rng = Rng::new(config.seed);
+    scheduler.queue.clear();
+    scheduler.seq = 0;
+}
+
+/// 当前的虚拟时间
+pub fn now() -> Duration {
+    Duration::from_nanos(SCHEDULER.lock().now)
+}
+
+/// 尚未到达的通知数量
+pub fn scheduled() -> usize {
+    SCHEDULER.lock().queue.len()
+}
+
+fn deliver(until: u64) -> Option<u64> {
+    let (id, waker) = SCHEDULER.lock().pop(until)?;
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+    Some(tagged(id))
+}
+
+/// 将虚拟时间推进到下一个通知到达的时刻并投递它，返回其id；没有待投递的通知时返回`None`
+pub fn step() -> Option<u64> {
+    deliver(u64::MAX)
+}
+
+/// 将虚拟时间推进`duration`，投递其间到达的所有通知，返回投递的数量
+pub fn advance(duration: Duration) -> usize {
+    let until = SCHEDULER.lock().now.saturating_add(nanos(duration));
+    let mut count = 0;
+    while deliver(until).is_some() {
+        count += 1;
+    }
+    SCHEDULER.lock().now = until;
+    count
+}
+
+/// 投递所有尚未到达的通知，返回投递的数量
+///
+/// 只投递调用时已在调度器中的通知之前到达的通知，被唤醒的协程此后发出的通知需再次推进虚拟时间。
+pub fn run_until_idle() -> usize {
+    let mut count = 0;
+    while step().is_some() {
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimConfig, advance, now, reset, run_until_idle, scheduled, step};
+    use crate::interface::{Notification, NotificationIf};
+    use alloc::vec::Vec;
+    use core::time::Duration;
+
+    /// 以`seed`向4个通知源各发送一个通知，返回它们的到达顺序
+    fn arrival_order(seed: u64) -> Vec<u64> {
+        reset(SimConfig {
+            seed,
+            min_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        });
+        let ids: Vec<u64> = (0..4)
+            .map(|_| Notification::new_id_sim().unwrap())
+            .collect();
+        for &id in &ids {
+            Notification::notify(0, id);
+        }
+        assert_eq!(scheduled(), 4);
+        let order = core::iter::from_fn(step)
+            .map(|id| ids.iter().position(|&i| i == id).unwrap() as u64)
+            .collect();
+        for id in ids {
+            unsafe { Notification::release_id(id) };
+        }
+        order
+    }
+
+    #[test]
+    fn test_sim() {
+        // 种子相同时到达顺序相同
+        let order = arrival_order(7);
+        assert_eq!(order, arrival_order(7));
+        assert!((1..100).any(|seed| arrival_order(seed) != order));
+
+        reset(SimConfig {
+            seed: 1,
+            min_delay: Duration::from_millis(5),
+            max_delay: Duration::from_millis(5),
+        });
+        let id = Notification::new_id_sim().unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = core::task::Context::from_waker(&waker);
+        Notification::notify(0, id);
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        assert_eq!(advance(Duration::from_millis(4)), 0);
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        assert_eq!(advance(Duration::from_millis(1)), 1);
+        assert_eq!(now(), Duration::from_millis(5));
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+
+        // 释放后尚未到达的通知被丢弃
+        Notification::notify(0, id);
+        unsafe { Notification::release_id(id) };
+        assert_eq!(run_until_idle(), 1);
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+    }
+}
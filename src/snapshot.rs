@@ -0,0 +1,197 @@
+//! 已申请的通知源的快照
+//!
+//! [`Notification::list_ids`]列出本进程中所有尚未被释放的通知源，包括其类型、底层资源、是否有协程正在等待与持有者数量，
+//! 启用`stats`时还包括其统计；[`Notification::dump_ids`]将其格式化为类似`/proc/net/*`的文本，供运维人员查看
+//! 一个行为异常的进程占用了哪些信号、中断向量或文件描述符。
+//!
+//! 只记录经[`Notification`]的`new_id_*`（及`from_raw_*`等）申请的通知源，直接使用具体通知机制申请的不被记录。
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, vec::Vec};
+use core::fmt::{self, Write};
+
+use crate::{interface::Notification, kind::NotificationKind, sync::SpinMutex};
+
+/// 通知源的底层资源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// 信号编号（`signal`与`signalfd`）
+    Signal(i32),
+    /// 用户态中断向量（`uintr`）
+    Vector(u32),
+    /// 文件描述符（`kqueue`、`kvm`与`pipe`）
+    Fd(i32),
+    /// 回环地址上的UDP端口（`net`）
+    Port(u16),
+    /// Zircon句柄（`fuchsia`）
+    Handle(u32),
+    /// 由通知机制内部分配的编号，不对应可从外部观察的资源
+    Other(u64),
+}
+
+impl Resource {
+    fn of(id: u64) -> Self {
+        let inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        match Notification::kind_of(id) {
+            Some(NotificationKind::Signal) => Resource::Signal((inner & 0xFF) as i32),
+            Some(NotificationKind::Signalfd) => Resource::Signal(inner as i32),
+            Some(NotificationKind::Uintr) => Resource::Vector((inner & 0xFF) as u32),
+            Some(NotificationKind::Kqueue | NotificationKind::Kvm | NotificationKind::Pipe) => {
+                Resource::Fd(inner as i32)
+            }
+            Some(NotificationKind::Net) => Resource::Port(inner as u16),
+            Some(NotificationKind::Fuchsia) => Resource::Handle(inner as u32),
+            _ => Resource::Other(inner),
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Signal(signum) => write!(f, "sig{}", signum),
+            Resource::Vector(vector) => write!(f, "vec{}", vector),
+            Resource::Fd(fd) => write!(f, "fd{}", fd),
+            Resource::Port(port) => write!(f, "port{}", port),
+            Resource::Handle(handle) => write!(f, "handle{}", handle),
+            Resource::Other(inner) => write!(f, "{}", inner),
+        }
+    }
+}
+
+/// 一个已申请的通知源的信息，由[`Notification::list_ids`]返回
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdInfo {
+    /// 通知源的id
+    pub id: u64,
+    /// 通知源的类型，未知类型为`None`
+    pub kind: Option<NotificationKind>,
+    /// 底层资源
+    pub resource: Resource,
+    /// 最近一次`poll_wait`是否返回了`Pending`，即是否有协程正在等待
+    pub waiting: bool,
+    /// 持有者数量，见[`Notification::dup_id`]
+    pub holders: usize,
+    /// 统计，从未被统计过时为`None`
+    #[cfg(feature = "stats")]
+    pub stats: Option<crate::stats::IdStats>,
+}
+
+impl fmt::Display for IdInfo {
+    /// 以[`Notification::dump_ids`]中一行的形式显示
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = self.kind.map_or("unknown", NotificationKind::name);
+        let state = if self.waiting { "waiting" } else { "idle" };
+        // 两者的`Display`不处理宽度，先格式化为字符串再对齐
+        let id = format!("{}", Notification::display(self.id));
+        let resource = format!("{}", self.resource);
+        write!(
+            f,
+            "{:<24} {:<8} {:<12} {:<7} {:>7}",
+            id, kind, resource, state, self.holders
+        )?;
+        #[cfg(feature = "stats")]
+        {
+            let stats = self.stats.unwrap_or_default();
+            write!(f, " {:>10} {:>10}", stats.notifies, stats.wakes)?;
+        }
+        Ok(())
+    }
+}
+
+/// 已申请的通知源，值为最近一次`poll_wait`是否返回了`Pending`
+static IDS: SpinMutex<BTreeMap<u64, bool>> = SpinMutex::new(BTreeMap::new());
+
+/// 申请了通知源`id`
+pub(crate) fn new_id(id: u64) {
+    IDS.lock().insert(id, false);
+}
+
+/// 通知源`id`即将被释放
+pub(crate) fn release(id: u64) {
+    IDS.lock().remove(&id);
+}
+
+/// 通知源`id`被`poll_wait`轮询，`ready`表示等待已结束
+pub(crate) fn polled(id: u64, ready: bool) {
+    if let Some(waiting) = IDS.lock().get_mut(&id) {
+        *waiting = !ready;
+    }
+}
+
+impl Notification {
+    /// 本进程中所有尚未被释放的通知源，按id排序
+    pub fn list_ids() -> Vec<IdInfo> {
+        let ids: Vec<(u64, bool)> = IDS.lock().iter().map(|(&id, &w)| (id, w)).collect();
+        ids.into_iter()
+            .map(|(id, waiting)| IdInfo {
+                id,
+                kind: Self::kind_of(id),
+                resource: Resource::of(id),
+                waiting,
+                holders: Self::holders(id),
+                #[cfg(feature = "stats")]
+                stats: Self::stats(id),
+            })
+            .collect()
+    }
+
+    /// 以文本形式列出所有尚未被释放的通知源：首行为表头，之后每行一个通知源
+    pub fn dump_ids() -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{:<24} {:<8} {:<12} {:<7} {:>7}",
+            "ID", "KIND", "RESOURCE", "STATE", "HOLDERS"
+        );
+        #[cfg(feature = "stats")]
+        let _ = write!(out, " {:>10} {:>10}", "NOTIFIES", "WAKES");
+        out.push('\n');
+        for info in Self::list_ids() {
+            let _ = writeln!(out, "{}", info);
+        }
+        out
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    #[test]
+    fn test_list_ids() {
+        let waker = futures::task::noop_waker();
+        let mut cx = core::task::Context::from_waker(&waker);
+        let id = Notification::new_id_mock().unwrap();
+        let info = Notification::list_ids()
+            .into_iter()
+            .find(|info| info.id == id)
+            .unwrap();
+        assert_eq!(info.kind, Some(NotificationKind::Mock));
+        assert_eq!(info.resource, Resource::Other(id & 0x00FF_FFFF_FFFF_FFFF));
+        assert!(!info.waiting);
+        assert_eq!(info.holders, 1);
+
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        let shared = Notification::dup_id(id);
+        let info = Notification::list_ids()
+            .into_iter()
+            .find(|info| info.id == id)
+            .unwrap();
+        assert!(info.waiting);
+        assert_eq!(info.holders, 2);
+        let dump = Notification::dump_ids();
+        assert!(dump.starts_with("ID "));
+        let line = dump
+            .lines()
+            .find(|line| line.starts_with(&format!("{} ", Notification::display(id))))
+            .unwrap();
+        assert!(line.contains(" mock ") && line.contains(" waiting "));
+
+        drop(shared);
+        unsafe { Notification::release_id(id) };
+        assert!(Notification::list_ids().iter().all(|info| info.id != id));
+        assert_eq!(Resource::of(0x0100_0000_0000_032a), Resource::Signal(42));
+        assert_eq!(format!("{}", Resource::Fd(3)), "fd3");
+    }
+}
@@ -0,0 +1,284 @@
+//! 基于文件描述符的通知源与外部事件循环的对接
+//!
+//! [`Notification::fd_source`]为eventfd（`kvm`）、signalfd（`signalfd`）与管道（`pipe`）通知源返回[`FdSource`]，
+//! 其实现了`AsFd`，可注册到mio、polling等不由本crate的future驱动的事件循环中：文件描述符可读即表示有通知到达，
+//! 之后应调用`poll_wait`（或[`SignalfdNotification::poll_wait_info`](crate::signalfd::SignalfdNotification::poll_wait_info)）
+//! 消费通知，直到其返回`Poll::Pending`。
+//!
+//! [`FdSource`]持有文件描述符的一个副本（`dup`），两者共享同一个打开的文件，因此可读状态相同，
+//! 而副本的生命周期与通知源无关，通知源被释放后使用[`FdSource`]也不会访问到无关的文件。
+//!
+//! 启用`mio`时，[`FdSource`]实现了`mio::event::Source`。不使用异步运行时的事件循环应以[`FdSource::drain`]消费通知；
+//! 申请通知源仍需在tokio运行时内部进行（可以是临时创建的运行时）。
+//!
+//! 已被本crate读出、但尚未被消费的通知（例如`register_waker`之后）不再使文件描述符可读，
+//! 因此同一个通知源应只由一种方式驱动。
+
+extern crate std;
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{interface::Notification, kind::NotificationKind};
+
+/// 可注册到外部事件循环中的通知源
+pub struct FdSource {
+    id: u64,
+    fd: OwnedFd,
+    drain: fn(RawFd) -> u64,
+}
+
+impl FdSource {
+    /// 对应的通知源
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 直接从文件描述符读出已到达的全部通知，返回其数量
+    ///
+    /// 读出的通知不再能被`wait_on`等待；signalfd通知所带的信息被丢弃。
+    pub fn drain(&self) -> u64 {
+        (self.drain)(self.fd.as_raw_fd())
+    }
+
+    /// 等待文件描述符变为可读，即通知源上有尚未被读出的通知；不消费通知
+    ///
+    /// 返回的future需在tokio运行时内部轮询。
+    pub fn readiness(&self) -> Readiness<'_> {
+        Readiness {
+            source: self,
+            fd: None,
+        }
+    }
+}
+
+impl AsFd for FdSource {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl core::fmt::Debug for FdSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FdSource")
+            .field("id", &self.id)
+            .field("fd", &self.fd)
+            .finish()
+    }
+}
+
+#[cfg(feature = "mio")]
+impl mio::event::Source for FdSource {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.fd.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl AsRawFd for FdSource {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// [`FdSource::readiness`]返回的future
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Readiness<'a> {
+    source: &'a FdSource,
+    /// 首次轮询时注册到tokio的reactor中的文件描述符副本
+    fd: Option<AsyncFd<OwnedFd>>,
+}
+
+impl Future for Readiness<'_> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.fd.is_none() {
+            this.fd = Some(AsyncFd::new(this.source.fd.try_clone()?)?);
+        }
+        let fd = this.fd.as_ref().unwrap();
+        loop {
+            let mut guard = match fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            if readable(fd.get_ref().as_raw_fd()) {
+                return Poll::Ready(Ok(()));
+            }
+            // 通知已被其他方读出
+            guard.clear_ready();
+        }
+    }
+}
+
+/// 文件描述符当前是否可读
+fn readable(fd: RawFd) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pollfd, 1, 0) == 1 && pollfd.revents & libc::POLLIN != 0 }
+}
+
+impl Notification {
+    /// 为基于文件描述符的通知源创建[`FdSource`]，用于注册到外部事件循环中
+    ///
+    /// 支持在其上等待的eventfd、signalfd与管道通知源；其他类型或未被占用的id返回`None`。
+    pub fn fd_source(id: u64) -> Option<FdSource> {
+        let id_inner = id & 0x00FF_FFFF_FFFF_FFFF;
+        let (fd, drain): (_, fn(RawFd) -> u64) = match Self::kind_of(id)? {
+            #[cfg(feature = "kvm")]
+            NotificationKind::Kvm => (
+                crate::kvm::KvmNotification::eventfd(id_inner)?,
+                crate::kvm::drain,
+            ),
+            #[cfg(feature = "signalfd")]
+            NotificationKind::Signalfd => (
+                crate::signalfd::SignalfdNotification::signalfd(id_inner)?,
+                crate::signalfd::drain,
+            ),
+            #[cfg(feature = "pipe")]
+            NotificationKind::Pipe => (
+                crate::pipe::PipeNotification::read_fd(id_inner)?,
+                crate::pipe::drain,
+            ),
+            _ => return None,
+        };
+        let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        (fd >= 0).then(|| FdSource {
+            id,
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            drain,
+        })
+    }
+}
+
+#[cfg(all(test, any(feature = "kvm", feature = "pipe")))]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[cfg(feature = "pipe")]
+    #[test]
+    fn test_pipe_source() {
+        use std::os::fd::AsRawFd;
+
+        extern crate std;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_pipe().unwrap();
+                let source = Notification::fd_source(id).unwrap();
+                assert_eq!(source.id(), id);
+                assert!(!super::readable(source.as_raw_fd()));
+                let readiness = source.readiness();
+                Notification::notify(pid, id);
+                tokio::time::timeout(Duration::from_secs(1), readiness)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                // 等待可读不消费通知
+                assert!(super::readable(source.as_raw_fd()));
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                assert!(!super::readable(source.as_raw_fd()));
+                unsafe { Notification::release_id(id) };
+                drop(source);
+            });
+    }
+
+    #[cfg(feature = "kvm")]
+    #[test]
+    fn test_eventfd_source() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                let source = Notification::fd_source(id).unwrap();
+                Notification::notify(0, id);
+                tokio::time::timeout(Duration::from_secs(1), source.readiness())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                unsafe { Notification::release_id(id) };
+                // 不支持的id
+                assert!(Notification::fd_source(0).is_none());
+            });
+    }
+
+    #[cfg(all(feature = "mio", feature = "pipe"))]
+    #[test]
+    fn test_mio_source() {
+        use mio::{Events, Interest, Poll, Token};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // 只在申请通知源时使用运行时，之后的等待由mio驱动
+        let id = runtime.block_on(async { Notification::new_id_pipe().unwrap() });
+        let mut source = Notification::fd_source(id).unwrap();
+        let mut poll = Poll::new().unwrap();
+        poll.registry()
+            .register(&mut source, Token(7), Interest::READABLE)
+            .unwrap();
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::from_millis(10)))
+            .unwrap();
+        assert!(events.is_empty());
+
+        let pid = unsafe { libc::getpid() } as u64;
+        Notification::notify(pid, id);
+        Notification::notify(pid, id);
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(
+            events
+                .iter()
+                .any(|e| e.token() == Token(7) && e.is_readable())
+        );
+        assert_eq!(source.drain(), 2);
+        assert_eq!(source.drain(), 0);
+
+        poll.registry().deregister(&mut source).unwrap();
+        unsafe { Notification::release_id(id) };
+    }
+}
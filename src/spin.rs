@@ -0,0 +1,304 @@
+//! 先自旋、再挂起的等待
+//!
+//! 默认情况下，`poll_wait`（包括`wait_on`）在通知尚未到达时立即注册waker并返回`Poll::Pending`，
+//! 之后的唤醒要经过发送方的系统调用与执行器的调度。生产者与消费者之间的交接只需亚微秒时，这一开销远大于等待本身。
+//!
+//! 为通知源设置[`SpinPolicy`]后，`poll_wait`在通知尚未到达时先在调用线程上自旋、再让出CPU，期间反复检查通知源，
+//! 用完预算仍未收到通知才注册waker并返回`Poll::Pending`。预算是自适应的：自旋期间收到通知时恢复为策略给出的上限，
+//! 否则减半（至少为1），使等待长的通知源很快不再浪费CPU，而交接变快时又能恢复自旋。
+//!
+//! 自旋期间执行器线程被占用，因此只应为交接很快的通知源设置，且预算不宜过大。策略在通知源被释放时清除。
+//!
+//! 对延迟极敏感、独占核心的线程，[`Notification::wait_on_busy`]一直自旋到通知到达，从不挂起；
+//! [`Notification::wait_on_hybrid`]自旋给定的时间后转为普通的等待。[`Notification::wait_on_mode`]以[`WaitMode`]选择其一，
+//! 便于在同一段代码中对比各种等待方式。自旋只轮询通知源本身，对`uintr`、`mock`与本进程内的`signal`通知不进行系统调用，
+//! 而基于文件描述符的通知源的每次轮询仍需读取文件描述符。
+
+extern crate std;
+
+use alloc::collections::btree_map::BTreeMap;
+use core::{
+    future::Future,
+    hint,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::time::Instant;
+
+use crate::{
+    interface::{Notification, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 自旋等待的预算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinPolicy {
+    /// 最多自旋检查的次数，每两次检查之间执行一次`spin_loop`提示
+    pub spins: u32,
+    /// 自旋之后最多以`yield_now`让出CPU再检查的次数
+    pub yields: u32,
+}
+
+impl SpinPolicy {
+    /// 最多自旋检查`spins`次、不让出CPU的策略
+    pub const fn new(spins: u32) -> Self {
+        Self { spins, yields: 0 }
+    }
+
+    /// 自旋之后再最多让出CPU`yields`次
+    pub const fn yields(mut self, yields: u32) -> Self {
+        self.yields = yields;
+        self
+    }
+}
+
+/// 通知源的策略与当前的自旋预算
+struct SpinState {
+    policy: SpinPolicy,
+    budget: u32,
+}
+
+/// 设置了策略的通知源，以id为key
+static STATES: SpinMutex<BTreeMap<u64, SpinState>> = SpinMutex::new(BTreeMap::new());
+
+/// 按通知源`id`的策略自旋检查`inner`，用完预算后以`cx`轮询`inner`；未设置策略时直接以`cx`轮询
+pub(crate) fn poll<T>(
+    id: u64,
+    cx: &mut Context<'_>,
+    mut inner: impl FnMut(&mut Context<'_>) -> Poll<T>,
+) -> Poll<T> {
+    let Some((policy, budget)) = STATES
+        .lock()
+        .get(&id)
+        .map(|state| (state.policy, state.budget))
+    else {
+        return inner(cx);
+    };
+    // 自旋期间不注册调用者的waker；最后一次轮询使用`cx`，期间到达的通知不会丢失
+    let waker = futures::task::noop_waker();
+    let mut spin_cx = Context::from_waker(&waker);
+    let spun = (0..budget)
+        .map(|_| hint::spin_loop())
+        .chain((0..policy.yields).map(|_| std::thread::yield_now()))
+        .find_map(|_| match inner(&mut spin_cx) {
+            Poll::Ready(value) => Some(value),
+            Poll::Pending => None,
+        });
+    if let Some(state) = STATES.lock().get_mut(&id) {
+        state.budget = if spun.is_some() {
+            policy.spins
+        } else {
+            (state.budget / 2).max(1)
+        };
+    }
+    if let Some(value) = spun {
+        return Poll::Ready(value);
+    }
+    inner(cx)
+}
+
+/// 等待的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WaitMode {
+    /// 通知未到达时挂起，与`wait_on`相同（默认）
+    #[default]
+    Park,
+    /// 一直自旋到通知到达，从不挂起
+    Busy,
+    /// 自旋给定的时间，之后挂起
+    Hybrid(Duration),
+}
+
+/// 每自旋检查多少次读取一次时钟
+const CLOCK_INTERVAL: u32 = 64;
+
+/// [`Notification::wait_on_mode`]等返回的future
+///
+/// 与`wait_on`一样是取消安全的。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct BusyWait {
+    id: u64,
+    mode: WaitMode,
+    /// `Hybrid`下开始自旋的时刻
+    start: Option<Instant>,
+}
+
+impl BusyWait {
+    /// 所等待的通知源id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Future for BusyWait {
+    type Output = WakeReason;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        use crate::interface::NotificationIf;
+
+        let this = self.get_mut();
+        let limit = match this.mode {
+            WaitMode::Park => {
+                return Notification::poll_wait_reason(this.id, cx);
+            }
+            WaitMode::Busy => None,
+            WaitMode::Hybrid(limit) => Some(limit),
+        };
+        let start = *this.start.get_or_insert_with(Instant::now);
+        let waker = futures::task::noop_waker();
+        let mut spin_cx = Context::from_waker(&waker);
+        let mut checks = 0u32;
+        loop {
+            if let Poll::Ready(reason) = Notification::poll_wait_reason(this.id, &mut spin_cx) {
+                return Poll::Ready(reason);
+            }
+            checks = checks.wrapping_add(1);
+            if let Some(limit) = limit {
+                if checks % CLOCK_INTERVAL == 0 && start.elapsed() >= limit {
+                    // 自旋的时间已用完，之后的轮询均为普通的等待
+                    this.mode = WaitMode::Park;
+                    return Notification::poll_wait_reason(this.id, cx);
+                }
+            }
+            hint::spin_loop();
+        }
+    }
+}
+
+/// 通知源`id`即将被释放
+pub(crate) fn release(id: u64) {
+    STATES.lock().remove(&id);
+}
+
+impl Notification {
+    /// 设置通知源`id`的自旋等待策略，`None`表示通知未到达时立即挂起（默认）
+    pub fn set_spin_policy(id: u64, policy: Option<SpinPolicy>) {
+        let mut states = STATES.lock();
+        match policy {
+            Some(policy) => {
+                states.insert(
+                    id,
+                    SpinState {
+                        policy,
+                        budget: policy.spins,
+                    },
+                );
+            }
+            None => {
+                states.remove(&id);
+            }
+        }
+    }
+
+    /// 通知源`id`的自旋等待策略
+    pub fn spin_policy(id: u64) -> Option<SpinPolicy> {
+        STATES.lock().get(&id).map(|state| state.policy)
+    }
+
+    /// 以`mode`给出的方式等待通知源`id`
+    pub fn wait_on_mode(id: u64, mode: WaitMode) -> BusyWait {
+        BusyWait {
+            id,
+            mode,
+            start: None,
+        }
+    }
+
+    /// 一直自旋到通知源`id`收到通知，从不挂起，只应在独占核心的线程中使用
+    pub fn wait_on_busy(id: u64) -> BusyWait {
+        Self::wait_on_mode(id, WaitMode::Busy)
+    }
+
+    /// 在通知源`id`上自旋等待至多`spin_for`，之后挂起
+    pub fn wait_on_hybrid(id: u64, spin_for: Duration) -> BusyWait {
+        Self::wait_on_mode(id, WaitMode::Hybrid(spin_for))
+    }
+
+    /// 通知源`id`当前的自旋预算，即下一次等待最多自旋检查的次数；未设置策略时返回`None`
+    pub fn spin_budget(id: u64) -> Option<u32> {
+        STATES.lock().get(&id).map(|state| state.budget)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    #[test]
+    fn test_spin_wait() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let id = Notification::new_id_mock().unwrap();
+        assert_eq!(Notification::spin_budget(id), None);
+        Notification::set_spin_policy(id, Some(SpinPolicy::new(8).yields(2)));
+        assert_eq!(
+            Notification::spin_policy(id),
+            Some(SpinPolicy::new(8).yields(2))
+        );
+
+        // 未收到通知时预算减半
+        assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        assert_eq!(Notification::spin_budget(id), Some(4));
+        for _ in 0..4 {
+            assert!(Notification::poll_wait(id, &mut cx).is_pending());
+        }
+        assert_eq!(Notification::spin_budget(id), Some(1));
+
+        // 自旋期间收到通知时预算恢复
+        crate::mock::trigger(id);
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        assert_eq!(Notification::spin_budget(id), Some(8));
+
+        // 另一线程在让出CPU期间发出的通知在同一次轮询中被消费
+        Notification::set_spin_policy(id, Some(SpinPolicy::new(16).yields(u32::MAX)));
+        let notifier = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            crate::mock::trigger(id);
+        });
+        assert!(Notification::poll_wait(id, &mut cx).is_ready());
+        notifier.join().unwrap();
+        assert_eq!(Notification::spin_budget(id), Some(16));
+
+        unsafe { Notification::release_id(id) };
+        assert_eq!(Notification::spin_policy(id), None);
+    }
+
+    #[test]
+    fn test_wait_modes() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let id = Notification::new_id_mock().unwrap();
+
+        let mut park = core::pin::pin!(Notification::wait_on_mode(id, WaitMode::default()));
+        assert!(park.as_mut().poll(&mut cx).is_pending());
+        crate::mock::trigger(id);
+        assert_eq!(
+            park.as_mut().poll(&mut cx),
+            Poll::Ready(WakeReason::NOTIFIED)
+        );
+
+        let notifier = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            crate::mock::trigger(id);
+        });
+        let mut busy = core::pin::pin!(Notification::wait_on_busy(id));
+        assert!(busy.as_mut().poll(&mut cx).is_ready());
+        notifier.join().unwrap();
+
+        let start = Instant::now();
+        let mut hybrid =
+            core::pin::pin!(Notification::wait_on_hybrid(id, Duration::from_millis(5)));
+        assert!(hybrid.as_mut().poll(&mut cx).is_pending());
+        assert!(start.elapsed() >= Duration::from_millis(5));
+        // 自旋的时间已用完后不再自旋
+        let start = Instant::now();
+        assert!(hybrid.as_mut().poll(&mut cx).is_pending());
+        assert!(start.elapsed() < Duration::from_millis(5));
+        crate::mock::trigger(id);
+        assert!(hybrid.as_mut().poll(&mut cx).is_ready());
+
+        unsafe { Notification::release_id(id) };
+    }
+}
@@ -0,0 +1,203 @@
+//! 每个通知源的统计
+//!
+//! 启用`stats` feature时，[`Notification`]为每个id累计发送、消费与等待时间等计数，
+//! 可通过[`Notification::stats`]与[`Notification::stats_all`]读出，供导出到监控系统或在基准测试中量化通知的丢失与延迟。
+//!
+//! 计数只在本进程内累计：发送计数记录在发送方进程中，以目标id为key；消费与等待计数记录在接收方进程中。
+//! 两者之差即为丢失或被合并的通知。计数在id被申请时清零，在释放后仍保留，直到该id被重新申请或调用[`Notification::reset_stats`]。
+//!
+//! 只统计经由[`Notification`]的操作，直接使用各通知机制时不统计。需要std。
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::time::Duration;
+
+use crate::{interface::Notification, sync::SpinMutex};
+
+/// 等待时间直方图的桶的上界（微秒），最后一个桶统计超过最大上界的等待
+pub const WAIT_BUCKET_BOUNDS_US: [u64; 7] = [1, 10, 100, 1_000, 10_000, 100_000, 1_000_000];
+
+/// 等待时间直方图的桶数
+pub const WAIT_BUCKETS: usize = WAIT_BUCKET_BOUNDS_US.len() + 1;
+
+/// 一个通知源的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdStats {
+    /// 成功发送的通知数
+    pub notifies: u64,
+    /// 发送失败的通知数
+    pub notify_errors: u64,
+    /// 发送成功、但与尚未被接收的通知合并的通知数（已计入`notifies`），目前只有`Delivery::BestEffort`的信号通知会被合并
+    pub coalesced: u64,
+    /// 被`poll_wait`（包括`wait_on`）消费的通知数
+    pub wakes: u64,
+    /// 由[`SentinelMonitor`](crate::sentinel::SentinelMonitor)统计的、在不排队的通知源上与其他通知合并为一次唤醒的通知数
+    #[cfg(feature = "sentinel")]
+    pub sentinel_coalesced: u64,
+    /// 由[`SentinelMonitor`](crate::sentinel::SentinelMonitor)统计的、在排队的通知源上疑似丢失的通知数
+    #[cfg(feature = "sentinel")]
+    pub sentinel_lost: u64,
+    /// 等待时间的直方图：`wait_buckets[i]`为等待时间不超过`WAIT_BUCKET_BOUNDS_US[i]`（且超过前一个上界）的`wait_on`次数
+    pub wait_buckets: [u64; WAIT_BUCKETS],
+}
+
+impl IdStats {
+    /// 完成的`wait_on`次数
+    pub fn waits(&self) -> u64 {
+        self.wait_buckets.iter().sum()
+    }
+}
+
+static STATS: SpinMutex<BTreeMap<u64, IdStats>> = SpinMutex::new(BTreeMap::new());
+
+fn update(id: u64, f: impl FnOnce(&mut IdStats)) {
+    f(STATS.lock().entry(id).or_default());
+}
+
+pub(crate) fn reset(id: u64) {
+    STATS.lock().insert(id, IdStats::default());
+}
+
+pub(crate) fn record_notify(id: u64, ok: bool) {
+    update(id, |stats| {
+        if ok {
+            stats.notifies += 1;
+        } else {
+            stats.notify_errors += 1;
+        }
+    });
+}
+
+pub(crate) fn record_coalesced(id: u64) {
+    update(id, |stats| stats.coalesced += 1);
+}
+
+pub(crate) fn record_wake(id: u64) {
+    update(id, |stats| stats.wakes += 1);
+}
+
+#[cfg(feature = "sentinel")]
+pub(crate) fn record_sentinel(id: u64, coalesced: u64, lost: u64) {
+    update(id, |stats| {
+        stats.sentinel_coalesced += coalesced;
+        stats.sentinel_lost += lost;
+    });
+}
+
+pub(crate) fn record_wait(id: u64, waited: Duration) {
+    let us = waited.as_micros();
+    let bucket = WAIT_BUCKET_BOUNDS_US
+        .iter()
+        .position(|&bound| us <= bound as u128)
+        .unwrap_or(WAIT_BUCKETS - 1);
+    update(id, |stats| stats.wait_buckets[bucket] += 1);
+}
+
+impl Notification {
+    /// 通知源`id`的统计，从未被统计过时返回`None`
+    pub fn stats(id: u64) -> Option<IdStats> {
+        STATS.lock().get(&id).copied()
+    }
+
+    /// 所有被统计过的通知源及其统计，按id排序
+    pub fn stats_all() -> Vec<(u64, IdStats)> {
+        STATS
+            .lock()
+            .iter()
+            .map(|(&id, &stats)| (id, stats))
+            .collect()
+    }
+
+    /// 清除所有统计
+    pub fn reset_stats() {
+        STATS.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        // 不与其他测试中的id冲突
+        let id = 0xff00_0000_0000_0319;
+        reset(id);
+        record_notify(id, true);
+        record_notify(id, true);
+        record_notify(id, false);
+        record_coalesced(id);
+        record_wake(id);
+        record_wait(id, Duration::from_micros(50));
+        record_wait(id, Duration::from_secs(5));
+        let stats = Notification::stats(id).unwrap();
+        assert_eq!(stats.notifies, 2);
+        assert_eq!(stats.notify_errors, 1);
+        assert_eq!(stats.coalesced, 1);
+        assert_eq!(stats.wakes, 1);
+        assert_eq!(stats.wait_buckets[2], 1);
+        assert_eq!(stats.wait_buckets[WAIT_BUCKETS - 1], 1);
+        assert_eq!(stats.waits(), 2);
+        assert!(Notification::stats_all().contains(&(id, stats)));
+        reset(id);
+        assert_eq!(Notification::stats(id), Some(IdStats::default()));
+    }
+
+    #[cfg(all(feature = "mock", feature = "no-panic"))]
+    #[test]
+    fn test_stats_failed_notify() {
+        use crate::interface::NotificationIf;
+
+        let id = Notification::new_id_mock().unwrap();
+        Notification::notify(0, id);
+        unsafe { Notification::release_id(id) };
+        // 发送到已被释放的通知源失败，不被计为成功
+        Notification::notify(0, id);
+        let stats = Notification::stats(id).unwrap();
+        assert_eq!((stats.notifies, stats.notify_errors), (1, 1));
+        reset(id);
+    }
+
+    #[cfg(feature = "kvm")]
+    #[test]
+    fn test_stats_kvm() {
+        use crate::interface::NotificationIf;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                assert_eq!(Notification::stats(id), Some(IdStats::default()));
+                Notification::notify(0, id);
+                Notification::notify(0, id);
+                Notification::wait_on(id).await;
+                let stats = Notification::stats(id).unwrap();
+                assert_eq!((stats.notifies, stats.wakes, stats.waits()), (2, 1, 1));
+                unsafe { Notification::release_id(id) };
+            });
+    }
+
+    #[cfg(feature = "mqueue")]
+    #[test]
+    fn test_stats_payload() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                use crate::interface::NotificationIf;
+
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_mqueue(2, 8).unwrap();
+                // 带有负载的发送与其他发送相同地被统计
+                Notification::notify_with_payload(pid, id, b"stats").unwrap();
+                assert!(Notification::notify_with_payload(pid, id, &[0; 16]).is_err());
+                let stats = Notification::stats(id).unwrap();
+                assert_eq!((stats.notifies, stats.notify_errors), (1, 1));
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
@@ -0,0 +1,170 @@
+//! 适用于`no_std`环境的同步原语
+//!
+//! 在`--cfg async_notification_loom`下，[`atomic`]、[`Mutex`]与[`AtomicWaker`]被替换为loom的实现，
+//! 以便对使用它们的数据结构进行模型检查。[`SpinMutex`]可在静态变量中构造，因此不被替换，不应用于被模型检查的数据结构中。
+
+use core::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// 自旋锁
+///
+/// 仅用于保护临界区很短的数据（例如对信号流进行一次轮询），不应在持有锁时等待。
+pub(crate) struct SpinMutex<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinMutex<T> {}
+unsafe impl<T: Send> Send for SpinMutex<T> {}
+
+impl<T> SpinMutex<T> {
+    /// 新建自旋锁
+    pub(crate) const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// 获取锁
+    pub(crate) fn lock(&self) -> SpinMutexGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+        SpinMutexGuard { mutex: self }
+    }
+}
+
+/// 自旋锁的守卫，离开作用域时释放锁
+pub(crate) struct SpinMutexGuard<'a, T> {
+    mutex: &'a SpinMutex<T>,
+}
+
+impl<T> Deref for SpinMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for SpinMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+/// 按缓存行对齐并填充的值，使相邻的值不在同一缓存行中，避免并发访问时的伪共享
+///
+/// 对齐为128字节：x86_64的相邻行预取与aarch64的部分实现以128字节为单位。
+#[cfg(feature = "signal")]
+#[repr(align(128))]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+#[cfg(feature = "signal")]
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// 原子类型，在`--cfg async_notification_loom`下为loom的实现
+///
+/// loom的原子类型不能在常量中构造，因此静态变量仍直接使用`core`中的原子类型。
+#[cfg(feature = "signal")]
+pub(crate) mod atomic {
+    #[cfg(not(async_notification_loom))]
+    pub(crate) use core::sync::atomic::*;
+    #[cfg(async_notification_loom)]
+    pub(crate) use loom::sync::atomic::*;
+}
+
+/// 可被模型检查的锁，平时为[`SpinMutex`]
+#[cfg(all(feature = "signal", not(async_notification_loom)))]
+pub(crate) type Mutex<T> = SpinMutex<T>;
+
+/// 可被模型检查的锁，在`--cfg async_notification_loom`下为loom的`Mutex`
+#[cfg(all(feature = "signal", async_notification_loom))]
+pub(crate) struct Mutex<T>(loom::sync::Mutex<T>);
+
+#[cfg(all(feature = "signal", async_notification_loom))]
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T) -> Self {
+        Self(loom::sync::Mutex::new(data))
+    }
+
+    pub(crate) fn lock(&self) -> loom::sync::MutexGuard<'_, T> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// 可被模型检查的`AtomicWaker`，平时为`futures`的实现
+#[cfg(all(feature = "signal", not(async_notification_loom)))]
+pub(crate) use futures::task::AtomicWaker;
+
+/// 可被模型检查的`AtomicWaker`，在`--cfg async_notification_loom`下为loom的实现
+#[cfg(all(feature = "signal", async_notification_loom))]
+pub(crate) struct AtomicWaker(loom::future::AtomicWaker);
+
+#[cfg(all(feature = "signal", async_notification_loom))]
+impl AtomicWaker {
+    pub(crate) fn new() -> Self {
+        Self(loom::future::AtomicWaker::new())
+    }
+
+    pub(crate) fn register(&self, waker: &core::task::Waker) {
+        self.0.register_by_ref(waker);
+    }
+
+    pub(crate) fn wake(&self) {
+        self.0.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{sync::Arc, vec::Vec};
+
+    extern crate std;
+
+    #[test]
+    fn test_spin_mutex_contention() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 10000;
+
+        let counter = Arc::new(SpinMutex::new(0usize));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let counter = counter.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..ROUNDS {
+                        *counter.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*counter.lock(), THREADS * ROUNDS);
+    }
+}
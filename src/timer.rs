@@ -0,0 +1,246 @@
+//! 使用timerfd的周期性通知机制
+//!
+//! 每个通知源对应一个周期性触发的timerfd，在其上`wait_on`会在每次定时器到期时结束，
+//! 从而使心跳等周期性事件可以与其他通知源使用相同的id与等待方式。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use std::os::fd::OwnedFd;
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用timerfd的周期性通知机制
+pub struct TimerNotification;
+
+struct TimerSlot {
+    fd: AsyncFd<OwnedRawFd>,
+    /// 已从timerfd中读出、但尚未被`poll_wait`消费的到期次数
+    pending: AtomicU64,
+}
+
+impl TimerSlot {
+    /// 读出timerfd上的到期次数，若尚未到期则返回0
+    fn read_expirations(&self) -> u64 {
+        let mut expirations: u64 = 0;
+        let res = unsafe {
+            libc::read(
+                self.fd.get_ref().0,
+                &mut expirations as *mut u64 as *mut libc::c_void,
+                core::mem::size_of::<u64>(),
+            )
+        };
+        if res == core::mem::size_of::<u64>() as isize {
+            expirations
+        } else {
+            0
+        }
+    }
+
+    /// 若有到期则消费一次，不阻塞
+    fn take(&self) -> bool {
+        let take = || {
+            self.pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+        };
+        take()
+            || match self.read_expirations() {
+                0 => false,
+                expirations => {
+                    self.pending.fetch_add(expirations, Ordering::AcqRel);
+                    take()
+                }
+            }
+    }
+
+    fn poll_tick(&self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if self
+                .pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+            {
+                return Poll::Ready(());
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(())),
+                Poll::Pending => return Poll::Pending,
+            };
+            match self.read_expirations() {
+                0 => guard.clear_ready(),
+                expirations => {
+                    self.pending.fetch_add(expirations, Ordering::AcqRel);
+                }
+            }
+        }
+    }
+}
+
+/// 所有被占用的timerfd，以fd为key
+static TIMERS: SpinMutex<BTreeMap<u64, Arc<TimerSlot>>> = SpinMutex::new(BTreeMap::new());
+
+impl NotificationIf for TimerNotification {
+    /// 返回`None`
+    ///
+    /// 定时器需要指定周期，应使用`new_id_with_period`。
+    fn new_id() -> Option<u64> {
+        None
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 每次定时器到期，都可使一次等待结束；若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_tick(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let Some(slot) = Self::slot(id) else {
+            waker.wake_by_ref();
+            return;
+        };
+        if slot.pending.load(Ordering::Acquire) > 0 {
+            waker.wake_by_ref();
+            return;
+        }
+        let mut cx = Context::from_waker(waker);
+        if let Poll::Ready(guard) = slot.fd.poll_read_ready(&mut cx) {
+            let mut guard = ready_guard!(guard, return waker.wake_by_ref());
+            match slot.read_expirations() {
+                0 => guard.clear_ready(),
+                expirations => {
+                    slot.pending.fetch_add(expirations, Ordering::AcqRel);
+                    waker.wake_by_ref();
+                }
+            }
+        }
+    }
+
+    /// 关闭timerfd
+    unsafe fn release_id(id: u64) {
+        let slot = TIMERS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 定时器不能由其他进程触发，调用该函数会panic（启用`no-panic`时输出错误日志后忽略）
+    fn notify(_process: u64, id: u64) {
+        fail!((), "notify: timer id 0x{:016x} cannot be notified", id)
+    }
+}
+
+impl TimerNotification {
+    /// 申请一个每隔`period`到期一次的定时器，并返回其id，首次到期在`period`之后
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将timerfd注册到tokio的reactor中。
+    pub fn new_id_with_period(period: Duration) -> Option<u64> {
+        assert!(!period.is_zero());
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        let timer = OwnedRawFd(fd);
+        let spec = libc::timespec {
+            tv_sec: period.as_secs() as libc::time_t,
+            tv_nsec: period.subsec_nanos() as libc::c_long,
+        };
+        let value = libc::itimerspec {
+            it_interval: spec,
+            it_value: spec,
+        };
+        let res = unsafe { libc::timerfd_settime(fd, 0, &value, core::ptr::null_mut()) };
+        if res != 0 {
+            return None;
+        }
+        let slot = TimerSlot {
+            fd: AsyncFd::new(timer).ok()?,
+            pending: AtomicU64::new(0),
+        };
+        TIMERS.lock().insert(fd as u64, Arc::new(slot));
+        Some(fd as u64)
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        crate::fd::take_unique(&TIMERS, id).map(|slot| slot.fd.into_inner().into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.fd.get_ref().0,
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<TimerSlot>> {
+        TIMERS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    extern crate std;
+
+    #[test]
+    fn test_timer_ticks() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // 在设置定时器之前取得开始时间，第三次到期不早于其后60ms
+                let start = std::time::Instant::now();
+                let id = Notification::new_id_timer(Duration::from_millis(20)).unwrap();
+                for _ in 0..3 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                assert!(start.elapsed() >= Duration::from_millis(60));
+
+                // 未被等待的到期被累计
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+
+                unsafe { Notification::release_id(id) };
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+            });
+    }
+}
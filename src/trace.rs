@@ -0,0 +1,209 @@
+//! 埋点
+//!
+//! [`Notification`]的`new_id_*`、`notify`（及`notify_with`、`notify_to`）、`poll_wait`、`release_id`与`wait_on`
+//! 在以下函数处报告事件，由启用的feature决定如何处理：
+//!
+//! - `tracing`：产生target为`async_notification`的事件，其中`id`字段以`类型:id`的形式显示。`wait_on`的事件带有
+//!   从首次轮询到被唤醒的等待时间`latency_us`，用于排查丢失的唤醒；
+//! - `stats`：累加[`stats`](crate::stats)中的计数；
+//! - `event-log`：记录在[`event_log`](crate::event_log)的环形缓冲区中；
+//! - `record`：被消费的通知记录在[`record`](crate::record)正在录制的序列中。
+//!
+//! 均未启用时以下函数为空。
+
+#[cfg(any(feature = "tracing", feature = "stats"))]
+extern crate std;
+
+#[cfg(feature = "tracing")]
+use crate::interface::Notification;
+use crate::interface::{NotifyError, ProcessRef};
+
+/// 事件的target
+#[cfg(feature = "tracing")]
+const TARGET: &str = "async_notification";
+
+/// 申请了通知源`id`
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "stats", feature = "event-log")),
+    allow(unused_variables)
+)]
+#[inline]
+pub(crate) fn new_id(id: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, id = %Notification::display(id), "new_id");
+    #[cfg(feature = "stats")]
+    crate::stats::reset(id);
+    #[cfg(feature = "event-log")]
+    crate::event_log::record(crate::event_log::EventKind::NewId, id);
+}
+
+/// 即将释放通知源`id`
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "event-log")),
+    allow(unused_variables)
+)]
+#[inline]
+pub(crate) fn release_id(id: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, id = %Notification::display(id), "release_id");
+    #[cfg(feature = "event-log")]
+    crate::event_log::record(crate::event_log::EventKind::Release, id);
+}
+
+/// 向`target`的通知源`id`发送了通知
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+pub(crate) fn notify(target: ProcessRef, id: u64, result: Result<(), NotifyError>) {
+    #[cfg(feature = "tracing")]
+    match result {
+        Ok(()) => tracing::trace!(
+            target: TARGET,
+            id = %Notification::display(id),
+            to = ?target,
+            "notify"
+        ),
+        Err(err) => tracing::warn!(
+            target: TARGET,
+            id = %Notification::display(id),
+            to = ?target,
+            error = %err,
+            "notify failed"
+        ),
+    }
+    #[cfg(feature = "stats")]
+    crate::stats::record_notify(id, result.is_ok());
+    #[cfg(feature = "event-log")]
+    crate::event_log::record(
+        match result {
+            Ok(()) => crate::event_log::EventKind::Notify,
+            Err(_) => crate::event_log::EventKind::NotifyFailed,
+        },
+        id,
+    );
+}
+
+/// 发往通知源`id`的通知被接受，但与尚未被接收的通知合并
+#[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+#[cfg_attr(not(feature = "signal"), allow(dead_code))]
+#[inline]
+pub(crate) fn coalesced(id: u64) {
+    #[cfg(feature = "stats")]
+    crate::stats::record_coalesced(id);
+}
+
+/// 通知源`id`上的一个通知被`poll_wait`消费
+#[cfg_attr(
+    not(any(feature = "stats", feature = "event-log", feature = "record")),
+    allow(unused_variables)
+)]
+#[inline]
+pub(crate) fn wake(id: u64) {
+    #[cfg(feature = "stats")]
+    crate::stats::record_wake(id);
+    #[cfg(feature = "event-log")]
+    crate::event_log::record(crate::event_log::EventKind::Wake, id);
+    #[cfg(feature = "record")]
+    crate::record::received(id, None, &[]);
+}
+
+/// 等待开始的时刻，在`wait_on`的future首次被轮询时记录
+#[cfg(any(feature = "tracing", feature = "stats"))]
+pub(crate) type WaitStart = Option<std::time::Instant>;
+
+/// `wait_on`的future被轮询，`ready`表示其已完成
+#[cfg(any(feature = "tracing", feature = "stats"))]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn wait_on(backend: &'static str, id: u64, start: &mut WaitStart, ready: bool) {
+    let start = start.get_or_insert_with(std::time::Instant::now);
+    if !ready {
+        return;
+    }
+    let waited = start.elapsed();
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        target: TARGET,
+        backend,
+        id = %Notification::display(id),
+        latency_us = waited.as_micros() as u64,
+        "wait_on woken"
+    );
+    #[cfg(feature = "stats")]
+    crate::stats::record_wait(id, waited);
+}
+
+#[cfg(all(test, feature = "tracing", feature = "kvm"))]
+mod tests {
+    extern crate std;
+
+    use crate::interface::{Notification, NotificationIf};
+    use alloc::{format, string::String, vec::Vec};
+    use std::sync::{Arc, Mutex};
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+
+    /// 以`message id=...`的形式记录事件
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            match field.name() {
+                "message" => self.0.insert_str(0, &format!("{:?}", value)),
+                "id" => self.0.push_str(&format!(" id={:?}", value)),
+                _ => {}
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "async_notification"
+        }
+        fn new_span(&self, _span: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+        fn enter(&self, _span: &Id) {}
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn test_trace_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Recorder(events.clone());
+        let id = tracing::subscriber::with_default(subscriber, || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let id = Notification::new_id_kvm_ioeventfd().unwrap();
+                    Notification::notify(0, id);
+                    Notification::wait_on(id).await;
+                    unsafe { Notification::release_id(id) };
+                    id
+                })
+        });
+        let id = Notification::display(id);
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            [
+                format!("new_id id={}", id),
+                format!("notify id={}", id),
+                format!("wait_on woken id={}", id),
+                format!("release_id id={}", id),
+            ]
+        );
+    }
+}
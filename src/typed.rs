@@ -0,0 +1,173 @@
+//! 以类型区分通知机制的id
+//!
+//! [`Notification`](crate::interface::Notification)在运行时根据id的高8位分发到各通知机制。
+//! 只使用一种通知机制的应用可以改用[`Id<B>`]：其方法直接调用`B`的实现，没有分发的开销，
+//! 且不同通知机制的id具有不同的类型，不会被混用。
+//!
+//! `Id<B>`中保存的是`B`自身的id，不带有`Notification`的类型标签，因此不能与`Notification`的id互换。
+
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    task::{Context, Poll, Waker},
+};
+
+use crate::interface::{NotificationIf, WaitOn};
+
+/// 通知机制`B`的通知源id
+pub struct Id<B: NotificationIf> {
+    raw: u64,
+    _marker: PhantomData<fn() -> B>,
+}
+
+/// 使用信号的通知源id
+#[cfg(feature = "signal")]
+pub type SignalId = Id<crate::signal::SignalNotification>;
+
+/// 使用用户态中断的通知源id
+#[cfg(feature = "uintr")]
+pub type UintrId = Id<crate::uintr::UIntrNotification>;
+
+impl<B: NotificationIf> Id<B> {
+    /// 申请一个新的通知源，见[`NotificationIf::new_id`]
+    pub fn new() -> Option<Self> {
+        B::new_id().map(|raw| unsafe { Self::from_raw(raw) })
+    }
+
+    /// 由`B`的id构造
+    ///
+    /// # Safety
+    ///
+    /// `raw`须为`B`的id（例如由`B::new_id`返回，或由其他进程经IPC传来的`B`的id）。
+    pub const unsafe fn from_raw(raw: u64) -> Self {
+        Self {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    /// `B`的id
+    pub const fn raw(self) -> u64 {
+        self.raw
+    }
+
+    /// 在通知源上等待，见[`NotificationIf::wait_on`]
+    pub fn wait_on(self) -> WaitOn<B> {
+        WaitOn::new(self.raw)
+    }
+
+    /// 轮询通知源，见[`NotificationIf::poll_wait`]
+    pub fn poll_wait(self, cx: &mut Context<'_>) -> Poll<()> {
+        B::poll_wait(self.raw, cx)
+    }
+
+    /// 注册waker，见[`NotificationIf::register_waker`]
+    pub fn register_waker(self, waker: &Waker) {
+        B::register_waker(self.raw, waker)
+    }
+
+    /// 向进程`process`的该通知源发送通知，见[`NotificationIf::notify`]
+    pub fn notify(self, process: u64) {
+        B::notify(process, self.raw)
+    }
+
+    /// 释放通知源，见[`NotificationIf::release_id`]
+    ///
+    /// # Safety
+    ///
+    /// 与[`NotificationIf::release_id`]相同。
+    pub unsafe fn release(self) {
+        unsafe { B::release_id(self.raw) }
+    }
+}
+
+impl<B: NotificationIf> Clone for Id<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B: NotificationIf> Copy for Id<B> {}
+
+impl<B: NotificationIf> PartialEq for Id<B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<B: NotificationIf> Eq for Id<B> {}
+
+impl<B: NotificationIf> PartialOrd for Id<B> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<B: NotificationIf> Ord for Id<B> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.raw.cmp(&other.raw)
+    }
+}
+
+impl<B: NotificationIf> Hash for Id<B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+impl<B: NotificationIf> fmt::Debug for Id<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Id<{}>(0x{:x})", core::any::type_name::<B>(), self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Id;
+    use crate::interface::NotificationIf;
+    use core::{
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll, Waker},
+    };
+
+    /// 只有一个通知源的通知机制
+    struct Flag;
+
+    static FLAG: AtomicBool = AtomicBool::new(false);
+
+    impl NotificationIf for Flag {
+        fn new_id() -> Option<u64> {
+            Some(7)
+        }
+
+        fn poll_wait(_id: u64, _cx: &mut Context<'_>) -> Poll<()> {
+            if FLAG.swap(false, Ordering::AcqRel) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn register_waker(_id: u64, _waker: &Waker) {}
+
+        unsafe fn release_id(_id: u64) {}
+
+        fn notify(_process: u64, _id: u64) {
+            FLAG.store(true, Ordering::Release);
+        }
+    }
+
+    #[test]
+    fn test_typed_id() {
+        let id = Id::<Flag>::new().unwrap();
+        assert_eq!(id.raw(), 7);
+        assert_eq!(id, unsafe { Id::from_raw(7) });
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(id.poll_wait(&mut cx).is_pending());
+        id.notify(0);
+        futures::executor::block_on(id.wait_on());
+        unsafe { id.release() };
+    }
+}
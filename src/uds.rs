@@ -0,0 +1,534 @@
+//! 使用unix域套接字的通知机制
+//!
+//! 每个通知源对应一个绑定到抽象命名空间地址`async_notification.<pid>.<id>`的数据报套接字，
+//! `notify`向接收方进程的该地址发送一个1字节的数据报，因此通知源的数量不受信号编号范围的限制。
+//!
+//! 发送方还可以通过[`UdsNotification::send_fds`]经`SCM_RIGHTS`向接收方传递文件描述符（例如eventfd或用户态中断的fd），
+//! 这同时会发送一个通知；接收方在等待结束后通过[`UdsNotification::take_fds`]取出收到的文件描述符。
+//! 因此该通知源也可用作其他通知机制交换文件描述符的引导通道。
+//!
+//! 接收方的套接字启用了`SO_PASSCRED`，每个数据报都带有内核填写的发送方进程号与用户id，
+//! 因此可以用[`UdsNotification::set_sender_filter`]只接受给定发送方的通知。
+//! 带令牌的通知（[`UdsNotification::notify_token`]）在1字节之后附加8字节的令牌。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, format, sync::Arc, vec::Vec};
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    filter::{FilterSlot, SenderFilter},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用unix域套接字的通知机制
+pub struct UdsNotification;
+
+/// 一次`send_fds`最多传递的文件描述符数量
+pub const MAX_FDS: usize = 16;
+
+/// 能容纳`MAX_FDS`个文件描述符的控制消息缓冲区
+type CmsgBuf = [u64; 12];
+
+/// 接收时的控制消息缓冲区，还需容纳`SCM_CREDENTIALS`
+type RecvCmsgBuf = [u64; 16];
+
+/// 带令牌的数据报的长度
+const TOKEN_LEN: usize = 1 + mem::size_of::<u64>();
+
+/// 所有被占用的套接字，以id为key
+static SOCKETS: SpinMutex<BTreeMap<u64, Arc<ReadySlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 已收到但尚未被取出的文件描述符，以套接字的文件描述符为key
+static RECEIVED: SpinMutex<BTreeMap<RawFd, Vec<OwnedFd>>> = SpinMutex::new(BTreeMap::new());
+
+/// 设置过过滤器的套接字，以套接字的文件描述符为key
+static FILTERS: SpinMutex<BTreeMap<RawFd, FilterSlot>> = SpinMutex::new(BTreeMap::new());
+
+/// 下一个被分配的id
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 本进程用于发送通知的套接字
+static SENDER: SpinMutex<Option<OwnedRawFd>> = SpinMutex::new(None);
+
+fn socket() -> Option<OwnedRawFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_UNIX,
+            libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    (fd >= 0).then_some(OwnedRawFd(fd))
+}
+
+/// 进程`pid`的通知源`id`的抽象命名空间地址
+fn addr(pid: u64, id: u64) -> (libc::sockaddr_un, libc::socklen_t) {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let name = format!("async_notification.{}.{}", pid, id);
+    // sun_path[0]为0表示抽象命名空间
+    for (dst, src) in addr.sun_path[1..].iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + 1 + name.len();
+    (addr, len as libc::socklen_t)
+}
+
+/// 读出套接字上的所有数据报，返回被过滤器接受的数据报的数量；随其到达的文件描述符被暂存到`RECEIVED`中
+///
+/// 被过滤器丢弃的数据报所带的文件描述符被关闭。
+fn drain(fd: RawFd) -> u64 {
+    let mut count = 0;
+    let mut fds = Vec::new();
+    loop {
+        let mut data = [0u8; TOKEN_LEN];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: TOKEN_LEN,
+        };
+        let mut cmsg_buf: RecvCmsgBuf = [0; 16];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of::<RecvCmsgBuf>() as _;
+        let len = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if len < 0 {
+            break;
+        }
+        let token =
+            (len as usize == TOKEN_LEN).then(|| u64::from_le_bytes(data[1..].try_into().unwrap()));
+        let mut msg_fds = Vec::new();
+        let mut sender = None;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_RIGHTS {
+                let data = unsafe { libc::CMSG_DATA(cmsg) } as *const libc::c_int;
+                let len = header.cmsg_len as usize - unsafe { libc::CMSG_LEN(0) } as usize;
+                for i in 0..len / mem::size_of::<libc::c_int>() {
+                    let raw = unsafe { ptr::read_unaligned(data.add(i)) };
+                    msg_fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+                }
+            } else if header.cmsg_level == libc::SOL_SOCKET
+                && header.cmsg_type == libc::SCM_CREDENTIALS
+            {
+                let cred =
+                    unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::ucred) };
+                sender = Some((cred.pid as u32, cred.uid));
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        let accepted = FILTERS
+            .lock()
+            .get(&fd)
+            .map_or(true, |filter| filter.accept(sender, token));
+        if accepted {
+            count += 1;
+            fds.extend(msg_fds);
+        }
+    }
+    if !fds.is_empty() {
+        RECEIVED.lock().entry(fd).or_default().extend(fds);
+    }
+    count
+}
+
+impl NotificationIf for UdsNotification {
+    /// 绑定一个抽象命名空间地址
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let fd = socket()?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (local, len) = addr(unsafe { libc::getpid() } as u64, id);
+        let res = unsafe {
+            libc::bind(
+                fd.0,
+                &local as *const libc::sockaddr_un as *const libc::sockaddr,
+                len,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        Self::register(id, fd)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_wait(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id) {
+            Some(slot) => slot.register_waker(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭套接字，并关闭已收到但尚未被取出的文件描述符
+    unsafe fn release_id(id: u64) {
+        let Some(slot) = SOCKETS.lock().remove(&id) else {
+            fail!(return, "release_id: id 0x{:016x} is not allocated", id)
+        };
+        RECEIVED.lock().remove(&slot.raw_fd());
+        FILTERS.lock().remove(&slot.raw_fd());
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl UdsNotification {
+    /// 向进程`process`的通知源`id`发送通知，失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        Self::send_fds(process, id, &[])
+    }
+
+    /// 向进程`process`的通知源`id`传递文件描述符，同时发送一个通知
+    ///
+    /// 文件描述符在接收方被复制，发送方仍持有`fds`。最多传递`MAX_FDS`个文件描述符，超出时返回`NotifyError::Overflow`。
+    pub fn send_fds(process: u64, id: u64, fds: &[RawFd]) -> Result<(), NotifyError> {
+        Self::send(process, id, fds, None)
+    }
+
+    /// 检查进程`process`的通知源`id`的地址是否已被绑定，不发送通知
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        let fd = socket().ok_or(NotifyError::Unsupported)?;
+        let (peer, peer_len) = addr(process, id);
+        let res = unsafe {
+            libc::connect(
+                fd.0,
+                &peer as *const libc::sockaddr_un as *const libc::sockaddr,
+                peer_len,
+            )
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(unsafe { *libc::__errno_location() }))
+        }
+    }
+
+    /// 向进程`process`的通知源`id`发送携带令牌`token`的通知
+    pub fn notify_token(process: u64, id: u64, token: u64) -> Result<(), NotifyError> {
+        Self::send(process, id, &[], Some(token))
+    }
+
+    fn send(process: u64, id: u64, fds: &[RawFd], token: Option<u64>) -> Result<(), NotifyError> {
+        if fds.len() > MAX_FDS {
+            return Err(NotifyError::Overflow);
+        }
+        let (peer, peer_len) = addr(process, id);
+        let mut data = [1u8; TOKEN_LEN];
+        let len = match token {
+            Some(token) => {
+                data[1..].copy_from_slice(&token.to_le_bytes());
+                TOKEN_LEN
+            }
+            None => 1,
+        };
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: len,
+        };
+        let mut cmsg_buf: CmsgBuf = [0; 12];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &peer as *const libc::sockaddr_un as *mut libc::c_void;
+        msg.msg_namelen = peer_len;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            let data_len = mem::size_of_val(fds) as libc::c_uint;
+            msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = unsafe { libc::CMSG_SPACE(data_len) } as _;
+            unsafe {
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+                ptr::copy_nonoverlapping(
+                    fds.as_ptr(),
+                    libc::CMSG_DATA(cmsg) as *mut RawFd,
+                    fds.len(),
+                );
+            }
+        }
+
+        let mut sender = SENDER.lock();
+        if sender.is_none() {
+            *sender = Some(socket().ok_or(NotifyError::Unsupported)?);
+        }
+        let fd = sender.as_ref().unwrap().0;
+        if unsafe { libc::sendmsg(fd, &msg, 0) } == len as isize {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            // 接收队列已满
+            libc::EAGAIN => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 取出通知源`id`上已收到的文件描述符
+    ///
+    /// 文件描述符在等待该通知源时被读出，因此应在等待结束后调用。
+    pub fn take_fds(id: u64) -> Vec<OwnedFd> {
+        let Some(slot) = Self::slot(id) else {
+            return Vec::new();
+        };
+        RECEIVED.lock().remove(&slot.raw_fd()).unwrap_or_default()
+    }
+
+    /// 只接受`filter`允许的发送方的通知，`None`表示接受所有通知；若通知源未被占用，则返回`false`
+    ///
+    /// 被丢弃的通知所带的文件描述符被关闭。已读出但尚未被消费的通知不受影响。
+    pub fn set_sender_filter(id: u64, filter: Option<SenderFilter>) -> bool {
+        let Some(slot) = Self::slot(id) else {
+            return false;
+        };
+        FILTERS
+            .lock()
+            .entry(slot.raw_fd())
+            .or_insert_with(FilterSlot::new)
+            .set(filter);
+        true
+    }
+
+    /// 为通知源生成新的令牌，之后只接受带有该令牌的通知；若通知源未被占用或无法取得随机数，则返回`None`
+    pub fn mint_token(id: u64) -> Option<u64> {
+        let slot = Self::slot(id)?;
+        let token = crate::filter::mint()?;
+        FILTERS
+            .lock()
+            .entry(slot.raw_fd())
+            .or_insert_with(FilterSlot::new)
+            .set_token(Some(token));
+        Some(token)
+    }
+
+    /// 不再要求通知源上的通知带有令牌；若通知源未被占用，则返回`false`
+    pub fn clear_token(id: u64) -> bool {
+        let Some(slot) = Self::slot(id) else {
+            return false;
+        };
+        if let Some(filter) = FILTERS.lock().get(&slot.raw_fd()) {
+            filter.set_token(None);
+        }
+        true
+    }
+
+    /// 被过滤器丢弃的通知数；若通知源未被占用，则返回`None`
+    pub fn dropped_by_filter(id: u64) -> Option<u64> {
+        let slot = Self::slot(id)?;
+        Some(
+            FILTERS
+                .lock()
+                .get(&slot.raw_fd())
+                .map_or(0, FilterSlot::dropped),
+        )
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 已收到但尚未被取出的文件描述符被关闭。
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        let slot = crate::fd::take_unique(&SOCKETS, id)?;
+        RECEIVED.lock().remove(&slot.raw_fd());
+        FILTERS.lock().remove(&slot.raw_fd());
+        Some(slot.into_fd().into_owned())
+    }
+
+    /// 接管一个已绑定地址的unix域数据报套接字（例如由[`into_raw_fd`](Self::into_raw_fd)从另一个进程交出），
+    /// 将其作为可在其上等待的通知源，并返回其id
+    ///
+    /// 套接字保留其地址，因此发送方仍以原来的进程号与id向其发送通知；返回的id只用于在本进程中等待。
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    pub fn new_id_from_fd(fd: OwnedFd) -> Option<u64> {
+        let fd = OwnedRawFd(fd.into_raw_fd());
+        let flags = unsafe { libc::fcntl(fd.0, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd.0, libc::F_SETFL, flags | libc::O_NONBLOCK) } != 0 {
+            return None;
+        }
+        Self::register(NEXT_ID.fetch_add(1, Ordering::Relaxed), fd)
+    }
+
+    /// 启用`SO_PASSCRED`，并将已绑定地址的套接字登记为通知源`id`
+    fn register(id: u64, fd: OwnedRawFd) -> Option<u64> {
+        // 使每个数据报都带有发送方的身份，供过滤器检查
+        let on: libc::c_int = 1;
+        let res = unsafe {
+            libc::setsockopt(
+                fd.0,
+                libc::SOL_SOCKET,
+                libc::SO_PASSCRED,
+                &on as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        let slot = ReadySlot::new(fd, drain)?;
+        SOCKETS.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.raw_fd(),
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<ReadySlot>> {
+        SOCKETS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+    use std::os::fd::AsRawFd;
+
+    extern crate std;
+
+    #[test]
+    fn test_uds_notify_and_fds() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let id = Notification::new_id_uds().unwrap();
+                assert_eq!(Notification::can_notify(pid, id), Ok(()));
+                Notification::notify(pid, id);
+                Notification::notify(pid, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+
+                let mut pipe = [0; 2];
+                assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+                Notification::uds_send_fds(pid, id, &[pipe[1]]).unwrap();
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                let fds = Notification::uds_take_fds(id);
+                assert_eq!(fds.len(), 1);
+                // 收到的文件描述符是管道写端的副本
+                let byte = 7u8;
+                let res = unsafe {
+                    libc::write(
+                        fds[0].as_raw_fd(),
+                        &byte as *const u8 as *const libc::c_void,
+                        1,
+                    )
+                };
+                assert_eq!(res, 1);
+                let mut read = 0u8;
+                unsafe { libc::read(pipe[0], &mut read as *mut u8 as *mut libc::c_void, 1) };
+                assert_eq!(read, 7);
+                unsafe {
+                    libc::close(pipe[0]);
+                    libc::close(pipe[1]);
+                    Notification::release_id(id);
+                }
+                // 接收方已不存在
+                assert_eq!(
+                    Notification::can_notify(pid, id),
+                    Err(crate::interface::NotifyError::Os(libc::ECONNREFUSED))
+                );
+                assert!(
+                    Notification::notify_with(pid, id, crate::interface::Delivery::Reliable)
+                        .is_err()
+                );
+            });
+    }
+
+    #[test]
+    fn test_uds_sender_filter() {
+        use crate::filter::SenderFilter;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let pid = unsafe { libc::getpid() } as u64;
+                let uid = unsafe { libc::getuid() };
+                let id = Notification::new_id_uds().unwrap();
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+
+                // 只允许其他进程时，本进程的通知及其文件描述符被丢弃
+                let other = SenderFilter::new().allow_pid(pid as u32 + 1);
+                assert!(Notification::set_sender_filter(id, Some(other)));
+                Notification::notify(pid, id);
+                Notification::uds_send_fds(pid, id, &[0]).unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                assert!(Notification::uds_take_fds(id).is_empty());
+                assert_eq!(Notification::dropped_by_filter(id), Some(2));
+
+                let own = SenderFilter::new().allow_uid(uid);
+                assert!(Notification::set_sender_filter(id, Some(own)));
+                Notification::notify(pid, id);
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                assert_eq!(Notification::dropped_by_filter(id), Some(2));
+
+                // 要求令牌时，不带令牌或令牌错误的通知被丢弃
+                assert!(Notification::set_sender_filter(id, None));
+                let token = Notification::mint_token(id).unwrap();
+                Notification::notify(pid, id);
+                Notification::notify_with_token(pid, id, token ^ 1).unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+                assert_eq!(Notification::dropped_by_filter(id), Some(4));
+                Notification::notify_with_token(pid, id, token).unwrap();
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+                assert!(Notification::clear_token(id));
+                Notification::notify(pid, id);
+                tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                    .await
+                    .unwrap();
+
+                unsafe { Notification::release_id(id) };
+                assert_eq!(Notification::dropped_by_filter(id), None);
+                assert!(!Notification::set_sender_filter(id, None));
+            });
+    }
+}
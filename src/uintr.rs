@@ -1,23 +1,1041 @@
 //! 使用用户态中断的通知机制
-use crate::interface::NotificationIf;
+//!
+//! 用户态中断（UIPI）的接收方是线程：每个接收线程有64个中断向量，发送方通过向量对应的uintr fd向其发送中断。
+//! 本模块像`signal`管理信号编号那样管理中断向量：接收线程先以[`UIntrNotification::register_receiver`]登记，
+//! 之后在该线程中`new_id`从其空闲的向量中按[`ReusePolicy`]分配一个；向量也可以被固定（pin）在某个接收线程上，
+//! 只能由[`UIntrNotification::new_id_with_vector`]显式申请。
+//!
+//! id的低8位为向量，其上的位为接收线程的登记序号，因此不同接收线程上的同一向量对应不同的id。
+//!
+//! 每个核心一个线程的运行时可以让每个核心上的线程以[`UIntrNotification::register_core_receiver`]登记为绑定到该核心的接收线程，
+//! 之后以[`UIntrNotification::new_id_local`]在当前核心的接收线程上分配向量，或以[`UIntrNotification::new_id_on_core`]
+//! 在指定核心上分配。通知源因此绑定到核心：`notify`的中断总是送往该核心，在同一核心上的本进程通知不经过中断直接送达，
+//! 避免核心之间来回发送处理器间中断。
+//!
+//! 发送方需先取得接收方为向量创建的uintr fd并以`uintr_register_sender`登记，之后才能以`senduipi`发送中断。
+//! 接收方调用[`UIntrNotification::serve_sender`]经unix域套接字交出uintr fd，发送方调用
+//! [`UIntrNotification::connect_sender`]收取并登记（已继承uintr fd时使用[`UIntrNotification::connect_sender_fd`]），
+//! 之后`notify`即可向该通知源发送中断。
+//!
+//! 主线内核尚未提供用户态中断的系统调用，各版本补丁的调用号也不相同（Intel的RFC v1使用的调用号在主线内核中已被其他系统调用占用），
+//! 因此需要先以[`UIntrNotification::set_syscalls`]按运行的内核设置调用号，否则以上函数返回`NotifyError::Unsupported`。
+//!
+//! 接收线程以[`UIntrNotification::install_handler`]安装本模块的中断处理函数。中断可能在接收线程执行任意代码时到达，
+//! 处理函数因此只将向量记入该线程的待处理位图，不获取锁、不分配内存，也不唤醒协程；`poll_wait`直接读取位图，
+//! 在中断处理函数中被推迟的唤醒由[`UIntrNotification::dispatch`]在普通的上下文中完成，应由执行器定期调用
+//! （例如在每次轮询协程之后，或在tokio的`on_thread_unpark`中）。
 
-/// 使用用户态中断的通知机制（未完成）
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::{
+    interface::{NotificationIf, NotifyError},
+    sync::SpinMutex,
+};
+
+/// 每个接收线程的中断向量数
+pub const VECTORS: u32 = 64;
+
+/// 最多同时登记的接收线程数
+pub const MAX_RECEIVERS: usize = 64;
+
+/// 使用用户态中断的通知机制
+///
+/// `poll_wait`是取消安全的：只在返回`Poll::Ready(())`时清除中断的待处理位。
 pub struct UIntrNotification;
 
+/// 从空闲的向量中选取向量的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReusePolicy {
+    /// 选取编号最小的空闲向量
+    #[default]
+    LowestFirst,
+    /// 从上次分配的向量之后开始选取，使刚被释放的向量最晚被重新分配，
+    /// 减少仍持有旧向量的发送方打扰新的占用者的机会
+    RoundRobin,
+}
+
+/// 一个接收线程的向量占用情况
+struct Receiver {
+    /// 登记序号，作为id中向量之上的位
+    index: u64,
+    /// 被占用的向量，第i位对应向量i
+    used: u64,
+    /// 被固定的向量，不被`new_id`自动分配
+    pinned: u64,
+    /// `RoundRobin`下一次开始选取的向量
+    next: u32,
+    /// 以`register_core_receiver`登记时绑定的核心
+    core: Option<u32>,
+}
+
+impl Receiver {
+    /// 按策略选取并占用一个既未被占用、也未被固定的向量
+    fn alloc(&mut self, policy: ReusePolicy) -> Option<u32> {
+        let free = !(self.used | self.pinned);
+        let start = match policy {
+            ReusePolicy::LowestFirst => 0,
+            ReusePolicy::RoundRobin => self.next,
+        };
+        let vector = (0..VECTORS)
+            .map(|i| (start + i) % VECTORS)
+            .find(|&vector| free & (1 << vector) != 0)?;
+        self.used |= 1 << vector;
+        self.next = (vector + 1) % VECTORS;
+        Some(vector)
+    }
+}
+
+/// 用户态中断的系统调用号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Syscalls {
+    /// `uintr_create_fd(vector, flags)`：为调用线程的向量创建uintr fd
+    pub create_fd: libc::c_long,
+    /// `uintr_register_sender(uintr_fd, flags)`：登记为发送方，返回`senduipi`使用的索引
+    pub register_sender: libc::c_long,
+    /// `uintr_unregister_sender(uintr_fd, flags)`：注销发送方的登记
+    pub unregister_sender: libc::c_long,
+    /// `uintr_register_handler(handler, flags)`：为调用线程安装中断处理函数
+    pub register_handler: libc::c_long,
+    /// `uintr_unregister_handler(flags)`：卸载调用线程的中断处理函数
+    pub unregister_handler: libc::c_long,
+}
+
+/// 由`set_syscalls`设置的调用号
+static SYSCALLS: SpinMutex<Option<Syscalls>> = SpinMutex::new(None);
+
+/// 发送方对一个通知源的登记
+struct Sender {
+    /// 接收方交出的uintr fd，注销登记时使用
+    fd: OwnedFd,
+    /// `senduipi`使用的索引
+    index: u64,
+}
+
+/// 本进程作为发送方登记过的通知源，以`(接收进程, id)`为key
+static SENDERS: SpinMutex<BTreeMap<(u64, u64), Sender>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程作为接收方为通知源创建的uintr fd，以id为key，在`release_id`时关闭
+static VECTOR_FDS: SpinMutex<BTreeMap<u64, OwnedFd>> = SpinMutex::new(BTreeMap::new());
+
+/// 以当前设置的调用号`nr`发起系统调用，返回非负的结果
+fn syscall(
+    nr: impl FnOnce(&Syscalls) -> libc::c_long,
+    a: libc::c_long,
+) -> Result<i64, NotifyError> {
+    let nr = nr(SYSCALLS.lock().as_ref().ok_or(NotifyError::Unsupported)?);
+    let res = unsafe { libc::syscall(nr, a, 0 as libc::c_long) };
+    if res < 0 {
+        return Err(NotifyError::Os(unsafe { *libc::__errno_location() }));
+    }
+    Ok(res)
+}
+
+/// 向`senduipi`索引为`index`的接收方发送中断
+#[cfg(target_arch = "x86_64")]
+fn senduipi(index: u64) -> Result<(), NotifyError> {
+    unsafe { core::arch::asm!("senduipi {}", in(reg) index, options(nomem, nostack)) };
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn senduipi(_index: u64) -> Result<(), NotifyError> {
+    Err(NotifyError::Unsupported)
+}
+
+/// 已登记的接收线程，以线程id为key
+static RECEIVERS: SpinMutex<BTreeMap<u64, Receiver>> = SpinMutex::new(BTreeMap::new());
+
+/// 一个接收线程的待处理中断，由中断处理函数写入
+struct Pending {
+    /// 已到达而尚未被`poll_wait`取走的中断，第i位对应向量i
+    bits: AtomicU64,
+    /// 自上次`dispatch`以来是否有中断到达
+    dirty: AtomicBool,
+}
+
+impl Pending {
+    /// 记录向量`vector`的中断已到达
+    ///
+    /// 中断处理函数只能调用这一个函数：它只进行原子操作，且不会panic。
+    #[inline(always)]
+    fn raise(&self, vector: u32) {
+        // 内核只投递已登记的向量；超出范围的向量不对应任何通知源，被忽略
+        if vector >= VECTORS {
+            return;
+        }
+        self.bits.fetch_or(1 << vector, Ordering::Release);
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// 取走向量`vector`的中断，返回其是否已到达
+    fn take(&self, vector: u32) -> bool {
+        self.bits.fetch_and(!(1 << vector), Ordering::Acquire) & (1 << vector) != 0
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_PENDING: Pending = Pending {
+    bits: AtomicU64::new(0),
+    dirty: AtomicBool::new(false),
+};
+
+/// 各接收线程的待处理中断，以登记序号为下标
+static PENDING: [Pending; MAX_RECEIVERS] = [NO_PENDING; MAX_RECEIVERS];
+
+/// 在`poll_wait`中等待的协程，以id为key
+static WAKERS: SpinMutex<BTreeMap<u64, Waker>> = SpinMutex::new(BTreeMap::new());
+
+std::thread_local! {
+    /// 调用线程的登记序号，未登记时为`usize::MAX`
+    ///
+    /// 常量初始化且不需要析构，中断处理函数读取它时不会分配内存或注册析构函数。
+    static SLOT: Cell<usize> = const { Cell::new(usize::MAX) };
+
+    /// 调用线程是否正在执行中断处理函数
+    static IN_HANDLER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 用户态中断处理函数，由`async_notification_uintr_entry`保存被打断的上下文后调用，`vector`为到达的向量
+///
+/// 中断可能打断接收线程中的任意代码，包括正持有本模块的锁、正在分配内存或正在唤醒协程的代码，因此这里只能读写
+/// 常量初始化的线程局部变量与调用[`Pending::raise`]，不能获取锁、分配或释放内存、调用`Waker::wake`，也不能panic。
+/// 唤醒协程被推迟到[`UIntrNotification::dispatch`]。
+extern "C" fn on_uintr(vector: u64) {
+    IN_HANDLER.with(|flag| flag.set(true));
+    if let Some(pending) = PENDING.get(SLOT.with(Cell::get)) {
+        pending.raise(vector as u32);
+    }
+    IN_HANDLER.with(|flag| flag.set(false));
+}
+
+/// 检查调用处不在中断处理函数中；本模块中会获取锁或唤醒协程的函数都先调用它
+fn assert_safe_context() {
+    debug_assert!(
+        !IN_HANDLER.with(Cell::get),
+        "uintr: called from the user interrupt handler"
+    );
+}
+
+// 中断处理函数的入口。进入时栈已按16字节对齐，栈顶依次为向量、被打断处的RIP、RFLAGS与RSP；
+// 保存调用者保存的通用寄存器与x87/SSE状态后调用`on_uintr`，恢复后弹出向量并以`uiret`返回。
+// 9个寄存器与520字节的保存区共592字节，使调用`on_uintr`与`fxsave64`时栈按16字节对齐。
+#[cfg(target_arch = "x86_64")]
+core::arch::global_asm!(
+    ".pushsection .text.async_notification_uintr_entry, \"ax\", @progbits",
+    ".globl async_notification_uintr_entry",
+    ".hidden async_notification_uintr_entry",
+    ".p2align 4",
+    "async_notification_uintr_entry:",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "sub rsp, 520",
+    "fxsave64 [rsp]",
+    "cld",
+    "mov rdi, [rsp + 592]",
+    "call {handler}",
+    "fxrstor64 [rsp]",
+    "add rsp, 520",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "add rsp, 8",
+    "uiret",
+    ".popsection",
+    handler = sym on_uintr,
+);
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    /// 以上汇编定义的中断处理函数入口，只应被交给`uintr_register_handler`，不能被直接调用
+    fn async_notification_uintr_entry();
+}
+
+/// 当前的`ReusePolicy`，以其在枚举中的顺序存储
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+fn current_tid() -> u64 {
+    unsafe { libc::gettid() as u64 }
+}
+
+/// 调用线程当前所在的核心
+fn current_core() -> Option<u32> {
+    u32::try_from(unsafe { libc::sched_getcpu() }).ok()
+}
+
+/// 将id拆分为接收线程的登记序号与向量；向量超出范围（不是由本模块分配的id）时返回`None`
+fn split(id: u64) -> Option<(u64, u32)> {
+    let vector = (id & 0xFF) as u32;
+    (vector < VECTORS).then_some((id >> 8, vector))
+}
+
 impl NotificationIf for UIntrNotification {
+    /// 在调用线程上分配一个向量；调用线程未登记为接收线程或其向量已被占用完时返回`None`
     fn new_id() -> Option<u64> {
-        todo!()
+        let policy = Self::reuse_policy();
+        let mut receivers = RECEIVERS.lock();
+        let receiver = receivers.get_mut(&current_tid())?;
+        let vector = receiver.alloc(policy)?;
+        Some((receiver.index << 8) | vector as u64)
+    }
+
+    /// 读取接收线程的待处理位图，中断未到达时保存waker，由`dispatch`唤醒
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        assert_safe_context();
+        let Some((pending, vector)) =
+            split(id).and_then(|(index, vector)| Some((PENDING.get(index as usize)?, vector)))
+        else {
+            fail!(
+                return Poll::Pending,
+                "poll_wait: invalid uintr id 0x{:x}",
+                id
+            )
+        };
+        if pending.take(vector) {
+            WAKERS.lock().remove(&id);
+            return Poll::Ready(());
+        }
+        WAKERS.lock().insert(id, cx.waker().clone());
+        // 保存waker之前到达、且已被`dispatch`跳过的中断
+        if pending.take(vector) {
+            WAKERS.lock().remove(&id);
+            return Poll::Ready(());
+        }
+        Poll::Pending
     }
 
-    async fn wait_on(id: u64) {
-        todo!()
+    fn register_waker(id: u64, waker: &Waker) {
+        assert_safe_context();
+        let Some((index, vector)) = split(id) else {
+            waker.wake_by_ref();
+            fail!(return, "register_waker: invalid uintr id 0x{:x}", id)
+        };
+        let pending = PENDING.get(index as usize);
+        if pending.map_or(true, |pending| {
+            pending.bits.load(Ordering::Acquire) & (1 << vector) != 0
+        }) {
+            waker.wake_by_ref();
+            return;
+        }
+        WAKERS.lock().insert(id, waker.clone());
     }
 
+    /// 释放向量，被固定的向量仍保持固定
     unsafe fn release_id(id: u64) {
-        todo!()
+        assert_safe_context();
+        let Some((index, vector)) = split(id) else {
+            fail!(return, "release_id: invalid uintr id 0x{:x}", id)
+        };
+        let mut receivers = RECEIVERS.lock();
+        let receiver = receivers
+            .values_mut()
+            .find(|receiver| receiver.index == index)
+            .filter(|receiver| receiver.used & (1 << vector) != 0);
+        check!(
+            receiver.is_some(),
+            "release_id: uintr id 0x{:x} is not allocated",
+            id
+        );
+        if let Some(receiver) = receiver {
+            receiver.used &= !(1 << vector);
+        }
+        drop(receivers);
+        VECTOR_FDS.lock().remove(&id);
+        WAKERS.lock().remove(&id);
+        if let Some(pending) = PENDING.get(index as usize) {
+            pending.take(vector);
+        }
     }
 
+    /// 需要先以`connect_sender`登记为该通知源的发送方
     fn notify(process: u64, id: u64) {
-        todo!()
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl UIntrNotification {
+    /// 将调用线程登记为接收线程，返回其登记序号；已登记时返回原来的序号
+    ///
+    /// 登记序号取最小的未被使用的值，已有`MAX_RECEIVERS`个接收线程时返回`None`。
+    pub fn register_receiver() -> Option<u64> {
+        assert_safe_context();
+        let mut receivers = RECEIVERS.lock();
+        let tid = current_tid();
+        if let Some(receiver) = receivers.get(&tid) {
+            return Some(receiver.index);
+        }
+        let used: Vec<u64> = receivers.values().map(|receiver| receiver.index).collect();
+        let index = (0..MAX_RECEIVERS as u64).find(|index| !used.contains(index))?;
+        // 上一个使用该序号的接收线程注销前已释放所有向量，这里只清除注销后才到达的中断
+        PENDING[index as usize].bits.store(0, Ordering::Relaxed);
+        receivers.insert(
+            tid,
+            Receiver {
+                index,
+                used: 0,
+                pinned: 0,
+                next: 0,
+                core: None,
+            },
+        );
+        SLOT.with(|slot| slot.set(index as usize));
+        Some(index)
+    }
+
+    /// 注销调用线程的登记；调用线程未登记或仍有被占用的向量时返回`false`
+    ///
+    /// 已安装中断处理函数时应先以`uninstall_handler`卸载。
+    pub fn unregister_receiver() -> bool {
+        assert_safe_context();
+        let mut receivers = RECEIVERS.lock();
+        let tid = current_tid();
+        match receivers.get(&tid) {
+            Some(receiver) if receiver.used == 0 => {
+                receivers.remove(&tid);
+                SLOT.with(|slot| slot.set(usize::MAX));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 为调用线程安装本模块的中断处理函数，需先以`register_receiver`登记
+    ///
+    /// 调用线程未登记时返回`NotifyError::Os(EPERM)`，非x86_64平台上返回`NotifyError::Unsupported`。
+    pub fn install_handler() -> Result<(), NotifyError> {
+        assert_safe_context();
+        if SLOT.with(Cell::get) == usize::MAX {
+            return Err(NotifyError::Os(libc::EPERM));
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            let entry = async_notification_uintr_entry as *const () as libc::c_long;
+            syscall(|nr| nr.register_handler, entry).map(|_| ())
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        Err(NotifyError::Unsupported)
+    }
+
+    /// 卸载调用线程的中断处理函数
+    pub fn uninstall_handler() -> Result<(), NotifyError> {
+        assert_safe_context();
+        syscall(|nr| nr.unregister_handler, 0).map(|_| ())
+    }
+
+    /// 唤醒中断已到达的通知源上等待的协程，返回被唤醒的协程数
+    ///
+    /// 中断处理函数不能唤醒协程，只记录到达的中断，因此需要由执行器在普通的上下文中定期调用该函数；
+    /// 可以在任意线程中调用。每个有中断到达的接收线程只读取一次待处理位图，并在一次加锁中取出所有对应的waker，
+    /// 释放锁后再依次唤醒。不消费中断，中断仍由`poll_wait`取走。
+    pub fn dispatch() -> usize {
+        assert_safe_context();
+        let mut woken = 0;
+        for (index, pending) in PENDING.iter().enumerate() {
+            if !pending.dirty.swap(false, Ordering::Acquire) {
+                continue;
+            }
+            let bits = pending.bits.load(Ordering::Acquire);
+            let mut wakers = WAKERS.lock();
+            let ready: Vec<Waker> = (0..VECTORS)
+                .filter(|vector| bits & (1 << vector) != 0)
+                .filter_map(|vector| wakers.remove(&(((index as u64) << 8) | vector as u64)))
+                .collect();
+            drop(wakers);
+            woken += ready.len();
+            ready.into_iter().for_each(Waker::wake);
+        }
+        woken
+    }
+
+    /// 调用线程上可被`new_id`分配的向量数，不包括被固定的向量；调用线程未登记时返回0
+    pub fn vectors_available() -> u32 {
+        Self::vectors_available_on(current_tid())
+    }
+
+    /// 线程`tid`上可被`new_id`分配的向量数；线程未登记时返回0
+    pub fn vectors_available_on(tid: u64) -> u32 {
+        RECEIVERS.lock().get(&tid).map_or(0, |receiver| {
+            (!(receiver.used | receiver.pinned)).count_ones()
+        })
+    }
+
+    /// 将线程`tid`上的向量`vector`固定，使其不被`new_id`自动分配，只能由`new_id_with_vector`申请
+    ///
+    /// 线程未登记或向量超出范围时返回`false`。向量已被占用时也可以固定，在其被释放后生效。
+    pub fn pin(tid: u64, vector: u32) -> bool {
+        if vector >= VECTORS {
+            return false;
+        }
+        match RECEIVERS.lock().get_mut(&tid) {
+            Some(receiver) => {
+                receiver.pinned |= 1 << vector;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 取消向量的固定；线程未登记或向量未被固定时返回`false`
+    pub fn unpin(tid: u64, vector: u32) -> bool {
+        if vector >= VECTORS {
+            return false;
+        }
+        match RECEIVERS.lock().get_mut(&tid) {
+            Some(receiver) if receiver.pinned & (1 << vector) != 0 => {
+                receiver.pinned &= !(1 << vector);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 占用线程`tid`上指定的向量（无论其是否被固定），返回其id
+    ///
+    /// 线程未登记、向量超出范围或已被占用时返回`None`。
+    pub fn new_id_with_vector(tid: u64, vector: u32) -> Option<u64> {
+        if vector >= VECTORS {
+            return None;
+        }
+        let mut receivers = RECEIVERS.lock();
+        let receiver = receivers.get_mut(&tid)?;
+        if receiver.used & (1 << vector) != 0 {
+            return None;
+        }
+        receiver.used |= 1 << vector;
+        Some((receiver.index << 8) | vector as u64)
+    }
+
+    /// 设置用户态中断的系统调用号，`None`表示不支持
+    pub fn set_syscalls(syscalls: Option<Syscalls>) {
+        *SYSCALLS.lock() = syscalls;
+    }
+
+    /// 接收方：为通知源`id`的向量创建uintr fd（已创建时复用），并经已连接的unix域套接字`socket`交给发送方
+    ///
+    /// 需在`id`所在的接收线程中调用，否则返回`NotifyError::Os(EPERM)`；通知源未被占用时返回`NotifyError::Os(EBADF)`。
+    pub fn serve_sender(id: u64, socket: RawFd) -> Result<(), NotifyError> {
+        let (index, vector) = split(id).ok_or(NotifyError::Os(libc::EBADF))?;
+        let owned = RECEIVERS
+            .lock()
+            .get(&current_tid())
+            .is_some_and(|receiver| receiver.index == index && receiver.used & (1 << vector) != 0);
+        if !owned {
+            return Err(NotifyError::Os(if Self::is_allocated(id) {
+                libc::EPERM
+            } else {
+                libc::EBADF
+            }));
+        }
+        let mut fds = VECTOR_FDS.lock();
+        let fd = match fds.get(&id) {
+            Some(fd) => fd.as_raw_fd(),
+            None => {
+                let raw = syscall(|nr| nr.create_fd, vector as libc::c_long)? as RawFd;
+                fds.entry(id)
+                    .or_insert(unsafe { OwnedFd::from_raw_fd(raw) })
+                    .as_raw_fd()
+            }
+        };
+        crate::scm::send(socket, id, Some(fd))
+    }
+
+    /// 发送方：从已连接的unix域套接字`socket`收取接收进程`process`以`serve_sender`交出的uintr fd并登记，返回通知源的id
+    pub fn connect_sender(process: u64, socket: RawFd) -> Result<u64, NotifyError> {
+        let (id, fd) = crate::scm::recv(socket)?;
+        let fd = fd.ok_or(NotifyError::Os(libc::EPROTO))?;
+        Self::connect_sender_fd(process, id, fd)?;
+        Ok(id)
+    }
+
+    /// 发送方：以已取得（例如从父进程继承）的uintr fd登记为进程`process`的通知源`id`的发送方
+    ///
+    /// 已登记过时替换原来的登记。
+    pub fn connect_sender_fd(process: u64, id: u64, fd: OwnedFd) -> Result<(), NotifyError> {
+        let index = syscall(|nr| nr.register_sender, fd.as_raw_fd() as libc::c_long)? as u64;
+        let old = SENDERS.lock().insert((process, id), Sender { fd, index });
+        if let Some(old) = old {
+            let _ = syscall(
+                |nr| nr.unregister_sender,
+                old.fd.as_raw_fd() as libc::c_long,
+            );
+        }
+        Ok(())
+    }
+
+    /// 发送方：注销对进程`process`的通知源`id`的登记；未登记时返回`false`
+    pub fn disconnect_sender(process: u64, id: u64) -> bool {
+        let Some(sender) = SENDERS.lock().remove(&(process, id)) else {
+            return false;
+        };
+        let _ = syscall(
+            |nr| nr.unregister_sender,
+            sender.fd.as_raw_fd() as libc::c_long,
+        );
+        true
+    }
+
+    /// 检查是否已登记为进程`process`的通知源`id`的发送方；未登记时返回`NotifyError::Os(ENOTCONN)`
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        if SENDERS.lock().contains_key(&(process, id)) {
+            Ok(())
+        } else {
+            Err(NotifyError::Os(libc::ENOTCONN))
+        }
+    }
+
+    /// 向进程`process`的通知源`id`发送中断；未登记为其发送方时返回`NotifyError::Os(ENOTCONN)`
+    ///
+    /// 通知本进程中绑定到调用线程所在核心的通知源时，直接记录中断并唤醒等待的协程，不需要登记为发送方。
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        assert_safe_context();
+        if process == unsafe { libc::getpid() } as u64 && Self::deliver_local(id) {
+            return Ok(());
+        }
+        let index = SENDERS
+            .lock()
+            .get(&(process, id))
+            .map(|sender| sender.index)
+            .ok_or(NotifyError::Os(libc::ENOTCONN))?;
+        senduipi(index)
+    }
+
+    /// 通知源`id`绑定到调用线程所在的核心时，直接记录其中断并唤醒等待的协程
+    fn deliver_local(id: u64) -> bool {
+        let Some((index, vector)) = split(id) else {
+            return false;
+        };
+        let local = current_core().is_some_and(|core| {
+            RECEIVERS.lock().values().any(|receiver| {
+                receiver.index == index
+                    && receiver.core == Some(core)
+                    && receiver.used & (1 << vector) != 0
+            })
+        });
+        if !local {
+            return false;
+        }
+        PENDING[index as usize].raise(vector);
+        if let Some(waker) = WAKERS.lock().remove(&id) {
+            waker.wake();
+        }
+        true
+    }
+
+    /// 一次取走调用线程上所有已到达的中断，返回对应的id，按向量排序；调用线程未登记时返回空的`Vec`
+    ///
+    /// 只以一次原子操作读取并清除待处理位图，适用于在每轮循环中批量处理中断、而不经过`poll_wait`的数据面线程。
+    /// 被取走的中断不再使`poll_wait`返回`Poll::Ready(())`。
+    pub fn take_pending() -> Vec<u64> {
+        assert_safe_context();
+        let Some(pending) = PENDING.get(SLOT.with(Cell::get)) else {
+            return Vec::new();
+        };
+        let index = SLOT.with(Cell::get) as u64;
+        pending.dirty.store(false, Ordering::Relaxed);
+        let bits = pending.bits.swap(0, Ordering::Acquire);
+        (0..VECTORS)
+            .filter(|vector| bits & (1 << vector) != 0)
+            .map(|vector| (index << 8) | vector as u64)
+            .collect()
+    }
+
+    /// 将调用线程绑定到核心`core`并登记为该核心的接收线程，返回其登记序号
+    ///
+    /// 核心已有其他接收线程时返回`NotifyError::Os(EBUSY)`，调用线程已登记过时返回`NotifyError::Os(EEXIST)`，
+    /// 无法绑定到该核心时返回`sched_setaffinity`的错误。
+    pub fn register_core_receiver(core: u32) -> Result<u64, NotifyError> {
+        assert_safe_context();
+        if Self::receiver_on_core(core).is_some() {
+            return Err(NotifyError::Os(libc::EBUSY));
+        }
+        if RECEIVERS.lock().contains_key(&current_tid()) {
+            return Err(NotifyError::Os(libc::EEXIST));
+        }
+        unsafe {
+            let mut set: libc::cpu_set_t = core::mem::zeroed();
+            libc::CPU_SET(core as usize, &mut set);
+            if libc::sched_setaffinity(0, core::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(NotifyError::Os(*libc::__errno_location()));
+            }
+        }
+        let index = Self::register_receiver().ok_or(NotifyError::Os(libc::ENOSPC))?;
+        let mut receivers = RECEIVERS.lock();
+        let busy = receivers
+            .values()
+            .any(|receiver| receiver.core == Some(core));
+        let receiver = receivers.get_mut(&current_tid()).unwrap();
+        if busy {
+            // 另一个线程在检查之后登记了同一核心
+            drop(receivers);
+            Self::unregister_receiver();
+            return Err(NotifyError::Os(libc::EBUSY));
+        }
+        receiver.core = Some(core);
+        Ok(index)
+    }
+
+    /// 绑定到核心`core`的接收线程的线程id
+    pub fn receiver_on_core(core: u32) -> Option<u64> {
+        RECEIVERS
+            .lock()
+            .iter()
+            .find(|(_, receiver)| receiver.core == Some(core))
+            .map(|(&tid, _)| tid)
+    }
+
+    /// 通知源`id`所绑定的核心；其接收线程不是以`register_core_receiver`登记的时返回`None`
+    pub fn core_of(id: u64) -> Option<u32> {
+        let (index, _) = split(id)?;
+        RECEIVERS
+            .lock()
+            .values()
+            .find(|receiver| receiver.index == index)
+            .and_then(|receiver| receiver.core)
+    }
+
+    /// 在绑定到核心`core`的接收线程上分配一个向量；该核心没有接收线程或其向量已被占用完时返回`None`
+    ///
+    /// 可在任意线程中调用。
+    pub fn new_id_on_core(core: u32) -> Option<u64> {
+        assert_safe_context();
+        let policy = Self::reuse_policy();
+        let mut receivers = RECEIVERS.lock();
+        let receiver = receivers
+            .values_mut()
+            .find(|receiver| receiver.core == Some(core))?;
+        let vector = receiver.alloc(policy)?;
+        Some((receiver.index << 8) | vector as u64)
+    }
+
+    /// 在调用线程当前所在核心的接收线程上分配一个向量
+    pub fn new_id_local() -> Option<u64> {
+        Self::new_id_on_core(current_core()?)
+    }
+
+    fn is_allocated(id: u64) -> bool {
+        let Some((index, vector)) = split(id) else {
+            return false;
+        };
+        RECEIVERS
+            .lock()
+            .values()
+            .any(|receiver| receiver.index == index && receiver.used & (1 << vector) != 0)
+    }
+
+    /// 设置之后的`new_id`选取向量的策略
+    pub fn set_reuse_policy(policy: ReusePolicy) {
+        POLICY.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// 当前选取向量的策略
+    pub fn reuse_policy() -> ReusePolicy {
+        match POLICY.load(Ordering::Relaxed) {
+            0 => ReusePolicy::LowestFirst,
+            _ => ReusePolicy::RoundRobin,
+        }
+    }
+}
+
+/// 以系统调用实现的投递：接收方为调用线程的登记序号，待处理向量为中断处理函数记录的位图
+#[cfg(feature = "uintr-hal")]
+impl crate::uintr_hal::UintrHal for UIntrNotification {
+    fn current_receiver() -> Option<u32> {
+        let slot = SLOT.with(Cell::get);
+        (slot < MAX_RECEIVERS).then_some(slot as u32)
+    }
+
+    fn take_pending() -> u64 {
+        PENDING.get(SLOT.with(Cell::get)).map_or(0, |pending| {
+            pending.dirty.store(false, Ordering::Relaxed);
+            pending.bits.swap(0, Ordering::Acquire)
+        })
+    }
+
+    fn deliver(process: u64, receiver: u32, vector: u32) -> Result<(), NotifyError> {
+        Self::try_notify(process, ((receiver as u64) << 8) | vector as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+
+    #[test]
+    fn test_receiver_alloc() {
+        let mut receiver = Receiver {
+            index: 0,
+            used: 0,
+            pinned: 1 << 1,
+            next: 0,
+            core: None,
+        };
+        assert_eq!(receiver.alloc(ReusePolicy::LowestFirst), Some(0));
+        // 被固定的向量被跳过
+        assert_eq!(receiver.alloc(ReusePolicy::LowestFirst), Some(2));
+        receiver.used &= !1;
+        assert_eq!(receiver.alloc(ReusePolicy::RoundRobin), Some(3));
+        assert_eq!(receiver.alloc(ReusePolicy::LowestFirst), Some(0));
+        receiver.used = !(1 << 1);
+        assert_eq!(receiver.alloc(ReusePolicy::RoundRobin), None);
+    }
+
+    #[test]
+    fn test_vector_pool() {
+        // 在新线程中测试，使登记不影响其他测试
+        std::thread::spawn(|| {
+            let tid = current_tid();
+            assert_eq!(UIntrNotification::new_id(), None);
+            assert_eq!(UIntrNotification::vectors_available(), 0);
+            let index = UIntrNotification::register_receiver().unwrap();
+            assert_eq!(UIntrNotification::register_receiver(), Some(index));
+            assert_eq!(UIntrNotification::vectors_available(), VECTORS);
+
+            assert!(UIntrNotification::pin(tid, 0));
+            assert_eq!(UIntrNotification::vectors_available(), VECTORS - 1);
+            let id = UIntrNotification::new_id().unwrap();
+            assert_eq!(split(id), Some((index, 1)));
+            let pinned = UIntrNotification::new_id_with_vector(tid, 0).unwrap();
+            assert_eq!(split(pinned), Some((index, 0)));
+            assert_eq!(UIntrNotification::new_id_with_vector(tid, 0), None);
+            // 向量超出范围的id不被接受，而不会使移位溢出
+            let invalid = (index << 8) | VECTORS as u64;
+            assert_eq!(split(invalid), None);
+            assert!(!UIntrNotification::is_allocated(invalid));
+            assert_eq!(
+                UIntrNotification::serve_sender(invalid, -1),
+                Err(NotifyError::Os(libc::EBADF))
+            );
+            assert!(!UIntrNotification::unregister_receiver());
+
+            unsafe {
+                UIntrNotification::release_id(id);
+                UIntrNotification::release_id(pinned);
+            }
+            assert!(UIntrNotification::unpin(tid, 0));
+            assert!(!UIntrNotification::unpin(tid, 0));
+            assert!(UIntrNotification::unregister_receiver());
+            assert_eq!(UIntrNotification::vectors_available_on(tid), 0);
+        })
+        .join()
+        .unwrap();
+    }
+
+    /// 记录被唤醒次数的waker
+    struct CountWaker(core::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for CountWaker {
+        fn wake(self: alloc::sync::Arc<Self>) {
+            self.0.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    #[test]
+    fn test_deferred_wake() {
+        std::thread::spawn(|| {
+            assert_eq!(
+                UIntrNotification::install_handler(),
+                Err(NotifyError::Os(libc::EPERM))
+            );
+            UIntrNotification::register_receiver().unwrap();
+            assert_eq!(
+                UIntrNotification::install_handler(),
+                Err(NotifyError::Unsupported)
+            );
+            let id = UIntrNotification::new_id().unwrap();
+            let (_, vector) = split(id).unwrap();
+            let count = alloc::sync::Arc::new(CountWaker(Default::default()));
+            let waker = Waker::from(count.clone());
+            let mut cx = Context::from_waker(&waker);
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+
+            // 以直接调用模拟中断的到达：处理函数只记录中断，不唤醒协程
+            on_uintr(vector as u64);
+            assert_eq!(count.0.load(Ordering::Acquire), 0);
+            assert_eq!(UIntrNotification::dispatch(), 1);
+            assert_eq!(count.0.load(Ordering::Acquire), 1);
+            assert_eq!(UIntrNotification::dispatch(), 0);
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_ready());
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+
+            // 在轮询之前到达的中断被`poll_wait`直接取走
+            on_uintr(vector as u64);
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_ready());
+
+            // 多个向量的中断在一次`dispatch`中唤醒，也可以一次取走
+            let other = UIntrNotification::new_id().unwrap();
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+            assert!(UIntrNotification::poll_wait(other, &mut cx).is_pending());
+            on_uintr(vector as u64);
+            on_uintr(split(other).unwrap().1 as u64);
+            assert_eq!(UIntrNotification::dispatch(), 2);
+            assert_eq!(count.0.load(Ordering::Acquire), 3);
+            assert_eq!(UIntrNotification::take_pending(), [id, other]);
+            assert!(UIntrNotification::take_pending().is_empty());
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+            unsafe { UIntrNotification::release_id(other) };
+            on_uintr(vector as u64);
+            unsafe { UIntrNotification::release_id(id) };
+            let id = UIntrNotification::new_id().unwrap();
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+            unsafe { UIntrNotification::release_id(id) };
+            assert!(UIntrNotification::unregister_receiver());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_core_receiver() {
+        std::thread::spawn(|| {
+            let core = current_core().unwrap();
+            let index = UIntrNotification::register_core_receiver(core).unwrap();
+            assert_eq!(
+                UIntrNotification::receiver_on_core(core),
+                Some(current_tid())
+            );
+            let other = std::thread::spawn(move || UIntrNotification::register_core_receiver(core))
+                .join()
+                .unwrap();
+            assert_eq!(other, Err(NotifyError::Os(libc::EBUSY)));
+
+            // 在其他线程中分配的通知源仍绑定到该核心
+            let id = std::thread::spawn(move || UIntrNotification::new_id_on_core(core))
+                .join()
+                .unwrap()
+                .unwrap();
+            assert_eq!(split(id).unwrap().0, index);
+            assert_eq!(UIntrNotification::core_of(id), Some(core));
+            let local = UIntrNotification::new_id_local().unwrap();
+            assert_eq!(UIntrNotification::core_of(local), Some(core));
+
+            // 同一核心上的通知不需要登记为发送方
+            let count = alloc::sync::Arc::new(CountWaker(Default::default()));
+            let waker = Waker::from(count.clone());
+            let mut cx = Context::from_waker(&waker);
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_pending());
+            let pid = unsafe { libc::getpid() } as u64;
+            assert_eq!(UIntrNotification::try_notify(pid, id), Ok(()));
+            assert_eq!(count.0.load(Ordering::Acquire), 1);
+            assert!(UIntrNotification::poll_wait(id, &mut cx).is_ready());
+            assert!(UIntrNotification::poll_wait(local, &mut cx).is_pending());
+
+            unsafe {
+                UIntrNotification::release_id(id);
+                UIntrNotification::release_id(local);
+            }
+            assert!(UIntrNotification::unregister_receiver());
+            assert_eq!(UIntrNotification::receiver_on_core(core), None);
+            assert_eq!(UIntrNotification::new_id_on_core(core), None);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_sender_handshake() {
+        // 测试不设置调用号，只检查不依赖内核的部分
+        let mut fds = [0; 2];
+        let res = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        assert_eq!(res, 0);
+        let (receiver, sender) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        std::thread::spawn(move || {
+            UIntrNotification::register_receiver().unwrap();
+            let id = UIntrNotification::new_id().unwrap();
+            assert_eq!(
+                UIntrNotification::serve_sender(id, receiver.as_raw_fd()),
+                Err(NotifyError::Unsupported)
+            );
+            // 不在接收线程中
+            let socket = receiver.as_raw_fd();
+            let res = std::thread::spawn(move || UIntrNotification::serve_sender(id, socket))
+                .join()
+                .unwrap();
+            assert_eq!(res, Err(NotifyError::Os(libc::EPERM)));
+
+            let pid = unsafe { libc::getpid() } as u64;
+            assert_eq!(
+                UIntrNotification::try_notify(pid, id),
+                Err(NotifyError::Os(libc::ENOTCONN))
+            );
+            assert!(UIntrNotification::can_notify(pid, id).is_err());
+            crate::scm::send(sender.as_raw_fd(), id, Some(sender.as_raw_fd())).unwrap();
+            assert_eq!(
+                UIntrNotification::connect_sender(pid, receiver.as_raw_fd()),
+                Err(NotifyError::Unsupported)
+            );
+            assert!(!UIntrNotification::disconnect_sender(pid, id));
+
+            unsafe { UIntrNotification::release_id(id) };
+            assert_eq!(
+                UIntrNotification::serve_sender(id, receiver.as_raw_fd()),
+                Err(NotifyError::Os(libc::EBADF))
+            );
+            assert!(UIntrNotification::unregister_receiver());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(feature = "uintr-hal")]
+    #[test]
+    fn test_uintr_hal_syscalls() {
+        use crate::uintr_hal::UintrHal;
+
+        crate::uintr_hal_notification!(HalUintr, UIntrNotification, MAX_RECEIVERS);
+
+        std::thread::spawn(|| {
+            assert_eq!(UIntrNotification::current_receiver(), None);
+            assert_eq!(HalUintr::new_id(), None);
+            let index = UIntrNotification::register_receiver().unwrap();
+            assert_eq!(UIntrNotification::current_receiver(), Some(index as u32));
+            let id = HalUintr::new_id().unwrap();
+            assert_eq!(id >> 8, index);
+            let waker = futures::task::noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            assert!(HalUintr::poll_wait(id, &mut cx).is_pending());
+            // 中断处理函数记录的向量由`on_interrupt`取走
+            on_uintr(id & 0xff);
+            assert_eq!(HalUintr::on_interrupt(), 1);
+            assert!(HalUintr::poll_wait(id, &mut cx).is_ready());
+            assert_eq!(
+                HalUintr::try_notify(0, id),
+                Err(NotifyError::Os(libc::ENOTCONN))
+            );
+            unsafe { HalUintr::release_id(id) };
+            assert!(UIntrNotification::unregister_receiver());
+        })
+        .join()
+        .unwrap();
     }
 }
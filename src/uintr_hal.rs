@@ -0,0 +1,470 @@
+//! 由内核或用户态实现投递的用户态中断通知机制
+//!
+//! [`UintrHal`]抽象了用户态中断的投递与接收：内核实现它时直接写UIPI的描述符
+//! （x86的UPID与UITT，见[`Upid`]与[`UittEntry`]）或RISC-V用户态中断扩展的CSR；
+//! 用户态进程同时启用`uintr`时，[`UIntrNotification`](crate::uintr::UIntrNotification)以系统调用实现了它。
+//! 在其上写成的代码因此可以不加修改地用于内核与用户态两侧。
+//!
+//! [`UintrPool`]保存`R`个接收方、每个接收方64个向量的状态，与[`fixed`](crate::fixed)一样不需要分配内存；
+//! [`uintr_hal_notification!`](crate::uintr_hal_notification)在其上定义实现了`NotificationIf`的通知机制。
+//! id的低8位为向量，其上的位为接收方的编号，与`uintr`的id相同。
+//!
+//! 中断到达后，应在可以唤醒协程的上下文中（例如从中断返回之前的内核路径、或执行器的每轮循环中）调用`on_interrupt`，
+//! 它以[`UintrHal::take_pending`]取走当前接收方的待处理向量并唤醒等待者。
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{fixed::StaticWaker, interface::NotifyError};
+
+/// 每个接收方的向量数
+pub const VECTORS: u32 = 64;
+
+/// 用户态中断的投递与接收原语
+pub trait UintrHal {
+    /// 当前执行的接收方的编号，当前线程不是接收方时返回`None`
+    fn current_receiver() -> Option<u32>;
+    /// 取走并清除当前接收方已到达、尚未处理的向量位图
+    fn take_pending() -> u64;
+    /// 向进程`process`中接收方`receiver`的向量`vector`投递中断
+    fn deliver(process: u64, receiver: u32, vector: u32) -> Result<(), NotifyError>;
+}
+
+/// x86的用户态中断通知描述符（UPID），由内核为每个接收线程维护
+///
+/// 发送方执行`senduipi`时，硬件置位`PIR`中的向量，并在`ON`与`SN`均未置位时置位`ON`、
+/// 以`NV`向`NDST`发送通知中断；内核代为投递时以[`Upid::post`]完成同样的操作。
+#[repr(C, align(64))]
+pub struct Upid {
+    /// 位0为`ON`（已发送通知中断），位1为`SN`（抑制通知中断），位23:16为`NV`，位63:32为`NDST`
+    control: AtomicU64,
+    /// 待处理的用户态中断请求，每个向量一位
+    pir: AtomicU64,
+}
+
+/// `ON`位：已发送通知中断
+const UPID_ON: u64 = 1 << 0;
+/// `SN`位：抑制通知中断
+const UPID_SN: u64 = 1 << 1;
+
+impl Upid {
+    /// 新建描述符，`nv`为通知中断的向量，`ndst`为接收线程所在核心的APIC ID
+    pub const fn new(nv: u8, ndst: u32) -> Self {
+        Self {
+            control: AtomicU64::new(((nv as u64) << 16) | ((ndst as u64) << 32)),
+            pir: AtomicU64::new(0),
+        }
+    }
+
+    /// 通知中断的向量
+    pub fn notification_vector(&self) -> u8 {
+        (self.control.load(Ordering::Acquire) >> 16) as u8
+    }
+
+    /// 接收线程所在核心的APIC ID
+    pub fn destination(&self) -> u32 {
+        (self.control.load(Ordering::Acquire) >> 32) as u32
+    }
+
+    /// 接收线程被调度到另一核心时更新`NDST`
+    pub fn set_destination(&self, ndst: u32) {
+        let _ = self
+            .control
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |control| {
+                Some((control & 0xffff_ffff) | ((ndst as u64) << 32))
+            });
+    }
+
+    /// 设置`SN`位：接收线程未在运行时抑制通知中断，请求只记录在`PIR`中
+    pub fn set_suppressed(&self, suppressed: bool) {
+        if suppressed {
+            self.control.fetch_or(UPID_SN, Ordering::AcqRel);
+        } else {
+            self.control.fetch_and(!UPID_SN, Ordering::AcqRel);
+        }
+    }
+
+    /// 置位向量`vector`的请求，返回是否需要由调用者以`NV`向`NDST`发送通知中断
+    pub fn post(&self, vector: u32) -> bool {
+        self.pir
+            .fetch_or(1 << (vector % VECTORS), Ordering::Release);
+        self.control
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |control| {
+                (control & (UPID_ON | UPID_SN) == 0).then_some(control | UPID_ON)
+            })
+            .is_ok()
+    }
+
+    /// 清除`ON`位并取走`PIR`中的全部请求
+    pub fn take(&self) -> u64 {
+        self.control.fetch_and(!UPID_ON, Ordering::AcqRel);
+        self.pir.swap(0, Ordering::AcqRel)
+    }
+}
+
+/// x86的用户态中断目标表（UITT）项，`senduipi`的操作数为其在表中的下标
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UittEntry {
+    /// 位0为有效位
+    valid: u8,
+    /// 投递到接收方的向量
+    user_vector: u8,
+    reserved: [u8; 6],
+    /// 接收方UPID的地址
+    upid: u64,
+}
+
+impl UittEntry {
+    /// 无效的表项
+    pub const INVALID: UittEntry = UittEntry {
+        valid: 0,
+        user_vector: 0,
+        reserved: [0; 6],
+        upid: 0,
+    };
+
+    /// 向地址为`upid`的描述符投递向量`vector`的表项
+    pub const fn new(vector: u8, upid: u64) -> Self {
+        Self {
+            valid: 1,
+            user_vector: vector,
+            reserved: [0; 6],
+            upid,
+        }
+    }
+
+    /// 表项是否有效
+    pub const fn is_valid(&self) -> bool {
+        self.valid & 1 != 0
+    }
+
+    /// 投递到接收方的向量
+    pub const fn vector(&self) -> u8 {
+        self.user_vector
+    }
+
+    /// 接收方UPID的地址
+    pub const fn upid(&self) -> u64 {
+        self.upid
+    }
+}
+
+/// 一个接收方上的向量
+struct Receiver {
+    /// 已被占用的向量
+    used: AtomicU64,
+    /// 已到达、尚未被`poll_wait`消费的向量
+    pending: AtomicU64,
+    wakers: [StaticWaker; VECTORS as usize],
+}
+
+impl Receiver {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NO_WAKER: StaticWaker = StaticWaker::new();
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Receiver = Receiver {
+        used: AtomicU64::new(0),
+        pending: AtomicU64::new(0),
+        wakers: [Self::NO_WAKER; VECTORS as usize],
+    };
+
+    fn is_used(&self, vector: u32) -> bool {
+        self.used.load(Ordering::Acquire) & (1 << vector) != 0
+    }
+
+    /// 消费向量`vector`上的中断；向量未被占用时也返回`true`
+    fn take(&self, vector: u32) -> bool {
+        self.pending.fetch_and(!(1 << vector), Ordering::AcqRel) & (1 << vector) != 0
+            || !self.is_used(vector)
+    }
+}
+
+fn split(id: u64) -> (usize, u32) {
+    ((id >> 8) as usize, (id & 0xff) as u32)
+}
+
+/// `R`个接收方的向量状态
+pub struct UintrPool<const R: usize> {
+    receivers: [Receiver; R],
+}
+
+impl<const R: usize> UintrPool<R> {
+    /// 新建所有向量均未被占用的池，可用于初始化静态变量
+    pub const fn new() -> Self {
+        Self {
+            receivers: [Receiver::INIT; R],
+        }
+    }
+
+    fn receiver(&self, id: u64) -> Option<(&Receiver, u32)> {
+        let (index, vector) = split(id);
+        if vector >= VECTORS {
+            return None;
+        }
+        Some((self.receivers.get(index)?, vector))
+    }
+
+    /// 在当前接收方上占用编号最小的空闲向量，返回其id；当前线程不是接收方或向量均已被占用时返回`None`
+    pub fn alloc<H: UintrHal>(&self) -> Option<u64> {
+        let index = H::current_receiver()?;
+        let receiver = self.receivers.get(index as usize)?;
+        let used = receiver
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                (used != u64::MAX).then(|| used | (1 << (!used).trailing_zeros()))
+            })
+            .ok()?;
+        let vector = (!used).trailing_zeros();
+        receiver.pending.fetch_and(!(1 << vector), Ordering::AcqRel);
+        Some(((index as u64) << 8) | vector as u64)
+    }
+
+    /// 释放向量，并唤醒正在等待的协程；向量未被占用时返回`false`
+    pub fn release(&self, id: u64) -> bool {
+        let Some((receiver, vector)) = self.receiver(id) else {
+            return false;
+        };
+        if receiver.used.fetch_and(!(1 << vector), Ordering::AcqRel) & (1 << vector) == 0 {
+            return false;
+        }
+        receiver.wakers[vector as usize].wake();
+        true
+    }
+
+    /// 向进程`process`的通知源`id`投递中断
+    pub fn notify<H: UintrHal>(process: u64, id: u64) -> Result<(), NotifyError> {
+        let (index, vector) = split(id);
+        if vector >= VECTORS {
+            return Err(NotifyError::Unsupported);
+        }
+        H::deliver(process, index as u32, vector)
+    }
+
+    /// 取走当前接收方的待处理向量并唤醒等待者，返回唤醒的向量数
+    pub fn on_interrupt<H: UintrHal>(&self) -> usize {
+        let Some(receiver) =
+            H::current_receiver().and_then(|index| self.receivers.get(index as usize))
+        else {
+            return 0;
+        };
+        let bits = H::take_pending() & receiver.used.load(Ordering::Acquire);
+        receiver.pending.fetch_or(bits, Ordering::AcqRel);
+        (0..VECTORS)
+            .filter(|vector| bits & (1 << vector) != 0)
+            .inspect(|&vector| receiver.wakers[vector as usize].wake())
+            .count()
+    }
+
+    /// 消费通知源`id`上的中断；没有中断时注册`cx`中的waker。向量未被占用时立即返回`Poll::Ready(())`
+    ///
+    /// `id`属于当前接收方时先以`on_interrupt`取走已到达的中断。
+    pub fn poll_wait<H: UintrHal>(&self, id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        let Some((receiver, vector)) = self.receiver(id) else {
+            return Poll::Ready(());
+        };
+        if H::current_receiver() == Some(split(id).0 as u32) {
+            self.on_interrupt::<H>();
+        }
+        if receiver.take(vector) {
+            return Poll::Ready(());
+        }
+        receiver.wakers[vector as usize].register(cx.waker());
+        if receiver.take(vector) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// 注册waker；已有未被消费的中断或向量未被占用时立即唤醒，不消费中断
+    pub fn register_waker(&self, id: u64, waker: &Waker) {
+        let ready = |receiver: &Receiver, vector: u32| {
+            receiver.pending.load(Ordering::Acquire) & (1 << vector) != 0
+                || !receiver.is_used(vector)
+        };
+        match self.receiver(id) {
+            Some((receiver, vector)) if !ready(receiver, vector) => {
+                receiver.wakers[vector as usize].register(waker);
+                if ready(receiver, vector) {
+                    waker.wake_by_ref();
+                }
+            }
+            _ => waker.wake_by_ref(),
+        }
+    }
+}
+
+impl<const R: usize> Default for UintrPool<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 定义一个使用静态[`UintrPool`](crate::uintr_hal::UintrPool)、以`$hal`投递中断、实现了`NotificationIf`的通知机制
+///
+/// 生成的类型另有`on_interrupt()`，应在中断到达后、可以唤醒协程的上下文中调用。
+///
+/// ```
+/// use async_notification::{interface::NotifyError, uintr_hal::UintrHal};
+///
+/// struct Kernel;
+///
+/// impl UintrHal for Kernel {
+///     fn current_receiver() -> Option<u32> {
+///         Some(0)
+///     }
+///
+///     fn take_pending() -> u64 {
+///         0
+///     }
+///
+///     fn deliver(_process: u64, _receiver: u32, _vector: u32) -> Result<(), NotifyError> {
+///         Ok(())
+///     }
+/// }
+///
+/// async_notification::uintr_hal_notification!(
+///     /// 内核中的用户态中断通知机制
+///     pub KernelUintr, Kernel, 8
+/// );
+/// ```
+#[macro_export]
+macro_rules! uintr_hal_notification {
+    ($(#[$meta:meta])* $vis:vis $name:ident, $hal:ty, $receivers:expr) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $name {
+            /// 保存向量状态的静态池
+            pub fn pool() -> &'static $crate::uintr_hal::UintrPool<{ $receivers }> {
+                static POOL: $crate::uintr_hal::UintrPool<{ $receivers }> =
+                    $crate::uintr_hal::UintrPool::new();
+                &POOL
+            }
+
+            /// 取走当前接收方的待处理向量并唤醒等待者，返回唤醒的向量数
+            pub fn on_interrupt() -> usize {
+                Self::pool().on_interrupt::<$hal>()
+            }
+
+            /// 向进程`process`的通知源`id`投递中断
+            pub fn try_notify(
+                process: u64,
+                id: u64,
+            ) -> ::core::result::Result<(), $crate::interface::NotifyError> {
+                $crate::uintr_hal::UintrPool::<{ $receivers }>::notify::<$hal>(process, id)
+            }
+        }
+
+        impl $crate::interface::NotificationIf for $name {
+            fn new_id() -> ::core::option::Option<u64> {
+                Self::pool().alloc::<$hal>()
+            }
+
+            fn poll_wait(
+                id: u64,
+                cx: &mut ::core::task::Context<'_>,
+            ) -> ::core::task::Poll<()> {
+                Self::pool().poll_wait::<$hal>(id, cx)
+            }
+
+            fn register_waker(id: u64, waker: &::core::task::Waker) {
+                Self::pool().register_waker(id, waker)
+            }
+
+            unsafe fn release_id(id: u64) {
+                Self::pool().release(id);
+            }
+
+            /// 投递失败时被忽略，需要得知失败时使用`try_notify`
+            fn notify(process: u64, id: u64) {
+                let _ = Self::try_notify(process, id);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::NotificationIf;
+
+    /// 以一个UPID模拟内核投递的接收方0
+    static UPID: Upid = Upid::new(0xec, 1);
+
+    struct TestHal;
+
+    impl UintrHal for TestHal {
+        fn current_receiver() -> Option<u32> {
+            Some(0)
+        }
+
+        fn take_pending() -> u64 {
+            UPID.take()
+        }
+
+        fn deliver(_process: u64, receiver: u32, vector: u32) -> Result<(), NotifyError> {
+            if receiver != 0 {
+                return Err(NotifyError::Os(22));
+            }
+            UPID.post(vector);
+            Ok(())
+        }
+    }
+
+    crate::uintr_hal_notification!(TestUintr, TestHal, 2);
+
+    #[test]
+    fn test_uintr_hal() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let a = TestUintr::new_id().unwrap();
+        let b = TestUintr::new_id().unwrap();
+        assert_eq!((a, b), (0, 1));
+
+        assert!(TestUintr::poll_wait(a, &mut cx).is_pending());
+        TestUintr::notify(0, b);
+        assert_eq!(TestUintr::on_interrupt(), 1);
+        assert!(TestUintr::poll_wait(a, &mut cx).is_pending());
+        assert!(TestUintr::poll_wait(b, &mut cx).is_ready());
+        assert!(TestUintr::poll_wait(b, &mut cx).is_pending());
+
+        // 中断在`poll_wait`中被取走
+        TestUintr::notify(0, a);
+        assert!(TestUintr::poll_wait(a, &mut cx).is_ready());
+        assert_eq!(
+            TestUintr::try_notify(0, (1 << 8) | 3),
+            Err(NotifyError::Os(22))
+        );
+
+        unsafe { TestUintr::release_id(a) };
+        assert!(TestUintr::poll_wait(a, &mut cx).is_ready());
+        assert_eq!(TestUintr::new_id(), Some(a));
+        unsafe {
+            TestUintr::release_id(a);
+            TestUintr::release_id(b);
+        }
+    }
+
+    #[test]
+    fn test_upid() {
+        let upid = Upid::new(0xec, 3);
+        assert_eq!((upid.notification_vector(), upid.destination()), (0xec, 3));
+        assert!(upid.post(5));
+        // `ON`已置位时不再发送通知中断
+        assert!(!upid.post(7));
+        assert_eq!(upid.take(), (1 << 5) | (1 << 7));
+        upid.set_suppressed(true);
+        assert!(!upid.post(1));
+        upid.set_destination(4);
+        assert_eq!((upid.notification_vector(), upid.destination()), (0xec, 4));
+        assert_eq!(upid.take(), 1 << 1);
+
+        let entry = UittEntry::new(5, &upid as *const Upid as u64);
+        assert!(entry.is_valid() && !UittEntry::INVALID.is_valid());
+        assert_eq!(entry.vector(), 5);
+        assert_eq!(core::mem::size_of::<UittEntry>(), 16);
+    }
+}
@@ -0,0 +1,510 @@
+//! 使用io_uring `IORING_OP_MSG_RING`的通知机制
+//!
+//! 每个通知源对应本进程的一个io_uring实例，id即为其文件描述符。发送方通过`IORING_OP_MSG_RING`
+//! 直接向接收方的完成队列投递一个`user_data`为[`MSG_USER_DATA`]的完成事件，整个过程不使用信号。
+//!
+//! 已有自己的io_uring完成循环的程序，也可以将其io_uring的文件描述符作为id告知发送方（需加上本类型的高8位），
+//! 并在自己的完成循环中处理`user_data`为[`MSG_USER_DATA`]的完成事件。
+//!
+//! 发送方通过`pidfd_getfd`获取接收方io_uring的文件描述符，因此需要具有对接收方进程的ptrace权限（通常要求同一用户）。
+//! 获取到的文件描述符会被缓存，对端进程退出或释放通知源后，应调用[`UringNotification::forget`]清除缓存。
+//!
+//! 要求Linux 5.18及以上，必须配合tokio运行时
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    fd::OwnedRawFd,
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 通知在接收方完成队列中的`user_data`
+pub const MSG_USER_DATA: u64 = 0x616e_6f74_6966_7900;
+
+const IORING_OP_MSG_RING: u8 = 40;
+const IORING_MSG_DATA: u64 = 0;
+const IORING_SETUP_CQSIZE: u32 = 1 << 3;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+const IORING_ENTER_GETEVENTS: u32 = 1 << 0;
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+
+/// 接收方完成队列的大小，超出的通知由内核暂存于溢出列表中
+const RECV_CQ_ENTRIES: u32 = 256;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    msg_ring_flags: u32,
+    user_data: u64,
+    pad: [u64; 3],
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// 一段内存映射，在被丢弃时解除映射
+struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: libc::c_int, len: usize, offset: libc::off_t) -> Option<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        (ptr != libc::MAP_FAILED).then_some(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// 偏移`offset`处的指针
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.ptr.add(offset as usize) as *mut T }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// 一个io_uring实例的提交与完成队列
+struct Ring {
+    fd: libc::c_int,
+    params: Params,
+    /// 完成队列（内核支持`IORING_FEAT_SINGLE_MMAP`时也包含提交队列）
+    cq: Mmap,
+    /// 内核不支持`IORING_FEAT_SINGLE_MMAP`时单独映射的提交队列
+    sq: Option<Mmap>,
+    sqes: Mmap,
+}
+
+// 队列只在持有外部的锁时被访问
+unsafe impl Send for Ring {}
+
+impl Ring {
+    /// 新建io_uring实例，返回其文件描述符与队列
+    fn new(entries: u32, cq_entries: u32) -> Option<(OwnedRawFd, Self)> {
+        let mut params = Params {
+            flags: IORING_SETUP_CQSIZE,
+            cq_entries,
+            ..Default::default()
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        let fd = fd as libc::c_int;
+        let owned = OwnedRawFd(fd);
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * core::mem::size_of::<Cqe>();
+        let (cq, sq) = if params.features & IORING_FEAT_SINGLE_MMAP != 0 {
+            (Mmap::new(fd, sq_len.max(cq_len), IORING_OFF_SQ_RING)?, None)
+        } else {
+            (
+                Mmap::new(fd, cq_len, IORING_OFF_CQ_RING)?,
+                Some(Mmap::new(fd, sq_len, IORING_OFF_SQ_RING)?),
+            )
+        };
+        let sqes = Mmap::new(
+            fd,
+            params.sq_entries as usize * core::mem::size_of::<Sqe>(),
+            IORING_OFF_SQES,
+        )?;
+        let ring = Self {
+            fd,
+            params,
+            cq,
+            sq,
+            sqes,
+        };
+        Some((owned, ring))
+    }
+
+    /// 取出完成队列中的所有事件，返回其中`user_data`为`user_data`的事件数量，以及最后一个这样的事件的结果
+    fn reap(&mut self, user_data: u64) -> (u64, Option<i32>) {
+        let off = &self.params.cq_off;
+        let head = unsafe { &*self.cq.at::<AtomicU32>(off.head) };
+        let tail = unsafe { &*self.cq.at::<AtomicU32>(off.tail) };
+        let mask = unsafe { *self.cq.at::<u32>(off.ring_mask) };
+        let cqes = self.cq.at::<Cqe>(off.cqes);
+        let (mut current, end) = (head.load(Ordering::Relaxed), tail.load(Ordering::Acquire));
+        let (mut count, mut last) = (0, None);
+        while current != end {
+            let cqe = unsafe { &*cqes.add((current & mask) as usize) };
+            if cqe.user_data == user_data {
+                count += 1;
+                last = Some(cqe.res);
+            }
+            current = current.wrapping_add(1);
+        }
+        head.store(end, Ordering::Release);
+        (count, last)
+    }
+
+    /// 向文件描述符为`target`的io_uring投递一个通知，并等待投递完成，返回投递结果
+    fn msg_ring(&mut self, target: libc::c_int) -> Result<(), i32> {
+        /// 发送方自身完成事件的`user_data`
+        const SEND_USER_DATA: u64 = 1;
+
+        let sq = self.sq.as_ref().unwrap_or(&self.cq);
+        let off = &self.params.sq_off;
+        let tail = unsafe { &*sq.at::<AtomicU32>(off.tail) };
+        let mask = unsafe { *sq.at::<u32>(off.ring_mask) };
+        let array = sq.at::<u32>(off.array);
+        let current = tail.load(Ordering::Relaxed);
+        let index = current & mask;
+        unsafe {
+            self.sqes.at::<Sqe>(0).add(index as usize).write(Sqe {
+                opcode: IORING_OP_MSG_RING,
+                fd: target,
+                off: MSG_USER_DATA,
+                addr: IORING_MSG_DATA,
+                user_data: SEND_USER_DATA,
+                ..Default::default()
+            });
+            array.add(index as usize).write(index);
+        }
+        tail.store(current.wrapping_add(1), Ordering::Release);
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.fd,
+                1u32,
+                1u32,
+                IORING_ENTER_GETEVENTS,
+                ptr::null::<libc::sigset_t>(),
+                0usize,
+            )
+        };
+        if res < 0 {
+            return Err(unsafe { *libc::__errno_location() });
+        }
+        match self.reap(SEND_USER_DATA).1 {
+            Some(0) => Ok(()),
+            Some(res) => Err(-res),
+            None => Err(libc::EIO),
+        }
+    }
+}
+
+/// 使用io_uring `IORING_OP_MSG_RING`的通知机制
+pub struct UringNotification;
+
+struct UringSlot {
+    fd: AsyncFd<OwnedRawFd>,
+    ring: SpinMutex<Ring>,
+    /// 已从完成队列中取出、但尚未被`poll_wait`消费的通知数量
+    pending: AtomicU64,
+}
+
+impl UringSlot {
+    /// 取出完成队列中的通知，返回是否有新的通知
+    fn reap(&self) -> bool {
+        match self.ring.lock().reap(MSG_USER_DATA).0 {
+            0 => false,
+            count => {
+                self.pending.fetch_add(count, Ordering::AcqRel);
+                true
+            }
+        }
+    }
+
+    fn poll_msg(&self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if self
+                .pending
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |p| p.checked_sub(1))
+                .is_ok()
+            {
+                return Poll::Ready(());
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => ready_guard!(guard, return Poll::Ready(())),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !self.reap() {
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+/// 所有被占用的io_uring，以fd为key
+static RINGS: SpinMutex<BTreeMap<u64, Arc<UringSlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程用于发送通知的io_uring，及创建它的进程
+///
+/// fork得到的子进程与父进程共享队列的内存映射，因此子进程需要创建自己的io_uring。
+static SENDER: SpinMutex<Option<(u64, OwnedRawFd, Ring)>> = SpinMutex::new(None);
+
+/// 已通过`pidfd_getfd`获取的对端io_uring，以(进程, id)为key
+static PEERS: SpinMutex<BTreeMap<(u64, u64), Arc<OwnedRawFd>>> = SpinMutex::new(BTreeMap::new());
+
+impl NotificationIf for UringNotification {
+    /// 新建一个io_uring实例，id即为其文件描述符
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将io_uring注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let (fd, ring) = Ring::new(1, RECV_CQ_ENTRIES)?;
+        let id = fd.0 as u64;
+        let slot = UringSlot {
+            fd: AsyncFd::new(fd).ok()?,
+            ring: SpinMutex::new(ring),
+            pending: AtomicU64::new(0),
+        };
+        RINGS.lock().insert(id, Arc::new(slot));
+        Some(id)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_msg(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        let Some(slot) = Self::slot(id) else {
+            waker.wake_by_ref();
+            return;
+        };
+        if slot.pending.load(Ordering::Acquire) > 0 {
+            waker.wake_by_ref();
+            return;
+        }
+        let mut cx = Context::from_waker(waker);
+        if let Poll::Ready(guard) = slot.fd.poll_read_ready(&mut cx) {
+            let mut guard = ready_guard!(guard, return waker.wake_by_ref());
+            if slot.reap() {
+                waker.wake_by_ref();
+            } else {
+                guard.clear_ready();
+            }
+        }
+    }
+
+    /// 关闭io_uring
+    unsafe fn release_id(id: u64) {
+        let slot = RINGS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl UringNotification {
+    /// 向进程`process`的io_uring投递一个通知，失败时返回错误
+    pub fn try_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        let pid = unsafe { libc::getpid() } as u64;
+        let target = if process == pid {
+            None
+        } else {
+            Some(Self::peer(process, id)?)
+        };
+        let target_fd = target.as_ref().map_or(id as libc::c_int, |fd| fd.0);
+        let mut sender = SENDER.lock();
+        if !matches!(sender.as_ref(), Some((owner, _, _)) if *owner == pid) {
+            let (fd, ring) = Ring::new(1, 2).ok_or(NotifyError::Unsupported)?;
+            *sender = Some((pid, fd, ring));
+        }
+        let (_, _, ring) = sender.as_mut().unwrap();
+        ring.msg_ring(target_fd).map_err(|errno| match errno {
+            libc::EOVERFLOW => NotifyError::Overflow,
+            errno => NotifyError::Os(errno),
+        })
+    }
+
+    /// 检查能否向进程`process`的通知源`id`发送通知，不发送通知
+    ///
+    /// 对其他进程，需能通过`pidfd_getfd`取得其io_uring的文件描述符，取得的文件描述符被缓存。
+    pub fn can_notify(process: u64, id: u64) -> Result<(), NotifyError> {
+        if process == unsafe { libc::getpid() } as u64 {
+            return match Self::slot(id) {
+                Some(_) => Ok(()),
+                None => Err(NotifyError::Os(libc::EBADF)),
+            };
+        }
+        Self::peer(process, id).map(drop)
+    }
+
+    /// 清除为进程`process`缓存的io_uring文件描述符
+    pub fn forget(process: u64) {
+        PEERS.lock().retain(|&(p, _), _| p != process);
+    }
+
+    /// 获取进程`process`中id为`id`的io_uring在本进程中的文件描述符
+    fn peer(process: u64, id: u64) -> Result<Arc<OwnedRawFd>, NotifyError> {
+        if let Some(fd) = PEERS.lock().get(&(process, id)) {
+            return Ok(fd.clone());
+        }
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, process as libc::pid_t, 0) };
+        if pidfd < 0 {
+            return Err(NotifyError::Os(unsafe { *libc::__errno_location() }));
+        }
+        let pidfd = OwnedRawFd(pidfd as libc::c_int);
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.0, id as libc::c_int, 0) };
+        if fd < 0 {
+            return Err(NotifyError::Os(unsafe { *libc::__errno_location() }));
+        }
+        let fd = Arc::new(OwnedRawFd(fd as libc::c_int));
+        PEERS.lock().insert((process, id), fd.clone());
+        Ok(fd)
+    }
+
+    fn slot(id: u64) -> Option<Arc<UringSlot>> {
+        RINGS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Delivery, Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[test]
+    fn test_uring_msg_ring() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let id = Notification::new_id_uring().unwrap();
+                let pid = unsafe { libc::getpid() } as u64;
+                let waker = futures::task::noop_waker();
+                let mut cx = core::task::Context::from_waker(&waker);
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                let waiter = tokio::spawn(Notification::wait_on(id));
+                tokio::task::yield_now().await;
+                Notification::notify(pid, id);
+                tokio::time::timeout(Duration::from_secs(1), waiter)
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                // 通知在完成队列中排队，不会被合并
+                for _ in 0..3 {
+                    Notification::notify(pid, id);
+                }
+                for _ in 0..3 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                assert!(Notification::poll_wait(id, &mut cx).is_pending());
+
+                // 从另一进程发送，需通过pidfd_getfd获取io_uring
+                match unsafe { libc::fork() } {
+                    0 => {
+                        let res = Notification::notify_with(pid, id, Delivery::Reliable);
+                        unsafe { libc::_exit(res.is_err() as libc::c_int) };
+                    }
+                    -1 => panic!("Fork failed!"),
+                    child => {
+                        tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                            .await
+                            .unwrap();
+                        let mut status = 0;
+                        unsafe { libc::waitpid(child, &mut status, 0) };
+                        assert_eq!(status, 0);
+                    }
+                }
+
+                unsafe { Notification::release_id(id) };
+                assert!(Notification::poll_wait(id, &mut cx).is_ready());
+            });
+    }
+}
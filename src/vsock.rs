@@ -0,0 +1,230 @@
+//! 使用vsock数据报的通知机制，用于虚拟机与宿主机之间的通知
+//!
+//! 每个通知源对应一个绑定到vsock端口的数据报套接字，id即为端口号；`notify`的`process`参数为对端的CID，
+//! 向对端的端口发送一个1字节的数据报。每个数据报对应一个通知，不会被合并。
+//!
+//! 需要支持vsock数据报的传输层（例如VMCI），virtio-vsock在多数内核版本上不支持数据报。
+//!
+//! 必须配合tokio运行时
+
+extern crate std;
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    mem,
+    task::{Context, Poll, Waker},
+};
+use std::os::fd::OwnedFd;
+
+use crate::{
+    fd::{OwnedRawFd, ReadySlot},
+    interface::{NotificationIf, NotifyError, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 使用vsock数据报的通知机制
+pub struct VsockNotification;
+
+/// 所有被占用的端口，以端口号为key
+static PORTS: SpinMutex<BTreeMap<u64, Arc<ReadySlot>>> = SpinMutex::new(BTreeMap::new());
+
+/// 本进程用于发送通知的套接字
+static SENDER: SpinMutex<Option<OwnedRawFd>> = SpinMutex::new(None);
+
+fn socket() -> Option<OwnedRawFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_VSOCK,
+            libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    (fd >= 0).then_some(OwnedRawFd(fd))
+}
+
+fn addr(cid: u32, port: u32) -> libc::sockaddr_vm {
+    let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+    addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    addr.svm_cid = cid;
+    addr.svm_port = port;
+    addr
+}
+
+/// 读出套接字上的所有数据报，返回数据报的数量
+fn drain(fd: libc::c_int) -> u64 {
+    let mut count = 0;
+    let mut buf = [0u8; 16];
+    while unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) } >= 0 {
+        count += 1;
+    }
+    count
+}
+
+impl NotificationIf for VsockNotification {
+    /// 绑定一个由内核分配的vsock端口，id即为端口号
+    ///
+    /// 该函数需要在tokio运行时内部调用，因为其会将套接字注册到tokio的reactor中。
+    fn new_id() -> Option<u64> {
+        let fd = socket()?;
+        let mut local = addr(libc::VMADDR_CID_ANY, libc::VMADDR_PORT_ANY);
+        let mut len = mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        let res = unsafe {
+            libc::bind(
+                fd.0,
+                &local as *const libc::sockaddr_vm as *const libc::sockaddr,
+                len,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        let res = unsafe {
+            libc::getsockname(
+                fd.0,
+                &mut local as *mut libc::sockaddr_vm as *mut libc::sockaddr,
+                &mut len,
+            )
+        };
+        if res != 0 {
+            return None;
+        }
+        let port = local.svm_port as u64;
+        let slot = ReadySlot::new(fd, drain)?;
+        PORTS.lock().insert(port, Arc::new(slot));
+        Some(port)
+    }
+
+    fn poll_wait(id: u64, cx: &mut Context<'_>) -> Poll<()> {
+        Self::poll_wait_reason(id, cx).map(|_| ())
+    }
+
+    /// 若通知源已被释放，则等待立即以`WakeReason::Shutdown`结束
+    fn poll_wait_reason(id: u64, cx: &mut Context<'_>) -> Poll<WakeReason> {
+        match Self::slot(id) {
+            Some(slot) => slot.poll_wait(cx).map(|()| WakeReason::NOTIFIED),
+            None => Poll::Ready(WakeReason::Shutdown),
+        }
+    }
+
+    fn register_waker(id: u64, waker: &Waker) {
+        match Self::slot(id) {
+            Some(slot) => slot.register_waker(waker),
+            None => waker.wake_by_ref(),
+        }
+    }
+
+    /// 关闭套接字
+    unsafe fn release_id(id: u64) {
+        let slot = PORTS.lock().remove(&id);
+        check!(
+            slot.is_some(),
+            "release_id: id 0x{:016x} is not allocated",
+            id
+        );
+    }
+
+    /// 向CID为`process`的虚拟机（或宿主机）的端口`id`发送通知
+    fn notify(process: u64, id: u64) {
+        let res = Self::try_notify(process, id);
+        check!(res.is_ok(), "notify: {:?}", res);
+    }
+}
+
+impl VsockNotification {
+    /// 向CID为`cid`的虚拟机（或宿主机）的端口`id`发送通知，失败时返回错误
+    pub fn try_notify(cid: u64, id: u64) -> Result<(), NotifyError> {
+        let mut sender = SENDER.lock();
+        if sender.is_none() {
+            *sender = Some(socket().ok_or(NotifyError::Unsupported)?);
+        }
+        let fd = sender.as_ref().unwrap().0;
+        let peer = addr(cid as u32, id as u32);
+        let res = unsafe {
+            libc::sendto(
+                fd,
+                [1u8].as_ptr() as *const libc::c_void,
+                1,
+                0,
+                &peer as *const libc::sockaddr_vm as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if res == 1 {
+            return Ok(());
+        }
+        match unsafe { *libc::__errno_location() } {
+            libc::EAGAIN | libc::ENOBUFS => Err(NotifyError::Overflow),
+            errno => Err(NotifyError::Os(errno)),
+        }
+    }
+
+    /// 本机的CID，需告知对端以便其发送通知
+    pub fn local_cid() -> Option<u64> {
+        /// `IOCTL_VM_SOCKETS_GET_LOCAL_CID`
+        const GET_LOCAL_CID: libc::c_ulong = 0x7b9;
+
+        let fd = unsafe {
+            libc::open(
+                b"/dev/vsock\0".as_ptr() as *const libc::c_char,
+                libc::O_RDONLY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        let fd = OwnedRawFd(fd);
+        let mut cid: libc::c_uint = 0;
+        let res = unsafe { libc::ioctl(fd.0, GET_LOCAL_CID, &mut cid) };
+        (res == 0).then_some(cid as u64)
+    }
+
+    /// 释放通知源，但不关闭其文件描述符，而是将其所有权交给调用者
+    ///
+    /// 若通知源正被其他线程使用（例如正在轮询），则返回`None`且不释放通知源。
+    pub fn into_raw_fd(id: u64) -> Option<OwnedFd> {
+        crate::fd::take_unique(&PORTS, id).map(|slot| slot.into_fd().into_owned())
+    }
+
+    /// 阻塞当前线程等待通知，不需要异步运行时；返回是否在超时前收到通知或通知源被释放
+    pub fn wait_blocking(id: u64, timeout: Option<core::time::Duration>) -> bool {
+        crate::fd::wait_blocking(
+            timeout,
+            || Self::slot(id),
+            |slot| slot.raw_fd(),
+            |slot| slot.take(),
+        )
+    }
+
+    fn slot(id: u64) -> Option<Arc<ReadySlot>> {
+        PORTS.lock().get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interface::{Notification, NotificationIf};
+    use core::time::Duration;
+
+    #[test]
+    fn test_vsock_datagram() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // 没有支持数据报的vsock传输层时无法测试
+                let Some(id) = Notification::new_id_vsock() else {
+                    return;
+                };
+                let cid = super::VsockNotification::local_cid().unwrap();
+                Notification::notify(cid, id);
+                Notification::notify(cid, id);
+                for _ in 0..2 {
+                    tokio::time::timeout(Duration::from_secs(1), Notification::wait_on(id))
+                        .await
+                        .unwrap();
+                }
+                unsafe { Notification::release_id(id) };
+            });
+    }
+}
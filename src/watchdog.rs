@@ -0,0 +1,190 @@
+//! 基于通知的心跳与看门狗
+//!
+//! 一对进程各申请一个专用于心跳的通知源，并通过其他途径告知对方。每一方以[`Watchdog::run`]每隔`interval`向对方的通知源发送心跳，
+//! 同时在自己的通知源上等待对方的心跳；连续`missed`个间隔都没有收到心跳时，`run`返回[`WatchdogError::PeerMissed`]。
+//! 只需要监视而不必发送心跳的一方（例如只有对方需要被监视时）使用[`Watchdog::monitor`]。
+//!
+//! 心跳是普通的通知，多个未被消费的心跳可能被合并为一个，但每个间隔内有一个心跳送达即可。
+//! `NotifyError::Overflow`与`NotifyError::Full`被视为一次丢失的心跳，其余的发送失败使`run`返回。
+//!
+//! 必须配合tokio运行时，并启用其时钟。
+
+use core::{fmt, time::Duration};
+use tokio::time::Instant;
+
+use crate::interface::{Notification, NotificationIf, NotifyError, ProcessRef};
+
+/// 心跳的间隔与容忍的丢失次数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// 发送心跳的间隔
+    pub interval: Duration,
+    /// 连续这么多个间隔没有收到心跳时认为对方已失联；为0时与1相同
+    pub missed: u32,
+}
+
+impl Default for WatchdogConfig {
+    /// 每100毫秒一个心跳，连续丢失3个时认为对方已失联
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            missed: 3,
+        }
+    }
+}
+
+impl WatchdogConfig {
+    /// 多长时间没有收到心跳时认为对方已失联
+    pub fn limit(&self) -> Duration {
+        self.interval.saturating_mul(self.missed.max(1))
+    }
+}
+
+/// 看门狗停止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogError {
+    /// 对方连续`missed`个间隔没有发送心跳
+    PeerMissed {
+        /// 最后一次收到心跳到看门狗停止的时间
+        silent_for: Duration,
+    },
+    /// 发送心跳失败
+    Notify(NotifyError),
+}
+
+impl fmt::Display for WatchdogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchdogError::PeerMissed { silent_for } => {
+                write!(f, "no heartbeat from peer for {:?}", silent_for)
+            }
+            WatchdogError::Notify(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// 一对进程中本地的一方
+pub struct Watchdog {
+    id: u64,
+    peer: ProcessRef,
+    peer_id: u64,
+    config: WatchdogConfig,
+}
+
+impl Watchdog {
+    /// 新建看门狗，在本进程的通知源`id`上接收心跳，向`peer`的通知源`peer_id`发送心跳
+    ///
+    /// `id`由调用者申请与释放，在看门狗被使用期间不能被释放，也不应被用于其他用途。
+    pub fn new(id: u64, peer: ProcessRef, peer_id: u64, config: WatchdogConfig) -> Self {
+        Self {
+            id,
+            peer,
+            peer_id,
+            config,
+        }
+    }
+
+    /// 接收心跳的通知源
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 心跳的配置
+    pub fn config(&self) -> WatchdogConfig {
+        self.config
+    }
+
+    /// 立即向对方发送一个心跳
+    pub fn beat(&self) -> Result<(), NotifyError> {
+        Notification::notify_to(self.peer, self.peer_id)
+    }
+
+    /// 每隔`interval`发送心跳并等待对方的心跳，直到对方失联或发送失败
+    pub async fn run(&self) -> WatchdogError {
+        self.watch(true).await
+    }
+
+    /// 只等待对方的心跳而不发送，直到对方失联
+    pub async fn monitor(&self) -> WatchdogError {
+        self.watch(false).await
+    }
+
+    async fn watch(&self, send: bool) -> WatchdogError {
+        let limit = self.config.limit();
+        let mut last_seen = Instant::now();
+        let mut next_beat = last_seen;
+        loop {
+            let now = Instant::now();
+            if send && now >= next_beat {
+                match self.beat() {
+                    Ok(()) | Err(NotifyError::Overflow | NotifyError::Full) => {}
+                    Err(err) => return WatchdogError::Notify(err),
+                }
+                next_beat = now + self.config.interval;
+            }
+            let expiry = last_seen + limit;
+            if now >= expiry {
+                return WatchdogError::PeerMissed {
+                    silent_for: now - last_seen,
+                };
+            }
+            let wake = if send { next_beat.min(expiry) } else { expiry };
+            if tokio::time::timeout_at(wake, Notification::wait_on(self.id))
+                .await
+                .is_ok()
+            {
+                last_seen = Instant::now();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let config = WatchdogConfig {
+                    interval: Duration::from_millis(10),
+                    missed: 3,
+                };
+                let local = Notification::new_id_mock().unwrap();
+                let remote = Notification::new_id_mock().unwrap();
+                let watchdog = Watchdog::new(local, ProcessRef::Process(0), remote, config);
+                let peer = tokio::spawn(async move {
+                    Watchdog::new(remote, ProcessRef::Process(0), local, config)
+                        .run()
+                        .await
+                });
+
+                // 对方持续发送心跳时不会停止
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(80), watchdog.run())
+                        .await
+                        .is_err()
+                );
+
+                peer.abort();
+                let start = Instant::now();
+                let WatchdogError::PeerMissed { silent_for } = watchdog.monitor().await else {
+                    panic!("watchdog stopped for another reason");
+                };
+                assert!(silent_for >= config.limit());
+                assert!(start.elapsed() < Duration::from_millis(200));
+
+                // 对方的通知源已被释放时发送失败
+                unsafe { Notification::release_id(remote) };
+                assert_eq!(
+                    watchdog.run().await,
+                    WatchdogError::Notify(NotifyError::Os(9))
+                );
+                unsafe { Notification::release_id(local) };
+            });
+    }
+}
@@ -0,0 +1,230 @@
+//! 对端进程的存活监视
+//!
+//! 服务端通常为每个对端进程申请专用的通知源，对端进程异常退出后，这些通知源不会被释放。
+//! [`PeerWatcher`]使用pidfd监视对端进程，在对端进程退出时产生[`PeerGone`]事件，并可自动释放该对端专用的通知源。
+//!
+//! 必须配合tokio运行时
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    interface::{Notification, NotificationIf, WakeReason},
+    sync::SpinMutex,
+};
+
+/// 对端进程退出事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerGone {
+    /// 已退出的对端进程
+    pub pid: u64,
+    /// 该对端专用的通知源，若监视器会自动释放通知源，则其均已被释放
+    pub ids: Vec<u64>,
+}
+
+impl From<PeerGone> for WakeReason {
+    fn from(_: PeerGone) -> Self {
+        WakeReason::PeerDied
+    }
+}
+
+struct Peer {
+    /// 在对端进程退出时被触发的通知源
+    exit_id: u64,
+    /// 该对端专用的通知源
+    ids: Vec<u64>,
+}
+
+/// 对端进程的存活监视器
+pub struct PeerWatcher {
+    peers: SpinMutex<BTreeMap<u64, Peer>>,
+    /// 最近一次等待`next_gone`的waker，在新增被监视的进程时唤醒
+    waker: SpinMutex<Option<Waker>>,
+    /// 是否在对端进程退出时自动释放其专用的通知源
+    auto_release: bool,
+}
+
+impl Default for PeerWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeerWatcher {
+    /// 新建监视器，对端进程退出时不释放其专用的通知源，由调用者根据[`PeerGone`]释放
+    pub const fn new() -> Self {
+        Self {
+            peers: SpinMutex::new(BTreeMap::new()),
+            waker: SpinMutex::new(None),
+            auto_release: false,
+        }
+    }
+
+    /// 新建监视器，对端进程退出时自动释放其专用的通知源
+    ///
+    /// # Safety
+    ///
+    /// 对端进程退出后，不能再在其专用的通知源上调用`wait_on`，也不能有相应的`wait_on`还在执行中，
+    /// 即调用者需满足这些通知源上`release_id`的安全条件。
+    pub const unsafe fn new_auto_release() -> Self {
+        Self {
+            peers: SpinMutex::new(BTreeMap::new()),
+            waker: SpinMutex::new(None),
+            auto_release: true,
+        }
+    }
+
+    /// 开始监视进程`pid`，`ids`为该对端专用的通知源
+    ///
+    /// 若该进程已被监视，则将`ids`追加到其专用的通知源中。若进程已不存在，则返回`false`。
+    /// 该函数需要在tokio运行时内部调用，因为其会将pidfd注册到tokio的reactor中。
+    pub fn watch(&self, pid: u64, ids: &[u64]) -> bool {
+        let mut peers = self.peers.lock();
+        match peers.get_mut(&pid) {
+            Some(peer) => peer.ids.extend_from_slice(ids),
+            None => {
+                let Some(exit_id) = Notification::new_id_child(pid) else {
+                    return false;
+                };
+                peers.insert(
+                    pid,
+                    Peer {
+                        exit_id,
+                        ids: ids.to_vec(),
+                    },
+                );
+            }
+        }
+        drop(peers);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+        true
+    }
+
+    /// 停止监视进程`pid`，返回其专用的通知源，这些通知源不会被释放
+    pub fn unwatch(&self, pid: u64) -> Option<Vec<u64>> {
+        let peer = self.peers.lock().remove(&pid)?;
+        unsafe { Notification::release_id(peer.exit_id) };
+        Some(peer.ids)
+    }
+
+    /// 被监视的进程数量
+    pub fn len(&self) -> usize {
+        self.peers.lock().len()
+    }
+
+    /// 是否没有被监视的进程
+    pub fn is_empty(&self) -> bool {
+        self.peers.lock().is_empty()
+    }
+
+    /// 轮询是否有被监视的进程退出
+    ///
+    /// 若有，则停止监视该进程并返回`Poll::Ready`；否则在任一被监视的进程退出或新增被监视的进程时唤醒`cx`中的waker。
+    pub fn poll_gone(&self, cx: &mut Context<'_>) -> Poll<PeerGone> {
+        *self.waker.lock() = Some(cx.waker().clone());
+        let mut peers = self.peers.lock();
+        let gone = peers
+            .iter()
+            .find(|(_, peer)| Notification::poll_wait(peer.exit_id, cx).is_ready())
+            .map(|(&pid, _)| pid);
+        let Some(pid) = gone else {
+            return Poll::Pending;
+        };
+        let peer = peers.remove(&pid).unwrap();
+        drop(peers);
+        unsafe { Notification::release_id(peer.exit_id) };
+        if self.auto_release {
+            for &id in &peer.ids {
+                // 安全条件由`new_auto_release`的调用者保证
+                unsafe { Notification::release_id(id) };
+            }
+        }
+        #[cfg(feature = "log")]
+        log::info!("peer {} gone, {} dedicated ids", pid, peer.ids.len());
+        Poll::Ready(PeerGone { pid, ids: peer.ids })
+    }
+
+    /// 等待下一个被监视的进程退出
+    pub fn next_gone(&self) -> NextGone<'_> {
+        NextGone { watcher: self }
+    }
+}
+
+/// 停止监视所有进程
+impl Drop for PeerWatcher {
+    fn drop(&mut self) {
+        for (_, peer) in core::mem::take(&mut *self.peers.lock()) {
+            unsafe { Notification::release_id(peer.exit_id) };
+        }
+    }
+}
+
+/// `PeerWatcher::next_gone`返回的future
+///
+/// 该future是取消安全的：在其完成之前丢弃它不会丢失进程退出事件。
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct NextGone<'a> {
+    watcher: &'a PeerWatcher,
+}
+
+impl Future for NextGone<'_> {
+    type Output = PeerGone;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<PeerGone> {
+        self.watcher.poll_gone(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+
+    extern crate std;
+
+    #[test]
+    fn test_watcher_peer_gone() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let watcher = PeerWatcher::new();
+                let mut short = std::process::Command::new("sleep")
+                    .arg("0.1")
+                    .spawn()
+                    .unwrap();
+                let mut long = std::process::Command::new("sleep")
+                    .arg("10")
+                    .spawn()
+                    .unwrap();
+                assert!(watcher.watch(short.id() as u64, &[1]));
+                assert!(watcher.watch(short.id() as u64, &[2]));
+                assert!(watcher.watch(long.id() as u64, &[3]));
+                assert_eq!(watcher.len(), 2);
+
+                let gone = tokio::time::timeout(Duration::from_secs(2), watcher.next_gone())
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    gone,
+                    PeerGone {
+                        pid: short.id() as u64,
+                        ids: alloc::vec![1, 2],
+                    }
+                );
+                short.wait().unwrap();
+
+                assert_eq!(watcher.unwatch(long.id() as u64), Some(alloc::vec![3]));
+                assert!(watcher.is_empty());
+                long.kill().unwrap();
+                long.wait().unwrap();
+            });
+    }
+}